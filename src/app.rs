@@ -1,9 +1,11 @@
 use std::collections::BTreeMap;
+use std::{future::Future, pin::Pin, sync::Arc};
 
 use leptos::*;
 use leptos_meta::*;
 use leptos_router::*;
 
+use crate::components::archive::{GamePage, UserGamesPage};
 use crate::components::board::BoardBackground;
 use crate::components::chess_board::ChessBoard;
 
@@ -15,9 +17,12 @@ use crate::entities::chess_board::{
     signals::{ChessBoardSignalsBuilder, StonesSignals},
     ChessBoard as ChessBoardEntity,
 };
+use crate::entities::connection::Connection;
 use crate::entities::notification::{Notification, NotifyType};
 use crate::entities::room::RoomStatus;
-use crate::handlers::{interaction_end, interaction_move};
+use crate::entities::{seek::Seek, session::SessionInfo, setup::Setup};
+use crate::handlers::{drawing_end, interaction_end, interaction_move};
+use crate::utils::url::{decode_uri_component, encode_uri_component};
 
 #[component]
 pub fn App() -> impl IntoView {
@@ -35,17 +40,76 @@ pub fn App() -> impl IntoView {
         <Link href="https://fonts.googleapis.com/css2?family=Fira+Code:wght@300;400;500;600;700&display=swap" rel="stylesheet"/>
         <Router>
             <Routes>
+                // the board stays mounted while the url follows its room
                 <Route
                     path=""
                     view=move || {
                         view! { <Home/> }
                     }
-                />
+                >
+                    <Route path="" view=|| ()/>
+                    <Route path="/room/:name" view=|| ()/>
+                    <Route path="/analysis" view=|| ()/>
+                </Route>
+                <Route path="/games/:id" view=GamePage/>
+                <Route path="/users/:id/games" view=UserGamesPage/>
             </Routes>
         </Router>
     }
 }
 
+/// Room of a `/room/:name` path
+fn route_room(path: &str) -> Option<String> {
+    path.strip_prefix("/room/")
+        .filter(|name| !name.is_empty())
+        .map(decode_uri_component)
+}
+
+/// Diagram of a room in the link previews, crawlers don't show SVG images
+const BOARD_IMAGE: &str = if cfg!(feature = "png") {
+    "board.png"
+} else {
+    "board.svg"
+};
+
+/// Scheme and host the page is served on, link previews need absolute urls
+fn origin() -> String {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "ssr")] {
+            use_context::<actix_web::HttpRequest>()
+                .map(|req| {
+                    let info = req.connection_info();
+                    format!("{}://{}", info.scheme(), info.host())
+                })
+                .unwrap_or_default()
+        } else {
+            window().location().origin().unwrap_or_default()
+        }
+    }
+}
+
+/// Private room of an analysis board, nobody else knows its name
+fn analysis_room() -> String {
+    let mut bytes = [0u8; 4];
+    let _ = getrandom::getrandom(&mut bytes);
+    let id: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!("analysis-{}", id)
+}
+
+/// Board and trash of a room by name, provided by the server so rooms are
+/// rendered with their position before the socket connects
+#[derive(Clone)]
+pub struct RoomBoards(
+    pub Arc<dyn Fn(String) -> Pin<Box<dyn Future<Output = Option<(String, String)>>>> + Send + Sync>,
+);
+
+impl RoomBoards {
+    pub async fn board(&self, room: String) -> Option<(String, String)> {
+        (self.0)(room).await
+    }
+}
+
 #[component]
 fn Home() -> impl IntoView {
     let fen = "8/8/8/8/8/8/8/8 w - - 0 1";
@@ -56,6 +120,9 @@ fn Home() -> impl IntoView {
     let room_status = create_rw_signal::<Option<RoomStatus>>(None);
     let stones_signals = create_rw_signal::<StonesSignals>(StonesSignals::new());
     let notification = create_rw_signal(Notification::new("".to_string(), NotifyType::Success));
+    let connection = create_rw_signal(Connection::new());
+    let session = create_rw_signal::<Option<SessionInfo>>(None);
+    let seeks = create_rw_signal::<Vec<Seek>>(vec![]);
 
     let chess_board_signals = ChessBoardSignalsBuilder::new()
         .chess_board(chess_board)
@@ -64,17 +131,99 @@ fn Home() -> impl IntoView {
         .stones_signals(stones_signals)
         .should_render(should_render)
         .notification(notification)
+        .connection(connection)
+        .session(session)
+        .seeks(seeks)
         .build()
         .unwrap();
 
-    view! {
-        <div
-            class="flex overflow-hidden relative justify-center items-center px-4 w-screen h-screen sm:py-16 sm:px-16 md:py-16 md:px-0"
-            on:touchmove=move |e| interaction_move(e)
-            on:touchend=move |e| interaction_end(chess_board_signals, e)
-            on:mousemove=move |e| interaction_move(e)
-            on:mouseup=move |e| interaction_end(chess_board_signals, e)
-        >
+    let app_title = env!("APP_TITLE");
+    let location = use_location();
+    let navigate = use_navigate();
+    let room_name = create_memo(move |_| room_status.with(|rs| rs.as_ref().map(|rs| rs.name())));
+
+    // room the url asks for, joined once the socket is in a room, with the
+    // FEN of an analysis board
+    let path = location.pathname.get_untracked();
+    let requested = store_value(match route_room(&path) {
+        Some(room) => Some((room, None)),
+        None if path == "/analysis" => {
+            let fen = use_query_map()
+                .with_untracked(|query| query.get("fen").cloned())
+                .unwrap_or_default()
+                .replace('+', " ");
+            Some((analysis_room(), Some(fen)))
+        }
+        None => None,
+    });
+
+    // the url follows the room, rooms joined later are pushed to the browser
+    // history
+    create_effect(move |_| {
+        let Some(name) = room_name.get() else {
+            return;
+        };
+
+        if let Some((room, fen)) = requested.get_value() {
+            requested.set_value(None);
+            if room != name {
+                let join = match fen.map(|fen| fen.trim().parse::<Setup>()) {
+                    Some(Ok(setup)) => format!("/join {} {}", room, setup.to_string()),
+                    Some(Err(_)) => {
+                        chess_board_signals.notify(NotifyType::Error, "Invalid FEN");
+                        return;
+                    }
+                    None => format!("/join {}", room),
+                };
+                chess_board_signals.send_message(&join);
+                return;
+            }
+        }
+
+        let current = location.pathname.get_untracked();
+        let path = format!("/room/{}", encode_uri_component(&name));
+        if current != path {
+            let options = NavigateOptions {
+                replace: route_room(&current).is_none(),
+                ..Default::default()
+            };
+            navigate(&path, options);
+        }
+    });
+
+    // back and forward go to the room of the url
+    create_effect(move |_| {
+        let Some(room) = route_room(&location.pathname.get()) else {
+            return;
+        };
+        if room_name.get_untracked().is_some_and(|name| name != room) {
+            chess_board_signals.send_message(&format!("/join {}", room));
+        }
+    });
+
+    // the server renders the position of the room of the url
+    let room_boards = use_context::<RoomBoards>();
+    let board = create_blocking_resource(
+        move || route_room(&path),
+        move |room| {
+            let room_boards = room_boards.clone();
+            async move { room_boards?.board(room?).await }
+        },
+    );
+    // the board starts from that position until the socket syncs it, the
+    // effect also runs on the server so the position is rendered there
+    create_isomorphic_effect(move |_| {
+        if let Some(Some((fen, trash))) = board.get() {
+            if !should_render.get_untracked() {
+                chess_board_signals.sync_board(&fen, &trash);
+            }
+        }
+    });
+    let show_board = move || {
+        // the suspense waits for the position
+        board.with(|_| ());
+
+        view! {
             <Show
                 when=move || should_render.get()
                 fallback=move || {
@@ -90,6 +239,46 @@ fn Home() -> impl IntoView {
             >
                 <ChessBoard chess_board_signals=chess_board_signals/>
             </Show>
+        }
+    };
+
+    let title = move || match room_name.get() {
+        Some(name) => format!("{} - {}", name, app_title),
+        None => app_title.to_string(),
+    };
+
+    // link previews of room pages show the room board
+    let preview = move || {
+        let room = route_room(&location.pathname.get())?;
+        let image = format!(
+            "{}/rooms/{}/{}",
+            origin(),
+            encode_uri_component(&room),
+            BOARD_IMAGE
+        );
+
+        Some(view! {
+            <Meta property="og:title" content=format!("{} - {}", room, app_title)/>
+            <Meta property="og:type" content="website"/>
+            <Meta property="og:image" content=image/>
+            <Meta name="twitter:card" content="summary_large_image"/>
+        })
+    };
+
+    view! {
+        <Title text=title/>
+        {preview}
+        <div
+            class="flex overflow-hidden relative justify-center items-center px-4 w-screen h-screen sm:py-16 sm:px-16 md:py-16 md:px-0"
+            on:touchmove=move |e| interaction_move(e)
+            on:touchend=move |e| interaction_end(chess_board_signals, e)
+            on:mousemove=move |e| interaction_move(e)
+            on:mouseup=move |e| {
+                drawing_end(chess_board_signals, &e);
+                interaction_end(chess_board_signals, e)
+            }
+        >
+            <Suspense fallback=|| ()>{show_board}</Suspense>
             <Overlay chess_board_signals=chess_board_signals/>
         </div>
     }
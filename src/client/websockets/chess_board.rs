@@ -4,13 +4,19 @@ use web_sys::{CloseEvent, Element, ErrorEvent, MessageEvent, WebSocket};
 
 use crate::{
     entities::{
-        chess_board::{signals::ChessBoardSignals, ChessBoardBuilder},
+        chess_board::{move_event::MoveEvent, signals::ChessBoardSignals},
+        game::GameStatus,
+        history::History,
         notification::NotifyType,
         room::{RoomStatus, User, UserStatus},
+        seek::Seek,
     },
-    utils::{class_list::ClassListExt, elements::document, WindowExt},
+    utils::{class_list::ClassListExt, elements::document, session::load_session, WindowExt},
 };
 
+/// Close code sent by the server when the session abused the limits
+const CLOSE_CODE_POLICY: u16 = 1008;
+
 fn query_position(square: &str) -> Option<Element> {
     document()
         .query_selector(&format!("[data-square=\"{}\"]", square))
@@ -21,209 +27,352 @@ fn on_message_callback(chess_board_signals: ChessBoardSignals) -> Closure<dyn Fn
     Closure::<dyn FnMut(_)>::new(move |e: MessageEvent| {
         if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
             let text: String = txt.as_string().unwrap();
-            if text.starts_with('/') {
-                let (cmd, input) = text.split_once(" ").unwrap_or((text.as_str(), ""));
-
-                match cmd {
-                    "/move" => {
-                        let mut input = input.split(" ");
-                        let piece_data = input.next().unwrap().to_string();
-
-                        let old_pos = input.next().unwrap().to_string();
-                        let new_pos = input.next().unwrap().to_string();
-
-                        let old_pos_clone = old_pos.clone();
-                        let new_pos_clone = new_pos.clone();
-                        let update_board = move || {
-                            chess_board_signals.move_piece(
-                                piece_data.to_string(),
-                                old_pos.to_string(),
-                                new_pos.to_string(),
-                            )
-                        };
-
-                        if old_pos_clone != "deleted" && new_pos_clone != "deleted" {
-                            if let Some(piece) = query_position(&old_pos_clone) {
-                                piece.class_list_remove(&format!("square-{}", old_pos_clone));
-                                piece.class_list_add(&format!("square-{}", new_pos_clone));
-
-                                let window = web_sys::window().unwrap();
-                                window.set_timeout_callback(update_board, 100);
-                            }
-                        } else {
-                            update_board();
-                        };
-                    }
-                    "/sync_board" => {
-                        chess_board_signals.should_render().set(false);
-                        let mut input = input.split("|");
-
-                        let room_name = input.next().unwrap();
-                        let fen = input.next().unwrap();
-                        let trash = input.next().unwrap();
-
-                        chess_board_signals
-                            .stones_signals()
-                            .update(|stones_signals| {
-                                stones_signals.clear_board_stones();
-                                stones_signals.clear_deleted_stones();
-                            });
-
-                        chess_board_signals.room_status().update(|room_status| {
-                            if let Some(room_status) = room_status {
-                                room_status.set_name(room_name);
-                                room_status.set_checkmate(false);
-                            } else {
-                                *room_status = Some(RoomStatus::new(room_name));
-                            }
-                        });
-
-                        chess_board_signals.chess_board().update(|chessboard| {
-                            let new_chessboard = ChessBoardBuilder::new()
-                                .fen(fen)
-                                .deleted_stones(trash)
-                                .is_white_view(chessboard.white_view())
-                                .validation(false)
-                                .build()
-                                .unwrap();
+            handle_message(chess_board_signals, &text);
+        }
+    })
+}
 
-                            *chessboard = new_chessboard;
-                        });
+fn set_last_seq(chess_board_signals: ChessBoardSignals, seq: &str) {
+    let Ok(seq) = seq.parse::<u64>() else {
+        log::error!("Invalid sequence number: {}", seq);
+        return;
+    };
+
+    if let Some(room_name) = chess_board_signals
+        .room_status()
+        .with_untracked(|rs| rs.as_ref().map(|rs| rs.name()))
+    {
+        chess_board_signals
+            .connection()
+            .update(|c| c.set_last_seq(&room_name, seq));
+    }
+}
 
-                        let positions_and_stones = chess_board_signals
-                            .chess_board()
-                            .with_untracked(|cb| cb.cloned_stones_and_positions());
-                        let deleted_stones = chess_board_signals
-                            .chess_board()
-                            .with_untracked(|cb| cb.cloned_deleted_stones());
-
-                        chess_board_signals
-                            .stones_signals()
-                            .update(|stones_signals| {
-                                for (position, stone) in positions_and_stones {
-                                    stones_signals.add_board_stone(position, stone);
-                                }
-                                for stone in deleted_stones {
-                                    stones_signals.add_deleted_stone(stone);
-                                }
-                            });
-
-                        chess_board_signals.should_render().set(true);
-                    }
-                    "/sync_users" => {
-                        let mut input = input.split("|");
-                        let room_name = input.next().unwrap();
-                        let users: Vec<String> =
-                            input.next().unwrap().split(",").map(String::from).collect();
-
-                        let room_status = chess_board_signals.room_status().get_untracked();
-
-                        if let Some(mut room_status) = room_status {
-                            for user in users.iter().map(|user| user.parse::<User>().unwrap()) {
-                                if let Some(old_user) = room_status.get_user(&user.id()) {
-                                    match user.status() {
-                                        UserStatus::Away => old_user.update(|u| u.disconnect()),
-                                        UserStatus::Online => old_user.update(|u| u.connect()),
-                                        _ => {}
-                                    }
-                                }
-                            }
+fn notify(chess_board_signals: ChessBoardSignals, notify_type: NotifyType, msg: &str) {
+    chess_board_signals.notify(notify_type, msg);
+}
 
-                            room_status.sync_users(users);
+/// Moves queued while the socket was down are dropped when the board changed
+/// in the meantime, the server would only roll them back as stale
+fn board_changed(chess_board_signals: ChessBoardSignals) {
+    let dropped = chess_board_signals
+        .connection()
+        .try_update(|c| c.board_changed())
+        .unwrap_or(0);
+
+    if dropped > 0 {
+        notify(
+            chess_board_signals,
+            NotifyType::Warning,
+            "The board changed while you were offline, your moves were not sent",
+        );
+    }
+}
 
-                            chess_board_signals.room_status().set(Some(room_status));
-                        } else {
-                            let mut new_room_status = RoomStatus::new(room_name);
-                            new_room_status.sync_users(users);
-                            chess_board_signals.room_status().set(Some(new_room_status));
+fn handle_message(chess_board_signals: ChessBoardSignals, text: &str) {
+    if text.starts_with('/') {
+        let (cmd, input) = text.split_once(" ").unwrap_or((text, ""));
+
+        match cmd {
+            "/event" => {
+                let (seq, event) = input.split_once(" ").unwrap_or((input, ""));
+                let Ok(event_seq) = seq.parse::<u64>() else {
+                    log::error!("Invalid sequence number: {}", seq);
+                    return;
+                };
+
+                match chess_board_signals.last_seq() {
+                    // already applied, resyncs may replay a few events twice
+                    Some(last_seq) if event_seq <= last_seq => return,
+                    Some(last_seq) if event_seq > last_seq + 1 => {
+                        log::warn!("Missed room events {}..{}", last_seq + 1, event_seq);
+                        if chess_board_signals
+                            .connection()
+                            .try_update(|c| c.request_resync())
+                            .unwrap_or(false)
+                        {
+                            chess_board_signals.send_message(&format!("/resync {}", last_seq));
                         }
+                        return;
                     }
-                    "/sync_options" => {
-                        chess_board_signals.room_status().update(|room_status| {
-                            if let Some(room_status) = room_status {
-                                room_status.set_options_from_str(input);
-                            }
-                        });
+                    _ => {}
+                }
+
+                set_last_seq(chess_board_signals, seq);
+                board_changed(chess_board_signals);
+                handle_message(chess_board_signals, event);
+            }
+            "/rollback" => {
+                let (reason, input) = input.split_once(" ").unwrap_or(("", input));
+                let mut input = input.split("|");
+
+                let _room_name = input.next().unwrap();
+                let fen = input.next().unwrap();
+                let trash = input.next().unwrap();
+                let seq = input.next();
+
+                chess_board_signals.sync_board(fen, trash);
+                board_changed(chess_board_signals);
+
+                if let Some(seq) = seq {
+                    set_last_seq(chess_board_signals, seq);
+                }
+
+                if reason == "stale" {
+                    notify(
+                        chess_board_signals,
+                        NotifyType::Warning,
+                        "The board changed before your move, it was undone",
+                    );
+                }
+            }
+            "/move_event" => {
+                let Ok(move_event) = input.parse::<MoveEvent>() else {
+                    log::error!("Invalid move event: {}", input);
+                    return;
+                };
+
+                // every piece of the move slides at the same time
+                let mut animated = false;
+                for change in move_event.changes.iter() {
+                    let (Some(from), Some(to)) = (&change.from, &change.to) else {
+                        continue;
+                    };
+                    if !chess_board_signals.is_pending_change(change) {
+                        continue;
                     }
-                    "/add_user" => {
-                        let room_status = chess_board_signals.room_status().get_untracked();
-
-                        let new_user = input.parse::<User>().unwrap();
-
-                        if let Some(mut room_status) = room_status {
-                            if let Some(old_user) = room_status.get_user(&new_user.id()) {
-                                old_user.update(|user| {
-                                    user.set_username(&new_user.username());
-                                    user.connect();
-                                });
-                            } else {
-                                room_status.add_user(new_user);
-                                chess_board_signals.room_status().set(Some(room_status));
-                            }
-                        }
+                    let (from, to) = (from.to_string(), to.to_string());
+                    if let Some(piece) = query_position(&from) {
+                        piece.class_list_remove(&format!("square-{}", from));
+                        piece.class_list_add(&format!("square-{}", to));
+                        animated = true;
                     }
-                    "/remove_user" => {
-                        chess_board_signals.room_status().update(|room_status| {
-                            if let Some(room_status) = room_status {
-                                room_status.remove_user(input);
-                            }
-                        });
+                }
+
+                let update_board = move || chess_board_signals.apply_move_event(&move_event);
+
+                if animated {
+                    let window = web_sys::window().unwrap();
+                    window.set_timeout_callback(update_board, 100);
+                } else {
+                    update_board();
+                }
+            }
+            "/sync_board" => {
+                let mut input = input.split("|");
+
+                let room_name = input.next().unwrap();
+                let fen = input.next().unwrap();
+                let trash = input.next().unwrap();
+                let seq = input.next();
+
+                // a full sync means the room could not resume from the last
+                // event, or it is another room
+                let unchanged = chess_board_signals
+                    .connection()
+                    .with_untracked(|c| c.last_seq())
+                    .is_some_and(|(room, last_seq)| {
+                        room == room_name && seq.and_then(|s| s.parse().ok()) == Some(last_seq)
+                    });
+                if !unchanged {
+                    board_changed(chess_board_signals);
+                }
+
+                chess_board_signals.room_status().update(|room_status| {
+                    if let Some(room_status) = room_status {
+                        room_status.set_name(room_name);
+                        room_status.set_checkmate(false);
+                    } else {
+                        *room_status = Some(RoomStatus::new(room_name));
                     }
-                    "/disconnect_user" => {
-                        let (id, _) = input.split_once(":").unwrap_or((input, ""));
+                });
 
-                        let user = chess_board_signals
-                            .room_status()
-                            .get_untracked()
-                            .and_then(|room_status| room_status.get_user(id));
+                chess_board_signals.sync_board(fen, trash);
 
-                        if let Some(user) = user {
-                            user.update(|u| u.disconnect());
+                if let Some(seq) = seq {
+                    set_last_seq(chess_board_signals, seq);
+                }
+            }
+            "/sync_users" => {
+                // users are synced after the board, the room has resumed
+                chess_board_signals.flush_pending_messages();
+
+                let mut input = input.split("|");
+                let room_name = input.next().unwrap();
+                let users: Vec<String> =
+                    input.next().unwrap().split(",").map(String::from).collect();
+
+                let room_status = chess_board_signals.room_status().get_untracked();
+
+                if let Some(mut room_status) = room_status {
+                    for user in users.iter().map(|user| user.parse::<User>().unwrap()) {
+                        if let Some(old_user) = room_status.get_user(&user.id()) {
+                            match user.status() {
+                                UserStatus::Away => old_user.update(|u| u.disconnect()),
+                                UserStatus::Online => old_user.update(|u| u.connect()),
+                                _ => {}
+                            }
                         }
                     }
-                    "/connect_user" => {
-                        let (id, _) = input.split_once(":").unwrap_or((input, ""));
 
-                        let user = chess_board_signals
-                            .room_status()
-                            .get_untracked()
-                            .and_then(|room_status| room_status.get_user(id));
+                    room_status.sync_users(users);
 
-                        if let Some(user) = user {
-                            user.update(|u| u.connect());
+                    chess_board_signals.room_status().set(Some(room_status));
+                } else {
+                    let mut new_room_status = RoomStatus::new(room_name);
+                    new_room_status.sync_users(users);
+                    chess_board_signals.room_status().set(Some(new_room_status));
+                }
+            }
+            "/sync_game" => {
+                let Ok(game) = input.parse::<GameStatus>() else {
+                    log::error!("Invalid game status: {}", input);
+                    return;
+                };
+
+                chess_board_signals.room_status().update(|room_status| {
+                    if let Some(room_status) = room_status {
+                        room_status.set_game(game, js_sys::Date::now());
+                    }
+                });
+            }
+            "/sync_history" => {
+                let Ok(history) = input.parse::<History>() else {
+                    log::error!("Invalid history: {}", input);
+                    return;
+                };
+
+                chess_board_signals.room_status().update(|room_status| {
+                    if let Some(room_status) = room_status {
+                        room_status.set_history(history);
+                    }
+                });
+            }
+            "/pgn" => {
+                chess_board_signals.room_status().update(|room_status| {
+                    if let Some(room_status) = room_status {
+                        room_status.set_pgn(Some(input.to_string()));
+                    }
+                });
+            }
+            "/sync_ratings" => {
+                chess_board_signals.room_status().update(|room_status| {
+                    if let Some(room_status) = room_status {
+                        if room_status.sync_ratings(input).is_err() {
+                            log::error!("Invalid ratings: {}", input);
                         }
                     }
-                    "/notify" => {
-                        let (notify_type, msg) = input.split_once(" ").unwrap_or((input, ""));
-                        let notify_type = match notify_type {
-                            "error" => NotifyType::Error,
-                            "warning" => NotifyType::Warning,
-                            "success" => NotifyType::Success,
-                            _ => return,
-                        };
-
-                        chess_board_signals.notification().update(|notification| {
-                            notification.notify_type = notify_type;
-                            notification.message = msg.to_string();
-                            notification.disable();
-                        });
+                });
+            }
+            "/sync_seeks" => {
+                let seeks = input
+                    .split(',')
+                    .filter(|seek| !seek.is_empty())
+                    .filter_map(|seek| seek.parse::<Seek>().ok())
+                    .collect();
+                chess_board_signals.seeks().set(seeks);
+            }
+            "/add_seek" => match input.parse::<Seek>() {
+                Ok(seek) => chess_board_signals.seeks().update(|seeks| seeks.push(seek)),
+                Err(_) => log::error!("Invalid seek: {}", input),
+            },
+            "/remove_seek" => {
+                chess_board_signals
+                    .seeks()
+                    .update(|seeks| seeks.retain(|seek| seek.id != input));
+            }
+            "/game_ready" => {
+                // a tournament round or a rematch started, the player is
+                // seated there
+                chess_board_signals.send_message(&format!("/join {}", input));
+                notify(
+                    chess_board_signals,
+                    NotifyType::Success,
+                    "Your game is ready",
+                );
+            }
+            "/sync_options" => {
+                chess_board_signals.room_status().update(|room_status| {
+                    if let Some(room_status) = room_status {
+                        room_status.set_options_from_str(input);
+                    }
+                });
+            }
+            "/add_user" => {
+                let room_status = chess_board_signals.room_status().get_untracked();
+
+                let new_user = input.parse::<User>().unwrap();
 
-                        chess_board_signals.notification().update(|notification| {
-                            notification.enable();
+                if let Some(mut room_status) = room_status {
+                    if let Some(old_user) = room_status.get_user(&new_user.id()) {
+                        old_user.update(|user| {
+                            user.set_username(&new_user.username());
+                            user.connect();
                         });
+                    } else {
+                        room_status.add_user(new_user);
+                        chess_board_signals.room_status().set(Some(room_status));
                     }
-                    "/checkmate" => {
-                        chess_board_signals.room_status().update(|room_status| {
-                            if let Some(room_status) = room_status {
-                                room_status.set_checkmate(true);
-                            }
-                        });
+                }
+            }
+            "/remove_user" => {
+                chess_board_signals.room_status().update(|room_status| {
+                    if let Some(room_status) = room_status {
+                        room_status.remove_user(input);
                     }
-                    _ => {}
+                });
+            }
+            "/disconnect_user" => {
+                let (id, _) = input.split_once(":").unwrap_or((input, ""));
+
+                let user = chess_board_signals
+                    .room_status()
+                    .get_untracked()
+                    .and_then(|room_status| room_status.get_user(id));
+
+                if let Some(user) = user {
+                    user.update(|u| u.disconnect());
                 }
             }
+            "/connect_user" => {
+                let (id, _) = input.split_once(":").unwrap_or((input, ""));
+
+                let user = chess_board_signals
+                    .room_status()
+                    .get_untracked()
+                    .and_then(|room_status| room_status.get_user(id));
+
+                if let Some(user) = user {
+                    user.update(|u| u.connect());
+                }
+            }
+            "/notify" => {
+                let (notify_type, msg) = input.split_once(" ").unwrap_or((input, ""));
+                let notify_type = match notify_type {
+                    "error" => NotifyType::Error,
+                    "warning" => NotifyType::Warning,
+                    "success" => NotifyType::Success,
+                    _ => return,
+                };
+
+                notify(chess_board_signals, notify_type, msg);
+            }
+            "/error" => {
+                let (code, msg) = input.split_once(" ").unwrap_or((input, ""));
+                log::warn!("Server error {}: {}", code, msg);
+
+                notify(chess_board_signals, NotifyType::Error, msg);
+            }
+            "/checkmate" => {
+                chess_board_signals.room_status().update(|room_status| {
+                    if let Some(room_status) = room_status {
+                        room_status.set_checkmate(true);
+                    }
+                });
+            }
+            _ => {}
         }
-    })
+    }
 }
 
 #[allow(dead_code)]
@@ -236,10 +385,16 @@ pub fn start_websocket(chess_board_signals: ChessBoardSignals) -> Result<WebSock
         .starts_with("https")
         .then(|| "wss")
         .unwrap_or("ws");
+    let resume_query = chess_board_signals
+        .connection()
+        .with_untracked(|c| c.last_seq())
+        .map(|(room, seq)| format!("?room={}&seq={}", js_sys::encode_uri_component(&room), seq))
+        .unwrap_or_default();
     let ws_uri = format!(
-        "{proto}://{host}/ws",
+        "{proto}://{host}/ws{query}",
         proto = proto,
-        host = location.host().unwrap()
+        host = location.host().unwrap(),
+        query = resume_query
     );
 
     // Connect to an echo server
@@ -257,13 +412,13 @@ pub fn start_websocket(chess_board_signals: ChessBoardSignals) -> Result<WebSock
     ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
     onerror_callback.forget();
 
-    // let onopen_callback = Closure::<dyn FnMut()>::new(move || {
-    //     log::info!("socket opened");
-    // });
-    // ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
-    // onopen_callback.forget();
+    let onopen_callback = Closure::<dyn FnMut()>::new(move || {
+        chess_board_signals.connection().update(|c| c.connected());
+    });
+    ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
+    onopen_callback.forget();
 
-    let onclose_callback = Closure::<dyn FnMut(_)>::new(move |_: CloseEvent| {
+    let onclose_callback = Closure::<dyn FnMut(_)>::new(move |e: CloseEvent| {
         chess_board_signals.socket().set(None);
         let room_status = chess_board_signals.room_status().get_untracked();
         if let Some(room_status) = room_status {
@@ -273,6 +428,37 @@ pub fn start_websocket(chess_board_signals: ChessBoardSignals) -> Result<WebSock
 
             chess_board_signals.room_status().set(Some(room_status));
         }
+
+        // the server closes abusive sessions with a policy violation,
+        // reconnecting would only get rate limited again
+        if e.code() == CLOSE_CODE_POLICY {
+            chess_board_signals
+                .connection()
+                .update(|c| c.disconnected());
+            return;
+        }
+
+        chess_board_signals
+            .connection()
+            .update(|c| c.reconnecting());
+        let delay = chess_board_signals
+            .connection()
+            .with_untracked(|c| c.reconnect_delay(js_sys::Math::random()));
+
+        // the access token may have expired while the socket was open
+        let reconnect = move || {
+            leptos::spawn_local(async move {
+                if load_session(chess_board_signals).await.is_some() {
+                    chess_board_signals.start_websocket();
+                } else {
+                    chess_board_signals
+                        .connection()
+                        .update(|c| c.disconnected());
+                }
+            })
+        };
+        let window = web_sys::window().unwrap();
+        window.set_timeout_callback(reconnect, delay);
     });
     ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
     onclose_callback.forget();
@@ -0,0 +1,94 @@
+use leptos::*;
+
+use crate::entities::{
+    annotation::{Shape, ShapeColor},
+    chess_board::signals::ChessBoardSignals,
+};
+
+const COLORS: [ShapeColor; 4] = [
+    ShapeColor::Green,
+    ShapeColor::Red,
+    ShapeColor::Yellow,
+    ShapeColor::Blue,
+];
+
+/// Arrows and circles drawn on the position shown, in the 100 x 100 units of
+/// the board background
+#[component]
+pub fn BoardAnnotations(chess_board_signals: ChessBoardSignals) -> impl IntoView {
+    let white_view = move || chess_board_signals.chess_board().with(|c| c.white_view());
+    let shapes = move || {
+        chess_board_signals.room_status().with(|rs| {
+            rs.as_ref()
+                .and_then(|rs| rs.history().current_annotation().cloned())
+                .map(|annotation| annotation.shapes)
+                .unwrap_or_default()
+        })
+    };
+
+    let center = move |x: usize, y: usize| {
+        let (x, y) = (12.5 * x as f64 + 6.25, 12.5 * y as f64 + 6.25);
+        if white_view() {
+            (x, y)
+        } else {
+            (100.0 - x, 100.0 - y)
+        }
+    };
+
+    let shape_view = move |shape: Shape| {
+        let color = shape.color.css_color();
+        let (x1, y1) = center(shape.from.x, shape.from.y);
+
+        if shape.is_circle() {
+            return view! {
+                <circle cx=x1 cy=y1 r="5.5" fill="none" stroke=color stroke-width="0.8"></circle>
+            }
+            .into_view();
+        }
+
+        // the arrow head ends a bit before the center of the square
+        let (x2, y2) = center(shape.to.x, shape.to.y);
+        let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+        let shorten = 3.0 / length;
+        let (x2, y2) = (x2 - (x2 - x1) * shorten, y2 - (y2 - y1) * shorten);
+
+        view! {
+            <line
+                x1=x1
+                y1=y1
+                x2=x2
+                y2=y2
+                stroke=color
+                stroke-width="1.8"
+                stroke-linecap="round"
+                marker-end=format!("url(#arrowhead-{})", shape.color.letter())
+            ></line>
+        }
+        .into_view()
+    };
+
+    let markers = COLORS
+        .into_iter()
+        .map(|color| {
+            view! {
+                <marker
+                    id=format!("arrowhead-{}", color.letter())
+                    orient="auto"
+                    markerWidth="4"
+                    markerHeight="8"
+                    refX="2.05"
+                    refY="2.01"
+                >
+                    <path d="M0,0 V4 L3,2 Z" fill=color.css_color()></path>
+                </marker>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <svg viewBox="0 0 100 100" class="annotations">
+            <defs>{markers}</defs>
+            <g opacity="0.8">{move || shapes().into_iter().map(shape_view).collect_view()}</g>
+        </svg>
+    }
+}
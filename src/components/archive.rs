@@ -0,0 +1,250 @@
+use leptos::*;
+use leptos_router::*;
+
+use crate::components::static_board::StaticBoard;
+use crate::entities::{
+    archive::{utc_date, GameRecord, Rematch},
+    chess_board::turns::Turn,
+    session::SessionInfo,
+};
+use crate::utils::{
+    http::{get_json, post_json_for},
+    url::encode_uri_component,
+};
+
+const BUTTON_CLASS: &str =
+    "border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-1 px-2";
+const INPUT_CLASS: &str = "border border-gray-400 rounded p-2";
+
+fn param(name: &'static str) -> impl Fn() -> String + Copy {
+    let params = use_params_map();
+    move || params.with(|params| params.get(name).cloned().unwrap_or_default())
+}
+
+/// Replay of an archived game, moves of the list can be clicked to see the
+/// position after them
+#[component]
+pub fn GamePage() -> impl IntoView {
+    let id = param("id");
+    let game = create_rw_signal::<Option<GameRecord>>(None);
+    let user_id = create_rw_signal::<Option<String>>(None);
+    let ply = create_rw_signal(0usize);
+    let error = create_rw_signal::<Option<String>>(None);
+    let rematch_offered = create_rw_signal(false);
+
+    create_effect(move |_| {
+        let id = id();
+        spawn_local(async move {
+            match get_json::<GameRecord>(&format!("/api/games/{}", id)).await {
+                Ok(record) => {
+                    ply.set(record.moves.len());
+                    game.set(Some(record));
+                }
+                Err(message) => error.set(Some(message)),
+            }
+        });
+    });
+    create_effect(move |_| {
+        spawn_local(async move {
+            if let Ok(session) = get_json::<SessionInfo>("/sessions/me").await {
+                user_id.set(Some(session.user_id));
+            }
+        });
+    });
+
+    let rematch = move |_| {
+        spawn_local(async move {
+            let url = format!("/api/games/{}/rematch", id());
+            match post_json_for::<_, Rematch>(&url, &()).await {
+                // the opponent asked first, the game is ready
+                Ok(Rematch { room: Some(_) }) => {
+                    let _ = window().location().set_href("/");
+                }
+                Ok(Rematch { room: None }) => rematch_offered.set(true),
+                Err(message) => error.set(Some(message)),
+            }
+        });
+    };
+
+    let game_view = move |record: GameRecord| {
+        let last = record.moves.len();
+        let player = user_id.get().and_then(|user_id| record.side_of(&user_id));
+        let flipped = player == Some(Turn::Black);
+        let fen_record = record.clone();
+        let fen = move || fen_record.fen_at(ply.get()).to_string();
+
+        let black_first = record.start_fen.split_whitespace().nth(1) == Some("b");
+        let moves = record
+            .moves
+            .iter()
+            .enumerate()
+            .map(|(i, recorded)| {
+                let number = (i + usize::from(black_first)) / 2 + 1;
+                let label = if (i + usize::from(black_first)) % 2 == 0 {
+                    format!("{}. {}", number, recorded.san)
+                } else if i == 0 {
+                    format!("{}... {}", number, recorded.san)
+                } else {
+                    recorded.san.clone()
+                };
+                let class = move || {
+                    if ply.get() == i + 1 {
+                        "px-1 rounded bg-blue-200"
+                    } else {
+                        "px-1 rounded hover:bg-gray-200"
+                    }
+                };
+                view! {
+                    <button class=class on:click=move |_| ply.set(i + 1)>
+                        {label}
+                    </button>
+                }
+            })
+            .collect_view();
+        let rematch_button = player.map(|_| {
+            view! {
+                <button class=BUTTON_CLASS on:click=rematch>
+                    "Rematch"
+                </button>
+            }
+        });
+
+        view! {
+            <div class="flex flex-col gap-2 items-center w-full sm:h-[32rem] sm:w-auto aspect-square">
+                <StaticBoard fen=fen flipped=flipped/>
+            </div>
+            <div class="flex flex-col gap-2 w-full max-w-md">
+                <label class="text-xl">
+                    {format!("{} - {}", record.white_name, record.black_name)}
+                </label>
+                <span class="text-gray-500">
+                    {format!(
+                        "{} · {}{} · {}",
+                        record.result,
+                        record.time_control.clone().unwrap_or("untimed".to_string()),
+                        if record.rated { " rated" } else { "" },
+                        utc_date(record.started_at)
+                    )}
+                </span>
+                <div class="flex flex-wrap gap-1 max-h-64 overflow-y-auto">{moves}</div>
+                <div class="flex gap-2">
+                    <button class=BUTTON_CLASS on:click=move |_| ply.set(0)>"<<"</button>
+                    <button class=BUTTON_CLASS on:click=move |_| ply.update(|ply| *ply = ply.saturating_sub(1))>
+                        "<"
+                    </button>
+                    <button class=BUTTON_CLASS on:click=move |_| ply.update(|ply| *ply = (*ply + 1).min(last))>
+                        ">"
+                    </button>
+                    <button class=BUTTON_CLASS on:click=move |_| ply.set(last)>">>"</button>
+                    {rematch_button}
+                </div>
+                <Show when=move || rematch_offered.get() fallback=|| ()>
+                    <span class="text-gray-500">
+                        "Rematch offered, the game starts when your opponent asks for it too"
+                    </span>
+                </Show>
+                <textarea class="w-full h-40 border border-gray-400 rounded p-2 font-mono text-sm" readonly=true>
+                    {record.pgn.clone()}
+                </textarea>
+            </div>
+        }
+        .into_view()
+    };
+
+    view! {
+        <div class="flex flex-col gap-4 justify-center items-center p-4 w-screen min-h-screen sm:flex-row">
+            {move || error.get().map(|message| view! { <span class="text-red-500">{message}</span> })}
+            {move || game.get().map(game_view)}
+        </div>
+    }
+}
+
+/// Games of a user, filtered by result, colour and opponent
+#[component]
+pub fn UserGamesPage() -> impl IntoView {
+    let id = param("id");
+    let games = create_rw_signal::<Vec<GameRecord>>(vec![]);
+    let result = create_rw_signal(String::new());
+    let color = create_rw_signal(String::new());
+    let opponent = create_rw_signal(String::new());
+    let error = create_rw_signal::<Option<String>>(None);
+
+    create_effect(move |_| {
+        let url = format!(
+            "/api/users/{}/games?result={}&color={}&opponent={}",
+            encode_uri_component(&id()),
+            encode_uri_component(&result.get()),
+            encode_uri_component(&color.get()),
+            encode_uri_component(&opponent.get())
+        );
+        spawn_local(async move {
+            match get_json::<Vec<GameRecord>>(&url).await {
+                Ok(list) => {
+                    error.set(None);
+                    games.set(list);
+                }
+                Err(message) => error.set(Some(message)),
+            }
+        });
+    });
+
+    let game_view = move |record: GameRecord| {
+        let user_id = id();
+        let outcome = record.outcome_for(&user_id).unwrap_or("-");
+        let opponent = record
+            .opponent_of(&user_id)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_default();
+        let side = if record.white_id == user_id {
+            "white"
+        } else {
+            "black"
+        };
+
+        view! {
+            <li class="w-full flex justify-between items-center gap-4 py-1">
+                <span class="text-gray-500">{utc_date(record.finished_at)}</span>
+                <span>{format!("{} as {}", opponent, side)}</span>
+                <span>{format!("{} ({})", outcome, record.result)}</span>
+                <span class="text-gray-500">
+                    {record.time_control.clone().unwrap_or("untimed".to_string())}
+                </span>
+                <a class=BUTTON_CLASS href=format!("/games/{}", record.id)>"Replay"</a>
+            </li>
+        }
+    };
+
+    view! {
+        <div class="flex flex-col gap-4 items-center p-4 w-screen min-h-screen">
+            <label class="text-xl">"Games"</label>
+            <div class="flex flex-wrap gap-2 items-center">
+                <select class=INPUT_CLASS on:change=move |e| result.set(event_target_value(&e))>
+                    <option value="">"Any result"</option>
+                    <option value="win">"Wins"</option>
+                    <option value="loss">"Losses"</option>
+                    <option value="draw">"Draws"</option>
+                </select>
+                <select class=INPUT_CLASS on:change=move |e| color.set(event_target_value(&e))>
+                    <option value="">"Any colour"</option>
+                    <option value="white">"White"</option>
+                    <option value="black">"Black"</option>
+                </select>
+                <input
+                    class=INPUT_CLASS
+                    type="text"
+                    placeholder="Opponent"
+                    on:change=move |e| opponent.set(event_target_value(&e))
+                />
+                <a class=BUTTON_CLASS href="/">"Back to the board"</a>
+            </div>
+            {move || error.get().map(|message| view! { <span class="text-red-500">{message}</span> })}
+            <ul class="w-full max-w-3xl">
+                <For
+                    each=move || games.get()
+                    key=|record| record.id.clone()
+                    children=game_view
+                />
+            </ul>
+        </div>
+    }
+}
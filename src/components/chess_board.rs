@@ -1,8 +1,10 @@
+use crate::components::annotations::BoardAnnotations;
 use crate::components::board::BoardBackground;
 use crate::components::coordinates::Coordinates;
 use crate::components::trash::{Trash, TrashType};
 use crate::entities::chess_board::signals::{ChessBoardSignals, StoneSignal};
-use crate::handlers::interaction_start;
+use crate::entities::position::Position;
+use crate::handlers::{drawing_start, interaction_start, press_start, select_square};
 use leptos::*;
 
 #[component]
@@ -25,6 +27,44 @@ pub fn ChessBoard(chess_board_signals: ChessBoardSignals) -> impl IntoView {
             .clone()
     };
 
+    // a piece selected by a click, with the squares it can move to when the
+    // room checks the moves
+    let selected = create_rw_signal::<Option<Position>>(None);
+    create_effect(move |_| {
+        let moved = chess_board_signals.chess_board().with(|cb| {
+            selected
+                .get_untracked()
+                .is_some_and(|s| cb.stone_at(s.x, s.y).is_none())
+        });
+        if moved {
+            selected.set(None);
+        }
+    });
+    let selection_view = move || {
+        let from = selected.get()?;
+        // redrawn with the board, the targets change with every move
+        chess_board_signals.chess_board().with(|_| ());
+        let chess_board = chess_board_signals.rules_board();
+        let mut targets: Vec<String> = if chess_board.validation {
+            chess_board
+                .possible_moves(&from)
+                .into_iter()
+                .map(|p| p.to_string())
+                .collect()
+        } else {
+            vec![]
+        };
+        targets.sort();
+
+        Some(view! {
+            <div class=format!("selected square-{}", from.to_string())></div>
+            {targets
+                .into_iter()
+                .map(|square| view! { <div class=format!("move-target square-{}", square)></div> })
+                .collect_view()}
+        })
+    };
+
     let piece_view = move |(key, stone_signal): (String, RwSignal<StoneSignal>)| {
         let position = move || stone_signal.get().position().map(|p| p.to_string());
         let stone = move || stone_signal.get().stone();
@@ -60,14 +100,25 @@ pub fn ChessBoard(chess_board_signals: ChessBoardSignals) -> impl IntoView {
     };
 
     view! {
-        <chess-board class=css_class id="chessboard">
+        <chess-board
+            class=css_class
+            id="chessboard"
+            on:mousedown=move |e| {
+                press_start(chess_board_signals, e.clone());
+                drawing_start(chess_board_signals, e)
+            }
+            on:click=move |e| select_square(chess_board_signals, selected, e)
+            on:contextmenu=move |e| e.prevent_default()
+        >
             <BoardBackground/>
             <Coordinates white_view=white_view/>
+            {selection_view}
             <For
                 each=stones_signals
                 key=move |(key, _)| key.to_string()
                 children=piece_view
             />
+            <BoardAnnotations chess_board_signals=chess_board_signals/>
             <Trash
                 chess_board_signals=chess_board_signals
                 id=TrashType::Dark
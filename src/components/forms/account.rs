@@ -0,0 +1,80 @@
+use leptos::*;
+
+#[component]
+pub fn Account<F>(submit: F) -> impl IntoView
+where
+    F: Fn(web_sys::SubmitEvent) -> () + 'static,
+{
+    view! {
+        <form
+            class="flex h-fit flex-col justify-center items-center bg-white rounded p-4"
+            on:submit=submit
+        >
+            <label class="w-full flex justify-center text-xl">"Log in or register"</label>
+            <input
+                class="w-full border border-gray-400 rounded p-2 mt-2"
+                type="text"
+                name="username"
+                placeholder="Username"
+                autocomplete="username"
+            />
+            <input
+                class="w-full border border-gray-400 rounded p-2 mt-2"
+                type="password"
+                name="password"
+                placeholder="Password"
+                autocomplete="current-password"
+            />
+            <div class="w-full flex gap-2 mt-4">
+                <button
+                    class="flex-grow border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-2 px-4"
+                    type="submit"
+                    name="action"
+                    value="login"
+                >
+                    "Log in"
+                </button>
+                <button
+                    class="flex-grow border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-2 px-4"
+                    type="submit"
+                    name="action"
+                    value="register"
+                >
+                    "Register"
+                </button>
+            </div>
+        </form>
+    }
+}
+
+#[component]
+pub fn Password<F>(submit: F) -> impl IntoView
+where
+    F: Fn(web_sys::SubmitEvent) -> () + 'static,
+{
+    view! {
+        <form
+            class="flex h-fit flex-col justify-center items-center bg-white rounded p-4"
+            on:submit=submit
+        >
+            <label class="w-full flex justify-center text-xl">"Change your password"</label>
+            <input
+                class="w-full border border-gray-400 rounded p-2 mt-2"
+                type="password"
+                name="current_password"
+                placeholder="Current password"
+                autocomplete="current-password"
+            />
+            <input
+                class="w-full border border-gray-400 rounded p-2 mt-2"
+                type="password"
+                name="new_password"
+                placeholder="New password"
+                autocomplete="new-password"
+            />
+            <button class="w-full border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-2 px-4 mt-4" type="submit">
+                "Change"
+            </button>
+        </form>
+    }
+}
@@ -0,0 +1,221 @@
+use leptos::*;
+
+use super::Form;
+use crate::components::static_board::StaticBoard;
+use crate::entities::{
+    chess_board::{signals::ChessBoardSignals, turns::Turn},
+    position::Position,
+    setup::{Setup, CASTLING},
+    stone::Stone,
+};
+use crate::utils::elements::mouse_position_in_bounding;
+
+const BUTTON_CLASS: &str =
+    "border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-1 px-2";
+const INPUT_CLASS: &str = "border border-gray-400 rounded p-1";
+
+/// Pieces of the palette with their glyphs, white first
+const PALETTE: [(char, &str); 12] = [
+    ('K', "♔"),
+    ('Q', "♕"),
+    ('R', "♖"),
+    ('B', "♗"),
+    ('N', "♘"),
+    ('P', "♙"),
+    ('k', "♚"),
+    ('q', "♛"),
+    ('r', "♜"),
+    ('b', "♝"),
+    ('n', "♞"),
+    ('p', "♟"),
+];
+
+/// Sets up any position with a piece palette, it is loaded into the room as
+/// its new start position
+#[component]
+pub fn Editor(chess_board_signals: ChessBoardSignals, show_form: RwSignal<Form>) -> impl IntoView {
+    let (fen, white_view) = chess_board_signals
+        .chess_board()
+        .with_untracked(|cb| (cb.fen.clone(), cb.white_view()));
+    let setup = create_rw_signal(fen.parse::<Setup>().unwrap_or_else(|_| Setup::start()));
+    // piece put on the squares clicked, `None` erases them
+    let selected = create_rw_signal::<Option<char>>(Some('P'));
+    let invalid_fen = create_rw_signal(false);
+    create_effect(move |_| {
+        setup.track();
+        invalid_fen.set(false);
+    });
+
+    let place = move |e: web_sys::MouseEvent| {
+        let Some(board) = e
+            .current_target()
+            .and_then(crate::utils::js_cast::<web_sys::Element, _>)
+        else {
+            return;
+        };
+        let bounding = board.get_bounding_client_rect();
+        let (x, y) =
+            mouse_position_in_bounding((e.client_x() as f64, e.client_y() as f64), &bounding);
+        let position = Position::from_ui_position(
+            x * 800.0 / bounding.width(),
+            y * 800.0 / bounding.height(),
+            white_view,
+        );
+        let stone = selected
+            .get_untracked()
+            .and_then(|c| Stone::try_from(c).ok());
+
+        setup.update(|setup| {
+            // clicking a square with the same piece takes it away
+            let same =
+                setup.stone_at(&position).map(|s| s.char()) == stone.as_ref().map(|s| s.char());
+            setup.set_stone(&position, if same { None } else { stone });
+        });
+    };
+
+    let palette = PALETTE
+        .into_iter()
+        .map(|(c, glyph)| {
+            let class = move || {
+                if selected.get() == Some(c) {
+                    "text-3xl px-1 rounded bg-blue-200"
+                } else {
+                    "text-3xl px-1 rounded hover:bg-gray-200"
+                }
+            };
+            view! {
+                <button class=class on:click=move |_| selected.set(Some(c))>
+                    {glyph}
+                </button>
+            }
+        })
+        .collect_view();
+    let eraser_class = move || {
+        if selected.get().is_none() {
+            "px-2 rounded bg-blue-200"
+        } else {
+            "px-2 rounded hover:bg-gray-200"
+        }
+    };
+
+    let castling = CASTLING
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| {
+            view! {
+                <label class="flex gap-1 items-center">
+                    <input
+                        type="checkbox"
+                        prop:checked=move || setup.with(|setup| setup.castling[i])
+                        on:change=move |e| {
+                            let checked = event_target_checked(&e);
+                            setup.update(|setup| setup.castling[i] = checked);
+                        }
+                    />
+                    {c.to_string()}
+                </label>
+            }
+        })
+        .collect_view();
+
+    let set_fen = move |e: web_sys::Event| match event_target_value(&e).trim().parse::<Setup>() {
+        Ok(parsed) => {
+            invalid_fen.set(false);
+            setup.set(parsed);
+        }
+        Err(_) => invalid_fen.set(true),
+    };
+    let status = move || {
+        if invalid_fen.get() {
+            return view! { <span class="text-red-500">"Invalid FEN"</span> };
+        }
+        match setup.with(|setup| setup.validate()) {
+            Ok(_) => view! { <span class="text-green-700">"Valid position"</span> },
+            Err(error) => view! { <span class="text-red-500">{error.as_str()}</span> },
+        }
+    };
+    let load = move |_| {
+        let fen = setup.with_untracked(|setup| setup.to_string());
+        chess_board_signals.send_message(&format!("/load_fen {}", fen));
+        show_form.set(Form::None);
+    };
+
+    view! {
+        <div class="flex h-fit flex-col gap-2 items-center bg-white rounded p-4 max-w-lg">
+            <label class="w-full flex justify-center text-xl">"Board editor"</label>
+            <div class="w-72 sm:w-96 aspect-square cursor-pointer" on:click=place>
+                <StaticBoard fen=move || setup.with(|setup| setup.to_string()) flipped=!white_view/>
+            </div>
+            <div class="flex flex-wrap gap-1 items-center justify-center">
+                {palette}
+                <button class=eraser_class on:click=move |_| selected.set(None)>"Erase"</button>
+            </div>
+            <div class="flex flex-wrap gap-2 items-center">
+                <select
+                    class=INPUT_CLASS
+                    on:change=move |e| {
+                        let turn = if event_target_value(&e) == "b" { Turn::Black } else { Turn::White };
+                        setup.update(|setup| setup.turn = turn);
+                    }
+                >
+                    <option value="w" selected=move || setup.with(|s| s.turn == Turn::White)>"White to move"</option>
+                    <option value="b" selected=move || setup.with(|s| s.turn == Turn::Black)>"Black to move"</option>
+                </select>
+                {castling}
+            </div>
+            <div class="flex flex-wrap gap-2 items-center">
+                <label>"En passant"</label>
+                <input
+                    class=format!("{} w-12", INPUT_CLASS)
+                    type="text"
+                    prop:value=move || setup.with(|s| s.passant.as_ref().map(|p| p.to_string()).unwrap_or("-".to_string()))
+                    on:change=move |e| {
+                        let passant = event_target_value(&e).trim().parse::<Position>().ok().filter(|p| p.x < 8 && p.y < 8);
+                        setup.update(|setup| setup.passant = passant);
+                    }
+                />
+                <label>"Half moves"</label>
+                <input
+                    class=format!("{} w-14", INPUT_CLASS)
+                    type="number"
+                    min="0"
+                    prop:value=move || setup.with(|s| s.half_move_clock.to_string())
+                    on:change=move |e| {
+                        let clock = event_target_value(&e).parse().unwrap_or(0);
+                        setup.update(|setup| setup.half_move_clock = clock);
+                    }
+                />
+                <label>"Move"</label>
+                <input
+                    class=format!("{} w-14", INPUT_CLASS)
+                    type="number"
+                    min="1"
+                    prop:value=move || setup.with(|s| s.full_move_number.to_string())
+                    on:change=move |e| {
+                        let number = event_target_value(&e).parse().unwrap_or(1);
+                        setup.update(|setup| setup.full_move_number = number);
+                    }
+                />
+            </div>
+            <input
+                class=format!("{} w-full font-mono text-sm", INPUT_CLASS)
+                type="text"
+                prop:value=move || setup.with(|setup| setup.to_string())
+                on:change=set_fen
+            />
+            {status}
+            <div class="flex gap-2">
+                <button class=BUTTON_CLASS on:click=move |_| setup.set(Setup::empty())>"Clear"</button>
+                <button class=BUTTON_CLASS on:click=move |_| setup.set(Setup::start())>"Start position"</button>
+                <button
+                    class=BUTTON_CLASS
+                    disabled=move || invalid_fen.get() || setup.with(|setup| setup.validate().is_err())
+                    on:click=load
+                >
+                    "Load into room"
+                </button>
+                <button class=BUTTON_CLASS on:click=move |_| show_form.set(Form::None)>"Cancel"</button>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,114 @@
+use leptos::*;
+
+use super::Form;
+use crate::entities::{chess_board::signals::ChessBoardSignals, seek::Seek};
+
+#[component]
+pub fn Lobby<F>(
+    chess_board_signals: ChessBoardSignals,
+    show_form: RwSignal<Form>,
+    submit: F,
+) -> impl IntoView
+where
+    F: Fn(web_sys::SubmitEvent) -> () + 'static,
+{
+    // seeks are only sent while the lobby is shown
+    chess_board_signals.send_message("/lobby");
+    on_cleanup(move || chess_board_signals.send_message("/leave_lobby"));
+
+    // a paired player is moved to the room of its game
+    let room_name = chess_board_signals
+        .room_status()
+        .with_untracked(|rs| rs.as_ref().map(|rs| rs.name()));
+    create_effect(move |_| {
+        let current = chess_board_signals
+            .room_status()
+            .with(|rs| rs.as_ref().map(|rs| rs.name()));
+        if current != room_name {
+            show_form.set(Form::None);
+        }
+    });
+
+    let seek_view = move |seek: Seek| {
+        let is_own = chess_board_signals.user_id().as_ref() == Some(&seek.user_id);
+        let id = seek.id.clone();
+        let button = if is_own {
+            view! {
+                <button
+                    class="border border-gray-400 hover:border-red-500 hover:text-red-500 rounded py-1 px-2"
+                    on:click=move |_| chess_board_signals.send_message("/cancel_seek")
+                >
+                    "Cancel"
+                </button>
+            }
+        } else {
+            view! {
+                <button
+                    class="border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-1 px-2"
+                    on:click=move |_| chess_board_signals.send_message(&format!("/accept {}", id))
+                >
+                    "Accept"
+                </button>
+            }
+        };
+        let rating = seek.rating.map(|rating| format!(" ({})", rating));
+
+        view! {
+            <li class="w-full flex justify-between items-center gap-4 py-1">
+                <span>{seek.name}{rating}</span>
+                <span class="text-gray-500">{seek.request.to_string()}</span>
+                {button}
+            </li>
+        }
+    };
+
+    view! {
+        <div class="flex h-fit flex-col justify-center items-center bg-white rounded p-4">
+            <label class="w-full flex justify-center text-xl mb-2">"Lobby"</label>
+            <ul class="w-full max-h-64 overflow-y-auto mb-4">
+                <For
+                    each=move || chess_board_signals.seeks().get()
+                    key=|seek| seek.id.clone()
+                    children=seek_view
+                />
+            </ul>
+            <form class="w-full flex flex-col items-center" on:submit=submit>
+                <div class="w-full flex gap-2 items-center">
+                    <input
+                        class="border border-gray-400 rounded p-2 w-20"
+                        type="text"
+                        name="time_control"
+                        value="5+3"
+                    />
+                    <select class="border border-gray-400 rounded p-2" name="color">
+                        <option value="random">"Random"</option>
+                        <option value="white">"White"</option>
+                        <option value="black">"Black"</option>
+                    </select>
+                    <label class="switch">
+                        <input type="checkbox" name="rated"/>
+                        <span class="slider round"></span>
+                    </label>
+                    <label>"Rated"</label>
+                </div>
+                <div class="w-full flex gap-2 items-center mt-2">
+                    <input
+                        class="border border-gray-400 rounded p-2 w-24"
+                        type="number"
+                        name="min_rating"
+                        placeholder="Min rating"
+                    />
+                    <input
+                        class="border border-gray-400 rounded p-2 w-24"
+                        type="number"
+                        name="max_rating"
+                        placeholder="Max rating"
+                    />
+                </div>
+                <button class="border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-2 px-4 m-2 mt-4" type="submit">
+                    "Seek"
+                </button>
+            </form>
+        </div>
+    }
+}
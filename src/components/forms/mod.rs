@@ -1,12 +1,25 @@
+pub mod account;
+pub mod editor;
 pub mod join;
+pub mod lobby;
 pub mod options;
+pub mod tournaments;
 pub mod username;
 
-use crate::entities::chess_board::signals::ChessBoardSignals;
-use crate::utils::WindowExt;
+use crate::entities::{chess_board::signals::ChessBoardSignals, notification::NotifyType};
+use crate::utils::{http::post_json, session::load_session};
 use leptos::*;
+use serde::Serialize;
 
-use self::{join::Join, options::Options, username::Username};
+use self::{
+    account::{Account, Password},
+    editor::Editor,
+    join::Join,
+    lobby::Lobby,
+    options::Options,
+    tournaments::Tournaments,
+    username::Username,
+};
 
 #[derive(Clone)]
 #[allow(dead_code)]
@@ -15,11 +28,82 @@ pub enum Form {
     Join,
     Username,
     Options,
+    Account,
+    Password,
+    Lobby,
+    Tournaments,
+    Editor,
 }
 
-fn set_username(username: &str, chess_board_signals: ChessBoardSignals) {
-    let window = web_sys::window().unwrap();
-    window.post_user_name(username, chess_board_signals);
+#[derive(Serialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+fn form_value(data: &web_sys::FormData, name: &str) -> String {
+    data.get(name).as_string().unwrap_or_default()
+}
+
+/// Logs in or registers, the socket reconnects as the account
+fn submit_account(
+    url: &'static str,
+    credentials: Credentials,
+    show_form: RwSignal<Form>,
+    chess_board_signals: ChessBoardSignals,
+) {
+    spawn_local(async move {
+        match post_json(url, &credentials).await {
+            Ok(_) => {
+                show_form.set(Form::None);
+                chess_board_signals.notify(
+                    NotifyType::Success,
+                    &format!("Logged in as {}", credentials.username),
+                );
+                load_session(chess_board_signals).await;
+                chess_board_signals.restart_websocket();
+            }
+            Err(error) => chess_board_signals.notify(NotifyType::Error, &error),
+        }
+    });
+}
+
+/// Goes back to an anonymous session and asks for a name for it
+pub fn logout(show_form: RwSignal<Form>, chess_board_signals: ChessBoardSignals) {
+    spawn_local(async move {
+        match post_json("/accounts/logout", &()).await {
+            Ok(_) => {
+                load_session(chess_board_signals).await;
+                chess_board_signals.restart_websocket();
+                show_form.set(Form::Username);
+            }
+            Err(error) => chess_board_signals.notify(NotifyType::Error, &error),
+        }
+    });
+}
+
+#[derive(Serialize)]
+struct NewSession {
+    username: String,
+}
+
+/// Names the session, a new one is created when there is none yet
+fn set_username(username: String, chess_board_signals: ChessBoardSignals) {
+    spawn_local(async move {
+        match post_json("/sessions", &NewSession { username }).await {
+            Ok(_) => {
+                load_session(chess_board_signals).await;
+                chess_board_signals.start_websocket();
+            }
+            Err(error) => chess_board_signals.notify(NotifyType::Error, &error),
+        }
+    });
 }
 
 #[component]
@@ -51,7 +135,7 @@ pub fn Forms(chess_board_signals: ChessBoardSignals, show_form: RwSignal<Form>)
         if let Some(form) = form {
             let data = web_sys::FormData::new_with_form(&form).unwrap();
             let username = data.get("username").as_string().unwrap();
-            set_username(&username, chess_board_signals);
+            set_username(username, chess_board_signals);
         }
         show_form.set(Form::None);
     };
@@ -73,6 +157,16 @@ pub fn Forms(chess_board_signals: ChessBoardSignals, show_form: RwSignal<Form>)
                 .as_string()
                 .map(|s| s == "on")
                 .unwrap_or(false);
+            let pause_clock = data
+                .get("pause_clock")
+                .as_string()
+                .map(|s| s == "on")
+                .unwrap_or(false);
+            let rated = data
+                .get("rated")
+                .as_string()
+                .map(|s| s == "on")
+                .unwrap_or(false);
             chess_board_signals.room_status().update(|status| {
                 if let Some(status) = status.as_mut() {
                     if validation {
@@ -86,6 +180,9 @@ pub fn Forms(chess_board_signals: ChessBoardSignals, show_form: RwSignal<Form>)
                     } else {
                         status.disable_sync();
                     }
+
+                    status.set_pause_clock(pause_clock);
+                    status.set_rated(rated);
                 };
             });
 
@@ -105,6 +202,89 @@ pub fn Forms(chess_board_signals: ChessBoardSignals, show_form: RwSignal<Form>)
         }
     };
 
+    let seek_submit = move |e: web_sys::SubmitEvent| {
+        e.prevent_default();
+        let target = e.target().unwrap();
+        let form = crate::utils::js_cast::<web_sys::HtmlFormElement, _>(target);
+
+        if let Some(form) = form {
+            let data = web_sys::FormData::new_with_form(&form).unwrap();
+            let rated = if form_value(&data, "rated") == "on" {
+                "rated"
+            } else {
+                "casual"
+            };
+            let mut seek = format!(
+                "/seek {} {} {}",
+                form_value(&data, "time_control").trim(),
+                form_value(&data, "color"),
+                rated
+            );
+
+            let (min, max) = (
+                form_value(&data, "min_rating"),
+                form_value(&data, "max_rating"),
+            );
+            if !min.is_empty() || !max.is_empty() {
+                let min = if min.is_empty() { "0".to_string() } else { min };
+                let max = if max.is_empty() {
+                    "9999".to_string()
+                } else {
+                    max
+                };
+                seek.push_str(&format!(" {}-{}", min, max));
+            }
+
+            chess_board_signals.send_message(&seek);
+        }
+    };
+
+    let account_submit = move |e: web_sys::SubmitEvent| {
+        e.prevent_default();
+        let url = match e
+            .submitter()
+            .and_then(|button| button.get_attribute("value"))
+        {
+            Some(action) if action == "register" => "/accounts",
+            _ => "/accounts/login",
+        };
+        let target = e.target().unwrap();
+        let form = crate::utils::js_cast::<web_sys::HtmlFormElement, _>(target);
+
+        if let Some(form) = form {
+            let data = web_sys::FormData::new_with_form(&form).unwrap();
+            let credentials = Credentials {
+                username: form_value(&data, "username"),
+                password: form_value(&data, "password"),
+            };
+            submit_account(url, credentials, show_form, chess_board_signals);
+        }
+    };
+
+    let password_submit = move |e: web_sys::SubmitEvent| {
+        e.prevent_default();
+        let target = e.target().unwrap();
+        let form = crate::utils::js_cast::<web_sys::HtmlFormElement, _>(target);
+
+        if let Some(form) = form {
+            let data = web_sys::FormData::new_with_form(&form).unwrap();
+            let change = PasswordChange {
+                current_password: form_value(&data, "current_password"),
+                new_password: form_value(&data, "new_password"),
+            };
+
+            spawn_local(async move {
+                match post_json("/accounts/password", &change).await {
+                    Ok(_) => {
+                        show_form.set(Form::None);
+                        chess_board_signals.notify(NotifyType::Success, "Password changed");
+                    }
+                    Err(error) => chess_board_signals.notify(NotifyType::Error, &error),
+                }
+            });
+        }
+    };
+
     let view = move || match show_form.get() {
         Form::Join => {
             view! {
@@ -127,6 +307,41 @@ pub fn Forms(chess_board_signals: ChessBoardSignals, show_form: RwSignal<Form>)
                 </div>
             }
         }
+        Form::Account => {
+            view! {
+                <div class="z-40 flex absolute w-full h-full justify-center items-center bg-neutral-900/30">
+                    <Account submit=account_submit/>
+                </div>
+            }
+        }
+        Form::Password => {
+            view! {
+                <div class="z-40 flex absolute w-full h-full justify-center items-center bg-neutral-900/30">
+                    <Password submit=password_submit/>
+                </div>
+            }
+        }
+        Form::Lobby => {
+            view! {
+                <div class="z-40 flex absolute w-full h-full justify-center items-center bg-neutral-900/30">
+                    <Lobby chess_board_signals=chess_board_signals show_form=show_form submit=seek_submit/>
+                </div>
+            }
+        }
+        Form::Tournaments => {
+            view! {
+                <div class="z-40 flex absolute w-full h-full justify-center items-center bg-neutral-900/30">
+                    <Tournaments chess_board_signals=chess_board_signals show_form=show_form/>
+                </div>
+            }
+        }
+        Form::Editor => {
+            view! {
+                <div class="z-40 flex absolute w-full h-full justify-center items-center bg-neutral-900/30">
+                    <Editor chess_board_signals=chess_board_signals show_form=show_form/>
+                </div>
+            }
+        }
         _ => {
             view! {
                 <div class="hidden"></div>
@@ -41,6 +41,38 @@ where
             }
         }
     };
+    let pause_clock_switch = move || {
+        let pause_clock = chess_board_signals
+            .room_status()
+            .get()
+            .map(|rs| rs.options().pause_clock())
+            .unwrap_or(false);
+        if pause_clock {
+            view! {
+                <input type="checkbox" name="pause_clock" checked/>
+            }
+        } else {
+            view! {
+                <input type="checkbox" name="pause_clock"/>
+            }
+        }
+    };
+    let rated_switch = move || {
+        let rated = chess_board_signals
+            .room_status()
+            .get()
+            .map(|rs| rs.options().rated())
+            .unwrap_or(false);
+        if rated {
+            view! {
+                <input type="checkbox" name="rated" checked/>
+            }
+        } else {
+            view! {
+                <input type="checkbox" name="rated"/>
+            }
+        }
+    };
     view! {
         <form
             class="flex h-fit flex-col justify-center items-center bg-white rounded p-4"
@@ -61,6 +93,20 @@ where
                 </label>
                 <label>"Sync"</label>
             </div>
+            <div class="w-full flex space-between gap-2 items-center mt-2">
+                <label class="switch">
+                    {pause_clock_switch}
+                    <span class="slider round"></span>
+                </label>
+                <label>"Pause clocks when a player is away"</label>
+            </div>
+            <div class="w-full flex space-between gap-2 items-center mt-2">
+                <label class="switch">
+                    {rated_switch}
+                    <span class="slider round"></span>
+                </label>
+                <label>"Rated, players must be logged in"</label>
+            </div>
             <button class="border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-2 px-4 m-2 mt-6" type="submit">
                 "Apply"
             </button>
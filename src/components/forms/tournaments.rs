@@ -0,0 +1,305 @@
+use leptos::*;
+
+use super::{form_value, Form};
+use crate::entities::{
+    chess_board::signals::ChessBoardSignals,
+    notification::NotifyType,
+    tournament::{
+        NewTournament, TournamentFormat, TournamentState, TournamentSummary, TournamentView,
+    },
+};
+use crate::utils::http::{get_json, post_json};
+
+const BUTTON_CLASS: &str =
+    "border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-1 px-2";
+
+fn load_list(list: RwSignal<Vec<TournamentSummary>>, chess_board_signals: ChessBoardSignals) {
+    spawn_local(async move {
+        match get_json::<Vec<TournamentSummary>>("/api/tournaments").await {
+            Ok(tournaments) => list.set(tournaments),
+            Err(error) => chess_board_signals.notify(NotifyType::Error, &error),
+        }
+    });
+}
+
+fn load_tournament(
+    id: String,
+    tournament: RwSignal<Option<TournamentView>>,
+    chess_board_signals: ChessBoardSignals,
+) {
+    spawn_local(async move {
+        match get_json::<TournamentView>(&format!("/api/tournaments/{}", id)).await {
+            Ok(view) => tournament.set(Some(view)),
+            Err(error) => chess_board_signals.notify(NotifyType::Error, &error),
+        }
+    });
+}
+
+/// Joins or starts a tournament and shows it again
+fn post_action(
+    id: String,
+    action: &'static str,
+    tournament: RwSignal<Option<TournamentView>>,
+    chess_board_signals: ChessBoardSignals,
+) {
+    spawn_local(async move {
+        match post_json(&format!("/api/tournaments/{}/{}", id, action), &()).await {
+            Ok(_) => load_tournament(id, tournament, chess_board_signals),
+            Err(error) => chess_board_signals.notify(NotifyType::Error, &error),
+        }
+    });
+}
+
+/// Tournaments with a form to create one, a selected tournament shows its
+/// standings, crosstable and the rooms of its current round
+#[component]
+pub fn Tournaments(
+    chess_board_signals: ChessBoardSignals,
+    show_form: RwSignal<Form>,
+) -> impl IntoView {
+    let list = create_rw_signal::<Vec<TournamentSummary>>(vec![]);
+    let tournament = create_rw_signal::<Option<TournamentView>>(None);
+    load_list(list, chess_board_signals);
+
+    let create_submit = move |e: web_sys::SubmitEvent| {
+        e.prevent_default();
+        let target = e.target().unwrap();
+        let Some(form) = crate::utils::js_cast::<web_sys::HtmlFormElement, _>(target) else {
+            return;
+        };
+
+        let data = web_sys::FormData::new_with_form(&form).unwrap();
+        let format = match form_value(&data, "format").as_str() {
+            "swiss" => TournamentFormat::Swiss,
+            _ => TournamentFormat::RoundRobin,
+        };
+        let new = NewTournament {
+            name: form_value(&data, "name"),
+            format,
+            time_control: form_value(&data, "time_control").trim().to_string(),
+            rounds: form_value(&data, "rounds").parse().unwrap_or(0),
+            rated: form_value(&data, "rated") == "on",
+        };
+
+        spawn_local(async move {
+            match post_json("/api/tournaments", &new).await {
+                Ok(_) => load_list(list, chess_board_signals),
+                Err(error) => chess_board_signals.notify(NotifyType::Error, &error),
+            }
+        });
+    };
+
+    let summary_view = move |summary: TournamentSummary| {
+        let id = summary.id.clone();
+        view! {
+            <li class="w-full flex justify-between items-center gap-4 py-1">
+                <span>{summary.name}</span>
+                <span class="text-gray-500">
+                    {format!(
+                        "{} {} · {} players · {}",
+                        summary.format.label(),
+                        summary.time_control,
+                        summary.players,
+                        summary.state.label()
+                    )}
+                </span>
+                <button
+                    class=BUTTON_CLASS
+                    on:click=move |_| load_tournament(id.clone(), tournament, chess_board_signals)
+                >
+                    "Show"
+                </button>
+            </li>
+        }
+    };
+
+    let list_view = move || {
+        view! {
+            <ul class="w-full max-h-64 overflow-y-auto mb-4">
+                <For
+                    each=move || list.get()
+                    key=|summary| (summary.id.clone(), summary.players, summary.state as u8)
+                    children=summary_view
+                />
+            </ul>
+            <form class="w-full flex flex-col items-center" on:submit=create_submit>
+                <input
+                    class="w-full border border-gray-400 rounded p-2"
+                    type="text"
+                    name="name"
+                    placeholder="Tournament name"
+                />
+                <div class="w-full flex gap-2 items-center mt-2">
+                    <select class="border border-gray-400 rounded p-2" name="format">
+                        <option value="round_robin">"Round robin"</option>
+                        <option value="swiss">"Swiss"</option>
+                    </select>
+                    <input
+                        class="border border-gray-400 rounded p-2 w-20"
+                        type="text"
+                        name="time_control"
+                        value="5+3"
+                    />
+                    <input
+                        class="border border-gray-400 rounded p-2 w-20"
+                        type="number"
+                        name="rounds"
+                        value="5"
+                        min="1"
+                        max="20"
+                    />
+                    <label class="switch">
+                        <input type="checkbox" name="rated"/>
+                        <span class="slider round"></span>
+                    </label>
+                    <label>"Rated"</label>
+                </div>
+                <button class="border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-2 px-4 m-2 mt-4" type="submit">
+                    "Create"
+                </button>
+            </form>
+        }
+        .into_view()
+    };
+
+    let tournament_view = move |details: TournamentView| {
+        let user_id = chess_board_signals.user_id().unwrap_or_default();
+        let registering = details.state == TournamentState::Registering;
+        let id = details.id.clone();
+
+        let join_button = (registering && !details.is_registered(&user_id)).then(|| {
+            let id = id.clone();
+            view! {
+                <button
+                    class=BUTTON_CLASS
+                    on:click=move |_| post_action(id.clone(), "join", tournament, chess_board_signals)
+                >
+                    "Join"
+                </button>
+            }
+        });
+        let start_button = (registering && details.creator_id == user_id).then(|| {
+            let id = id.clone();
+            view! {
+                <button
+                    class=BUTTON_CLASS
+                    on:click=move |_| post_action(id.clone(), "start", tournament, chess_board_signals)
+                >
+                    "Start"
+                </button>
+            }
+        });
+        let refresh_id = id.clone();
+
+        let rounds = details.pairings.len();
+        let crosstable = details
+            .standings
+            .iter()
+            .map(|standing| {
+                let cells = (0..rounds)
+                    .map(|round| {
+                        view! { <td class="px-1">{details.crosstable_cell(&standing.user_id, round)}</td> }
+                    })
+                    .collect_view();
+                view! {
+                    <tr>
+                        <td class="px-1">{standing.rank}</td>
+                        <td class="px-1">{standing.name.clone()}</td>
+                        {cells}
+                        <td class="px-1 font-bold">{standing.points}</td>
+                        <td class="px-1">{standing.buchholz}</td>
+                        <td class="px-1">{standing.sonneborn_berger}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+        let round_headers = (1..=rounds)
+            .map(|round| view! { <th class="px-1">{round}</th> })
+            .collect_view();
+
+        let name_of = |user_id: &str| {
+            details
+                .players
+                .iter()
+                .find(|player| player.user_id == user_id)
+                .map(|player| player.name.clone())
+                .unwrap_or_default()
+        };
+        let games = details
+            .pairings
+            .last()
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|pairing| {
+                let black = name_of(pairing.black.as_deref()?);
+                let room = pairing.room?;
+                let result = pairing.result.unwrap_or_else(|| "*".to_string());
+                Some(view! {
+                    <li class="w-full flex justify-between items-center gap-4 py-1">
+                        <span>{format!("{} - {}", name_of(&pairing.white), black)}</span>
+                        <span class="text-gray-500">{result}</span>
+                        <button
+                            class=BUTTON_CLASS
+                            on:click=move |_| {
+                                chess_board_signals.send_message(&format!("/join {}", room));
+                                show_form.set(Form::None);
+                            }
+                        >
+                            "Go to room"
+                        </button>
+                    </li>
+                })
+            })
+            .collect_view();
+
+        view! {
+            <div class="w-full flex justify-between items-center gap-2 mb-2">
+                <button class=BUTTON_CLASS on:click=move |_| tournament.set(None)>"Back"</button>
+                <span class="text-gray-500">
+                    {format!(
+                        "{} {}{} · {}",
+                        details.format.label(),
+                        details.time_control,
+                        if details.rated { " rated" } else { "" },
+                        details.state.label()
+                    )}
+                </span>
+                {join_button}
+                {start_button}
+                <button
+                    class=BUTTON_CLASS
+                    on:click=move |_| load_tournament(refresh_id.clone(), tournament, chess_board_signals)
+                >
+                    "Refresh"
+                </button>
+            </div>
+            <label class="w-full flex justify-center text-lg">{details.name.clone()}</label>
+            <div class="w-full max-h-64 overflow-auto">
+                <table class="w-full text-sm">
+                    <tr>
+                        <th class="px-1">"#"</th>
+                        <th class="px-1">"Player"</th>
+                        {round_headers}
+                        <th class="px-1">"Pts"</th>
+                        <th class="px-1">"Buch"</th>
+                        <th class="px-1">"SB"</th>
+                    </tr>
+                    {crosstable}
+                </table>
+            </div>
+            <ul class="w-full max-h-48 overflow-y-auto mt-4">{games}</ul>
+        }
+        .into_view()
+    };
+
+    view! {
+        <div class="flex h-fit flex-col justify-center items-center bg-white rounded p-4">
+            <label class="w-full flex justify-center text-xl mb-2">"Tournaments"</label>
+            {move || match tournament.get() {
+                Some(details) => tournament_view(details),
+                None => list_view(),
+            }}
+        </div>
+    }
+}
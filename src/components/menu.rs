@@ -2,7 +2,7 @@ use leptos::*;
 
 use crate::{
     components::{
-        forms::Form,
+        forms::{logout, Form},
         overlay::{clear_timeout, toggle_sub_menu},
     },
     entities::chess_board::signals::ChessBoardSignals,
@@ -73,6 +73,62 @@ pub fn Menu(show_form: RwSignal<Form>, chess_board_signals: ChessBoardSignals) -
         show_form.set(Form::Options);
     };
 
+    let lobby = move |_| {
+        show_form.set(Form::Lobby);
+    };
+
+    let tournaments = move |_| {
+        show_form.set(Form::Tournaments);
+    };
+
+    let editor = move |_| {
+        show_form.set(Form::Editor);
+    };
+
+    let games = move |_| {
+        if let Some(user_id) = chess_board_signals.user_id() {
+            let _ = window()
+                .location()
+                .set_href(&format!("/users/{}/games", user_id));
+        }
+    };
+
+    let account_buttons = move || {
+        let logged_in = chess_board_signals
+            .session()
+            .with(|session| session.as_ref().map(|s| s.logged_in).unwrap_or(false));
+
+        if logged_in {
+            view! {
+                <div class="split-button">
+                    <button
+                        class="split-button-item"
+                        on:click=move |_| show_form.set(Form::Password)
+                    >
+                        "Password"
+                    </button>
+                    <button
+                        class="split-button-item"
+                        on:click=move |_| logout(show_form, chess_board_signals)
+                    >
+                        "Log out"
+                    </button>
+                </div>
+            }
+        } else {
+            view! {
+                <div class="split-button">
+                    <button
+                        class="split-button-item"
+                        on:click=move |_| show_form.set(Form::Account)
+                    >
+                        "Log in"
+                    </button>
+                </div>
+            }
+        }
+    };
+
     view! {
         <div class=menu_css>
             <div class="menu-header">
@@ -106,6 +162,30 @@ pub fn Menu(show_form: RwSignal<Form>, chess_board_signals: ChessBoardSignals) -
                 >
                     "Join"
                 </button>
+                <button
+                    class="sub-menu-item"
+                    on:click=lobby
+                >
+                    "Lobby"
+                </button>
+                <button
+                    class="sub-menu-item"
+                    on:click=tournaments
+                >
+                    "Tournaments"
+                </button>
+                <button
+                    class="sub-menu-item"
+                    on:click=editor
+                >
+                    "Editor"
+                </button>
+                <button
+                    class="sub-menu-item"
+                    on:click=games
+                >
+                    "Games"
+                </button>
                 <button
                     class="sub-menu-item"
                     on:click=options
@@ -127,6 +207,7 @@ pub fn Menu(show_form: RwSignal<Form>, chess_board_signals: ChessBoardSignals) -
                         "Redo"
                     </button>
                 </div>
+                {account_buttons}
             </div>
         </div>
     }
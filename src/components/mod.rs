@@ -1,10 +1,15 @@
+pub mod annotations;
+pub mod archive;
 pub mod board;
 pub mod check_mate;
 pub mod chess_board;
 pub mod coordinates;
 pub mod forms;
 pub mod menu;
+pub mod move_list;
 pub mod notifications;
 pub mod overlay;
+pub mod seats;
+pub mod static_board;
 pub mod status_menu;
 pub mod trash;
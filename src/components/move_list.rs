@@ -0,0 +1,228 @@
+use leptos::*;
+
+use crate::entities::{
+    annotation::{nag_glyph, NAGS},
+    chess_board::{notation::parse_move, signals::ChessBoardSignals},
+    history::{node_to_string, HistoryToken},
+};
+
+const BUTTON_CLASS: &str =
+    "text-sm border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded px-2";
+
+/// Moves of the room with their variations, a click on a move shows its
+/// position to everyone in the room
+#[component]
+pub fn MoveList(chess_board_signals: ChessBoardSignals) -> impl IntoView {
+    let show_moves = create_rw_signal(true);
+    let history = move || {
+        chess_board_signals.room_status().with(|rs| {
+            rs.as_ref()
+                .map(|rs| rs.history().clone())
+                .unwrap_or_default()
+        })
+    };
+    let pgn = move || {
+        chess_board_signals
+            .room_status()
+            .with(|rs| rs.as_ref().and_then(|rs| rs.pgn().cloned()))
+    };
+    let send = move |msg: String| chess_board_signals.send_message(&msg);
+    let fork_name = create_rw_signal(String::new());
+    let fork_history = create_rw_signal(false);
+
+    let typed_move = create_rw_signal(String::new());
+    let typed_error = create_rw_signal::<Option<String>>(None);
+
+    // moves typed in SAN or UCI are read against the board shown
+    let play_typed = move |e: web_sys::SubmitEvent| {
+        e.prevent_default();
+        let text = typed_move.get_untracked();
+        match parse_move(&chess_board_signals.rules_board(), &text) {
+            Ok(typed) => {
+                chess_board_signals.play(&typed);
+                typed_move.set(String::new());
+                typed_error.set(None);
+            }
+            Err(error) => typed_error.set(Some(error.to_string())),
+        }
+    };
+
+    let moves_view = move || {
+        let history = history();
+        let current = history.current;
+
+        history
+            .tokens()
+            .into_iter()
+            .map(|token| match token {
+                HistoryToken::Move { id, text } => {
+                    let class = if current == Some(id) {
+                        "px-1 rounded bg-blue-200"
+                    } else if history.is_variation(id) {
+                        "px-1 rounded text-gray-500 hover:bg-gray-300"
+                    } else {
+                        "px-1 rounded hover:bg-gray-300"
+                    };
+                    view! {
+                        <button class=class on:click=move |_| send(format!("/goto {}", id))>
+                            {text}
+                        </button>
+                    }
+                    .into_view()
+                }
+                HistoryToken::Annotation(annotation) => {
+                    let glyphs: String = annotation
+                        .nags
+                        .iter()
+                        .filter_map(|nag| nag_glyph(*nag))
+                        .collect();
+                    view! {
+                        <span class="font-bold">{glyphs}</span>
+                        <span class="text-green-800">{annotation.comment}</span>
+                    }
+                    .into_view()
+                }
+                HistoryToken::StartVariation => view! { <span>"("</span> }.into_view(),
+                HistoryToken::EndVariation => view! { <span>")"</span> }.into_view(),
+            })
+            .collect_view()
+    };
+
+    // promoting and deleting act on the move shown on the board
+    let current_actions = move || {
+        let history = history();
+        let current = history.current?;
+
+        Some(view! {
+            {history.is_variation(current).then(|| view! {
+                <button class=BUTTON_CLASS on:click=move |_| send(format!("/promote {}", current))>
+                    "Promote"
+                </button>
+            })}
+            <button class=BUTTON_CLASS on:click=move |_| send(format!("/delete_move {}", current))>
+                "Delete"
+            </button>
+        })
+    };
+
+    // comments, NAGs and shapes go on the position shown on the board
+    let annotation_editor = move || {
+        let history = history();
+        let node = node_to_string(history.current);
+        let annotation = history.current_annotation().cloned().unwrap_or_default();
+
+        let nag_buttons = NAGS
+            .into_iter()
+            .map(|(nag, glyph)| {
+                let class = if annotation.nags.contains(&nag) {
+                    "px-1 rounded bg-blue-200"
+                } else {
+                    "px-1 rounded hover:bg-gray-300"
+                };
+                let node = node.clone();
+                view! {
+                    <button class=class on:click=move |_| send(format!("/nag {} {}", node, nag))>
+                        {glyph}
+                    </button>
+                }
+            })
+            .collect_view();
+        let comment_node = node.clone();
+        let clear_button = (!annotation.shapes.is_empty()).then(|| {
+            view! {
+                <button class=BUTTON_CLASS on:click=move |_| send(format!("/clear_shapes {}", node))>
+                    "Clear arrows"
+                </button>
+            }
+        });
+
+        view! {
+            <div class="flex flex-wrap gap-x-1 text-sm">{nag_buttons}</div>
+            <input
+                class="w-full border border-gray-400 rounded px-1 text-sm"
+                type="text"
+                placeholder="Comment"
+                prop:value=annotation.comment
+                on:change=move |e| send(format!("/comment {} {}", comment_node, event_target_value(&e)))
+            />
+            {clear_button}
+        }
+    };
+
+    // the new room starts from the move shown, or replays the moves to it
+    let fork = move |_| {
+        let name = fork_name.get_untracked().trim().replace([' ', '|'], "-");
+        if name.is_empty() {
+            return;
+        }
+        let node = node_to_string(history().current);
+        let history = if fork_history.get_untracked() {
+            " history"
+        } else {
+            ""
+        };
+        send(format!("/fork {} {}{}", name, node, history));
+        fork_name.set(String::new());
+    };
+
+    view! {
+        <div class="fixed bottom-0 left-0 z-30 flex flex-col gap-1 bg-neutral-200 rounded-tr-lg drop-shadow p-2 w-64">
+            <div class="flex gap-2 items-center justify-between">
+                <button class="text-sm font-medium" on:click=move |_| show_moves.update(|show| *show = !*show)>
+                    "Moves"
+                </button>
+                <div class="flex gap-1">
+                    <button class=BUTTON_CLASS on:click=move |_| send("/goto start".to_string())>
+                        "Start"
+                    </button>
+                    <button class=BUTTON_CLASS on:click=move |_| send("/pgn".to_string())>
+                        "PGN"
+                    </button>
+                </div>
+            </div>
+            <Show when=move || show_moves.get() fallback=|| ()>
+                <div class="flex flex-wrap gap-x-1 text-sm max-h-48 overflow-y-auto">{moves_view}</div>
+                <form class="flex gap-1 items-center text-sm" on:submit=play_typed>
+                    <input
+                        class="w-full border border-gray-400 rounded px-1 font-mono"
+                        type="text"
+                        placeholder="Move, like Nf3 or g1f3"
+                        aria-label="Move"
+                        autocomplete="off"
+                        prop:value=move || typed_move.get()
+                        on:input=move |e| typed_move.set(event_target_value(&e))
+                    />
+                    <button class=BUTTON_CLASS type="submit">"Play"</button>
+                </form>
+                {move || typed_error.get().map(|error| view! {
+                    <p class="text-xs text-red-700" role="alert">{error}</p>
+                })}
+                <div class="flex gap-1 justify-end">{current_actions}</div>
+                {annotation_editor}
+                <div class="flex gap-1 items-center text-sm">
+                    <input
+                        class="w-24 border border-gray-400 rounded px-1"
+                        type="text"
+                        placeholder="New room"
+                        prop:value=move || fork_name.get()
+                        on:input=move |e| fork_name.set(event_target_value(&e))
+                    />
+                    <label class="flex gap-1 items-center">
+                        <input
+                            type="checkbox"
+                            prop:checked=move || fork_history.get()
+                            on:change=move |e| fork_history.set(event_target_checked(&e))
+                        />
+                        "moves"
+                    </label>
+                    <button class=BUTTON_CLASS on:click=fork>"Fork"</button>
+                </div>
+                {move || pgn().map(|pgn| view! {
+                    <textarea class="w-full h-24 border border-gray-400 rounded p-1 font-mono text-xs" readonly=true>
+                        {pgn}
+                    </textarea>
+                })}
+            </Show>
+        </div>
+    }
+}
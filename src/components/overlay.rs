@@ -5,11 +5,13 @@ use crate::{
         check_mate::CheckMate,
         forms::{Form, Forms},
         menu::Menu,
+        move_list::MoveList,
         notifications::Notifications,
+        seats::Seats,
         status_menu::StatusMenu,
     },
     entities::chess_board::signals::ChessBoardSignals,
-    utils::{get_cookie_value, jwt::decode, SessionPayload, WindowExt},
+    utils::{session::load_session, WindowExt},
 };
 
 pub fn toggle_sub_menu(
@@ -47,31 +49,18 @@ pub fn clear_timeout(id: Option<i32>) {
     }
 }
 
-pub fn get_user_payload() -> Option<SessionPayload> {
-    if let Some(session_token) = get_cookie_value("session_token") {
-        let Ok(token) = decode::<SessionPayload>(&session_token) else {
-            log::error!("Session token is not a valid JWT: {}", session_token);
-            return None;
-        };
-        Some(token.claims().clone())
-    } else {
-        None
-    }
-}
-
 #[component]
 pub fn Overlay(chess_board_signals: ChessBoardSignals) -> impl IntoView {
     let show_form = create_rw_signal(Form::None);
 
     create_effect(move |_| {
-        use crate::utils::WindowExt;
-
-        if let Some(_) = get_user_payload().map(|payload| payload.name) {
-            chess_board_signals.start_websocket();
-        } else {
-            let window = web_sys::window().unwrap();
-            window.set_timeout_callback(move || show_form.set(Form::Username), 0);
-        };
+        spawn_local(async move {
+            if load_session(chess_board_signals).await.is_some() {
+                chess_board_signals.start_websocket();
+            } else {
+                show_form.set(Form::Username);
+            }
+        });
     });
 
     view! {
@@ -80,6 +69,8 @@ pub fn Overlay(chess_board_signals: ChessBoardSignals) -> impl IntoView {
             <Menu show_form=show_form chess_board_signals=chess_board_signals />
             <StatusMenu show_form=show_form chess_board_signals=chess_board_signals />
             <Forms show_form=show_form chess_board_signals=chess_board_signals />
+            <Seats chess_board_signals=chess_board_signals />
+            <MoveList chess_board_signals=chess_board_signals />
             <CheckMate chess_board_signals=chess_board_signals />
         </>
     }
@@ -0,0 +1,198 @@
+use leptos::*;
+
+use crate::{
+    entities::{
+        chess_board::{signals::ChessBoardSignals, turns::Turn},
+        game::{GameStatus, Outcome},
+    },
+    utils::{now_ms, WindowExt},
+};
+
+const TIME_CONTROLS: [&str; 6] = ["off", "1+0", "3+2", "5+3", "10+0", "15+10"];
+
+fn format_ms(ms: f64) -> String {
+    let seconds = (ms.max(0.0) / 1000.0).ceil() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn side_name(side: Turn) -> &'static str {
+    match side {
+        Turn::White => "white",
+        Turn::Black => "black",
+    }
+}
+
+#[component]
+pub fn Seats(chess_board_signals: ChessBoardSignals) -> impl IntoView {
+    // ticks the clocks and countdowns shown from the last game status
+    let now = create_rw_signal(now_ms());
+    create_effect(move |_| {
+        let window = web_sys::window().unwrap();
+        if let Some(id) = window.set_interval_callback(move || now.set(now_ms()), 250) {
+            on_cleanup(move || {
+                if let Some(window) = web_sys::window() {
+                    window.clear_interval_with_handle(id);
+                }
+            });
+        }
+    });
+
+    let game = move || {
+        chess_board_signals.room_status().with(|rs| {
+            rs.as_ref()
+                .map(|rs| (rs.game().clone(), rs.game_received_at()))
+        })
+    };
+    let user_id = move || chess_board_signals.user_id().unwrap_or_default();
+    let elapsed = move |received_at: f64| now.get() - received_at;
+
+    let send = move |msg: String| chess_board_signals.send_message(&msg);
+
+    let clock = move |game: &GameStatus, received_at: f64, side: Turn| {
+        game.clock.as_ref().map(|clock| {
+            let mut left = clock.time_left(side) as f64;
+            if clock.running == Some(side) {
+                left -= elapsed(received_at);
+            }
+            format_ms(left)
+        })
+    };
+
+    let seat_view = move |side: Turn| {
+        move || {
+            let (game, received_at) = game()?;
+            let time = clock(&game, received_at, side);
+            let in_game = game.result.is_none() && game.seat(!side).is_some();
+
+            let player = match game.seat(side) {
+                Some(seat) => {
+                    let countdown = seat.grace_ms.map(|grace_ms| {
+                        let left = grace_ms as f64 - elapsed(received_at);
+                        if left > 0.0 {
+                            format!("away, {} to reconnect", format_ms(left))
+                        } else {
+                            "left the game".to_string()
+                        }
+                    });
+                    let is_me = seat.user_id == user_id();
+
+                    view! {
+                        <span class="font-medium">{seat.name.clone()}</span>
+                        {countdown.map(|c| view! { <span class="text-sm text-red-600">{c}</span> })}
+                        {is_me.then(|| view! {
+                            <button
+                                class="text-sm border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded px-2"
+                                on:click=move |_| send("/stand".to_string())
+                            >
+                                "Stand"
+                            </button>
+                        })}
+                    }
+                    .into_view()
+                }
+                None => view! {
+                    <button
+                        class="text-sm border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded px-2"
+                        on:click=move |_| send(format!("/sit {}", side_name(side)))
+                    >
+                        {format!("Play {}", side_name(side))}
+                    </button>
+                }
+                .into_view(),
+            };
+
+            Some(view! {
+                <div class="flex gap-2 items-center justify-between">
+                    <div class="flex gap-2 items-center">
+                        <span class={format!("w-3 h-3 rounded-full border border-gray-500 {}", if side == Turn::White { "bg-white" } else { "bg-neutral-800" })}></span>
+                        {player}
+                    </div>
+                    {time.map(|t| view! {
+                        <span class={if in_game { "font-mono" } else { "font-mono text-gray-500" }}>{t}</span>
+                    })}
+                </div>
+            })
+        }
+    };
+
+    // the opponent of a player gone for longer than the grace period ends
+    // the game
+    let claim_view = move || {
+        let (game, received_at) = game()?;
+        if game.result.is_some() {
+            return None;
+        }
+        let side = game.side_of(&user_id())?;
+        let grace_ms = game.seat(!side)?.grace_ms?;
+        if (grace_ms as f64) > elapsed(received_at) {
+            return None;
+        }
+
+        Some(view! {
+            <div class="flex gap-2">
+                <button
+                    class="flex-grow border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-1 px-2"
+                    on:click=move |_| send("/claim win".to_string())
+                >
+                    "Claim win"
+                </button>
+                <button
+                    class="flex-grow border border-gray-400 hover:border-blue-500 hover:text-blue-500 rounded py-1 px-2"
+                    on:click=move |_| send("/claim draw".to_string())
+                >
+                    "Claim draw"
+                </button>
+            </div>
+        })
+    };
+
+    let result_view = move || {
+        let (game, _) = game()?;
+        let result = game.result?;
+        let winner = match result.outcome {
+            Outcome::Win(Turn::White) => "White wins",
+            Outcome::Win(Turn::Black) => "Black wins",
+            Outcome::Draw => "Draw",
+        };
+
+        Some(view! {
+            <span class="text-sm text-center">
+                {format!("{} by {} ({})", winner, result.termination.as_str(), result.score())}
+            </span>
+        })
+    };
+
+    let time_control_view = move || {
+        let (game, _) = game()?;
+        let current = game
+            .clock
+            .as_ref()
+            .map(|c| c.time_control.to_string())
+            .unwrap_or("off".to_string());
+
+        Some(view! {
+            <select
+                class="text-sm bg-transparent border border-gray-400 rounded px-1"
+                on:change=move |e| send(format!("/clock {}", event_target_value(&e)))
+            >
+                {TIME_CONTROLS
+                    .into_iter()
+                    .map(|tc| view! { <option value=tc selected={tc == current}>{tc}</option> })
+                    .collect_view()}
+            </select>
+        })
+    };
+
+    view! {
+        <div class="fixed bottom-0 right-0 z-30 flex flex-col gap-1 bg-neutral-200 rounded-tl-lg drop-shadow p-2 w-64">
+            {seat_view(Turn::Black)}
+            {seat_view(Turn::White)}
+            {claim_view}
+            {result_view}
+            <div class="flex gap-2 items-center justify-end">
+                <span class="text-sm">"Clock"</span>
+                {time_control_view}
+            </div>
+        </div>
+    }
+}
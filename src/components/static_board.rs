@@ -0,0 +1,44 @@
+use leptos::*;
+
+use crate::components::board::BoardBackground;
+use crate::components::coordinates::Coordinates;
+use crate::entities::chess_board::ChessBoard as ChessBoardEntity;
+
+/// Board showing a position without moving its pieces
+#[component]
+pub fn StaticBoard<F>(fen: F, #[prop(optional)] flipped: bool) -> impl IntoView
+where
+    F: Fn() -> String + 'static,
+{
+    let pieces = move || {
+        let Ok(chess_board) = ChessBoardEntity::new(&fen()) else {
+            return vec![];
+        };
+
+        chess_board
+            .stones_and_positions()
+            .into_iter()
+            .map(|(position, stone)| {
+                let class = format!(
+                    "piece {} square-{}",
+                    stone.image_class(),
+                    position.to_string()
+                );
+                view! { <div class=class></div> }
+            })
+            .collect::<Vec<_>>()
+    };
+    let css_class = if flipped {
+        "chessboard flipped"
+    } else {
+        "chessboard"
+    };
+
+    view! {
+        <chess-board class=css_class>
+            <BoardBackground/>
+            <Coordinates white_view=move || !flipped/>
+            {pieces}
+        </chess-board>
+    }
+}
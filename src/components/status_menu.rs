@@ -3,7 +3,7 @@ use leptos::*;
 use crate::{
     components::{
         forms::Form,
-        overlay::{clear_timeout, get_user_payload, toggle_sub_menu},
+        overlay::{clear_timeout, toggle_sub_menu},
     },
     entities::{chess_board::signals::ChessBoardSignals, room::User},
 };
@@ -50,13 +50,18 @@ pub fn StatusMenu(
 
     let status_menu_btn_css = move || {
         let is_active = show_status_menu.get();
-        let is_online = chess_board_signals.socket().with(|s| s.is_some());
+        let is_online = chess_board_signals.connection().with(|c| c.is_connected());
+        let is_reconnecting = chess_board_signals
+            .connection()
+            .with(|c| c.is_reconnecting());
         let mut class = "status-menu-btn".to_string();
 
         if is_online && is_active {
             class.push_str(" status-menu-btn--is-online--is-active");
         } else if is_online {
             class.push_str(" status-menu-btn--is-online");
+        } else if is_reconnecting {
+            class.push_str(" status-menu-btn--is-reconnecting");
         } else if is_active {
             class.push_str(" status-menu-btn--is-active");
         }
@@ -65,7 +70,10 @@ pub fn StatusMenu(
     };
 
     let status_refresh_btn_css = move || {
-        let needs_refresh = chess_board_signals.socket().get().is_none();
+        let needs_refresh = chess_board_signals.socket().get().is_none()
+            && !chess_board_signals
+                .connection()
+                .with(|c| c.is_reconnecting());
         let mut class = "status-refresh-btn".to_string();
 
         if needs_refresh {
@@ -104,11 +112,25 @@ pub fn StatusMenu(
 
     let user_view = move |user: RwSignal<User>| {
         let status_class = move || format!("status status--{}", user.with(|u| u.status_str()));
-        if user.with(|u| u.id()) == get_user_payload().map(|p| p.sub).unwrap_or_default() {
+        let rating = move || {
+            let user_id = user.with(|u| u.id());
+            chess_board_signals.room_status().with(|status| {
+                status
+                    .as_ref()
+                    .and_then(|s| s.rating_of(&user_id))
+                    .map(|rating| {
+                        view! {
+                            <span class="text-xs text-gray-500 ml-1">{rating}</span>
+                        }
+                    })
+            })
+        };
+        if Some(user.with(|u| u.id())) == chess_board_signals.user_id() {
             view! {
                 <li class="current-user">
                     <span>
                         {move || user.with(|u| u.username())}
+                        {rating}
                     </span>
                     <button on:click=username>
                         <svg xmlns="http://www.w3.org/2000/svg" id="Layer_1" data-name="Layer 1" viewBox="0 0 24 24" width="512" height="512">
@@ -123,6 +145,7 @@ pub fn StatusMenu(
                 <li>
                     <span>
                         {move || user.with(|u| u.username())}
+                        {rating}
                     </span>
                     <span class=status_class>
                     </span>
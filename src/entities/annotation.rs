@@ -0,0 +1,275 @@
+use std::str::FromStr;
+
+use super::position::Position;
+
+/// NAG codes that can be set on a move with their glyphs, the first six
+/// judge the move and the others the position
+pub const NAGS: [(u8, &str); 14] = [
+    (1, "!"),
+    (2, "?"),
+    (3, "!!"),
+    (4, "??"),
+    (5, "!?"),
+    (6, "?!"),
+    (10, "="),
+    (13, "∞"),
+    (14, "⩲"),
+    (15, "⩱"),
+    (16, "±"),
+    (17, "∓"),
+    (18, "+-"),
+    (19, "-+"),
+];
+
+pub fn nag_glyph(nag: u8) -> Option<&'static str> {
+    NAGS.iter()
+        .find(|(code, _)| *code == nag)
+        .map(|(_, glyph)| *glyph)
+}
+
+/// A move judgement replaces the previous one, and so does a position one
+pub fn is_move_nag(nag: u8) -> bool {
+    (1..=6).contains(&nag)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShapeColor {
+    Green,
+    Red,
+    Yellow,
+    Blue,
+}
+
+impl ShapeColor {
+    pub fn letter(&self) -> char {
+        match self {
+            ShapeColor::Green => 'G',
+            ShapeColor::Red => 'R',
+            ShapeColor::Yellow => 'Y',
+            ShapeColor::Blue => 'B',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Result<Self, ()> {
+        match letter {
+            'G' => Ok(ShapeColor::Green),
+            'R' => Ok(ShapeColor::Red),
+            'Y' => Ok(ShapeColor::Yellow),
+            'B' => Ok(ShapeColor::Blue),
+            _ => Err(()),
+        }
+    }
+
+    pub fn css_color(&self) -> &'static str {
+        match self {
+            ShapeColor::Green => "#15781b",
+            ShapeColor::Red => "#882020",
+            ShapeColor::Yellow => "#e68f00",
+            ShapeColor::Blue => "#003088",
+        }
+    }
+}
+
+/// Arrow drawn on the board, a circle when it starts and ends on the same
+/// square
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Shape {
+    pub color: ShapeColor,
+    pub from: Position,
+    pub to: Position,
+}
+
+impl Shape {
+    pub fn is_circle(&self) -> bool {
+        self.from == self.to
+    }
+
+    /// Like `Ge2e4` for an arrow and `Rd4` for a circle, as in the `%cal`
+    /// and `%csl` commands of PGN comments
+    pub fn to_string(&self) -> String {
+        if self.is_circle() {
+            format!("{}{}", self.color.letter(), self.from.to_string())
+        } else {
+            format!(
+                "{}{}{}",
+                self.color.letter(),
+                self.from.to_string(),
+                self.to.to_string()
+            )
+        }
+    }
+}
+
+impl FromStr for Shape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let square = |square: Option<&str>| {
+            let position = square.ok_or(())?.parse::<Position>()?;
+            if position.x < 8 && position.y < 8 {
+                Ok(position)
+            } else {
+                Err(())
+            }
+        };
+
+        let color = ShapeColor::from_letter(s.chars().next().ok_or(())?)?;
+        let from = square(s.get(1..3))?;
+        let to = match s.len() {
+            3 => from.clone(),
+            5 => square(s.get(3..5))?,
+            _ => return Err(()),
+        };
+
+        Ok(Self { color, from, to })
+    }
+}
+
+/// Notes on a move of the history, or on the start position
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Annotation {
+    pub comment: String,
+    pub nags: Vec<u8>,
+    pub shapes: Vec<Shape>,
+}
+
+/// Comments are the last field of the synced annotations, the separators of
+/// the history still have to be escaped
+fn escape(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace(',', "%2C")
+        .replace('|', "%7C")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("%2C", ",")
+        .replace("%7C", "|")
+        .replace("%25", "%")
+}
+
+impl Annotation {
+    pub fn is_empty(&self) -> bool {
+        self.comment.is_empty() && self.nags.is_empty() && self.shapes.is_empty()
+    }
+
+    /// Drawing the same shape again removes it
+    pub fn toggle_shape(&mut self, shape: Shape) {
+        match self.shapes.iter().position(|other| *other == shape) {
+            Some(i) => {
+                self.shapes.remove(i);
+            }
+            None => self.shapes.push(shape),
+        }
+    }
+
+    /// Sets a NAG or clears it when already set, there is one move and one
+    /// position judgement at most
+    pub fn toggle_nag(&mut self, nag: u8) {
+        if self.nags.contains(&nag) {
+            self.nags.retain(|other| *other != nag);
+            return;
+        }
+
+        self.nags
+            .retain(|other| is_move_nag(*other) != is_move_nag(nag));
+        self.nags.push(nag);
+        self.nags.sort();
+    }
+
+    /// Comment of the PGN with the shapes as `[%csl]` and `[%cal]` commands
+    pub fn pgn_comment(&self) -> Option<String> {
+        let shapes = |circles: bool| {
+            self.shapes
+                .iter()
+                .filter(|shape| shape.is_circle() == circles)
+                .map(Shape::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        let mut parts = vec![];
+        for (command, shapes) in [("%csl", shapes(true)), ("%cal", shapes(false))] {
+            if !shapes.is_empty() {
+                parts.push(format!("[{} {}]", command, shapes));
+            }
+        }
+        if !self.comment.is_empty() {
+            parts.push(self.comment.replace('}', ")"));
+        }
+
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+
+    /// Format: `{nag}.{nag}:{shape}.{shape}:{comment}` with the comment
+    /// escaped
+    pub fn to_string(&self) -> String {
+        let nags: Vec<String> = self.nags.iter().map(|nag| nag.to_string()).collect();
+        let shapes: Vec<String> = self.shapes.iter().map(Shape::to_string).collect();
+
+        format!(
+            "{}:{}:{}",
+            nags.join("."),
+            shapes.join("."),
+            escape(&self.comment)
+        )
+    }
+}
+
+impl FromStr for Annotation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.splitn(3, ':');
+        let nags = fields
+            .next()
+            .ok_or(())?
+            .split('.')
+            .filter(|nag| !nag.is_empty())
+            .map(|nag| nag.parse::<u8>().map_err(|_| ()))
+            .collect::<Result<Vec<_>, ()>>()?;
+        let shapes = fields
+            .next()
+            .ok_or(())?
+            .split('.')
+            .filter(|shape| !shape.is_empty())
+            .map(|shape| shape.parse::<Shape>())
+            .collect::<Result<Vec<_>, ()>>()?;
+        let comment = unescape(fields.next().ok_or(())?);
+
+        Ok(Self {
+            comment,
+            nags,
+            shapes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotation() {
+        let mut annotation = Annotation::default();
+        annotation.toggle_nag(1);
+        annotation.toggle_nag(14);
+        annotation.toggle_nag(5);
+        annotation.toggle_shape("Ge2e4".parse().unwrap());
+        annotation.toggle_shape("Rd4".parse().unwrap());
+        annotation.comment = "Good, 100% | sure: {no}".to_string();
+
+        assert_eq!(annotation.nags, vec![5, 14]);
+        assert_eq!(
+            annotation.pgn_comment().unwrap(),
+            "[%csl Rd4] [%cal Ge2e4] Good, 100% | sure: {no)"
+        );
+        assert_eq!(
+            annotation.to_string().parse::<Annotation>().unwrap(),
+            annotation
+        );
+
+        annotation.toggle_shape("Rd4".parse().unwrap());
+        assert_eq!(annotation.shapes.len(), 1);
+        assert!("Gz9".parse::<Shape>().is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    chess_board::turns::Turn,
+    game::{GameResult, Outcome},
+};
+
+/// `YYYY.MM.DD` date of a timestamp, in UTC
+pub fn utc_date(secs: u64) -> String {
+    // days to civil date, from Howard Hinnant's date algorithms
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// Answer to a rematch offer, the room is set once both players asked
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Rematch {
+    pub room: Option<String>,
+}
+
+/// Move of an archived game with the position it led to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordedMove {
+    pub san: String,
+    pub fen: String,
+}
+
+/// Finished game kept in the archive
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GameRecord {
+    pub id: String,
+    pub room: String,
+    pub white_id: String,
+    pub white_name: String,
+    pub black_id: String,
+    pub black_name: String,
+    /// Like `1-0 checkmate`
+    pub result: String,
+    pub rated: bool,
+    /// Like `5+3`, unset for untimed games
+    pub time_control: Option<String>,
+    pub start_fen: String,
+    pub moves: Vec<RecordedMove>,
+    pub pgn: String,
+    pub final_fen: String,
+    /// Seconds since the epoch
+    pub started_at: u64,
+    pub finished_at: u64,
+}
+
+impl GameRecord {
+    pub fn game_result(&self) -> Option<GameResult> {
+        self.result.parse().ok()
+    }
+
+    pub fn side_of(&self, user_id: &str) -> Option<Turn> {
+        if self.white_id == user_id {
+            Some(Turn::White)
+        } else if self.black_id == user_id {
+            Some(Turn::Black)
+        } else {
+            None
+        }
+    }
+
+    /// `win`, `loss` or `draw` for a player of the game
+    pub fn outcome_for(&self, user_id: &str) -> Option<&'static str> {
+        let side = self.side_of(user_id)?;
+
+        Some(match self.game_result()?.outcome {
+            Outcome::Win(winner) if winner == side => "win",
+            Outcome::Win(_) => "loss",
+            Outcome::Draw => "draw",
+        })
+    }
+
+    /// Id and name of the opponent of a player of the game
+    pub fn opponent_of(&self, user_id: &str) -> Option<(&str, &str)> {
+        match self.side_of(user_id)? {
+            Turn::White => Some((&self.black_id, &self.black_name)),
+            Turn::Black => Some((&self.white_id, &self.white_name)),
+        }
+    }
+
+    /// Position after `ply` half moves, the start position for 0 and the
+    /// final one past the end
+    pub fn fen_at(&self, ply: usize) -> &str {
+        match ply.min(self.moves.len()).checked_sub(1) {
+            Some(i) => &self.moves[i].fen,
+            None => &self.start_fen,
+        }
+    }
+}
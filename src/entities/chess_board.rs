@@ -4,6 +4,7 @@ use crate::entities::chess_board::enums::{CastlePosition, PromotionKind};
 
 use self::castle_rules::{fen_to_castle_rules, CastleOptions, CastleRules};
 use self::enums::{ChessBoardError, FenError, Move, MoveError};
+use self::move_event::{MoveEvent, SquareChange};
 use self::passants::fen_to_passant;
 use self::stones::fen_to_stones;
 use self::turns::{fen_to_turn, Turn};
@@ -13,6 +14,8 @@ use super::stone::{Color, Kind, Stone};
 
 pub mod castle_rules;
 pub mod enums;
+pub mod move_event;
+pub mod notation;
 pub mod passants;
 pub mod signals;
 pub mod stones;
@@ -414,6 +417,18 @@ impl ChessBoard {
         piece: &str,
         from: Option<Position>,
         to: Option<Position>,
+    ) -> Result<Move, ChessBoardError> {
+        self.move_piece_promoting(piece, from, to, PromotionKind::Queen)
+    }
+
+    /// Moves a piece like `move_piece`, a pawn reaching the last rank becomes
+    /// a `promotion`
+    pub fn move_piece_promoting(
+        &mut self,
+        piece: &str,
+        from: Option<Position>,
+        to: Option<Position>,
+        promotion: PromotionKind,
     ) -> Result<Move, ChessBoardError> {
         if from == to {
             return Ok(Move::Normal);
@@ -482,7 +497,7 @@ impl ChessBoard {
             }
 
             result = if self.validation {
-                self.apply_move_validation_and_effects((&stone, &from, &to))
+                self.apply_move_validation_and_effects((&stone, &from, &to), promotion)
             } else {
                 Move::Normal
             }
@@ -497,9 +512,99 @@ impl ChessBoard {
         Ok(result)
     }
 
+    /// Moves a piece like `move_piece_promoting` and describes every square
+    /// it changed, side effects included
+    pub fn play_move(
+        &mut self,
+        piece: &str,
+        from: Option<Position>,
+        to: Option<Position>,
+        promotion: PromotionKind,
+    ) -> Result<(Move, MoveEvent), ChessBoardError> {
+        let stone = piece
+            .parse::<Stone>()
+            .map_err(|_| ChessBoardError::InvalidMove(MoveError::NoStoneFound))?;
+        let before = self.clone();
+        let chess_move = self.move_piece_promoting(piece, from.clone(), to.clone(), promotion)?;
+        // the fen only follows the moves when the turns are synced, the event
+        // must always carry the resulting position
+        if !self.sync {
+            self.sync_fen();
+        }
+
+        let mut changes = Vec::new();
+        let mut captured = None;
+        let mut promotion = None;
+
+        if from != to {
+            if let Some(to) = to.as_ref() {
+                let captured_at = match (&chess_move, stone.color()) {
+                    (Move::Passant, Color::Light) => Position::new(to.x, to.y + 1),
+                    (Move::Passant, Color::Dark) => Position::new(to.x, to.y - 1),
+                    _ => to.clone(),
+                };
+                captured = before.stone_at(captured_at.x, captured_at.y).cloned();
+                if let Some(captured) = captured.as_ref() {
+                    changes.push(SquareChange::new(captured.clone(), Some(captured_at), None));
+                }
+            }
+
+            changes.push(SquareChange::new(stone.clone(), from.clone(), to.clone()));
+
+            match &chess_move {
+                Move::Castle(castle_side) => {
+                    let y = match stone.color() {
+                        Color::Light => 7,
+                        Color::Dark => 0,
+                    };
+                    let (rook_from, rook_to) = match castle_side {
+                        CastlePosition::KingSide => (7, 5),
+                        CastlePosition::QueenSide => (0, 3),
+                    };
+                    if let Some(rook) = self.stone_at(rook_to, y) {
+                        changes.push(SquareChange::new(
+                            rook.clone(),
+                            Some(Position::new(rook_from, y)),
+                            Some(Position::new(rook_to, y)),
+                        ));
+                    }
+                }
+                Move::Promotion(_) => {
+                    promotion = to
+                        .as_ref()
+                        .and_then(|to| self.stone_at(to.x, to.y))
+                        .cloned();
+                }
+                _ => {}
+            }
+        }
+
+        let san = move_event::san(
+            &before,
+            self,
+            &stone,
+            &from,
+            &to,
+            &chess_move,
+            captured.is_some(),
+        );
+
+        let move_event = MoveEvent {
+            san,
+            changes,
+            captured,
+            promotion,
+            fen: self.fen.clone(),
+            trash: self.trash_string(),
+        };
+
+        Ok((chess_move, move_event))
+    }
+
     pub fn apply_move_validation_and_effects(
         &mut self,
         stone_move: (&Stone, &Position, &Position),
+        promotion: PromotionKind,
     ) -> Move {
         match stone_move {
             (stone, _, to) if Some(to.clone()) == self.passant => {
@@ -522,25 +627,15 @@ impl ChessBoard {
             }
             (stone, _, to) if stone.as_str() == "lp" && to.y == 0 => {
                 self.passant = None;
-                let new_stone: Stone = match PromotionKind::Queen {
-                    PromotionKind::Queen => 'Q'.try_into().unwrap(),
-                    PromotionKind::Rook => 'R'.try_into().unwrap(),
-                    PromotionKind::Bishop => 'B'.try_into().unwrap(),
-                    PromotionKind::Knight => 'N'.try_into().unwrap(),
-                };
+                let new_stone: Stone = promotion.letter().try_into().unwrap();
                 self.stones[to.y as usize][to.x as usize] = Some(new_stone);
-                Move::Promotion(PromotionKind::Queen)
+                Move::Promotion(promotion)
             }
             (stone, _, to) if stone.as_str() == "dp" && to.y == 7 => {
                 self.passant = None;
-                let new_stone: Stone = match PromotionKind::Queen {
-                    PromotionKind::Queen => 'q'.try_into().unwrap(),
-                    PromotionKind::Rook => 'r'.try_into().unwrap(),
-                    PromotionKind::Bishop => 'b'.try_into().unwrap(),
-                    PromotionKind::Knight => 'n'.try_into().unwrap(),
-                };
+                let new_stone: Stone = promotion.letter().to_ascii_lowercase().try_into().unwrap();
                 self.stones[to.y as usize][to.x as usize] = Some(new_stone);
-                Move::Promotion(PromotionKind::Queen)
+                Move::Promotion(promotion)
             }
             (stone, from, to)
                 if stone.as_str() == "lk"
@@ -725,4 +820,65 @@ mod tests {
             chess_board.possible_moves(&Position::new(3, 2))
         );
     }
+
+    #[test]
+    fn test_move_event() {
+        let fen = "r3k2r/pppq1ppp/8/3Pp3/8/8/PPP2PPP/R3K2R w KQkq e6 0 1";
+        let mut chess_board = ChessBoardBuilder::new()
+            .fen(fen)
+            .validation(true)
+            .sync(true)
+            .build()
+            .unwrap();
+
+        let (_, passant) = chess_board
+            .play_move(
+                "lp",
+                "d5".parse().ok(),
+                "e6".parse().ok(),
+                PromotionKind::Queen,
+            )
+            .unwrap();
+        assert_eq!("dxe6", passant.san);
+        assert_eq!(Some("dp"), passant.captured.as_ref().map(|s| s.as_str()));
+        assert_eq!(
+            "dp:e5:deleted,lp:d5:e6",
+            passant.to_string().split('|').nth(1).unwrap()
+        );
+
+        let (_, castle) = chess_board
+            .play_move(
+                "dk",
+                "e8".parse().ok(),
+                "c8".parse().ok(),
+                PromotionKind::Queen,
+            )
+            .unwrap();
+        assert_eq!("O-O-O", castle.san);
+        assert_eq!(
+            "dk:e8:c8,dr:a8:d8",
+            castle.to_string().split('|').nth(1).unwrap()
+        );
+        assert_eq!(
+            castle.to_string(),
+            castle.to_string().parse::<MoveEvent>().unwrap().to_string()
+        );
+
+        let mut chess_board = ChessBoardBuilder::new()
+            .fen("8/4P3/8/8/8/8/8/k6K w - - 0 1")
+            .validation(true)
+            .sync(true)
+            .build()
+            .unwrap();
+        let (_, promotion) = chess_board
+            .play_move(
+                "lp",
+                "e7".parse().ok(),
+                "e8".parse().ok(),
+                PromotionKind::Knight,
+            )
+            .unwrap();
+        assert_eq!("e8=N", promotion.san);
+        assert_eq!(Some("ln"), promotion.promotion.as_ref().map(|s| s.as_str()));
+    }
 }
@@ -31,8 +31,7 @@ pub enum Move {
     Passant,
 }
 
-#[derive(Clone, Debug)]
-#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PromotionKind {
     Queen,
     Rook,
@@ -40,6 +39,29 @@ pub enum PromotionKind {
     Knight,
 }
 
+impl PromotionKind {
+    /// Letter of the piece in SAN, like the `N` of `e8=N`
+    pub fn letter(&self) -> char {
+        match self {
+            PromotionKind::Queen => 'Q',
+            PromotionKind::Rook => 'R',
+            PromotionKind::Bishop => 'B',
+            PromotionKind::Knight => 'N',
+        }
+    }
+
+    /// Either case, UCI writes them in lower case
+    pub fn from_letter(letter: char) -> Result<Self, ()> {
+        match letter.to_ascii_uppercase() {
+            'Q' => Ok(PromotionKind::Queen),
+            'R' => Ok(PromotionKind::Rook),
+            'B' => Ok(PromotionKind::Bishop),
+            'N' => Ok(PromotionKind::Knight),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum CastlePosition {
     KingSide,
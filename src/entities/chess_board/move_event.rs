@@ -0,0 +1,222 @@
+use std::str::FromStr;
+
+use super::{
+    enums::{CastlePosition, Move},
+    turns::Turn,
+    ChessBoard,
+};
+use crate::entities::{
+    position::Position,
+    stone::{Color, Kind, Stone},
+};
+
+/// A stone that changed square during a move, `None` is the trash
+#[derive(Clone, Debug)]
+pub struct SquareChange {
+    pub stone: Stone,
+    pub from: Option<Position>,
+    pub to: Option<Position>,
+}
+
+impl SquareChange {
+    pub fn new(stone: Stone, from: Option<Position>, to: Option<Position>) -> Self {
+        Self { stone, from, to }
+    }
+
+    pub fn to_string(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            self.stone.image_class(),
+            square_to_string(&self.from),
+            square_to_string(&self.to)
+        )
+    }
+}
+
+impl FromStr for SquareChange {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let stone = parts.next().ok_or(())?.parse::<Stone>()?;
+        let from = square_from_str(parts.next().ok_or(())?)?;
+        let to = square_from_str(parts.next().ok_or(())?)?;
+
+        Ok(Self { stone, from, to })
+    }
+}
+
+/// Everything a move changed on the board, sent to the clients as a single
+/// `/move_event` so castling, en passant and promotions are applied at once.
+///
+/// Format: `san|changes|captured|promotion|fen|trash`, where changes are
+/// `stone:from:to` separated by `,` and a missing stone is `-`.
+#[derive(Clone, Debug)]
+pub struct MoveEvent {
+    pub san: String,
+    pub changes: Vec<SquareChange>,
+    pub captured: Option<Stone>,
+    pub promotion: Option<Stone>,
+    pub fen: String,
+    pub trash: String,
+}
+
+impl MoveEvent {
+    pub fn to_string(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}|{}",
+            self.san,
+            self.changes
+                .iter()
+                .map(|change| change.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            stone_to_string(&self.captured),
+            stone_to_string(&self.promotion),
+            self.fen,
+            self.trash
+        )
+    }
+}
+
+impl FromStr for MoveEvent {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('|');
+        let san = parts.next().ok_or(())?.to_string();
+        let changes = parts
+            .next()
+            .ok_or(())?
+            .split(',')
+            .filter(|change| !change.is_empty())
+            .map(|change| change.parse::<SquareChange>())
+            .collect::<Result<Vec<SquareChange>, ()>>()?;
+        let captured = stone_from_str(parts.next().ok_or(())?)?;
+        let promotion = stone_from_str(parts.next().ok_or(())?)?;
+        let fen = parts.next().ok_or(())?.to_string();
+        let trash = parts.next().unwrap_or("").to_string();
+
+        Ok(Self {
+            san,
+            changes,
+            captured,
+            promotion,
+            fen,
+            trash,
+        })
+    }
+}
+
+fn square_to_string(square: &Option<Position>) -> String {
+    square
+        .as_ref()
+        .map(|p| p.to_string())
+        .unwrap_or("deleted".to_string())
+}
+
+fn square_from_str(s: &str) -> Result<Option<Position>, ()> {
+    match s {
+        "deleted" => Ok(None),
+        _ => s.parse::<Position>().map(Some),
+    }
+}
+
+fn stone_to_string(stone: &Option<Stone>) -> String {
+    stone
+        .as_ref()
+        .map(|s| s.image_class())
+        .unwrap_or("-".to_string())
+}
+
+fn stone_from_str(s: &str) -> Result<Option<Stone>, ()> {
+    match s {
+        "-" => Ok(None),
+        _ => s.parse::<Stone>().map(Some),
+    }
+}
+
+fn piece_letter(stone: &Stone) -> String {
+    stone.char().to_uppercase().to_string()
+}
+
+/// Standard algebraic notation of a move, `before` is the board before the
+/// move and `after` the board after it. Moves from and to the trash have no
+/// SAN, they are written as drops `Q@e4` and removals `Qe4@`.
+pub fn san(
+    before: &ChessBoard,
+    after: &ChessBoard,
+    stone: &Stone,
+    from: &Option<Position>,
+    to: &Option<Position>,
+    chess_move: &Move,
+    is_capture: bool,
+) -> String {
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) => (from, to),
+        (None, Some(to)) => return format!("{}@{}", piece_letter(stone), to.to_string()),
+        (Some(from), None) => return format!("{}{}@", piece_letter(stone), from.to_string()),
+        (None, None) => return "-".to_string(),
+    };
+
+    let mut san = match chess_move {
+        Move::Castle(CastlePosition::KingSide) => "O-O".to_string(),
+        Move::Castle(CastlePosition::QueenSide) => "O-O-O".to_string(),
+        _ => {
+            let mut san = String::new();
+            let file = from.to_string()[..1].to_string();
+
+            if matches!(stone.kind(), Kind::Pawn) {
+                if is_capture {
+                    san.push_str(&file);
+                }
+            } else {
+                san.push_str(&piece_letter(stone));
+
+                let rivals: Vec<Position> = before
+                    .stones_and_positions_iter()
+                    .filter(|(position, other)| {
+                        position != from && other.as_str() == stone.as_str()
+                    })
+                    .map(|(position, _)| position)
+                    .filter(|position| before.possible_moves(position).contains(to))
+                    .collect();
+
+                if !rivals.is_empty() {
+                    if rivals.iter().all(|p| p.x != from.x) {
+                        san.push_str(&file);
+                    } else if rivals.iter().all(|p| p.y != from.y) {
+                        san.push_str(&from.to_string()[1..]);
+                    } else {
+                        san.push_str(&from.to_string());
+                    }
+                }
+            }
+
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&to.to_string());
+
+            if let Some(promoted) = after.stone_at(to.x, to.y) {
+                if matches!(chess_move, Move::Promotion(_)) {
+                    san.push('=');
+                    san.push_str(&piece_letter(promoted));
+                }
+            }
+
+            san
+        }
+    };
+
+    // check is only meaningful when the turn passed to the opponent
+    let mover_turn = match stone.color() {
+        Color::Light => Turn::White,
+        Color::Dark => Turn::Black,
+    };
+    if after.sync && before.turn == mover_turn && after.is_in_check() {
+        san.push(if after.is_checkmate() { '#' } else { '+' });
+    }
+
+    san
+}
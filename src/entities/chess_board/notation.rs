@@ -0,0 +1,297 @@
+//! Moves typed as text, in SAN like `Nf3`, `exd5`, `O-O` and `e8=N` or in
+//! UCI like `g1f3` and `e7e8n`, read against the position they are played in
+
+use super::{enums::PromotionKind, turns::Turn, ChessBoard};
+use crate::entities::{
+    position::Position,
+    stone::{Color, Kind},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotationError {
+    Unreadable,
+    NoPiece,
+    Illegal,
+    /// Squares of the pieces that can all play the move
+    Ambiguous(Vec<Position>),
+}
+
+impl NotationError {
+    pub fn to_string(&self) -> String {
+        match self {
+            NotationError::Unreadable => {
+                "Write moves like Nf3, exd5, O-O, e8=N or g1f3".to_string()
+            }
+            NotationError::NoPiece => "There is no piece to move".to_string(),
+            NotationError::Illegal => "This move is not legal here".to_string(),
+            NotationError::Ambiguous(squares) => {
+                let squares: Vec<String> = squares.iter().map(|s| s.to_string()).collect();
+                format!(
+                    "Ambiguous move, the pieces on {} can play it",
+                    squares.join(" and ")
+                )
+            }
+        }
+    }
+}
+
+/// A move as sent with `/move`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypedMove {
+    /// Image class of the piece, like `ln`
+    pub piece: String,
+    pub from: Position,
+    pub to: Position,
+    pub promotion: PromotionKind,
+}
+
+/// Square written like `e4`
+fn square(text: &str) -> Option<Position> {
+    match text.as_bytes() {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some(Position::new(
+            (file - b'a') as usize,
+            (b'8' - rank) as usize,
+        )),
+        _ => None,
+    }
+}
+
+fn kind_of(letter: char) -> Option<Kind> {
+    match letter {
+        'K' => Some(Kind::King),
+        'Q' => Some(Kind::Queen),
+        'R' => Some(Kind::Rook),
+        'B' => Some(Kind::Bishop),
+        'N' => Some(Kind::Knight),
+        _ => None,
+    }
+}
+
+fn side_color(turn: Turn) -> Color {
+    match turn {
+        Turn::White => Color::Light,
+        Turn::Black => Color::Dark,
+    }
+}
+
+/// Checks the move and sets its promotion, a pawn reaching the last rank
+/// becomes a queen unless told otherwise
+pub fn typed_move(
+    chess_board: &ChessBoard,
+    from: Position,
+    to: Position,
+    promotion: Option<PromotionKind>,
+) -> Result<TypedMove, NotationError> {
+    let stone = chess_board
+        .stone_at(from.x, from.y)
+        .ok_or(NotationError::NoPiece)?;
+
+    if chess_board.validation
+        && (stone.color() != side_color(chess_board.turn)
+            || !chess_board.possible_moves(&from).contains(&to))
+    {
+        return Err(NotationError::Illegal);
+    }
+
+    let last_rank = match stone.color() {
+        Color::Light => 0,
+        Color::Dark => 7,
+    };
+    let promotes = stone.kind() == Kind::Pawn && to.y == last_rank;
+    if promotion.is_some() && !promotes {
+        return Err(NotationError::Illegal);
+    }
+
+    Ok(TypedMove {
+        piece: stone.image_class(),
+        from,
+        to,
+        promotion: promotion.unwrap_or(PromotionKind::Queen),
+    })
+}
+
+fn parse_uci(chess_board: &ChessBoard, text: &str) -> Option<Result<TypedMove, NotationError>> {
+    let from = square(text.get(0..2)?)?;
+    let to = square(text.get(2..4)?)?;
+    let promotion = match text.get(4..)? {
+        "" => None,
+        letter if letter.len() == 1 => {
+            Some(PromotionKind::from_letter(letter.chars().next()?).ok()?)
+        }
+        _ => return None,
+    };
+
+    Some(typed_move(chess_board, from, to, promotion))
+}
+
+fn parse_san(chess_board: &ChessBoard, text: &str) -> Result<TypedMove, NotationError> {
+    let color = side_color(chess_board.turn);
+    let castle = match text {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None,
+    };
+    if let Some(king_x) = castle {
+        let y = match color {
+            Color::Light => 7,
+            Color::Dark => 0,
+        };
+        let from = Position::new(4, y);
+        if !chess_board
+            .stone_at(4, y)
+            .is_some_and(|s| s.kind() == Kind::King && s.color() == color)
+        {
+            return Err(NotationError::Illegal);
+        }
+        return typed_move(chess_board, from, Position::new(king_x, y), None);
+    }
+
+    // the promotion is written `e8=N` or `e8N`
+    let (text, promotion) = match text.split_once('=') {
+        Some((text, letter)) => (text, Some(letter)),
+        None => match text.char_indices().last() {
+            Some((i, c)) if i >= 2 && kind_of(c).is_some() => (&text[..i], Some(&text[i..])),
+            _ => (text, None),
+        },
+    };
+    let promotion = match promotion {
+        Some(letter) => {
+            let mut chars = letter.chars();
+            match (
+                chars.next().filter(|c| c.is_ascii_uppercase()),
+                chars.next(),
+            ) {
+                (Some(c), None) => {
+                    Some(PromotionKind::from_letter(c).map_err(|_| NotationError::Unreadable)?)
+                }
+                _ => return Err(NotationError::Unreadable),
+            }
+        }
+        None => None,
+    };
+
+    let (kind, text) = match text.chars().next().and_then(kind_of) {
+        Some(kind) => (kind, &text[1..]),
+        None => (Kind::Pawn, text),
+    };
+    if text.len() < 2 || !text.is_ascii() {
+        return Err(NotationError::Unreadable);
+    }
+    let (hint, to) = text.split_at(text.len() - 2);
+    let to = square(to).ok_or(NotationError::Unreadable)?;
+    let hint = hint.strip_suffix('x').unwrap_or(hint);
+    // pawns only capture when told so
+    if kind == Kind::Pawn && hint.is_empty() && chess_board.stone_at(to.x, to.y).is_some() {
+        return Err(NotationError::Illegal);
+    }
+
+    // file and rank the piece comes from, when several pieces can move
+    let mut file = None;
+    let mut rank = None;
+    // a pawn without a hint stays on its file, en passant is written as a capture
+    if kind == Kind::Pawn && hint.is_empty() {
+        file = Some(to.x);
+    }
+    for c in hint.chars() {
+        match c {
+            'a'..='h' if file.is_none() && rank.is_none() => file = Some(c as usize - 'a' as usize),
+            '1'..='8' if rank.is_none() => rank = Some(8 - (c as usize - '0' as usize)),
+            _ => return Err(NotationError::Unreadable),
+        }
+    }
+
+    let candidates: Vec<Position> = chess_board
+        .stones_and_positions_iter()
+        .filter(|(position, stone)| {
+            stone.kind() == kind
+                && (stone.color() == color || !chess_board.validation)
+                && file.is_none_or(|x| position.x == x)
+                && rank.is_none_or(|y| position.y == y)
+        })
+        .map(|(position, _)| position)
+        .filter(|position| chess_board.possible_moves(position).contains(&to))
+        .collect();
+
+    match candidates.len() {
+        0 => Err(NotationError::Illegal),
+        1 => typed_move(chess_board, candidates[0].clone(), to, promotion),
+        _ => Err(NotationError::Ambiguous(candidates)),
+    }
+}
+
+/// Reads a move in SAN or UCI, check and annotation marks are ignored
+pub fn parse_move(chess_board: &ChessBoard, text: &str) -> Result<TypedMove, NotationError> {
+    let text = text.trim().trim_end_matches(['+', '#', '!', '?']);
+    if text.is_empty() {
+        return Err(NotationError::Unreadable);
+    }
+
+    match parse_uci(chess_board, text) {
+        Some(result) => result,
+        None => parse_san(chess_board, text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::chess_board::ChessBoardBuilder;
+
+    fn parse(fen: &str, text: &str) -> Result<(String, String, String), NotationError> {
+        let chess_board = ChessBoardBuilder::new().fen(fen).build().unwrap();
+        parse_move(&chess_board, text).map(|m| {
+            let promotion = match m.promotion {
+                PromotionKind::Queen => String::new(),
+                kind => kind.letter().to_string(),
+            };
+            (m.piece, m.from.to_string() + &m.to.to_string(), promotion)
+        })
+    }
+
+    #[test]
+    fn test_parse_move() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let ok = |piece: &str, squares: &str, promotion: &str| {
+            Ok((
+                piece.to_string(),
+                squares.to_string(),
+                promotion.to_string(),
+            ))
+        };
+        assert_eq!(parse(start, "Nf3"), ok("ln", "g1f3", ""));
+        assert_eq!(parse(start, "g1f3"), ok("ln", "g1f3", ""));
+        assert_eq!(parse(start, "e4!"), ok("lp", "e2e4", ""));
+        assert_eq!(parse(start, "Nf4"), Err(NotationError::Illegal));
+        assert_eq!(parse(start, "e5"), Err(NotationError::Illegal));
+        assert_eq!(parse(start, "g8f6"), Err(NotationError::Illegal));
+        assert_eq!(parse(start, "e3e4"), Err(NotationError::NoPiece));
+        assert_eq!(parse(start, "hello"), Err(NotationError::Unreadable));
+
+        let middle = "r3k2r/pp3ppp/8/3p4/4P3/5N2/PP3PPP/RN2K2R w KQkq - 0 1";
+        assert_eq!(parse(middle, "exd5"), ok("lp", "e4d5", ""));
+        assert_eq!(parse(middle, "O-O"), ok("lk", "e1g1", ""));
+        assert_eq!(parse(middle, "O-O-O"), Err(NotationError::Illegal));
+        assert_eq!(
+            parse(middle, "Nd2"),
+            Err(NotationError::Ambiguous(vec![
+                "f3".parse().unwrap(),
+                "b1".parse().unwrap()
+            ]))
+        );
+        assert_eq!(parse(middle, "Nbd2"), ok("ln", "b1d2", ""));
+        assert_eq!(parse(middle, "Nfd2"), ok("ln", "f3d2", ""));
+        assert_eq!(parse(middle, "d5"), Err(NotationError::Illegal));
+
+        let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(parse(en_passant, "d6"), Err(NotationError::Illegal));
+        assert_eq!(parse(en_passant, "exd6"), ok("lp", "e5d6", ""));
+        assert_eq!(parse(en_passant, "e6"), ok("lp", "e5e6", ""));
+
+        let promotion = "8/4P3/8/8/8/8/8/k6K w - - 0 1";
+        assert_eq!(parse(promotion, "e8=N+"), ok("lp", "e7e8", "N"));
+        assert_eq!(parse(promotion, "e8R"), ok("lp", "e7e8", "R"));
+        assert_eq!(parse(promotion, "e7e8b"), ok("lp", "e7e8", "B"));
+        assert_eq!(parse(promotion, "e8"), ok("lp", "e7e8", ""));
+        assert_eq!(parse(promotion, "Kg1=Q"), Err(NotationError::Illegal));
+    }
+}
@@ -1,15 +1,26 @@
 use cfg_if::cfg_if;
 use leptos::{
-    create_rw_signal, RwSignal, SignalGet, SignalGetUntracked, SignalSet, SignalUpdate,
+    create_rw_signal, RwSignal, SignalGet, SignalGetUntracked, SignalSet, SignalUpdate, SignalWith,
     SignalWithUntracked,
 };
 use std::collections::BTreeMap;
 use web_sys::WebSocket;
 
-use super::ChessBoard;
+use super::{
+    enums::PromotionKind,
+    move_event::{MoveEvent, SquareChange},
+    notation::TypedMove,
+    ChessBoard, ChessBoardBuilder,
+};
 
 use crate::entities::{
-    notification::Notification, position::Position, room::RoomStatus, stone::Stone,
+    connection::Connection,
+    notification::{Notification, NotifyType},
+    position::Position,
+    room::RoomStatus,
+    seek::Seek,
+    session::SessionInfo,
+    stone::{Kind, Stone},
 };
 
 pub struct ChessBoardSignalsBuilder {
@@ -19,6 +30,9 @@ pub struct ChessBoardSignalsBuilder {
     stones_signals: Option<RwSignal<StonesSignals>>,
     should_render: Option<RwSignal<bool>>,
     notification: Option<RwSignal<Notification>>,
+    connection: Option<RwSignal<Connection>>,
+    session: Option<RwSignal<Option<SessionInfo>>>,
+    seeks: Option<RwSignal<Vec<Seek>>>,
 }
 
 impl ChessBoardSignalsBuilder {
@@ -30,6 +44,9 @@ impl ChessBoardSignalsBuilder {
             stones_signals: None,
             should_render: None,
             notification: None,
+            connection: None,
+            session: None,
+            seeks: None,
         }
     }
 
@@ -63,6 +80,21 @@ impl ChessBoardSignalsBuilder {
         self
     }
 
+    pub fn connection(mut self, connection: RwSignal<Connection>) -> Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn session(mut self, session: RwSignal<Option<SessionInfo>>) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    pub fn seeks(mut self, seeks: RwSignal<Vec<Seek>>) -> Self {
+        self.seeks = Some(seeks);
+        self
+    }
+
     pub fn build(self) -> Result<ChessBoardSignals, ()> {
         let Some(chess_board) = self.chess_board else {
             return Err(());
@@ -82,6 +114,15 @@ impl ChessBoardSignalsBuilder {
         let Some(notification) = self.notification else {
             return Err(());
         };
+        let Some(connection) = self.connection else {
+            return Err(());
+        };
+        let Some(session) = self.session else {
+            return Err(());
+        };
+        let Some(seeks) = self.seeks else {
+            return Err(());
+        };
 
         Ok(ChessBoardSignals {
             chess_board,
@@ -90,6 +131,9 @@ impl ChessBoardSignalsBuilder {
             stones_signals,
             should_render,
             notification,
+            connection,
+            session,
+            seeks,
         })
     }
 }
@@ -255,6 +299,9 @@ pub struct ChessBoardSignals {
     stones_signals: RwSignal<StonesSignals>,
     should_render: RwSignal<bool>,
     notification: RwSignal<Notification>,
+    connection: RwSignal<Connection>,
+    session: RwSignal<Option<SessionInfo>>,
+    seeks: RwSignal<Vec<Seek>>,
 }
 
 #[allow(dead_code)]
@@ -283,6 +330,84 @@ impl ChessBoardSignals {
         self.notification
     }
 
+    pub fn connection(&self) -> RwSignal<Connection> {
+        self.connection
+    }
+
+    pub fn session(&self) -> RwSignal<Option<SessionInfo>> {
+        self.session
+    }
+
+    /// Open seeks of the lobby, only kept up to date while it is shown
+    pub fn seeks(&self) -> RwSignal<Vec<Seek>> {
+        self.seeks
+    }
+
+    /// Id of the current user, tracked
+    pub fn user_id(&self) -> Option<String> {
+        self.session
+            .with(|session| session.as_ref().map(|s| s.user_id.clone()))
+    }
+
+    pub fn notify(&self, notify_type: NotifyType, msg: &str) {
+        self.notification.update(|notification| {
+            notification.notify_type = notify_type;
+            notification.message = msg.to_string();
+            notification.disable();
+        });
+
+        self.notification.update(|notification| {
+            notification.enable();
+        });
+    }
+
+    /// Sends a message through the socket, moves made while the socket is not
+    /// open are queued and flushed once the room has resumed.
+    pub fn send_message(&self, msg: &str) {
+        let resuming = self.connection.with_untracked(|c| c.is_resuming());
+        let sent = !resuming
+            && self.chess_board_socket.with_untracked(|ws| match ws {
+                Some(ws) if ws.ready_state() == WebSocket::OPEN => match ws.send_with_str(msg) {
+                    Ok(_) => true,
+                    Err(err) => {
+                        log::error!("error sending message: {:?}", err);
+                        false
+                    }
+                },
+                _ => false,
+            });
+
+        if !sent && msg.starts_with("/move") {
+            self.connection.update(|c| c.enqueue(msg));
+        }
+    }
+
+    /// Sequence number of the last event applied to the board of the current
+    /// room, moves are sent with it so the server can spot stale ones
+    pub fn last_seq(&self) -> Option<u64> {
+        let room_name = self
+            .room_status
+            .with_untracked(|rs| rs.as_ref().map(|rs| rs.name()))?;
+
+        self.connection
+            .with_untracked(|c| c.last_seq())
+            .filter(|(room, _)| *room == room_name)
+            .map(|(_, seq)| seq)
+    }
+
+    /// Sends the moves queued while the socket was down, once the room sent
+    /// what was missed
+    pub fn flush_pending_messages(&self) {
+        if !self.connection.with_untracked(|c| c.is_resuming()) {
+            return;
+        }
+        let pending = self.connection.try_update(|c| c.drain_pending());
+
+        for msg in pending.unwrap_or_default() {
+            self.send_message(&msg);
+        }
+    }
+
     pub fn is_checkmate(&self) -> bool {
         self.room_status()
             .get()
@@ -290,6 +415,50 @@ impl ChessBoardSignals {
             .unwrap_or(false)
     }
 
+    /// The board shown with the rules of the room, clicked and typed moves
+    /// are checked against it before being sent
+    pub fn rules_board(&self) -> ChessBoard {
+        let validation = self.room_status.with_untracked(|rs| {
+            rs.as_ref()
+                .map(|rs| rs.options().validation())
+                .unwrap_or(false)
+        });
+        let mut chess_board = self.chess_board.get_untracked();
+        chess_board.validation = validation;
+        chess_board
+    }
+
+    /// Sends a move made without dragging and shows it right away, the
+    /// castling rook and the promoted piece come with the move event
+    pub fn play(&self, typed_move: &TypedMove) {
+        let to = match typed_move.promotion {
+            PromotionKind::Queen => typed_move.to.to_string(),
+            promotion => format!("{}={}", typed_move.to.to_string(), promotion.letter()),
+        };
+        let msg = match self.last_seq() {
+            Some(seq) => format!(
+                "/move {} {} {} {}",
+                typed_move.piece,
+                typed_move.from.to_string(),
+                to,
+                seq
+            ),
+            None => format!(
+                "/move {} {} {}",
+                typed_move.piece,
+                typed_move.from.to_string(),
+                to
+            ),
+        };
+
+        self.move_piece(
+            typed_move.piece.clone(),
+            typed_move.from.to_string(),
+            typed_move.to.to_string(),
+        );
+        self.send_message(&msg);
+    }
+
     pub fn move_piece(&self, piece: String, old_pos: String, new_pos: String) {
         if old_pos == new_pos {
             return;
@@ -468,7 +637,161 @@ impl ChessBoardSignals {
         }
     }
 
+    /// Replaces the whole board, every stone is rendered again
+    pub fn sync_board(&self, fen: &str, trash: &str) {
+        self.should_render().set(false);
+
+        self.stones_signals().update(|stones_signals| {
+            stones_signals.clear_board_stones();
+            stones_signals.clear_deleted_stones();
+        });
+
+        self.chess_board().update(|chessboard| {
+            let new_chessboard = ChessBoardBuilder::new()
+                .fen(fen)
+                .deleted_stones(trash)
+                .is_white_view(chessboard.white_view())
+                .validation(false)
+                .build()
+                .unwrap();
+
+            *chessboard = new_chessboard;
+        });
+
+        let positions_and_stones = self
+            .chess_board()
+            .with_untracked(|cb| cb.cloned_stones_and_positions());
+        let deleted_stones = self
+            .chess_board()
+            .with_untracked(|cb| cb.cloned_deleted_stones());
+
+        self.stones_signals().update(|stones_signals| {
+            for (position, stone) in positions_and_stones {
+                stones_signals.add_board_stone(position, stone);
+            }
+            for stone in deleted_stones {
+                stones_signals.add_deleted_stone(stone);
+            }
+        });
+
+        self.should_render().set(true);
+    }
+
+    /// Whether the board still shows the stone where the change starts, the
+    /// player who made the move already applied its own part of it
+    pub fn is_pending_change(&self, change: &SquareChange) -> bool {
+        self.chess_board()
+            .with_untracked(|cb| match (&change.from, &change.to) {
+                (Some(from), _) => cb
+                    .stone_at(from.x, from.y)
+                    .map(|s| s.as_str() == change.stone.as_str())
+                    .unwrap_or(false),
+                (None, Some(to)) => cb
+                    .stone_at(to.x, to.y)
+                    .map(|s| s.as_str() != change.stone.as_str())
+                    .unwrap_or(true),
+                (None, None) => false,
+            })
+    }
+
+    /// Applies all the square changes of a move and ends on the position of
+    /// the event, the board is rebuilt if it drifted away from it
+    pub fn apply_move_event(&self, move_event: &MoveEvent) {
+        let square = |position: &Option<Position>| {
+            position
+                .as_ref()
+                .map(|p| p.to_string())
+                .unwrap_or("deleted".to_string())
+        };
+
+        for change in move_event.changes.iter() {
+            if self.is_pending_change(change) {
+                self.move_piece(
+                    change.stone.image_class(),
+                    square(&change.from),
+                    square(&change.to),
+                );
+            }
+        }
+
+        let promoted = move_event
+            .changes
+            .iter()
+            .find(|c| matches!(c.stone.kind(), Kind::Pawn) && c.to.is_some());
+        if let (Some(promotion), Some(pawn)) = (move_event.promotion.clone(), promoted) {
+            let to = pawn
+                .to
+                .clone()
+                .expect("promoted pawn should be on the board");
+            let key = StoneSignal::new(Some(to.clone()), pawn.stone.clone()).unique_key();
+
+            self.stones_signals().update(|stones| {
+                if stones.remove_board_stone(key).is_some() {
+                    stones.add_board_stone(to, promotion);
+                }
+            });
+        }
+
+        let Ok(new_chessboard) = ChessBoardBuilder::new()
+            .fen(&move_event.fen)
+            .deleted_stones(&move_event.trash)
+            .is_white_view(self.chess_board().with_untracked(|cb| cb.white_view()))
+            .validation(false)
+            .build()
+        else {
+            log::error!("Invalid move event position: {}", move_event.fen);
+            return;
+        };
+
+        let mut expected: Vec<String> = new_chessboard
+            .stones_and_positions_iter()
+            .map(|(position, stone)| format!("{}_{}", position.to_string(), stone.as_str()))
+            .chain(
+                new_chessboard
+                    .deleted_stones
+                    .iter()
+                    .map(|s| s.image_class()),
+            )
+            .collect();
+        let mut shown: Vec<String> = self.stones_signals().with_untracked(|ss| {
+            ss.board_stones()
+                .values()
+                .chain(ss.deleted_stones().values())
+                .map(|stone_signal| {
+                    let stone_signal = stone_signal.get_untracked();
+                    match stone_signal.position() {
+                        Some(position) => {
+                            format!("{}_{}", position.to_string(), stone_signal.stone().as_str())
+                        }
+                        None => stone_signal.stone().image_class(),
+                    }
+                })
+                .collect()
+        });
+        expected.sort();
+        shown.sort();
+
+        if expected == shown {
+            self.chess_board().set(new_chessboard);
+        } else {
+            self.sync_board(&move_event.fen, &move_event.trash);
+        }
+    }
+
     #[allow(unused_variables)]
+    /// Reconnects with the current session cookie, the closed socket resumes
+    /// its room like any dropped connection
+    pub fn restart_websocket(&self) {
+        let closed = self.chess_board_socket.with_untracked(|ws| match ws {
+            Some(ws) => ws.close().is_ok(),
+            None => false,
+        });
+
+        if !closed {
+            self.start_websocket();
+        }
+    }
+
     pub fn start_websocket(&self) {
         if self.chess_board_socket.with_untracked(|ws| ws.is_some()) {
             return;
@@ -482,6 +805,14 @@ impl ChessBoardSignals {
             }
         }
 
+        if ws.is_some() {
+            self.connection.update(|c| {
+                if !c.is_reconnecting() {
+                    c.connecting();
+                }
+            });
+        }
+
         self.chess_board_socket.set(ws);
     }
 }
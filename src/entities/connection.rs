@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+
+/// Delay before the first reconnection attempt, in milliseconds
+const RECONNECT_BASE_DELAY: f64 = 500.0;
+
+/// Upper bound for the reconnection delay, in milliseconds
+const RECONNECT_MAX_DELAY: f64 = 30_000.0;
+
+/// Maximum amount of moves kept while the socket is down
+const MAX_PENDING_MESSAGES: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+    Reconnecting,
+    Disconnected,
+}
+
+#[derive(Clone, Debug)]
+pub struct Connection {
+    status: ConnectionStatus,
+    attempts: u32,
+    last_seq: Option<(String, u64)>,
+    resync_requested: bool,
+    pending: VecDeque<String>,
+    /// Moves queued while the socket was down wait for the room to resume
+    resuming: bool,
+}
+
+#[allow(dead_code)]
+impl Connection {
+    pub fn new() -> Self {
+        Self {
+            status: ConnectionStatus::Disconnected,
+            attempts: 0,
+            last_seq: None,
+            resync_requested: false,
+            pending: VecDeque::new(),
+            resuming: false,
+        }
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        self.status
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self.status, ConnectionStatus::Connected)
+    }
+
+    pub fn is_reconnecting(&self) -> bool {
+        matches!(
+            self.status,
+            ConnectionStatus::Connecting | ConnectionStatus::Reconnecting
+        )
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn connecting(&mut self) {
+        self.status = ConnectionStatus::Connecting;
+    }
+
+    /// The socket is open, queued moves are held until the room sent the
+    /// events missed in the meantime
+    pub fn connected(&mut self) {
+        self.status = ConnectionStatus::Connected;
+        self.attempts = 0;
+        self.resuming = !self.pending.is_empty();
+    }
+
+    pub fn is_resuming(&self) -> bool {
+        self.resuming
+    }
+
+    pub fn reconnecting(&mut self) {
+        self.status = ConnectionStatus::Reconnecting;
+        self.attempts += 1;
+    }
+
+    pub fn disconnected(&mut self) {
+        self.status = ConnectionStatus::Disconnected;
+        self.attempts = 0;
+    }
+
+    /// Room and sequence number of the last room event applied by this client
+    pub fn last_seq(&self) -> Option<(String, u64)> {
+        self.last_seq.clone()
+    }
+
+    pub fn set_last_seq(&mut self, room: &str, seq: u64) {
+        self.last_seq = Some((room.to_string(), seq));
+        self.resync_requested = false;
+    }
+
+    /// Marks a resync as requested, returns `false` if one is already on its
+    /// way so a burst of out of order events only asks once
+    pub fn request_resync(&mut self) -> bool {
+        !std::mem::replace(&mut self.resync_requested, true)
+    }
+
+    pub fn enqueue(&mut self, msg: &str) {
+        if self.pending.len() >= MAX_PENDING_MESSAGES {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(msg.to_string());
+    }
+
+    /// The room is synced again, the queued moves can be sent
+    pub fn drain_pending(&mut self) -> Vec<String> {
+        self.resuming = false;
+        self.pending.drain(..).collect()
+    }
+
+    /// The board changed while the socket was down, queued moves were made on
+    /// the old one and would only be rolled back as stale. Returns how many
+    /// were dropped.
+    pub fn board_changed(&mut self) -> usize {
+        if !self.resuming {
+            return 0;
+        }
+        self.resuming = false;
+        let dropped = self.pending.len();
+        self.pending.clear();
+        dropped
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Exponential backoff with jitter, `random` must be in the `[0, 1)` range
+    pub fn reconnect_delay(&self, random: f64) -> i32 {
+        let exponent = self.attempts.saturating_sub(1).min(16) as i32;
+        let delay = (RECONNECT_BASE_DELAY * 2f64.powi(exponent)).min(RECONNECT_MAX_DELAY);
+
+        (delay / 2.0 + random * delay / 2.0) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_delay() {
+        let mut connection = Connection::new();
+        let mut delays = vec![];
+        for _ in 0..8 {
+            connection.reconnecting();
+            delays.push((
+                connection.reconnect_delay(0.0),
+                connection.reconnect_delay(0.5),
+            ));
+        }
+        // half the delay plus up to another half of jitter, doubling up to
+        // the cap
+        assert_eq!(
+            delays,
+            vec![
+                (250, 375),
+                (500, 750),
+                (1000, 1500),
+                (2000, 3000),
+                (4000, 6000),
+                (8000, 12000),
+                (15000, 22500),
+                (15000, 22500),
+            ]
+        );
+
+        connection.connected();
+        connection.reconnecting();
+        assert_eq!(connection.reconnect_delay(0.0), 250);
+    }
+
+    #[test]
+    fn test_pending_moves() {
+        let mut connection = Connection::new();
+        for i in 0..MAX_PENDING_MESSAGES + 2 {
+            connection.enqueue(&format!("/move lp e2 e4 {}", i));
+        }
+        assert_eq!(connection.pending_count(), MAX_PENDING_MESSAGES);
+
+        // the oldest moves are dropped first
+        connection.connected();
+        assert!(connection.is_resuming());
+        let pending = connection.drain_pending();
+        assert_eq!(pending[0], "/move lp e2 e4 2");
+        assert!(!connection.is_resuming());
+        assert_eq!(connection.board_changed(), 0);
+
+        // moves made on a board that changed meanwhile are not sent
+        connection.reconnecting();
+        connection.enqueue("/move lp e2 e4 7");
+        connection.enqueue("/move dp e7 e5 8");
+        connection.connected();
+        assert_eq!(connection.board_changed(), 2);
+        assert!(connection.drain_pending().is_empty());
+
+        // nothing to hold without queued moves
+        connection.connected();
+        assert!(!connection.is_resuming());
+    }
+}
@@ -0,0 +1,340 @@
+use std::str::FromStr;
+
+use super::chess_board::turns::Turn;
+
+/// Clock of a game, `base` minutes for each side plus `increment` seconds per
+/// move, written `5+3`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: u64,
+    pub increment: u64,
+}
+
+impl TimeControl {
+    pub fn base_ms(&self) -> u64 {
+        self.base * 60 * 1000
+    }
+
+    pub fn increment_ms(&self) -> u64 {
+        self.increment * 1000
+    }
+
+    pub fn to_string(&self) -> String {
+        format!("{}+{}", self.base, self.increment)
+    }
+
+    /// Rating pool, by the expected length of a 40 moves game
+    pub fn pool(&self) -> Pool {
+        let estimate = self.base * 60 + self.increment * 40;
+
+        match estimate {
+            0..=179 => Pool::Bullet,
+            180..=479 => Pool::Blitz,
+            480..=1499 => Pool::Rapid,
+            _ => Pool::Classical,
+        }
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, increment) = s.split_once('+').ok_or(())?;
+        let base = base.parse::<u64>().map_err(|_| ())?;
+        let increment = increment.parse::<u64>().map_err(|_| ())?;
+
+        if base == 0 {
+            return Err(());
+        }
+
+        Ok(Self { base, increment })
+    }
+}
+
+/// Ratings are kept apart for every kind of time control
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Pool {
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+}
+
+impl Pool {
+    pub const ALL: [Pool; 4] = [Pool::Bullet, Pool::Blitz, Pool::Rapid, Pool::Classical];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Pool::Bullet => "bullet",
+            Pool::Blitz => "blitz",
+            Pool::Rapid => "rapid",
+            Pool::Classical => "classical",
+        }
+    }
+}
+
+impl FromStr for Pool {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pool::ALL
+            .into_iter()
+            .find(|pool| pool.as_str() == s)
+            .ok_or(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win(Turn),
+    Draw,
+}
+
+/// Why a game ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Timeout,
+    /// A player disconnected and did not come back within the grace period
+    Abandonment,
+    /// The server could not start the game, none of the players is to blame
+    Forfeit,
+}
+
+impl Termination {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Timeout => "timeout",
+            Termination::Abandonment => "abandonment",
+            Termination::Forfeit => "forfeit",
+        }
+    }
+}
+
+/// Result of a finished game, written `1-0 checkmate`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameResult {
+    pub outcome: Outcome,
+    pub termination: Termination,
+}
+
+impl GameResult {
+    pub fn new(outcome: Outcome, termination: Termination) -> Self {
+        Self {
+            outcome,
+            termination,
+        }
+    }
+
+    pub fn score(&self) -> &'static str {
+        match self.outcome {
+            Outcome::Win(Turn::White) => "1-0",
+            Outcome::Win(Turn::Black) => "0-1",
+            Outcome::Draw => "1/2-1/2",
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        format!("{} {}", self.score(), self.termination.as_str())
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (score, termination) = s.split_once(' ').ok_or(())?;
+        let outcome = match score {
+            "1-0" => Outcome::Win(Turn::White),
+            "0-1" => Outcome::Win(Turn::Black),
+            "1/2-1/2" => Outcome::Draw,
+            _ => return Err(()),
+        };
+        let termination = match termination {
+            "checkmate" => Termination::Checkmate,
+            "timeout" => Termination::Timeout,
+            "abandonment" => Termination::Abandonment,
+            "forfeit" => Termination::Forfeit,
+            _ => return Err(()),
+        };
+
+        Ok(Self::new(outcome, termination))
+    }
+}
+
+pub fn side_from_str(s: &str) -> Result<Turn, ()> {
+    match s {
+        "white" | "w" => Ok(Turn::White),
+        "black" | "b" => Ok(Turn::Black),
+        _ => Err(()),
+    }
+}
+
+pub fn side_to_str(side: Turn) -> &'static str {
+    match side {
+        Turn::White => "w",
+        Turn::Black => "b",
+    }
+}
+
+/// Player sitting on one side of the board, `grace_ms` is set while the
+/// player is disconnected and counts down to the moment the opponent can
+/// claim the game
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeatStatus {
+    pub user_id: String,
+    pub name: String,
+    pub grace_ms: Option<u64>,
+}
+
+impl SeatStatus {
+    pub fn is_away(&self) -> bool {
+        self.grace_ms.is_some()
+    }
+
+    pub fn to_string(&self) -> String {
+        let grace = self
+            .grace_ms
+            .map(|ms| ms.to_string())
+            .unwrap_or("-".to_string());
+        format!("{}:{}:{}", self.user_id, grace, self.name)
+    }
+}
+
+impl FromStr for SeatStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let user_id = parts.next().ok_or(())?.to_string();
+        let grace_ms = match parts.next().ok_or(())? {
+            "-" => None,
+            ms => Some(ms.parse::<u64>().map_err(|_| ())?),
+        };
+        let name = parts.next().ok_or(())?.to_string();
+
+        Ok(Self {
+            user_id,
+            name,
+            grace_ms,
+        })
+    }
+}
+
+/// Time left on both clocks when the status was sent, `running` is the side
+/// whose clock is ticking
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClockStatus {
+    pub time_control: TimeControl,
+    pub white_ms: u64,
+    pub black_ms: u64,
+    pub running: Option<Turn>,
+}
+
+impl ClockStatus {
+    pub fn time_left(&self, side: Turn) -> u64 {
+        match side {
+            Turn::White => self.white_ms,
+            Turn::Black => self.black_ms,
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.time_control.to_string(),
+            self.white_ms,
+            self.black_ms,
+            self.running.map(side_to_str).unwrap_or("-")
+        )
+    }
+}
+
+impl FromStr for ClockStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let time_control = parts.next().ok_or(())?.parse::<TimeControl>()?;
+        let white_ms = parts.next().ok_or(())?.parse::<u64>().map_err(|_| ())?;
+        let black_ms = parts.next().ok_or(())?.parse::<u64>().map_err(|_| ())?;
+        let running = match parts.next().ok_or(())? {
+            "-" => None,
+            side => Some(side_from_str(side)?),
+        };
+
+        Ok(Self {
+            time_control,
+            white_ms,
+            black_ms,
+            running,
+        })
+    }
+}
+
+/// Seats, clock and result of the game played in a room, sent to the clients
+/// as `/sync_game white|black|clock|result` with `-` for anything missing
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameStatus {
+    pub white: Option<SeatStatus>,
+    pub black: Option<SeatStatus>,
+    pub clock: Option<ClockStatus>,
+    pub result: Option<GameResult>,
+}
+
+impl GameStatus {
+    pub fn seat(&self, side: Turn) -> Option<&SeatStatus> {
+        match side {
+            Turn::White => self.white.as_ref(),
+            Turn::Black => self.black.as_ref(),
+        }
+    }
+
+    pub fn side_of(&self, user_id: &str) -> Option<Turn> {
+        [Turn::White, Turn::Black]
+            .into_iter()
+            .find(|side| self.seat(*side).map(|s| s.user_id == user_id) == Some(true))
+    }
+
+    pub fn to_string(&self) -> String {
+        fn or_dash(value: Option<String>) -> String {
+            value.unwrap_or("-".to_string())
+        }
+
+        format!(
+            "{}|{}|{}|{}",
+            or_dash(self.white.as_ref().map(|s| s.to_string())),
+            or_dash(self.black.as_ref().map(|s| s.to_string())),
+            or_dash(self.clock.as_ref().map(|c| c.to_string())),
+            or_dash(self.result.as_ref().map(|r| r.to_string()))
+        )
+    }
+}
+
+impl FromStr for GameStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn parse<T: FromStr<Err = ()>>(s: Option<&str>) -> Result<Option<T>, ()> {
+            match s.ok_or(())? {
+                "-" => Ok(None),
+                s => s.parse::<T>().map(Some),
+            }
+        }
+
+        let mut parts = s.split('|');
+        let white = parse::<SeatStatus>(parts.next())?;
+        let black = parse::<SeatStatus>(parts.next())?;
+        let clock = parse::<ClockStatus>(parts.next())?;
+        let result = parse::<GameResult>(parts.next())?;
+
+        Ok(Self {
+            white,
+            black,
+            clock,
+            result,
+        })
+    }
+}
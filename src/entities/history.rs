@@ -0,0 +1,277 @@
+use std::str::FromStr;
+
+use super::annotation::Annotation;
+
+/// Move of the history in commands, `start` for the start position
+pub fn node_to_string(node: Option<usize>) -> String {
+    node.map(|id| id.to_string()).unwrap_or("start".to_string())
+}
+
+pub fn node_from_str(s: &str) -> Result<Option<usize>, ()> {
+    match s {
+        "start" => Ok(None),
+        id => id.parse::<usize>().map(Some).map_err(|_| ()),
+    }
+}
+
+/// Move of the history tree of a room
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryMove {
+    pub id: usize,
+    /// Move this one follows, `None` for the moves from the start position
+    pub parent: Option<usize>,
+    pub san: String,
+    pub annotation: Annotation,
+}
+
+/// Piece of the move list, variations are written between parentheses
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HistoryToken {
+    Move {
+        id: usize,
+        text: String,
+    },
+    /// Notes on the move before it, or on the start position
+    Annotation(Annotation),
+    StartVariation,
+    EndVariation,
+}
+
+/// Moves played in a room as a tree, the first child of a move is its main
+/// line and the others are variations
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct History {
+    /// Move shown on the board, `None` at the start position
+    pub current: Option<usize>,
+    /// Full move number of the start position
+    pub start_number: u32,
+    pub black_first: bool,
+    /// Notes on the start position
+    pub start: Annotation,
+    /// Parents always come before their children, and siblings in order
+    pub moves: Vec<HistoryMove>,
+}
+
+impl History {
+    pub fn children(&self, parent: Option<usize>) -> impl Iterator<Item = &HistoryMove> + '_ {
+        self.moves.iter().filter(move |m| m.parent == parent)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Notes on the move shown on the board
+    pub fn current_annotation(&self) -> Option<&Annotation> {
+        match self.current {
+            Some(id) => self
+                .moves
+                .iter()
+                .find(|m| m.id == id)
+                .map(|m| &m.annotation),
+            None => Some(&self.start),
+        }
+    }
+
+    /// Whether a move is a variation rather than the main line
+    pub fn is_variation(&self, id: usize) -> bool {
+        let mut node = self.moves.iter().find(|m| m.id == id);
+        while let Some(m) = node {
+            if self.children(m.parent).next().map(|first| first.id) != Some(m.id) {
+                return true;
+            }
+            node = m.parent.and_then(|p| self.moves.iter().find(|m| m.id == p));
+        }
+
+        false
+    }
+
+    fn move_text(&self, m: &HistoryMove, ply: usize, with_number: bool) -> String {
+        let ply = ply + usize::from(self.black_first);
+        let number = self.start_number as usize + ply / 2;
+        let black = ply % 2 == 1;
+
+        if !black {
+            format!("{}. {}", number, m.san)
+        } else if with_number {
+            format!("{}... {}", number, m.san)
+        } else {
+            m.san.clone()
+        }
+    }
+
+    fn push_annotation(annotation: &Annotation, tokens: &mut Vec<HistoryToken>) {
+        if !annotation.is_empty() {
+            tokens.push(HistoryToken::Annotation(annotation.clone()));
+        }
+    }
+
+    fn push_line(
+        &self,
+        parent: Option<usize>,
+        ply: usize,
+        with_number: bool,
+        tokens: &mut Vec<HistoryToken>,
+    ) {
+        let children: Vec<&HistoryMove> = self.children(parent).collect();
+        let Some(main) = children.first() else {
+            return;
+        };
+
+        tokens.push(HistoryToken::Move {
+            id: main.id,
+            text: self.move_text(main, ply, with_number),
+        });
+        Self::push_annotation(&main.annotation, tokens);
+        for variation in &children[1..] {
+            tokens.push(HistoryToken::StartVariation);
+            tokens.push(HistoryToken::Move {
+                id: variation.id,
+                text: self.move_text(variation, ply, true),
+            });
+            Self::push_annotation(&variation.annotation, tokens);
+            let commented = !variation.annotation.comment.is_empty();
+            self.push_line(Some(variation.id), ply + 1, commented, tokens);
+            tokens.push(HistoryToken::EndVariation);
+        }
+
+        // black moves after a comment or a variation get their number again
+        let interrupted = children.len() > 1 || !main.annotation.comment.is_empty();
+        self.push_line(Some(main.id), ply + 1, interrupted, tokens);
+    }
+
+    /// Move list in PGN order, each variation right after the move it
+    /// replaces
+    pub fn tokens(&self) -> Vec<HistoryToken> {
+        let mut tokens = vec![];
+        Self::push_annotation(&self.start, &mut tokens);
+        self.push_line(None, 0, true, &mut tokens);
+
+        tokens
+    }
+
+    /// Movetext of the PGN, like `1. e4 $1 {Best by test} e5 (1... c5 2. Nf3)
+    /// 2. Nf3`
+    pub fn movetext(&self) -> String {
+        let mut text = String::new();
+        for token in self.tokens() {
+            match token {
+                HistoryToken::Move {
+                    text: move_text, ..
+                } => push_word(&mut text, &move_text),
+                HistoryToken::Annotation(annotation) => {
+                    for nag in annotation.nags.iter() {
+                        push_word(&mut text, &format!("${}", nag));
+                    }
+                    if let Some(comment) = annotation.pgn_comment() {
+                        push_word(&mut text, &format!("{{{}}}", comment));
+                    }
+                }
+                HistoryToken::StartVariation => text.push_str(" ("),
+                HistoryToken::EndVariation => text.push(')'),
+            }
+        }
+
+        text
+    }
+
+    /// Format: `{current}|{number}{w|b}|{start}|{id}:{parent}:{san}:{annotation},...`
+    /// where a missing current move or parent is `-`
+    pub fn to_string(&self) -> String {
+        let id_str = |id: Option<usize>| id.map(|id| id.to_string()).unwrap_or("-".to_string());
+        let moves: Vec<String> = self
+            .moves
+            .iter()
+            .map(|m| {
+                format!(
+                    "{}:{}:{}:{}",
+                    m.id,
+                    id_str(m.parent),
+                    m.san,
+                    m.annotation.to_string()
+                )
+            })
+            .collect();
+
+        format!(
+            "{}|{}{}|{}|{}",
+            id_str(self.current),
+            self.start_number,
+            if self.black_first { "b" } else { "w" },
+            self.start.to_string(),
+            moves.join(",")
+        )
+    }
+}
+
+fn push_word(text: &mut String, word: &str) {
+    if !text.is_empty() && !text.ends_with('(') {
+        text.push(' ');
+    }
+    text.push_str(word);
+}
+
+impl FromStr for History {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_id = |id: &str| match id {
+            "-" => Ok(None),
+            id => id.parse::<usize>().map(Some).map_err(|_| ()),
+        };
+
+        let mut parts = s.splitn(4, '|');
+        let current = parse_id(parts.next().ok_or(())?)?;
+        let start = parts.next().ok_or(())?;
+        let black_first = start.ends_with('b');
+        let start_number = start
+            .trim_end_matches(['w', 'b'])
+            .parse::<u32>()
+            .map_err(|_| ())?;
+        let start = parts.next().ok_or(())?.parse::<Annotation>()?;
+
+        let moves = parts
+            .next()
+            .ok_or(())?
+            .split(',')
+            .filter(|m| !m.is_empty())
+            .map(|m| {
+                let mut fields = m.splitn(4, ':');
+                Ok(HistoryMove {
+                    id: parse_id(fields.next().ok_or(())?)?.ok_or(())?,
+                    parent: parse_id(fields.next().ok_or(())?)?,
+                    san: fields.next().ok_or(())?.to_string(),
+                    annotation: fields.next().ok_or(())?.parse()?,
+                })
+            })
+            .collect::<Result<Vec<_>, ()>>()?;
+
+        Ok(Self {
+            current,
+            start_number,
+            black_first,
+            start,
+            moves,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_movetext() {
+        let synced = "3|1w|::|0:-:e4:1::Best%2C by test,1:0:e5:::,4:1:Nf3:::,\
+                      2:0:c5::Gc5d4:,3:2:Nf3:::,5:-:d4:::";
+        let history: History = synced.parse().unwrap();
+
+        assert_eq!(
+            history.movetext(),
+            "1. e4 $1 {Best, by test} (1. d4) 1... e5 (1... c5 {[%cal Gc5d4]} 2. Nf3) 2. Nf3"
+        );
+        assert!(history.is_variation(3));
+        assert!(!history.is_variation(4));
+        assert_eq!(history.to_string(), synced);
+    }
+}
@@ -1,5 +1,14 @@
+pub mod annotation;
+pub mod archive;
 pub mod chess_board;
+pub mod connection;
+pub mod game;
+pub mod history;
 pub mod notification;
 pub mod position;
 pub mod room;
+pub mod seek;
+pub mod session;
+pub mod setup;
 pub mod stone;
+pub mod tournament;
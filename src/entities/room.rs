@@ -1,19 +1,40 @@
-use std::{collections::BTreeMap, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
 
 use leptos::{create_rw_signal, RwSignal};
 
+use super::{
+    game::{GameStatus, Pool},
+    history::History,
+};
+
 #[derive(Clone)]
 pub struct RoomStatus {
     name: String,
     users: BTreeMap<String, RwSignal<User>>,
     options: ChessBoardOptions,
     checkmate: bool,
+    game: GameStatus,
+    /// Client time in ms when the game status was received, its clocks
+    /// count down from there
+    game_received_at: f64,
+    /// Pool of the shown ratings
+    rating_pool: Pool,
+    /// Ratings of the registered users by user id, like `1512` or `1500?`
+    ratings: HashMap<String, String>,
+    history: History,
+    /// Last PGN export asked for
+    pgn: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct ChessBoardOptions {
     validation: bool,
     sync: bool,
+    pause_clock: bool,
+    rated: bool,
 }
 
 impl ChessBoardOptions {
@@ -24,6 +45,14 @@ impl ChessBoardOptions {
     pub fn sync(&self) -> bool {
         self.sync
     }
+
+    pub fn pause_clock(&self) -> bool {
+        self.pause_clock
+    }
+
+    pub fn rated(&self) -> bool {
+        self.rated
+    }
 }
 
 #[derive(Clone)]
@@ -123,8 +152,16 @@ impl RoomStatus {
             options: ChessBoardOptions {
                 validation: false,
                 sync: true,
+                pause_clock: false,
+                rated: false,
             },
             checkmate: false,
+            game: GameStatus::default(),
+            game_received_at: 0.0,
+            rating_pool: Pool::Blitz,
+            ratings: HashMap::new(),
+            history: History::default(),
+            pgn: None,
         }
     }
 
@@ -143,6 +180,14 @@ impl RoomStatus {
             options.push_str(" sync");
         }
 
+        if self.options.pause_clock {
+            options.push_str(" pause");
+        }
+
+        if self.options.rated {
+            options.push_str(" rated");
+        }
+
         options.trim().to_string()
     }
 
@@ -151,15 +196,22 @@ impl RoomStatus {
 
         let mut validation = false;
         let mut sync = false;
+        let mut pause_clock = false;
+        let mut rated = false;
 
         while let Some(option) = options.next() {
             match option {
                 "validation" => validation = true,
                 "sync" => sync = true,
+                "pause" => pause_clock = true,
+                "rated" => rated = true,
                 _ => (),
             }
         }
 
+        self.options.pause_clock = pause_clock;
+        self.options.rated = rated;
+
         if validation {
             self.enable_validation();
         } else {
@@ -189,6 +241,14 @@ impl RoomStatus {
         self.options.sync = false;
     }
 
+    pub fn set_pause_clock(&mut self, pause_clock: bool) {
+        self.options.pause_clock = pause_clock;
+    }
+
+    pub fn set_rated(&mut self, rated: bool) {
+        self.options.rated = rated;
+    }
+
     pub fn users_count(&self) -> usize {
         self.users.values().len()
     }
@@ -208,6 +268,7 @@ impl RoomStatus {
     pub fn set_name(&mut self, name: &str) {
         if self.name != name {
             self.name = name.to_string();
+            self.pgn = None;
         }
     }
 
@@ -246,4 +307,55 @@ impl RoomStatus {
     pub fn set_checkmate(&mut self, checkmate: bool) {
         self.checkmate = checkmate;
     }
+
+    pub fn game(&self) -> &GameStatus {
+        &self.game
+    }
+
+    pub fn game_received_at(&self) -> f64 {
+        self.game_received_at
+    }
+
+    pub fn set_game(&mut self, game: GameStatus, received_at: f64) {
+        self.game = game;
+        self.game_received_at = received_at;
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
+
+    pub fn pgn(&self) -> Option<&String> {
+        self.pgn.as_ref()
+    }
+
+    pub fn set_pgn(&mut self, pgn: Option<String>) {
+        self.pgn = pgn;
+    }
+
+    pub fn rating_pool(&self) -> Pool {
+        self.rating_pool
+    }
+
+    pub fn rating_of(&self, user_id: &str) -> Option<String> {
+        self.ratings.get(user_id).cloned()
+    }
+
+    /// Applies `{pool}|{user_id}:{rating},...`
+    pub fn sync_ratings(&mut self, ratings: &str) -> Result<(), ()> {
+        let (pool, ratings) = ratings.split_once('|').ok_or(())?;
+
+        self.rating_pool = pool.parse::<Pool>()?;
+        self.ratings = ratings
+            .split(',')
+            .filter_map(|rating| rating.rsplit_once(':'))
+            .map(|(user_id, rating)| (user_id.to_string(), rating.to_string()))
+            .collect();
+
+        Ok(())
+    }
 }
@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+use super::game::TimeControl;
+
+/// Side a seeker wants to play
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    White,
+    Black,
+    Random,
+}
+
+impl ColorChoice {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColorChoice::White => "white",
+            ColorChoice::Black => "black",
+            ColorChoice::Random => "random",
+        }
+    }
+
+    /// Two seekers can't both insist on the same side
+    pub fn fits(&self, other: ColorChoice) -> bool {
+        !matches!(
+            (self, other),
+            (ColorChoice::White, ColorChoice::White) | (ColorChoice::Black, ColorChoice::Black)
+        )
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "white" => Ok(ColorChoice::White),
+            "black" => Ok(ColorChoice::Black),
+            "random" => Ok(ColorChoice::Random),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Ratings of the opponents a seeker accepts, written `1400-1700`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RatingRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl RatingRange {
+    pub fn contains(&self, rating: f64) -> bool {
+        (self.min as f64..=self.max as f64).contains(&rating.round())
+    }
+
+    pub fn to_string(&self) -> String {
+        format!("{}-{}", self.min, self.max)
+    }
+}
+
+impl FromStr for RatingRange {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s.split_once('-').ok_or(())?;
+        let min = min.parse::<u32>().map_err(|_| ())?;
+        let max = max.parse::<u32>().map_err(|_| ())?;
+
+        if min > max {
+            return Err(());
+        }
+
+        Ok(Self { min, max })
+    }
+}
+
+/// Game a user is looking for, written `5+3 random rated 1400-1700`. The
+/// colour defaults to random, the game to casual and any rating is fine
+/// without a range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeekRequest {
+    pub time_control: TimeControl,
+    pub color: ColorChoice,
+    pub rated: bool,
+    pub range: Option<RatingRange>,
+}
+
+impl SeekRequest {
+    pub fn to_string(&self) -> String {
+        let rated = if self.rated { "rated" } else { "casual" };
+        let mut str = format!(
+            "{} {} {}",
+            self.time_control.to_string(),
+            self.color.as_str(),
+            rated
+        );
+
+        if let Some(range) = self.range {
+            str.push(' ');
+            str.push_str(&range.to_string());
+        }
+
+        str
+    }
+}
+
+impl FromStr for SeekRequest {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let time_control = words.next().ok_or(())?.parse::<TimeControl>()?;
+        let mut request = Self {
+            time_control,
+            color: ColorChoice::Random,
+            rated: false,
+            range: None,
+        };
+
+        for word in words {
+            match word {
+                "rated" => request.rated = true,
+                "casual" => request.rated = false,
+                _ => match word.parse::<ColorChoice>() {
+                    Ok(color) => request.color = color,
+                    Err(_) => request.range = Some(word.parse::<RatingRange>()?),
+                },
+            }
+        }
+
+        Ok(request)
+    }
+}
+
+/// Open seek shown in the lobby, written
+/// `id|user_id|name|rating|5+3 random rated`, the rating is empty for
+/// anonymous seekers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Seek {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub rating: Option<String>,
+    pub request: SeekRequest,
+}
+
+impl Seek {
+    pub fn to_string(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}",
+            self.id,
+            self.user_id,
+            self.name,
+            self.rating.clone().unwrap_or_default(),
+            self.request.to_string()
+        )
+    }
+}
+
+impl FromStr for Seek {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.splitn(5, '|');
+        let id = split.next().ok_or(())?;
+        let user_id = split.next().ok_or(())?;
+        let name = split.next().ok_or(())?;
+        let rating = split.next().ok_or(())?;
+        let request = split.next().ok_or(())?.parse::<SeekRequest>()?;
+
+        Ok(Self {
+            id: id.to_string(),
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+            rating: Some(rating.to_string()).filter(|r| !r.is_empty()),
+            request,
+        })
+    }
+}
@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// What the client may know about its session, the tokens themselves stay
+/// in `HttpOnly` cookies
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub user_id: String,
+    pub name: String,
+    pub logged_in: bool,
+    /// Seconds left before the access token expires
+    pub expires_in: u64,
+}
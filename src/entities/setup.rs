@@ -0,0 +1,288 @@
+//! Position built in the board editor before it becomes the start position
+//! of a room
+
+use std::str::FromStr;
+
+use super::chess_board::{stones::fen_to_stones, turns::Turn, ChessBoard};
+use super::position::Position;
+use super::stone::{Color, Kind, Stone};
+
+pub const DEFAULT_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Castling rights in FEN order
+pub const CASTLING: [char; 4] = ['K', 'Q', 'k', 'q'];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SetupError {
+    KingCount,
+    TooManyPieces,
+    PawnOnBackRank,
+    CastlingRights,
+    Passant,
+    OpponentInCheck,
+    MoveNumber,
+}
+
+impl SetupError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SetupError::KingCount => "Each side needs exactly one king",
+            SetupError::TooManyPieces => "A side has more than 16 pieces or 8 pawns",
+            SetupError::PawnOnBackRank => "Pawns can't stand on the first or last rank",
+            SetupError::CastlingRights => {
+                "Castling needs the king and the rook on their starting squares"
+            }
+            SetupError::Passant => {
+                "The en passant square must be behind a pawn that just moved two squares"
+            }
+            SetupError::OpponentInCheck => "The side that just moved is in check",
+            SetupError::MoveNumber => "The move number starts at 1",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Setup {
+    pub stones: [[Option<Stone>; 8]; 8],
+    pub turn: Turn,
+    /// Rights of `CASTLING`, in the same order
+    pub castling: [bool; 4],
+    pub passant: Option<Position>,
+    pub half_move_clock: u32,
+    pub full_move_number: u32,
+}
+
+impl Setup {
+    pub fn empty() -> Self {
+        const ROW: [Option<Stone>; 8] = [None, None, None, None, None, None, None, None];
+
+        Self {
+            stones: [ROW; 8],
+            turn: Turn::White,
+            castling: [false; 4],
+            passant: None,
+            half_move_clock: 0,
+            full_move_number: 1,
+        }
+    }
+
+    pub fn start() -> Self {
+        DEFAULT_FEN.parse().unwrap()
+    }
+
+    pub fn stone_at(&self, position: &Position) -> Option<&Stone> {
+        self.stones[position.y][position.x].as_ref()
+    }
+
+    /// Puts a stone on a square, or empties it for `None`
+    pub fn set_stone(&mut self, position: &Position, stone: Option<Stone>) {
+        self.stones[position.y][position.x] = stone;
+    }
+
+    fn is(&self, x: usize, y: usize, c: char) -> bool {
+        self.stones[y][x].as_ref().map(|stone| stone.char()) == Some(c)
+    }
+
+    fn count(&self, color: Color, kind: Option<Kind>) -> usize {
+        self.stones
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|stone| stone.color() == color && kind.is_none_or(|k| stone.kind() == k))
+            .count()
+    }
+
+    /// Checks the position could happen in a game
+    pub fn validate(&self) -> Result<(), SetupError> {
+        for color in [Color::Light, Color::Dark] {
+            if self.count(color, Some(Kind::King)) != 1 {
+                return Err(SetupError::KingCount);
+            }
+            if self.count(color, None) > 16 || self.count(color, Some(Kind::Pawn)) > 8 {
+                return Err(SetupError::TooManyPieces);
+            }
+        }
+
+        let back_ranks = [&self.stones[0], &self.stones[7]];
+        if back_ranks
+            .iter()
+            .any(|rank| rank.iter().flatten().any(|s| s.kind() == Kind::Pawn))
+        {
+            return Err(SetupError::PawnOnBackRank);
+        }
+
+        // king and rook squares of each castling right
+        let castling_squares = [
+            (4, 7, 'K', 7, 'R'),
+            (4, 7, 'K', 0, 'R'),
+            (4, 0, 'k', 7, 'r'),
+            (4, 0, 'k', 0, 'r'),
+        ];
+        for (allowed, (king_x, y, king, rook_x, rook)) in self.castling.iter().zip(castling_squares)
+        {
+            if *allowed && !(self.is(king_x, y, king) && self.is(rook_x, y, rook)) {
+                return Err(SetupError::CastlingRights);
+            }
+        }
+
+        if let Some(passant) = &self.passant {
+            // rank behind the pawn, the pawn and the square it came from
+            let (behind, pawn, origin, c) = match self.turn {
+                Turn::White => (2, 3, 1, 'p'),
+                Turn::Black => (5, 4, 6, 'P'),
+            };
+            let x = passant.x;
+            if passant.x > 7
+                || passant.y != behind
+                || !self.is(x, pawn, c)
+                || self.stones[behind][x].is_some()
+                || self.stones[origin][x].is_some()
+            {
+                return Err(SetupError::Passant);
+            }
+        }
+
+        if self.full_move_number == 0 {
+            return Err(SetupError::MoveNumber);
+        }
+
+        // the side that just moved can't have left its king in check
+        let mut opponent = self.clone();
+        opponent.turn = !self.turn;
+        opponent.passant = None;
+        let in_check = ChessBoard::new(&opponent.to_string())
+            .map(|board| board.is_in_check())
+            .unwrap_or(false);
+        if in_check {
+            return Err(SetupError::OpponentInCheck);
+        }
+
+        Ok(())
+    }
+
+    pub fn castling_string(&self) -> String {
+        let rights: String = CASTLING
+            .iter()
+            .zip(self.castling)
+            .filter(|(_, allowed)| *allowed)
+            .map(|(c, _)| *c)
+            .collect();
+
+        if rights.is_empty() {
+            "-".to_string()
+        } else {
+            rights
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        let ranks: Vec<String> = self
+            .stones
+            .iter()
+            .map(|rank| {
+                let mut text = String::new();
+                let mut empty = 0;
+                for stone in rank {
+                    match stone {
+                        Some(stone) => {
+                            if empty > 0 {
+                                text.push_str(&empty.to_string());
+                                empty = 0;
+                            }
+                            text.push(stone.char());
+                        }
+                        None => empty += 1,
+                    }
+                }
+                if empty > 0 {
+                    text.push_str(&empty.to_string());
+                }
+                text
+            })
+            .collect();
+
+        format!(
+            "{} {} {} {} {} {}",
+            ranks.join("/"),
+            if self.turn == Turn::White { "w" } else { "b" },
+            self.castling_string(),
+            self.passant
+                .as_ref()
+                .map(|passant| passant.to_string())
+                .unwrap_or("-".to_string()),
+            self.half_move_clock,
+            self.full_move_number
+        )
+    }
+}
+
+impl FromStr for Setup {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [stones, turn, castling, passant, half_move_clock, full_move_number] = fields[..]
+        else {
+            return Err(());
+        };
+
+        if stones.split('/').count() != 8 {
+            return Err(());
+        }
+        let turn = match turn {
+            "w" => Turn::White,
+            "b" => Turn::Black,
+            _ => return Err(()),
+        };
+        if castling != "-" && !castling.chars().all(|c| CASTLING.contains(&c)) {
+            return Err(());
+        }
+        let passant = match passant {
+            "-" => None,
+            square => {
+                let position = square.parse::<Position>()?;
+                if position.x > 7 || position.y > 7 {
+                    return Err(());
+                }
+                Some(position)
+            }
+        };
+
+        Ok(Self {
+            stones: fen_to_stones(stones).map_err(|_| ())?,
+            turn,
+            castling: CASTLING.map(|c| castling.contains(c)),
+            passant,
+            half_move_clock: half_move_clock.parse().map_err(|_| ())?,
+            full_move_number: full_move_number.parse().map_err(|_| ())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setup() {
+        let mut setup = Setup::start();
+        assert_eq!(setup.to_string(), DEFAULT_FEN);
+        assert_eq!(setup.validate(), Ok(()));
+
+        setup.set_stone(&"h1".parse().unwrap(), None);
+        assert_eq!(setup.validate(), Err(SetupError::CastlingRights));
+        setup.castling[0] = false;
+        assert_eq!(setup.castling_string(), "Qkq");
+        assert_eq!(setup.validate(), Ok(()));
+
+        let passant: Setup = "4k3/8/8/8/4Pp2/8/8/4K3 b - e3 0 1".parse().unwrap();
+        assert_eq!(passant.validate(), Ok(()));
+        let passant: Setup = "4k3/8/8/8/4Pp2/8/8/4K3 b - d3 0 1".parse().unwrap();
+        assert_eq!(passant.validate(), Err(SetupError::Passant));
+
+        let in_check: Setup = "4k3/8/8/8/8/8/4R3/4K3 w - - 0 1".parse().unwrap();
+        assert_eq!(in_check.validate(), Err(SetupError::OpponentInCheck));
+        assert_eq!(Setup::empty().validate(), Err(SetupError::KingCount));
+        assert!("8/8/8 w - - 0 1".parse::<Setup>().is_err());
+    }
+}
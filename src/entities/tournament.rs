@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TournamentFormat {
+    RoundRobin,
+    Swiss,
+}
+
+impl TournamentFormat {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TournamentFormat::RoundRobin => "Round robin",
+            TournamentFormat::Swiss => "Swiss",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TournamentState {
+    Registering,
+    Running,
+    Finished,
+}
+
+impl TournamentState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TournamentState::Registering => "Registering",
+            TournamentState::Running => "Running",
+            TournamentState::Finished => "Finished",
+        }
+    }
+}
+
+/// Sent to create a tournament, `rounds` is ignored for round robins which
+/// play everyone once
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NewTournament {
+    pub name: String,
+    pub format: TournamentFormat,
+    /// Like `5+3`
+    pub time_control: String,
+    pub rounds: u32,
+    #[serde(default)]
+    pub rated: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TournamentSummary {
+    pub id: String,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub time_control: String,
+    pub rounds: u32,
+    pub state: TournamentState,
+    pub players: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlayerView {
+    pub user_id: String,
+    pub name: String,
+}
+
+/// Game of a round, a pairing without black is a bye
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PairingView {
+    pub white: String,
+    pub black: Option<String>,
+    pub room: Option<String>,
+    /// Score like `1-0`, unset while the game is played
+    pub result: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub rank: usize,
+    pub user_id: String,
+    pub name: String,
+    pub points: f64,
+    pub buchholz: f64,
+    pub sonneborn_berger: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TournamentView {
+    pub id: String,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub time_control: String,
+    pub rounds: u32,
+    pub rated: bool,
+    pub state: TournamentState,
+    pub creator_id: String,
+    pub players: Vec<PlayerView>,
+    /// Pairings of the rounds played so far
+    pub pairings: Vec<Vec<PairingView>>,
+    /// Best first
+    pub standings: Vec<Standing>,
+}
+
+impl TournamentView {
+    pub fn is_registered(&self, user_id: &str) -> bool {
+        self.players.iter().any(|player| player.user_id == user_id)
+    }
+
+    fn rank_of(&self, user_id: &str) -> Option<usize> {
+        self.standings
+            .iter()
+            .find(|standing| standing.user_id == user_id)
+            .map(|standing| standing.rank)
+    }
+
+    /// Crosstable cell of a player in a round, like `w3 1`: the colour, the
+    /// rank of the opponent and the score, `+` for a bye
+    pub fn crosstable_cell(&self, user_id: &str, round: usize) -> String {
+        let Some(pairing) = self.pairings.get(round).and_then(|pairings| {
+            pairings
+                .iter()
+                .find(|p| p.white == user_id || p.black.as_deref() == Some(user_id))
+        }) else {
+            return String::new();
+        };
+
+        let Some(black) = pairing.black.as_deref() else {
+            return "+".to_string();
+        };
+
+        let (color, opponent) = if pairing.white == user_id {
+            ("w", black)
+        } else {
+            ("b", pairing.white.as_str())
+        };
+        let rank = self
+            .rank_of(opponent)
+            .map(|rank| rank.to_string())
+            .unwrap_or_default();
+        let score = match (pairing.result.as_deref(), color) {
+            (None, _) => "*",
+            (Some("1/2-1/2"), _) => "½",
+            (Some("1-0"), "w") | (Some("0-1"), "b") => "1",
+            _ => "0",
+        };
+
+        format!("{}{} {}", color, rank, score)
+    }
+}
@@ -1,12 +1,24 @@
-use leptos::{RwSignal, SignalGetUntracked, SignalUpdate, SignalWithUntracked};
+use std::cell::RefCell;
 
+use leptos::{RwSignal, SignalGetUntracked, SignalSet, SignalUpdate, SignalWithUntracked};
+
+use crate::entities::annotation::{Shape, ShapeColor};
+use crate::entities::chess_board::notation::typed_move;
 use crate::entities::chess_board::signals::{ChessBoardSignals, StoneSignal};
+use crate::entities::history::node_to_string;
 use crate::entities::position::Position;
 use crate::utils::class_list::ClassListExt;
 use crate::utils::elements::{self, mouse_position_in_bounding, query_selector};
-use crate::utils::events::{EventPositionExt, EventTargetExt};
+use crate::utils::events::{EventButtonExt, EventPositionExt, EventTargetExt};
 use crate::utils::style::StyleExt;
 
+thread_local! {
+    /// Square a right drag started on
+    static DRAWING_FROM: RefCell<Option<Position>> = const { RefCell::new(None) };
+    /// Square a left press started on, a click ending on another one was a drag
+    static PRESSED_AT: RefCell<Option<Position>> = const { RefCell::new(None) };
+}
+
 pub fn interaction_move<E>(event: E)
 where
     E: EventPositionExt,
@@ -21,8 +33,12 @@ where
 
 pub fn interaction_start<E>(chess_board_signals: ChessBoardSignals, event: E)
 where
-    E: EventPositionExt + EventTargetExt,
+    E: EventPositionExt + EventTargetExt + EventButtonExt,
 {
+    if event.is_secondary_button() {
+        return;
+    }
+
     let Some(piece) = event.target_element() else {
         log::error!("No target found to start interaction");
         return;
@@ -159,14 +175,11 @@ where
             return;
         }
 
-        if let Some(socket) = chess_board_signals.socket().get_untracked().as_ref() {
-            let msg = format!("/move {} {} {}", piece_data, old_pos, new_pos);
-
-            match socket.send_with_str(&msg) {
-                Ok(_) => {}
-                Err(err) => log::error!("error sending message: {:?}", err),
-            }
-        }
+        let msg = match chess_board_signals.last_seq() {
+            Some(seq) => format!("/move {} {} {} {}", piece_data, old_pos, new_pos, seq),
+            None => format!("/move {} {} {}", piece_data, old_pos, new_pos),
+        };
+        chess_board_signals.send_message(&msg);
 
         let old_pos = match old_pos.as_str() {
             "deleted" => None,
@@ -183,6 +196,110 @@ where
     }
 }
 
+/// Starts drawing an arrow or a circle with a right drag
+pub fn drawing_start(chess_board_signals: ChessBoardSignals, event: web_sys::MouseEvent) {
+    if !event.is_secondary_button() {
+        return;
+    }
+
+    let from = get_piece_position(chess_board_signals, event);
+    DRAWING_FROM.with(|drawing| drawing.replace(Some(from)));
+}
+
+/// Draws the shape of a right drag for the whole room, or erases it when it
+/// was already drawn. Shift draws in red, alt in blue and both in yellow.
+pub fn drawing_end(chess_board_signals: ChessBoardSignals, event: &web_sys::MouseEvent) {
+    if !event.is_secondary_button() {
+        return;
+    }
+    let Some(from) = DRAWING_FROM.with(|drawing| drawing.take()) else {
+        return;
+    };
+
+    let color = match (event.shift_key(), event.alt_key()) {
+        (true, true) => ShapeColor::Yellow,
+        (true, false) => ShapeColor::Red,
+        (false, true) => ShapeColor::Blue,
+        (false, false) => ShapeColor::Green,
+    };
+    let to = get_piece_position(chess_board_signals, event.clone());
+    let shape = Shape { color, from, to };
+    let node = chess_board_signals.room_status().with_untracked(|rs| {
+        rs.as_ref()
+            .map(|rs| rs.history().current)
+            .unwrap_or_default()
+    });
+
+    chess_board_signals.send_message(&format!(
+        "/shape {} {}",
+        node_to_string(node),
+        shape.to_string()
+    ));
+}
+
+/// Remembers the square of a left press to tell clicks from drags
+pub fn press_start(chess_board_signals: ChessBoardSignals, event: web_sys::MouseEvent) {
+    if event.is_secondary_button() {
+        return;
+    }
+
+    let position = get_piece_position(chess_board_signals, event);
+    PRESSED_AT.with(|pressed| pressed.replace(Some(position)));
+}
+
+/// Click to move, a first click selects a piece and a second one plays it on
+/// the square clicked. A click on another piece of the same side selects it.
+pub fn select_square(
+    chess_board_signals: ChessBoardSignals,
+    selected: RwSignal<Option<Position>>,
+    event: web_sys::MouseEvent,
+) {
+    let pressed = PRESSED_AT.with(|pressed| pressed.take());
+    // the trash is not part of the board
+    if event
+        .target_element()
+        .and_then(|target| target.closest(".trash").ok().flatten())
+        .is_some()
+    {
+        selected.set(None);
+        return;
+    }
+    let square = get_piece_position(chess_board_signals, event);
+    if pressed.as_ref() != Some(&square) {
+        selected.set(None);
+        return;
+    }
+
+    let chess_board = chess_board_signals.rules_board();
+    let stone = chess_board.stone_at(square.x, square.y).cloned();
+    let piece = selected
+        .get_untracked()
+        .and_then(|from| Some((chess_board.stone_at(from.x, from.y).cloned()?, from)));
+    let Some((piece, from)) = piece else {
+        selected.set(stone.map(|_| square));
+        return;
+    };
+
+    if from == square {
+        selected.set(None);
+        return;
+    }
+    if stone.as_ref().is_some_and(|s| s.color() == piece.color()) {
+        selected.set(Some(square));
+        return;
+    }
+
+    match typed_move(&chess_board, from, square.clone(), None) {
+        Ok(typed_move) => {
+            selected.set(None);
+            chess_board_signals.play(&typed_move);
+        }
+        // a piece it can't take gets selected instead
+        Err(_) if stone.is_some() => selected.set(Some(square)),
+        Err(_) => selected.set(None),
+    }
+}
+
 pub fn get_stone_signal(
     chess_board_signals: ChessBoardSignals,
     key: String,
@@ -17,37 +17,65 @@ cfg_if! {
         use crate::app::*;
         use leptos_actix::{generate_route_list, LeptosRoutes};
         use actix_web::{
-            middleware, App, HttpServer, get, post, error, web, Error, HttpRequest, HttpResponse, Responder
+            middleware, App, HttpServer, get, post, error, web, Error, HttpRequest, HttpResponse, Responder,
+            http::StatusCode,
         };
         use actix_web_actors::ws;
         use server::{
-            middlewares::cache_control::CacheControlInterceptor,
+            accounts::{self, AccountError, AccountRepository},
+            archive,
+            diagram,
+            middlewares::{
+                cache_control::CacheControlInterceptor,
+                csrf::{AllowedOrigins, CsrfProtection},
+            },
+            sessions::{now_secs, SessionPayload, Sessions},
             websockets::session::WsChessSession,
-            chess_server::ChessServer,
+            chess_server::{ChessServer, RoomBoard},
+            lobby::Lobby,
+            metrics::Metrics,
+            rate_limit::{trusted_proxies_from_env, IpRateLimiter, RateLimitConfig},
+            ratings,
+            telemetry,
+            tournaments::{self, director::TournamentDirector},
         };
+        use tracing_actix_web::TracingLogger;
         use actix::Addr;
         use actix::Actor;
 
         use std::env;
         use std::net::SocketAddr;
-        use std::sync::{atomic::AtomicUsize, Arc};
+        use std::sync::Arc;
         use std::path::Path;
-        use std::time::{SystemTime, UNIX_EPOCH};
 
         use futures::StreamExt;
         use serde::{Deserialize, Serialize};
 
-        use utils::SessionPayload;
+        use entities::session::SessionInfo;
 
         #[derive(Serialize, Deserialize)]
         struct PostSessionPayload {
             username: String
         }
 
+        /// Last room event seen by a reconnecting client
+        #[derive(Deserialize)]
+        struct ResumeParams {
+            room: Option<String>,
+            seq: Option<u64>,
+        }
+
         const MAX_SIZE: usize = 262_144; // max payload size is 256k
 
         #[post("/sessions")]
-        async fn create_session(req: HttpRequest, mut payload: web::Payload, srv: web::Data<Addr<ChessServer>>) -> impl Responder {
+        #[tracing::instrument(skip_all, fields(user_id = tracing::field::Empty))]
+        async fn create_session(
+            req: HttpRequest,
+            mut payload: web::Payload,
+            srv: web::Data<Addr<ChessServer>>,
+            repository: web::Data<Arc<dyn AccountRepository>>,
+            sessions: web::Data<Sessions>,
+        ) -> impl Responder {
             // payload is a stream of Bytes objects
             let mut body = web::BytesMut::new();
             while let Some(chunk) = payload.next().await {
@@ -62,37 +90,102 @@ cfg_if! {
             // payload is loaded, now we can deserialize serde-json
             let payload: PostSessionPayload = serde_json::from_slice::<PostSessionPayload>(&body)?;
 
-            let session_payload = if let Some(session_cookie) = req.cookie("session_token") {
-                let Ok(token) = utils::jwt::verified_decode::<SessionPayload>(&session_cookie.value().to_string()) else {
-                    return Ok(HttpResponse::Unauthorized().finish());
-                };
+            // an expired access token keeps its identity through the refresh token,
+            // unless the account logged out or changed its password since
+            let current_session = match sessions.from_request(&req) {
+                Some((session, _)) => Some(session),
+                None => match sessions.refresh_from_request(&req) {
+                    Some(session) => match accounts::is_session_current(repository.as_ref().as_ref(), &session).await {
+                        Ok(true) => Some(session),
+                        Ok(false) => None,
+                        Err(_) => return Ok(HttpResponse::ServiceUnavailable().finish()),
+                    },
+                    None => None,
+                },
+            };
+
+            // this is the only way to rename, registered usernames are reserved to their account
+            let account_id = current_session.as_ref().and_then(|s| s.account_id.as_deref());
+            match accounts::check_display_name(repository.as_ref().as_ref(), &payload.username, account_id).await {
+                Ok(()) => {}
+                Err(AccountError::UsernameTaken) => return Ok(HttpResponse::Conflict().finish()),
+                Err(_) => return Ok(HttpResponse::ServiceUnavailable().finish()),
+            }
 
+            let session_payload = if let Some(token) = current_session {
                 let chess_server_addr = srv.get_ref().clone();
 
                 chess_server_addr.do_send(
-                    chess_server::UserSync { id: token.claims().sub.clone(), name: payload.username.clone() }
+                    chess_server::UserSync { user_id: token.sub.clone(), name: payload.username.clone() }
                 );
 
                 SessionPayload {
-                    sub: token.claims().sub.clone(),
                     name: payload.username,
-                    iat: token.claims().iat,
+                    ..token
                 }
             } else {
-                let iat = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
                 SessionPayload {
                     sub: uuid::Uuid::new_v4().to_string(),
                     name: payload.username,
-                    iat: iat,
+                    iat: now_secs(),
+                    account_id: None,
+                    generation: 0,
+                    login_id: None,
                 }
             };
 
 
-            let session_token = utils::jwt::encode(session_payload).expect("Failed to encode JWT");
+            tracing::Span::current().record("user_id", session_payload.sub.as_str());
+
+            Ok(sessions.response(StatusCode::OK, &session_payload)) // <- send response
+        }
 
-            let session_cookie = actix_web::cookie::Cookie::build("session_token", session_token).path("/").finish();
+        /// Non sensitive part of the session, the cookies are not readable by
+        /// the client
+        #[get("/sessions/me")]
+        async fn session_info(req: HttpRequest, sessions: web::Data<Sessions>) -> impl Responder {
+            let Some((session, exp)) = sessions.from_request(&req) else {
+                return HttpResponse::Unauthorized().finish();
+            };
 
-            Ok(HttpResponse::Ok().cookie(session_cookie).finish()) // <- send response
+            HttpResponse::Ok().json(SessionInfo {
+                user_id: session.sub,
+                name: session.name,
+                logged_in: session.account_id.is_some(),
+                expires_in: exp.saturating_sub(now_secs()),
+            })
+        }
+
+        /// Trades the refresh token for a new token pair, refresh tokens of an
+        /// account are revoked by a logout or a password change
+        #[post("/sessions/refresh")]
+        async fn refresh_session(
+            req: HttpRequest,
+            repository: web::Data<Arc<dyn AccountRepository>>,
+            sessions: web::Data<Sessions>,
+        ) -> impl Responder {
+            let Some(session) = sessions.refresh_from_request(&req) else {
+                return HttpResponse::Unauthorized().finish();
+            };
+
+            match accounts::is_session_current(repository.as_ref().as_ref(), &session).await {
+                Ok(true) => sessions.response(StatusCode::OK, &session),
+                Ok(false) => HttpResponse::Unauthorized().finish(),
+                Err(_) => HttpResponse::ServiceUnavailable().finish(),
+            }
+        }
+
+        #[get("/metrics")]
+        async fn render_metrics(metrics: web::Data<Metrics>) -> impl Responder {
+            match metrics.render() {
+                Ok(body) => HttpResponse::Ok()
+                    .content_type("text/plain; version=0.0.4")
+                    .body(body),
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to render metrics");
+                    HttpResponse::InternalServerError().finish()
+                }
+            }
         }
 
         #[get("/style.css")]
@@ -103,31 +196,74 @@ cfg_if! {
         }
 
         /// Entry point for our websocket route
+        #[tracing::instrument(skip_all, fields(user_id = tracing::field::Empty))]
         async fn chess_route(
             req: HttpRequest,
             stream: web::Payload,
             srv: web::Data<Addr<ChessServer>>,
+            lobby: web::Data<Addr<Lobby>>,
+            resume: web::Query<ResumeParams>,
+            ip_limiter: web::Data<IpRateLimiter>,
+            limits: web::Data<RateLimitConfig>,
+            metrics: web::Data<Metrics>,
+            sessions: web::Data<Sessions>,
         ) -> Result<HttpResponse, Error> {
-            let Some(session_cookie) = req.cookie("session_token") else {
-                return Ok(HttpResponse::Unauthorized().finish());
-            };
-            let Ok(token) = utils::jwt::verified_decode::<SessionPayload>(&session_cookie.value().to_string()) else {
+            let Some((session, _)) = sessions.from_request(&req) else {
                 return Ok(HttpResponse::Unauthorized().finish());
             };
 
-            let username = token.claims().name.clone();
-            let id = token.claims().sub.clone();
+            let registered = session.account_id.is_some();
+            let username = session.name;
+            let user_id = session.sub;
+            tracing::Span::current().record("user_id", user_id.as_str());
+            let ResumeParams { room, seq } = resume.into_inner();
+            let resume = room.zip(seq);
+            let ip = ip_limiter.client_ip(
+                req.peer_addr().map(|addr| addr.ip()),
+                req.headers()
+                    .get("x-forwarded-for")
+                    .and_then(|value| value.to_str().ok()),
+            );
+            let limits = *limits.get_ref();
 
-            ws::start(
-                WsChessSession::new(srv.get_ref().clone(), id, username),
+            ws::WsResponseBuilder::new(
+                WsChessSession::new(
+                    srv.get_ref().clone(),
+                    lobby.get_ref().clone(),
+                    uuid::Uuid::new_v4().to_string(),
+                    user_id,
+                    username,
+                    registered,
+                    resume,
+                    ip,
+                    ip_limiter.into_inner(),
+                    limits,
+                    metrics.into_inner(),
+                ),
                 &req,
                 stream,
             )
+            .frame_size(limits.max_frame_size)
+            .start()
+        }
+
+        /// Websocket and API routes, served behind the cross site checks
+        fn api_routes(cfg: &mut web::ServiceConfig) {
+            cfg.route("/ws", web::get().to(chess_route))
+                .service(create_session)
+                .service(session_info)
+                .service(refresh_session)
+                .configure(accounts::routes::configure)
+                .configure(tournaments::routes::configure)
+                .configure(archive::routes::configure)
+                .configure(diagram::routes::configure)
+                .service(render_metrics)
+                .service(css);
         }
 
         #[actix_web::main]
         async fn main() -> std::io::Result<()> {
-            env_logger::init();
+            telemetry::init();
 
             // Setting this to None means we'll be using cargo-leptos and its env vars.
             let conf = get_configuration(None).await.unwrap();
@@ -139,35 +275,180 @@ cfg_if! {
             let routes = generate_route_list(|| view! { <App/> });
 
             // set up applications state
-            // keep a count of the number of visitors
-            let app_state = Arc::new(AtomicUsize::new(0));
+            // metrics also keep the count of the number of visitors
+            let metrics = Arc::new(Metrics::new().expect("Failed to register metrics"));
+
+            // websocket limits
+            let limits = RateLimitConfig::from_env();
+            let ip_limiter =
+                web::Data::new(IpRateLimiter::new(&limits, trusted_proxies_from_env()));
+
+            // ratings of the registered users, kept with their rated games
+            let rating_repository = ratings::repository_from_env().await;
+
+            // finished games, kept after their room is gone
+            let game_archive = archive::archive_from_env().await;
 
             // start chat server actor
-            let server = ChessServer::new(app_state.clone()).start();
+            let server = ChessServer::new(
+                metrics.clone(),
+                limits,
+                rating_repository.clone(),
+                game_archive.clone(),
+            )
+            .start();
+
+            // open seeks and pairing of the players looking for a game
+            let lobby = Lobby::new(server.clone(), rating_repository, metrics.clone()).start();
 
-            log::info!("starting HTTP server at http://0.0.0.0:{}", port);
+            // tournaments and the rooms of their rounds
+            let director = TournamentDirector::new(server.clone(), metrics.clone(), limits).start();
 
-            HttpServer::new(move || {
+            // signing keys and cookie attributes of the sessions
+            let sessions = match Sessions::from_env() {
+                Ok(sessions) => web::Data::new(sessions),
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to load the session keys");
+                    telemetry::shutdown();
+                    return Err(std::io::Error::other(e));
+                }
+            };
+
+            // origins allowed besides the page itself
+            let allowed_origins = AllowedOrigins::from_env();
+            let secure_cookies = sessions.config().secure;
+
+            // accounts storage, anonymous sessions don't need it
+            let account_repository = accounts::repository_from_env().await;
+
+            // rooms are rendered with their position, read from their last snapshot
+            let room_boards = {
+                let server = server.clone();
+                RoomBoards(Arc::new(move |name| {
+                    let server = server.clone();
+                    Box::pin(async move { server.send(RoomBoard { name }).await.ok().flatten() })
+                }))
+            };
+
+            tracing::info!("starting HTTP server at http://0.0.0.0:{}", port);
+
+            let result = HttpServer::new(move || {
                 let leptos_options = &conf.leptos_options;
                 let site_root = &leptos_options.site_root;
                 let routes = &routes;
+                let room_boards = room_boards.clone();
                 App::new()
-                    .app_data(web::Data::from(app_state.clone()))
+                    .app_data(web::Data::from(metrics.clone()))
                     .app_data(web::Data::new(server.clone()))
-                    .wrap(middleware::Logger::default())
+                    .app_data(web::Data::new(lobby.clone()))
+                    .app_data(web::Data::new(director.clone()))
+                    .app_data(ip_limiter.clone())
+                    .app_data(web::Data::new(limits))
+                    .app_data(web::Data::new(account_repository.clone()))
+                    .app_data(web::Data::new(game_archive.clone()))
+                    .app_data(sessions.clone())
+                    .wrap(CsrfProtection::new(allowed_origins.clone(), secure_cookies))
+                    .wrap(TracingLogger::default())
                     .wrap(CacheControlInterceptor)
-                    // websocket route
-                    .route("/ws", web::get().to(chess_route))
-                    .service(create_session)
-                    .service(css)
-                    .leptos_routes(leptos_options.to_owned(), routes.to_owned(), || view! { <App/> })
+                    .configure(api_routes)
+                    .leptos_routes_with_context(
+                        leptos_options.to_owned(),
+                        routes.to_owned(),
+                        move || provide_context(room_boards.clone()),
+                        || view! { <App/> },
+                    )
                     .service(Files::new("/", site_root).show_files_listing())
                     .wrap(middleware::Compress::default())
             })
             .workers(2)
             .bind(&addr)?
             .run()
-            .await
+            .await;
+
+            telemetry::shutdown();
+
+            result
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use actix_web::{cookie::Cookie, http::header, test};
+            use server::{
+                accounts::memory::InMemoryAccountRepository,
+                jwt::SigningKeys,
+                middlewares::csrf::{CSRF_COOKIE, CSRF_HEADER},
+                sessions::SessionConfig,
+            };
+
+            const HOST: &str = "chess.example.com";
+
+            /// Status of a request sent to the app wrapped like in `main`
+            async fn status(req: test::TestRequest) -> StatusCode {
+                let keys = SigningKeys::new([("test", b"secret".as_slice())]).unwrap();
+                let sessions = Sessions::new(keys, SessionConfig::default());
+                let repository: Arc<dyn AccountRepository> = Arc::new(InMemoryAccountRepository::new());
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(repository))
+                        .app_data(web::Data::new(sessions))
+                        .wrap(CsrfProtection::new(AllowedOrigins::default(), false))
+                        .wrap(TracingLogger::default())
+                        .wrap(CacheControlInterceptor)
+                        .configure(api_routes)
+                        .wrap(middleware::Compress::default()),
+                )
+                .await;
+
+                test::call_service(&app, req.insert_header((header::HOST, HOST)).to_request())
+                    .await
+                    .status()
+            }
+
+            fn post(uri: &str, token: Option<&str>) -> test::TestRequest {
+                let mut req = test::TestRequest::post()
+                    .uri(uri)
+                    .insert_header((header::ORIGIN, "https://chess.example.com"))
+                    .cookie(Cookie::new(CSRF_COOKIE, "token"));
+                if let Some(token) = token {
+                    req = req.insert_header((CSRF_HEADER, token));
+                }
+                req
+            }
+
+            #[actix_web::test]
+            async fn test_csrf_token_required() {
+                let login = serde_json::json!({ "username": "alice", "password": "password123" });
+
+                assert_eq!(
+                    status(post("/accounts/login", None).set_json(&login)).await,
+                    StatusCode::FORBIDDEN
+                );
+                assert_eq!(
+                    status(post("/sessions/refresh", None)).await,
+                    StatusCode::FORBIDDEN
+                );
+
+                // with the token the requests reach the handlers
+                assert_eq!(
+                    status(post("/accounts/login", Some("token")).set_json(&login)).await,
+                    StatusCode::UNAUTHORIZED
+                );
+                assert_eq!(
+                    status(post("/sessions/refresh", Some("token"))).await,
+                    StatusCode::UNAUTHORIZED
+                );
+            }
+
+            #[actix_web::test]
+            async fn test_cross_origin_websocket() {
+                let upgrade = test::TestRequest::get()
+                    .uri("/ws")
+                    .insert_header((header::UPGRADE, "websocket"))
+                    .insert_header((header::ORIGIN, "https://evil.example.com"));
+
+                assert_eq!(status(upgrade).await, StatusCode::FORBIDDEN);
+            }
         }
     }
     else {
@@ -0,0 +1,97 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+
+use super::{Account, AccountError, AccountRepository};
+
+/// Accounts lost on restart, used without a database and in tests
+#[derive(Default)]
+pub struct InMemoryAccountRepository {
+    accounts: Mutex<HashMap<String, Account>>,
+    /// Account and login ids
+    revoked_logins: Mutex<HashSet<(String, String)>>,
+}
+
+impl InMemoryAccountRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AccountRepository for InMemoryAccountRepository {
+    async fn create(&self, account: &Account) -> Result<(), AccountError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let username = account.username.to_lowercase();
+
+        if accounts
+            .values()
+            .any(|a| a.username.to_lowercase() == username)
+        {
+            return Err(AccountError::UsernameTaken);
+        }
+        accounts.insert(account.id.clone(), account.clone());
+
+        Ok(())
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<Account>, AccountError> {
+        let username = username.to_lowercase();
+
+        Ok(self
+            .accounts
+            .lock()
+            .unwrap()
+            .values()
+            .find(|a| a.username.to_lowercase() == username)
+            .cloned())
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<Account>, AccountError> {
+        Ok(self.accounts.lock().unwrap().get(id).cloned())
+    }
+
+    async fn update_password_hash(
+        &self,
+        id: &str,
+        password_hash: &str,
+    ) -> Result<(), AccountError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let account = accounts.get_mut(id).ok_or(AccountError::NotFound)?;
+        account.password_hash = password_hash.to_string();
+
+        Ok(())
+    }
+
+    async fn revoke_sessions(&self, id: &str) -> Result<u64, AccountError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let account = accounts.get_mut(id).ok_or(AccountError::NotFound)?;
+        account.session_generation += 1;
+        self.revoked_logins
+            .lock()
+            .unwrap()
+            .retain(|(account_id, _)| account_id != id);
+
+        Ok(account.session_generation)
+    }
+
+    async fn revoke_login(&self, id: &str, login_id: &str) -> Result<(), AccountError> {
+        self.revoked_logins
+            .lock()
+            .unwrap()
+            .insert((id.to_string(), login_id.to_string()));
+
+        Ok(())
+    }
+
+    async fn is_login_revoked(&self, id: &str, login_id: &str) -> Result<bool, AccountError> {
+        Ok(self
+            .revoked_logins
+            .lock()
+            .unwrap()
+            .contains(&(id.to_string(), login_id.to_string())))
+    }
+}
@@ -0,0 +1,339 @@
+//! Optional registered accounts.
+//!
+//! Anonymous sessions keep working, an account only ties a session to a
+//! username and password so the identity survives cleared cookies. Accounts
+//! are stored in Postgres when `DATABASE_URL` is set and in memory otherwise.
+
+pub mod memory;
+pub mod password;
+pub mod postgres;
+pub mod routes;
+
+use std::{env, sync::Arc};
+
+use async_trait::async_trait;
+
+use self::{memory::InMemoryAccountRepository, postgres::PgAccountRepository};
+use super::sessions::{now_secs, SessionPayload};
+
+const USERNAME_MIN_LEN: usize = 3;
+const USERNAME_MAX_LEN: usize = 32;
+const PASSWORD_MIN_LEN: usize = 8;
+const PASSWORD_MAX_LEN: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct Account {
+    pub id: String,
+    pub username: String,
+    pub password_hash: String,
+    /// Seconds since the epoch
+    pub created_at: u64,
+    /// Bumped by password changes and logouts of every session, sessions
+    /// logged in before can't be refreshed anymore
+    pub session_generation: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AccountError {
+    InvalidUsername,
+    WeakPassword,
+    UsernameTaken,
+    InvalidCredentials,
+    NotFound,
+    Storage(String),
+}
+
+impl AccountError {
+    /// Message shown to the user
+    pub fn as_str(&self) -> &str {
+        match self {
+            AccountError::InvalidUsername => "Usernames have 3 to 32 letters, digits, `-` or `_`",
+            AccountError::WeakPassword => "Passwords need at least 8 characters",
+            AccountError::UsernameTaken => "This username is already registered",
+            AccountError::InvalidCredentials => "Wrong username or password",
+            AccountError::NotFound => "Account not found",
+            AccountError::Storage(_) => "Accounts are not available right now",
+        }
+    }
+}
+
+/// Storage of the accounts
+#[async_trait]
+pub trait AccountRepository: Send + Sync {
+    /// Fails with `UsernameTaken` when the username is used, ignoring case
+    async fn create(&self, account: &Account) -> Result<(), AccountError>;
+
+    /// Case insensitive lookup
+    async fn find_by_username(&self, username: &str) -> Result<Option<Account>, AccountError>;
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<Account>, AccountError>;
+
+    async fn update_password_hash(&self, id: &str, password_hash: &str)
+        -> Result<(), AccountError>;
+
+    /// Moves the account to its next sessions generation and returns it,
+    /// the logins revoked one by one before are forgotten
+    async fn revoke_sessions(&self, id: &str) -> Result<u64, AccountError>;
+
+    /// Ends a single login of the account
+    async fn revoke_login(&self, id: &str, login_id: &str) -> Result<(), AccountError>;
+
+    async fn is_login_revoked(&self, id: &str, login_id: &str) -> Result<bool, AccountError>;
+}
+
+/// Postgres when `DATABASE_URL` is set, in memory otherwise
+pub async fn repository_from_env() -> Arc<dyn AccountRepository> {
+    match env::var("DATABASE_URL") {
+        Ok(url) => match PgAccountRepository::connect(&url).await {
+            Ok(repository) => {
+                tracing::info!("Storing accounts in Postgres");
+                Arc::new(repository)
+            }
+            Err(e) => panic!("Failed to connect to the accounts database: {:?}", e),
+        },
+        Err(_) => {
+            tracing::warn!("DATABASE_URL is not set, accounts are kept in memory");
+            Arc::new(InMemoryAccountRepository::new())
+        }
+    }
+}
+
+fn validate_username(username: &str) -> Result<(), AccountError> {
+    let valid_len = (USERNAME_MIN_LEN..=USERNAME_MAX_LEN).contains(&username.chars().count());
+    let valid_chars = username
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+    if valid_len && valid_chars {
+        Ok(())
+    } else {
+        Err(AccountError::InvalidUsername)
+    }
+}
+
+fn validate_password(password: &str) -> Result<(), AccountError> {
+    if (PASSWORD_MIN_LEN..=PASSWORD_MAX_LEN).contains(&password.chars().count()) {
+        Ok(())
+    } else {
+        Err(AccountError::WeakPassword)
+    }
+}
+
+pub async fn register(
+    repository: &dyn AccountRepository,
+    username: &str,
+    password: &str,
+) -> Result<Account, AccountError> {
+    validate_username(username)?;
+    validate_password(password)?;
+
+    if repository.find_by_username(username).await?.is_some() {
+        return Err(AccountError::UsernameTaken);
+    }
+
+    let account = Account {
+        id: uuid::Uuid::new_v4().to_string(),
+        username: username.to_string(),
+        password_hash: password::hash(password).await?,
+        created_at: now_secs(),
+        session_generation: 0,
+    };
+    repository.create(&account).await?;
+
+    Ok(account)
+}
+
+pub async fn login(
+    repository: &dyn AccountRepository,
+    username: &str,
+    password: &str,
+) -> Result<Account, AccountError> {
+    let Some(account) = repository.find_by_username(username).await? else {
+        // spend the same time as a wrong password so usernames can't be probed
+        let _ = password::verify(password, password::DUMMY_HASH).await;
+        return Err(AccountError::InvalidCredentials);
+    };
+
+    if password::verify(password, &account.password_hash).await? {
+        Ok(account)
+    } else {
+        Err(AccountError::InvalidCredentials)
+    }
+}
+
+/// Changes the password and logs out every session of the account, returns
+/// the account to log the current one in again
+pub async fn change_password(
+    repository: &dyn AccountRepository,
+    id: &str,
+    current_password: &str,
+    new_password: &str,
+) -> Result<Account, AccountError> {
+    validate_password(new_password)?;
+
+    let account = repository
+        .find_by_id(id)
+        .await?
+        .ok_or(AccountError::NotFound)?;
+    if !password::verify(current_password, &account.password_hash).await? {
+        return Err(AccountError::InvalidCredentials);
+    }
+
+    let password_hash = password::hash(new_password).await?;
+    repository.update_password_hash(id, &password_hash).await?;
+    let session_generation = repository.revoke_sessions(id).await?;
+
+    Ok(Account {
+        password_hash,
+        session_generation,
+        ..account
+    })
+}
+
+/// Registered usernames are reserved to their account, anyone else choosing
+/// one as display name is refused
+pub async fn check_display_name(
+    repository: &dyn AccountRepository,
+    name: &str,
+    account_id: Option<&str>,
+) -> Result<(), AccountError> {
+    match repository.find_by_username(name).await? {
+        Some(account) if Some(account.id.as_str()) != account_id => {
+            Err(AccountError::UsernameTaken)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether a session may still be refreshed, anonymous sessions always can
+/// and account sessions until their login is logged out or the account moves
+/// to another generation
+pub async fn is_session_current(
+    repository: &dyn AccountRepository,
+    session: &SessionPayload,
+) -> Result<bool, AccountError> {
+    let Some(account_id) = &session.account_id else {
+        return Ok(true);
+    };
+
+    let current_generation = repository
+        .find_by_id(account_id)
+        .await?
+        .is_some_and(|account| account.session_generation == session.generation);
+    if !current_generation {
+        return Ok(false);
+    }
+
+    match &session.login_id {
+        Some(login_id) => Ok(!repository.is_login_revoked(account_id, login_id).await?),
+        None => Ok(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_accounts() {
+        let repository = InMemoryAccountRepository::new();
+
+        assert_eq!(
+            register(&repository, "a", "password123").await.err(),
+            Some(AccountError::InvalidUsername)
+        );
+        assert_eq!(
+            register(&repository, "alice", "short").await.err(),
+            Some(AccountError::WeakPassword)
+        );
+
+        let account = register(&repository, "alice", "password123").await.unwrap();
+        assert_eq!(
+            register(&repository, "Alice", "password123").await.err(),
+            Some(AccountError::UsernameTaken)
+        );
+
+        assert_eq!(
+            login(&repository, "alice", "wrong password").await.err(),
+            Some(AccountError::InvalidCredentials)
+        );
+        assert_eq!(
+            login(&repository, "ALICE", "password123").await.unwrap().id,
+            account.id
+        );
+
+        assert_eq!(
+            check_display_name(&repository, "ALICE", None).await,
+            Err(AccountError::UsernameTaken)
+        );
+        assert_eq!(
+            check_display_name(&repository, "alice", Some("another account")).await,
+            Err(AccountError::UsernameTaken)
+        );
+        assert_eq!(
+            check_display_name(&repository, "alice", Some(&account.id)).await,
+            Ok(())
+        );
+        assert_eq!(check_display_name(&repository, "bob", None).await, Ok(()));
+
+        let session = |account: &Account| SessionPayload {
+            sub: account.id.clone(),
+            name: account.username.clone(),
+            iat: now_secs(),
+            account_id: Some(account.id.clone()),
+            generation: account.session_generation,
+            login_id: Some(uuid::Uuid::new_v4().to_string()),
+        };
+        let old_session = session(&account);
+
+        // a logout ends its own login only
+        let other_session = session(&account);
+        let login_id = other_session.login_id.as_deref().unwrap();
+        repository
+            .revoke_login(&account.id, login_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            is_session_current(&repository, &other_session).await,
+            Ok(false)
+        );
+        assert_eq!(
+            is_session_current(&repository, &old_session).await,
+            Ok(true)
+        );
+
+        let account = change_password(&repository, &account.id, "password123", "new password")
+            .await
+            .unwrap();
+        assert!(login(&repository, "alice", "password123").await.is_err());
+        assert!(login(&repository, "alice", "new password").await.is_ok());
+
+        // sessions logged in before the change are over, the new one is not
+        assert_eq!(
+            is_session_current(&repository, &old_session).await,
+            Ok(false)
+        );
+        let new_session = session(&account);
+        assert_eq!(
+            is_session_current(&repository, &new_session).await,
+            Ok(true)
+        );
+        repository.revoke_sessions(&account.id).await.unwrap();
+        assert_eq!(
+            is_session_current(&repository, &new_session).await,
+            Ok(false)
+        );
+
+        let anonymous = SessionPayload {
+            account_id: None,
+            ..new_session
+        };
+        assert_eq!(is_session_current(&repository, &anonymous).await, Ok(true));
+    }
+
+    #[test]
+    fn test_dummy_hash() {
+        // an invalid hash would make unknown usernames fail faster
+        assert!(argon2::PasswordHash::new(password::DUMMY_HASH).is_ok());
+    }
+}
@@ -0,0 +1,43 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+use super::AccountError;
+
+/// Hash of a random password, verified against when the username does not
+/// exist so logins take as long either way
+pub const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$c29tZXNhbHRzb21lc2FsdA$0xNq0M4JUqAqzDMmfyaU4pS1GXUSqMEtBJLKyShk2v0";
+
+/// Argon2id hash in PHC format, hashing is slow on purpose so it runs on the
+/// blocking thread pool
+pub async fn hash(password: &str) -> Result<String, AccountError> {
+    let password = password.to_string();
+
+    actix_web::web::block(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .map_err(|e| AccountError::Storage(e.to_string()))?
+    .map_err(|e| AccountError::Storage(e.to_string()))
+}
+
+pub async fn verify(password: &str, password_hash: &str) -> Result<bool, AccountError> {
+    let password = password.to_string();
+    let password_hash = password_hash.to_string();
+
+    actix_web::web::block(move || {
+        let Ok(parsed) = PasswordHash::new(&password_hash) else {
+            return false;
+        };
+        Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok()
+    })
+    .await
+    .map_err(|e| AccountError::Storage(e.to_string()))
+}
@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use sqlx::{postgres::PgPoolOptions, PgPool, Row};
+
+use super::{Account, AccountError, AccountRepository};
+
+const CREATE_ACCOUNTS: &str = "
+    CREATE TABLE IF NOT EXISTS accounts (
+        id TEXT PRIMARY KEY,
+        username TEXT NOT NULL,
+        password_hash TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        session_generation BIGINT NOT NULL DEFAULT 0
+    )";
+
+/// Accounts created before sessions could be revoked
+const ADD_SESSION_GENERATION: &str =
+    "ALTER TABLE accounts ADD COLUMN IF NOT EXISTS session_generation BIGINT NOT NULL DEFAULT 0";
+
+/// Logins ended by a logout, until the account moves to another generation
+const CREATE_REVOKED_LOGINS: &str = "
+    CREATE TABLE IF NOT EXISTS revoked_logins (
+        account_id TEXT NOT NULL,
+        login_id TEXT NOT NULL,
+        PRIMARY KEY (account_id, login_id)
+    )";
+
+const CREATE_USERNAME_INDEX: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS accounts_username_idx ON accounts (LOWER(username))";
+
+/// Postgres error code of a unique constraint violation
+const UNIQUE_VIOLATION: &str = "23505";
+
+pub struct PgAccountRepository {
+    pool: PgPool,
+}
+
+impl PgAccountRepository {
+    /// Connects and creates the accounts table when missing
+    pub async fn connect(url: &str) -> Result<Self, AccountError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(url)
+            .await
+            .map_err(storage_error)?;
+
+        sqlx::query(CREATE_ACCOUNTS)
+            .execute(&pool)
+            .await
+            .map_err(storage_error)?;
+        sqlx::query(ADD_SESSION_GENERATION)
+            .execute(&pool)
+            .await
+            .map_err(storage_error)?;
+        sqlx::query(CREATE_USERNAME_INDEX)
+            .execute(&pool)
+            .await
+            .map_err(storage_error)?;
+        sqlx::query(CREATE_REVOKED_LOGINS)
+            .execute(&pool)
+            .await
+            .map_err(storage_error)?;
+
+        Ok(Self { pool })
+    }
+}
+
+fn storage_error(e: sqlx::Error) -> AccountError {
+    tracing::error!(error = %e, "Accounts database error");
+    AccountError::Storage(e.to_string())
+}
+
+fn account_from_row(row: &sqlx::postgres::PgRow) -> Result<Account, AccountError> {
+    Ok(Account {
+        id: row.try_get("id").map_err(storage_error)?,
+        username: row.try_get("username").map_err(storage_error)?,
+        password_hash: row.try_get("password_hash").map_err(storage_error)?,
+        created_at: row.try_get::<i64, _>("created_at").map_err(storage_error)? as u64,
+        session_generation: row
+            .try_get::<i64, _>("session_generation")
+            .map_err(storage_error)? as u64,
+    })
+}
+
+#[async_trait]
+impl AccountRepository for PgAccountRepository {
+    async fn create(&self, account: &Account) -> Result<(), AccountError> {
+        let result = sqlx::query(
+            "INSERT INTO accounts (id, username, password_hash, created_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&account.id)
+        .bind(&account.username)
+        .bind(&account.password_hash)
+        .bind(account.created_at as i64)
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(UNIQUE_VIOLATION) => {
+                Err(AccountError::UsernameTaken)
+            }
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<Account>, AccountError> {
+        sqlx::query("SELECT * FROM accounts WHERE LOWER(username) = LOWER($1)")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?
+            .as_ref()
+            .map(account_from_row)
+            .transpose()
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<Account>, AccountError> {
+        sqlx::query("SELECT * FROM accounts WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?
+            .as_ref()
+            .map(account_from_row)
+            .transpose()
+    }
+
+    async fn update_password_hash(
+        &self,
+        id: &str,
+        password_hash: &str,
+    ) -> Result<(), AccountError> {
+        let result = sqlx::query("UPDATE accounts SET password_hash = $1 WHERE id = $2")
+            .bind(password_hash)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(AccountError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_sessions(&self, id: &str) -> Result<u64, AccountError> {
+        let row = sqlx::query(
+            "UPDATE accounts SET session_generation = session_generation + 1 WHERE id = $1 RETURNING session_generation",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(storage_error)?
+        .ok_or(AccountError::NotFound)?;
+
+        sqlx::query("DELETE FROM revoked_logins WHERE account_id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        Ok(row
+            .try_get::<i64, _>("session_generation")
+            .map_err(storage_error)? as u64)
+    }
+
+    async fn revoke_login(&self, id: &str, login_id: &str) -> Result<(), AccountError> {
+        sqlx::query(
+            "INSERT INTO revoked_logins (account_id, login_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(id)
+        .bind(login_id)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    async fn is_login_revoked(&self, id: &str, login_id: &str) -> Result<bool, AccountError> {
+        let row =
+            sqlx::query("SELECT 1 FROM revoked_logins WHERE account_id = $1 AND login_id = $2")
+                .bind(id)
+                .bind(login_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(storage_error)?;
+
+        Ok(row.is_some())
+    }
+}
@@ -0,0 +1,193 @@
+//! `/accounts` endpoints, every successful login replaces the session cookies
+//! with ones bound to the account.
+
+use std::sync::Arc;
+
+use actix_web::{http::StatusCode, post, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+
+use super::{Account, AccountError, AccountRepository};
+use crate::server::{
+    responses,
+    sessions::{now_secs, SessionPayload, Sessions},
+};
+
+type Repository = web::Data<Arc<dyn AccountRepository>>;
+
+#[derive(Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+fn error_response(error: AccountError) -> HttpResponse {
+    let status = match error {
+        AccountError::InvalidUsername | AccountError::WeakPassword => StatusCode::BAD_REQUEST,
+        AccountError::UsernameTaken => StatusCode::CONFLICT,
+        AccountError::InvalidCredentials | AccountError::NotFound => StatusCode::UNAUTHORIZED,
+        AccountError::Storage(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    responses::error(status, error.as_str())
+}
+
+/// Answers with session cookies logged in to `account`
+fn logged_in(sessions: &Sessions, status: StatusCode, account: &Account) -> HttpResponse {
+    let payload = SessionPayload {
+        sub: account.id.clone(),
+        name: account.username.clone(),
+        iat: now_secs(),
+        account_id: Some(account.id.clone()),
+        generation: account.session_generation,
+        login_id: Some(uuid::Uuid::new_v4().to_string()),
+    };
+
+    sessions.response(status, &payload)
+}
+
+#[post("/accounts")]
+#[tracing::instrument(skip_all, fields(account_id = tracing::field::Empty))]
+async fn register(
+    repository: Repository,
+    sessions: web::Data<Sessions>,
+    body: web::Json<Credentials>,
+) -> HttpResponse {
+    match super::register(repository.as_ref().as_ref(), &body.username, &body.password).await {
+        Ok(account) => {
+            tracing::Span::current().record("account_id", account.id.as_str());
+            tracing::info!("Account registered");
+            logged_in(&sessions, StatusCode::CREATED, &account)
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+#[post("/accounts/login")]
+#[tracing::instrument(skip_all, fields(account_id = tracing::field::Empty))]
+async fn login(
+    repository: Repository,
+    sessions: web::Data<Sessions>,
+    body: web::Json<Credentials>,
+) -> HttpResponse {
+    match super::login(repository.as_ref().as_ref(), &body.username, &body.password).await {
+        Ok(account) => {
+            tracing::Span::current().record("account_id", account.id.as_str());
+            logged_in(&sessions, StatusCode::OK, &account)
+        }
+        Err(e) => {
+            tracing::info!(error = ?e, "Failed login");
+            error_response(e)
+        }
+    }
+}
+
+/// Name of the anonymous session given after a logout, until the user picks
+/// a new one
+const GUEST_NAME: &str = "Guest";
+
+/// Answers with the cookies of a new anonymous session
+fn guest_session(sessions: &Sessions) -> HttpResponse {
+    let payload = SessionPayload {
+        sub: uuid::Uuid::new_v4().to_string(),
+        name: GUEST_NAME.to_string(),
+        iat: now_secs(),
+        account_id: None,
+        generation: 0,
+        login_id: None,
+    };
+
+    sessions.response(StatusCode::OK, &payload)
+}
+
+/// Account session of the access cookie, or of the refresh cookie once the
+/// access token expired
+fn account_session(req: &HttpRequest, sessions: &Sessions) -> Option<SessionPayload> {
+    sessions
+        .from_request(req)
+        .map(|(session, _)| session)
+        .or_else(|| sessions.refresh_from_request(req))
+        .filter(|session| session.account_id.is_some())
+}
+
+/// Starts a new anonymous session, the login of the account this browser
+/// used can't be refreshed anymore while its other sessions go on
+#[post("/accounts/logout")]
+async fn logout(
+    req: HttpRequest,
+    repository: Repository,
+    sessions: web::Data<Sessions>,
+) -> HttpResponse {
+    if let Some(SessionPayload {
+        account_id: Some(account_id),
+        login_id: Some(login_id),
+        ..
+    }) = account_session(&req, &sessions)
+    {
+        if let Err(e) = repository.revoke_login(&account_id, &login_id).await {
+            return error_response(e);
+        }
+    }
+
+    guest_session(&sessions)
+}
+
+/// Like a logout, for every session of the account on every device
+#[post("/accounts/logout/all")]
+async fn logout_all(
+    req: HttpRequest,
+    repository: Repository,
+    sessions: web::Data<Sessions>,
+) -> HttpResponse {
+    if let Some(account_id) = account_session(&req, &sessions).and_then(|s| s.account_id) {
+        match repository.revoke_sessions(&account_id).await {
+            Ok(_) | Err(AccountError::NotFound) => {}
+            Err(e) => return error_response(e),
+        }
+    }
+
+    guest_session(&sessions)
+}
+
+#[post("/accounts/password")]
+#[tracing::instrument(skip_all, fields(account_id = tracing::field::Empty))]
+async fn change_password(
+    req: HttpRequest,
+    repository: Repository,
+    sessions: web::Data<Sessions>,
+    body: web::Json<PasswordChange>,
+) -> HttpResponse {
+    let Some(account_id) = sessions
+        .from_request(&req)
+        .and_then(|(session, _)| session.account_id)
+    else {
+        return error_response(AccountError::NotFound);
+    };
+    tracing::Span::current().record("account_id", account_id.as_str());
+
+    match super::change_password(
+        repository.as_ref().as_ref(),
+        &account_id,
+        &body.current_password,
+        &body.new_password,
+    )
+    .await
+    {
+        // the other sessions are over, this one continues in the new generation
+        Ok(account) => logged_in(&sessions, StatusCode::OK, &account),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(register)
+        .service(login)
+        .service(logout)
+        .service(logout_all)
+        .service(change_password);
+}
@@ -0,0 +1,49 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use super::{ArchiveError, GameArchive};
+use crate::entities::archive::GameRecord;
+
+/// Games lost on restart, used without a database and in tests
+#[derive(Debug, Default)]
+pub struct InMemoryGameArchive {
+    games: Mutex<Vec<GameRecord>>,
+}
+
+impl InMemoryGameArchive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl GameArchive for InMemoryGameArchive {
+    async fn store(&self, game: &GameRecord) -> Result<(), ArchiveError> {
+        self.games.lock().unwrap().push(game.clone());
+
+        Ok(())
+    }
+
+    async fn find(&self, id: &str) -> Result<Option<GameRecord>, ArchiveError> {
+        Ok(self
+            .games
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|game| game.id == id)
+            .cloned())
+    }
+
+    async fn games_of(&self, user_id: &str) -> Result<Vec<GameRecord>, ArchiveError> {
+        Ok(self
+            .games
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|game| game.white_id == user_id || game.black_id == user_id)
+            .cloned()
+            .collect())
+    }
+}
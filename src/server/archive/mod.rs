@@ -0,0 +1,250 @@
+//! Archive of the finished games.
+//!
+//! A room reports its game when it ends and the chess server stores it with
+//! the players, the result, the moves with the position after each one, the
+//! PGN and the timestamps, so the game outlives its room. Like accounts,
+//! games are stored in Postgres when `DATABASE_URL` is set and in memory
+//! otherwise.
+
+pub mod memory;
+pub mod postgres;
+pub mod routes;
+
+use std::{env, fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use self::{memory::InMemoryGameArchive, postgres::PgGameArchive};
+use super::room::DEFAULT_FEN;
+use crate::entities::{
+    archive::{utc_date, GameRecord},
+    game::{Termination, TimeControl},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    Storage(String),
+}
+
+/// Storage of the finished games
+#[async_trait]
+pub trait GameArchive: Debug + Send + Sync {
+    async fn store(&self, game: &GameRecord) -> Result<(), ArchiveError>;
+
+    async fn find(&self, id: &str) -> Result<Option<GameRecord>, ArchiveError>;
+
+    /// Games of a user, most recent first
+    async fn games_of(&self, user_id: &str) -> Result<Vec<GameRecord>, ArchiveError>;
+}
+
+/// Postgres when `DATABASE_URL` is set, in memory otherwise
+pub async fn archive_from_env() -> Arc<dyn GameArchive> {
+    match env::var("DATABASE_URL") {
+        Ok(url) => match PgGameArchive::connect(&url).await {
+            Ok(archive) => Arc::new(archive),
+            Err(e) => panic!("Failed to connect to the games database: {:?}", e),
+        },
+        Err(_) => Arc::new(InMemoryGameArchive::new()),
+    }
+}
+
+/// Filter of the games of a user, from the query string of
+/// `/api/users/{id}/games`
+#[derive(Deserialize, Debug, Default)]
+pub struct GameFilter {
+    /// `win`, `loss` or `draw` for the user
+    pub result: Option<String>,
+    /// `white` or `black`
+    pub color: Option<String>,
+    /// Part of the name, or the id, of the opponent
+    pub opponent: Option<String>,
+}
+
+impl GameFilter {
+    pub fn matches(&self, game: &GameRecord, user_id: &str) -> bool {
+        let given = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_lowercase)
+        };
+
+        if let Some(result) = given(&self.result) {
+            if game.outcome_for(user_id) != Some(result.as_str()) {
+                return false;
+            }
+        }
+
+        if let Some(color) = given(&self.color) {
+            let side = if game.white_id == user_id {
+                "white"
+            } else {
+                "black"
+            };
+            if side != color {
+                return false;
+            }
+        }
+
+        if let Some(opponent) = given(&self.opponent) {
+            let Some((id, name)) = game.opponent_of(user_id) else {
+                return false;
+            };
+            if id != opponent && !name.to_lowercase().contains(&opponent) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+fn pgn_termination(termination: Termination) -> &'static str {
+    match termination {
+        Termination::Checkmate => "normal",
+        Termination::Timeout => "time forfeit",
+        Termination::Abandonment => "abandoned",
+        Termination::Forfeit => "emergency",
+    }
+}
+
+/// PGN of an archived game, games that did not start from the initial
+/// position carry it in a `FEN` tag
+pub fn pgn(game: &GameRecord) -> String {
+    let result = game.game_result();
+    let score = result.map(|result| result.score()).unwrap_or("*");
+    let event = if game.rated {
+        "Rated game"
+    } else {
+        "Casual game"
+    };
+    let time_control = game
+        .time_control
+        .as_deref()
+        .and_then(|tc| tc.parse::<TimeControl>().ok())
+        .map(|tc| format!("{}+{}", tc.base_ms() / 1000, tc.increment_ms() / 1000))
+        .unwrap_or("-".to_string());
+
+    let mut tags = vec![
+        ("Event", event.to_string()),
+        ("Site", game.room.clone()),
+        ("Date", utc_date(game.started_at)),
+        ("White", game.white_name.clone()),
+        ("Black", game.black_name.clone()),
+        ("Result", score.to_string()),
+        ("TimeControl", time_control),
+    ];
+    if let Some(result) = result {
+        tags.push((
+            "Termination",
+            pgn_termination(result.termination).to_string(),
+        ));
+    }
+    if game.start_fen != DEFAULT_FEN {
+        tags.push(("SetUp", "1".to_string()));
+        tags.push(("FEN", game.start_fen.clone()));
+    }
+
+    let mut pgn: String = tags
+        .into_iter()
+        .map(|(tag, value)| format!("[{} \"{}\"]\n", tag, value.replace('"', "'")))
+        .collect();
+    pgn.push('\n');
+
+    let mut fields = game.start_fen.split_whitespace().skip(1);
+    let mut white_to_move = fields.next() != Some("b");
+    let mut number = fields
+        .nth(3)
+        .and_then(|n| n.parse::<u32>().ok())
+        .unwrap_or(1);
+
+    let mut words = vec![];
+    for (i, recorded) in game.moves.iter().enumerate() {
+        if white_to_move {
+            words.push(format!("{}.", number));
+        } else if i == 0 {
+            words.push(format!("{}...", number));
+        }
+        words.push(recorded.san.clone());
+
+        if !white_to_move {
+            number += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+    words.push(score.to_string());
+    pgn.push_str(&words.join(" "));
+    pgn.push('\n');
+
+    pgn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::archive::RecordedMove;
+
+    fn game() -> GameRecord {
+        let moves = ["e4", "e5", "Qh5"]
+            .iter()
+            .map(|san| RecordedMove {
+                san: san.to_string(),
+                fen: String::new(),
+            })
+            .collect();
+
+        GameRecord {
+            id: "g1".to_string(),
+            room: "main".to_string(),
+            white_id: "w".to_string(),
+            white_name: "Alice".to_string(),
+            black_id: "b".to_string(),
+            black_name: "Bob".to_string(),
+            result: "0-1 timeout".to_string(),
+            rated: false,
+            time_control: Some("5+3".to_string()),
+            start_fen: DEFAULT_FEN.to_string(),
+            moves,
+            pgn: String::new(),
+            final_fen: String::new(),
+            started_at: 1_700_000_000,
+            finished_at: 1_700_000_600,
+        }
+    }
+
+    #[test]
+    fn test_pgn() {
+        assert_eq!(
+            pgn(&game()),
+            "[Event \"Casual game\"]\n[Site \"main\"]\n[Date \"2023.11.14\"]\n\
+             [White \"Alice\"]\n[Black \"Bob\"]\n[Result \"0-1\"]\n\
+             [TimeControl \"300+3\"]\n[Termination \"time forfeit\"]\n\n\
+             1. e4 e5 2. Qh5 0-1\n"
+        );
+
+        let mut from_position = game();
+        from_position.start_fen = "8/8/8/8/8/8/8/K6k b - - 0 12".to_string();
+        assert!(pgn(&from_position).ends_with(
+            "[SetUp \"1\"]\n[FEN \"8/8/8/8/8/8/8/K6k b - - 0 12\"]\n\n12... e4 13. e5 Qh5 0-1\n"
+        ));
+    }
+
+    #[test]
+    fn test_filter() {
+        let game = game();
+        let filter = |result: &str, color: &str, opponent: &str| GameFilter {
+            result: Some(result.to_string()),
+            color: Some(color.to_string()),
+            opponent: Some(opponent.to_string()),
+        };
+
+        assert!(GameFilter::default().matches(&game, "w"));
+        assert!(filter("win", "black", "ali").matches(&game, "b"));
+        assert!(filter("loss", "", "").matches(&game, "w"));
+        assert!(!filter("win", "", "").matches(&game, "w"));
+        assert!(!filter("", "white", "").matches(&game, "b"));
+        assert!(!filter("", "", "carol").matches(&game, "b"));
+    }
+}
@@ -0,0 +1,151 @@
+use async_trait::async_trait;
+use sqlx::{postgres::PgPoolOptions, PgPool, Row};
+
+use super::{ArchiveError, GameArchive};
+use crate::entities::archive::{GameRecord, RecordedMove};
+
+const CREATE_GAMES: &str = "
+    CREATE TABLE IF NOT EXISTS games (
+        id TEXT PRIMARY KEY,
+        room TEXT NOT NULL,
+        white_id TEXT NOT NULL,
+        white_name TEXT NOT NULL,
+        black_id TEXT NOT NULL,
+        black_name TEXT NOT NULL,
+        result TEXT NOT NULL,
+        rated BOOLEAN NOT NULL,
+        time_control TEXT,
+        start_fen TEXT NOT NULL,
+        moves JSONB NOT NULL,
+        pgn TEXT NOT NULL,
+        final_fen TEXT NOT NULL,
+        started_at BIGINT NOT NULL,
+        finished_at BIGINT NOT NULL
+    )";
+
+const CREATE_GAMES_WHITE_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS games_white_idx ON games (white_id, finished_at)";
+
+const CREATE_GAMES_BLACK_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS games_black_idx ON games (black_id, finished_at)";
+
+#[derive(Debug)]
+pub struct PgGameArchive {
+    pool: PgPool,
+}
+
+impl PgGameArchive {
+    /// Connects and creates the games table when missing
+    pub async fn connect(url: &str) -> Result<Self, ArchiveError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(url)
+            .await
+            .map_err(storage_error)?;
+
+        for query in [
+            CREATE_GAMES,
+            CREATE_GAMES_WHITE_INDEX,
+            CREATE_GAMES_BLACK_INDEX,
+        ] {
+            sqlx::query(query)
+                .execute(&pool)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        Ok(Self { pool })
+    }
+}
+
+fn storage_error(e: sqlx::Error) -> ArchiveError {
+    tracing::error!(error = %e, "Games database error");
+    ArchiveError::Storage(e.to_string())
+}
+
+fn game_from_row(row: &sqlx::postgres::PgRow) -> Result<GameRecord, ArchiveError> {
+    let text = |name: &str| row.try_get::<String, _>(name).map_err(storage_error);
+    let secs = |name: &str| {
+        row.try_get::<i64, _>(name)
+            .map(|secs| secs as u64)
+            .map_err(storage_error)
+    };
+    let moves = row
+        .try_get::<serde_json::Value, _>("moves")
+        .map_err(storage_error)?;
+
+    Ok(GameRecord {
+        id: text("id")?,
+        room: text("room")?,
+        white_id: text("white_id")?,
+        white_name: text("white_name")?,
+        black_id: text("black_id")?,
+        black_name: text("black_name")?,
+        result: text("result")?,
+        rated: row.try_get("rated").map_err(storage_error)?,
+        time_control: row.try_get("time_control").map_err(storage_error)?,
+        start_fen: text("start_fen")?,
+        moves: serde_json::from_value::<Vec<RecordedMove>>(moves)
+            .map_err(|_| ArchiveError::Storage("invalid moves in games".to_string()))?,
+        pgn: text("pgn")?,
+        final_fen: text("final_fen")?,
+        started_at: secs("started_at")?,
+        finished_at: secs("finished_at")?,
+    })
+}
+
+#[async_trait]
+impl GameArchive for PgGameArchive {
+    async fn store(&self, game: &GameRecord) -> Result<(), ArchiveError> {
+        sqlx::query(
+            "INSERT INTO games (id, room, white_id, white_name, black_id, black_name, result, \
+             rated, time_control, start_fen, moves, pgn, final_fen, started_at, finished_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+        )
+        .bind(&game.id)
+        .bind(&game.room)
+        .bind(&game.white_id)
+        .bind(&game.white_name)
+        .bind(&game.black_id)
+        .bind(&game.black_name)
+        .bind(&game.result)
+        .bind(game.rated)
+        .bind(&game.time_control)
+        .bind(&game.start_fen)
+        .bind(serde_json::to_value(&game.moves).unwrap_or_default())
+        .bind(&game.pgn)
+        .bind(&game.final_fen)
+        .bind(game.started_at as i64)
+        .bind(game.finished_at as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    async fn find(&self, id: &str) -> Result<Option<GameRecord>, ArchiveError> {
+        sqlx::query("SELECT * FROM games WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?
+            .as_ref()
+            .map(game_from_row)
+            .transpose()
+    }
+
+    async fn games_of(&self, user_id: &str) -> Result<Vec<GameRecord>, ArchiveError> {
+        sqlx::query(
+            "SELECT * FROM games WHERE white_id = $1 OR black_id = $1 \
+             ORDER BY finished_at DESC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(storage_error)?
+        .iter()
+        .map(game_from_row)
+        .collect()
+    }
+}
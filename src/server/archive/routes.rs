@@ -0,0 +1,112 @@
+//! `/api/games` and `/api/users/{id}/games` endpoints. Reading is open to
+//! everyone, a rematch is offered by a player of the game and starts when
+//! the other one asks for it too.
+
+use std::sync::Arc;
+
+use actix::Addr;
+use actix_web::{get, http::StatusCode, post, web, HttpRequest, HttpResponse};
+
+use super::{GameArchive, GameFilter};
+use crate::{
+    entities::archive::Rematch,
+    server::{
+        accounts::AccountRepository,
+        chess_server::{ChessServer, OfferRematch},
+        responses::{self, error},
+        sessions::Sessions,
+    },
+};
+
+type Archive = web::Data<Arc<dyn GameArchive>>;
+
+fn unavailable() -> HttpResponse {
+    responses::unavailable("Games")
+}
+
+fn not_found() -> HttpResponse {
+    error(StatusCode::NOT_FOUND, "Game not found")
+}
+
+#[get("/api/games/{id}")]
+async fn show(archive: Archive, id: web::Path<String>) -> HttpResponse {
+    match archive.find(&id).await {
+        Ok(Some(game)) => HttpResponse::Ok().json(game),
+        Ok(None) => not_found(),
+        Err(_) => unavailable(),
+    }
+}
+
+#[get("/api/users/{id}/games")]
+async fn games_of(
+    archive: Archive,
+    id: web::Path<String>,
+    filter: web::Query<GameFilter>,
+) -> HttpResponse {
+    match archive.games_of(&id).await {
+        Ok(games) => {
+            let games: Vec<_> = games
+                .into_iter()
+                .filter(|game| filter.matches(game, &id))
+                .collect();
+            HttpResponse::Ok().json(games)
+        }
+        Err(_) => unavailable(),
+    }
+}
+
+/// Offers a rematch with the colours swapped, the game starts in a new room
+/// once the opponent asks for it too
+#[post("/api/games/{id}/rematch")]
+#[tracing::instrument(skip_all, fields(game_id = %id))]
+async fn rematch(
+    req: HttpRequest,
+    archive: Archive,
+    accounts: web::Data<Arc<dyn AccountRepository>>,
+    server: web::Data<Addr<ChessServer>>,
+    sessions: web::Data<Sessions>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    let Some((session, _)) = sessions.from_request(&req) else {
+        return error(StatusCode::UNAUTHORIZED, "No session");
+    };
+
+    let game = match archive.find(&id).await {
+        Ok(Some(game)) => game,
+        Ok(None) => return not_found(),
+        Err(_) => return unavailable(),
+    };
+
+    if game.side_of(&session.sub).is_none() {
+        return error(
+            StatusCode::FORBIDDEN,
+            "Only the players can ask for a rematch",
+        );
+    }
+
+    // players with an account have it as user id
+    let (white_registered, black_registered) = match (
+        accounts.find_by_id(&game.white_id).await,
+        accounts.find_by_id(&game.black_id).await,
+    ) {
+        (Ok(white), Ok(black)) => (white.is_some(), black.is_some()),
+        _ => return unavailable(),
+    };
+
+    let msg = OfferRematch {
+        game,
+        user_id: session.sub,
+        white_registered,
+        black_registered,
+    };
+    match server.send(msg).await {
+        Ok(Ok(None)) => HttpResponse::Accepted().json(Rematch { room: None }),
+        Ok(Ok(Some(room))) => HttpResponse::Created().json(Rematch { room: Some(room) }),
+        Ok(Err(message)) => error(StatusCode::TOO_MANY_REQUESTS, message),
+        Err(_) => unavailable(),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(show).service(games_of).service(rematch);
+}
@@ -1,26 +1,46 @@
-//! `ChessServer` is an actor. It maintains list of connection client session.
-//! And manages available rooms. Peers send messages to other peers in same
-//! room through `ChessServer`.
+//! `ChessServer` is an actor. It maintains list of connection client session
+//! and the registry of rooms, each room is a `RoomActor` of its own. The
+//! server only routes sessions to their room, once a session is in a room it
+//! talks to the room directly.
+//!
+//! A user can have several sessions open at once, one per tab or device, each
+//! in its own room. The user is shown away in a room once its last session in
+//! that room is gone.
+//!
+//! Rooms are spread over a few arbiters so busy rooms do not stall the others.
+//! When a room actor dies the server restarts it from the last snapshot the
+//! room reported.
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use actix::prelude::*;
+use tracing::{field, Span};
 
 use crate::entities::{
-    chess_board::{self, enums::CastlePosition, turns::Turn, ChessBoard, ChessBoardBuilder},
-    position::Position,
-    stone::Stone,
+    archive::GameRecord,
+    chess_board::turns::Turn,
+    game::{GameResult, Outcome, Termination, TimeControl},
 };
 
-use super::websockets::session::WsChessSession;
+use super::{
+    archive::GameArchive,
+    diagram::Diagram,
+    metrics::Metrics,
+    rate_limit::RateLimitConfig,
+    ratings::RatingRepository,
+    room::{self, Arrival, CurrentDiagram, Room, RoomActor, RoomSnapshot},
+    sessions::now_secs,
+    telemetry::traced_handlers,
+    websockets::session::WsChessSession,
+};
+
+#[cfg(feature = "gif")]
+use super::diagram::animation::Animation;
 
 /// Chat server sends this messages to session
 #[derive(Message)]
@@ -33,9 +53,16 @@ pub struct Message(pub String);
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Connect {
+    /// Session id, unique for every socket
     pub id: String,
+    /// Id of the user owning the session
+    pub user_id: String,
     pub name: String,
+    /// Logged in to an account
+    pub registered: bool,
     pub addr: Addr<WsChessSession>,
+    /// Room and last event sequence seen by the client before reconnecting
+    pub resume: Option<(String, u64)>,
 }
 
 /// Session is disconnected
@@ -45,16 +72,6 @@ pub struct Disconnect {
     pub id: String,
 }
 
-/// Send message to specific room
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct ClientMessage {
-    /// Id of the client session
-    pub id: String,
-    /// Peer message
-    pub msg: String,
-}
-
 /// Join room, if room does not exists create new one.
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -72,336 +89,404 @@ pub struct Join {
     pub trash: Option<String>,
 }
 
+/// Open a room forked from another one and move the session into it
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct Move {
+pub struct ForkRoom {
     pub id: String,
-    pub piece: String,
-    pub from: String,
-    pub to: String,
+    pub name: String,
+    pub room: Room,
 }
 
+/// Board and trash of a room, to render it before the socket connects
 #[derive(Message)]
-#[rtype(result = "()")]
-pub struct Reset {
-    pub id: String,
+#[rtype(result = "Option<(String, String)>")]
+pub struct RoomBoard {
+    pub name: String,
 }
 
-#[derive(Message, Clone)]
-#[rtype(result = "()")]
-pub struct Undo {
-    pub id: String,
+/// Position shown in a room, `None` when there is no such room
+#[derive(Message)]
+#[rtype(result = "Option<Diagram>")]
+pub struct RoomDiagram {
+    pub name: String,
 }
 
-#[derive(Message, Clone)]
-#[rtype(result = "()")]
-pub struct Redo {
-    pub id: String,
+/// Moves played in a room, `None` when there is no such room
+#[cfg(feature = "gif")]
+#[derive(Message)]
+#[rtype(result = "Option<Animation>")]
+pub struct RoomAnimation {
+    pub name: String,
 }
 
+/// User changed its name, applied to all its sessions
 #[derive(Message, Clone)]
 #[rtype(result = "()")]
 pub struct UserSync {
-    pub id: String,
+    pub user_id: String,
+    pub name: String,
+}
+
+/// Tells a session which room actor it should talk to
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RoomJoined {
+    pub name: String,
+    pub addr: Addr<RoomActor>,
+}
+
+/// Player paired in the lobby or in a tournament
+#[derive(Clone, Debug)]
+pub struct Player {
+    /// Session that posted or accepted the seek, it is moved to the new room.
+    /// Without one every session of the user is told the room is ready.
+    pub session_id: Option<String>,
+    pub user_id: String,
     pub name: String,
+    pub registered: bool,
 }
 
+/// Create a room for two paired players, seated and with their clock
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct StartGame {
+    pub room: String,
+    pub white: Player,
+    pub black: Player,
+    pub time_control: Option<TimeControl>,
+    pub rated: bool,
+    /// Told when the game ends, the room is kept until then
+    pub watcher: Option<Recipient<GameFinished>>,
+}
+
+/// Game of a room ended, sent by the room to the server which archives it
 #[derive(Message, Clone)]
 #[rtype(result = "()")]
-pub struct Options {
-    pub id: String,
-    pub validation: bool,
-    pub sync: bool,
+pub struct GameFinished {
+    pub room: String,
+    pub result: GameResult,
+    pub record: GameRecord,
+}
+
+/// A player of an archived game asks for a rematch with the colours swapped.
+/// The opponent is told, the game starts once it asks too and the room is
+/// returned.
+#[derive(Message)]
+#[rtype(result = "Result<Option<String>, &'static str>")]
+pub struct OfferRematch {
+    pub game: GameRecord,
+    pub user_id: String,
+    /// Whether the players have an account, rated games need both
+    pub white_registered: bool,
+    pub black_registered: bool,
+}
+
+/// Rematch asked by a player, waiting for its opponent
+#[derive(Debug)]
+struct RematchOffer {
+    user_id: String,
+    offered_at: Instant,
+}
+
+/// Time the opponent has to take a rematch offer
+const REMATCH_OFFER_TTL: Duration = Duration::from_secs(600);
+
+/// Draw by forfeit of a paired game the server could not start
+fn not_started(
+    room: &str,
+    white: &Player,
+    black: &Player,
+    time_control: Option<TimeControl>,
+) -> GameFinished {
+    let result = GameResult::new(Outcome::Draw, Termination::Forfeit);
+    let now = now_secs();
+
+    GameFinished {
+        room: room.to_string(),
+        result,
+        record: GameRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            room: room.to_string(),
+            white_id: white.user_id.clone(),
+            white_name: white.name.clone(),
+            black_id: black.user_id.clone(),
+            black_name: black.name.clone(),
+            result: result.to_string(),
+            rated: false,
+            time_control: time_control.map(|tc| tc.to_string()),
+            start_fen: room::DEFAULT_FEN.to_string(),
+            moves: vec![],
+            pgn: String::new(),
+            final_fen: room::DEFAULT_FEN.to_string(),
+            started_at: now,
+            finished_at: now,
+        },
+    }
+}
+
+/// Name of a new room for a game between two players
+pub fn new_game_room() -> String {
+    format!("game-{}", &uuid::Uuid::new_v4().simple().to_string()[..8])
+}
+
+/// Latest state of a room, sent by the room after every change
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SaveSnapshot {
+    pub name: String,
+    pub snapshot: RoomSnapshot,
+}
+
+#[derive(Debug)]
+struct RoomEntry {
+    addr: Addr<RoomActor>,
+    snapshot: RoomSnapshot,
+    created_by: Option<String>,
+    empty_at: Option<Instant>,
 }
 
 /// `ChessServer` manages chat rooms and responsible for coordinating chat session.
-///
-/// Implementation is very naïve.
 #[derive(Debug)]
 pub struct ChessServer {
-    sessions: HashMap<String, User>,
-    rooms: HashMap<String, Room>,
-    visitor_count: Arc<AtomicUsize>,
+    users: HashMap<String, User>,
+    sessions: HashMap<String, Session>,
+    rooms: HashMap<String, RoomEntry>,
+    arbiters: Vec<ArbiterHandle>,
+    next_arbiter: usize,
+    metrics: Arc<Metrics>,
+    limits: RateLimitConfig,
+    ratings: Arc<dyn RatingRepository>,
+    archive: Arc<dyn GameArchive>,
+    /// Rooms whose game result someone waits for, they are not removed when
+    /// empty
+    game_watchers: HashMap<String, Recipient<GameFinished>>,
+    /// Pending rematch offers, by archived game id
+    rematch_offers: HashMap<String, RematchOffer>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+/// An open socket of a user
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub id: String,
+    pub user_id: String,
+    pub registered: bool,
+    pub addr: Addr<WsChessSession>,
+    pub room: String,
+}
+
+#[derive(Clone, Debug)]
 pub struct User {
     pub id: String,
     pub name: String,
-    pub addr: Addr<WsChessSession>,
+    pub registered: bool,
+    /// Room the user joined last, new sessions start there
     pub current_room: String,
+    /// Rooms where the user is shown away, with the time it left them
+    pub away_rooms: HashMap<String, Instant>,
+    /// Set when the last session of the user closed
     pub disconected_at: Option<Instant>,
 }
 
 impl User {
-    pub fn new(
-        id: String,
-        name: String,
-        addr: Addr<WsChessSession>,
-        current_room: String,
-        disconected_at: Option<Instant>,
-    ) -> Self {
+    pub fn new(id: String, name: String, registered: bool, current_room: String) -> Self {
         Self {
             id,
             name,
-            addr,
+            registered,
             current_room,
-            disconected_at,
+            away_rooms: HashMap::new(),
+            disconected_at: None,
         }
     }
-
-    pub fn to_string(&self) -> String {
-        let status = if self.disconected_at.is_some() {
-            "away"
-        } else {
-            "online"
-        };
-        format!("{}:{}:{}", self.id, self.name, status)
-    }
 }
 
-#[derive(Clone, Debug)]
-pub struct MoveResult {
-    pub from: Option<Position>,
-    pub to: Option<Position>,
-    pub stone: Stone,
-    pub chess_board_move: chess_board::enums::Move,
-    pub msg: String,
-    pub previous_fen: String,
-    pub previous_trash: String,
-    pub current_fen: String,
-    pub current_trash: String,
-}
+impl ChessServer {
+    pub fn new(
+        metrics: Arc<Metrics>,
+        limits: RateLimitConfig,
+        ratings: Arc<dyn RatingRepository>,
+        archive: Arc<dyn GameArchive>,
+    ) -> ChessServer {
+        let arbiters_count = env::var("ROOM_ARBITERS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .or_else(|| std::thread::available_parallelism().map(|n| n.get()).ok())
+            .unwrap_or(1)
+            .max(1);
+
+        let arbiters = (0..arbiters_count)
+            .map(|_| Arbiter::new().handle())
+            .collect();
 
-#[derive(Debug)]
-pub struct Room {
-    original_fen: String,
-    current_fen: String,
-    chess_board: ChessBoard,
-    moves: Vec<MoveResult>,
-    sessions: HashMap<String, User>,
-    original_trash: String,
-    trash: String,
-    empty_at: Option<Instant>,
-    current_move_index: Option<usize>,
-}
-
-impl Room {
-    pub fn new(fen: Option<String>, trash: Option<String>) -> Result<Self, ()> {
-        let fen =
-            fen.unwrap_or("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string());
-        let trash = trash.unwrap_or("".to_string());
-        let chess_board = ChessBoardBuilder::new()
-            .fen(&fen)
-            .deleted_stones(&trash)
-            .validation(false)
-            .sync(true)
-            .build()
-            .map_err(|_| ())?;
-
-        Ok(Self {
-            original_fen: fen.clone(),
-            current_fen: fen.clone(),
-            chess_board,
-            moves: vec![],
+        ChessServer {
+            users: HashMap::new(),
             sessions: HashMap::new(),
-            trash: trash.clone(),
-            original_trash: trash,
-            empty_at: Some(Instant::now()),
-            current_move_index: None,
-        })
-    }
-
-    pub fn sessions(&self) -> &HashMap<String, User> {
-        &self.sessions
-    }
-
-    pub fn insert_session(&mut self, id: String, user: User) {
-        self.sessions.insert(id, user);
-    }
-
-    pub fn remove_session(&mut self, id: &str) -> Option<User> {
-        self.sessions.remove(id)
-    }
-
-    pub fn disconnect_session(&mut self, id: &str) {
-        if let Some(user) = self.sessions.get_mut(id) {
-            user.disconected_at = Some(Instant::now());
+            rooms: HashMap::new(),
+            arbiters,
+            next_arbiter: 0,
+            metrics,
+            limits,
+            ratings,
+            archive,
+            game_watchers: HashMap::new(),
+            rematch_offers: HashMap::new(),
         }
     }
+}
 
-    pub fn connect_session(&mut self, id: &str, addr: Addr<WsChessSession>) {
-        if let Some(user) = self.sessions.get_mut(id) {
-            user.disconected_at = None;
-            user.addr = addr;
+impl ChessServer {
+    fn send_message_to_session(&self, id: &str, message: &str) {
+        if let Some(session) = self.sessions.get(id) {
+            session.addr.do_send(Message(message.to_owned()));
         }
     }
 
-    pub fn usernames(&self) -> Vec<String> {
+    fn sessions_of<'a>(&'a self, user_id: &'a str) -> impl Iterator<Item = &'a Session> + 'a {
         self.sessions
             .values()
-            .map(|user| user.to_string())
-            .collect()
+            .filter(move |session| session.user_id == user_id)
     }
 
-    pub fn options_string(&self) -> String {
-        let validation = self.chess_board.validation;
-        let sync = self.chess_board.sync;
-        let mut str = String::new();
+    /// Refresh the users and rooms gauges
+    fn sync_metrics(&self) {
+        let away = self
+            .users
+            .values()
+            .filter(|user| user.disconected_at.is_some())
+            .count();
 
-        if validation {
-            str.push_str(" validation");
-        }
+        self.metrics.set_users(self.users.len() - away, away);
+        self.metrics.rooms.set(self.rooms.len() as i64);
+    }
 
-        if sync {
-            str.push_str(" sync");
+    /// Check the room caps before a user creates a new room
+    fn check_room_limits(&self, user_id: &str) -> Result<(), &'static str> {
+        if self.rooms.len() >= self.limits.max_rooms {
+            return Err("The server reached the maximum number of rooms");
         }
 
-        str.trim().to_string()
-    }
-
-    pub fn push_move(&mut self, result: MoveResult) {
-        self.moves.push(result);
-        match self.current_move_index {
-            Some(_) => {
-                self.current_move_index = Some(self.moves.len() - 1);
-            }
-            None => {
-                self.current_move_index = Some(0);
-            }
+        let created_by_user = self
+            .rooms
+            .values()
+            .filter(|room| room.created_by.as_deref() == Some(user_id))
+            .count();
+        if created_by_user >= self.limits.max_rooms_per_user {
+            return Err("You reached the maximum number of rooms");
         }
-    }
 
-    pub fn get_move(&self, idx: &usize) -> Option<MoveResult> {
-        self.moves.get(idx.clone()).cloned()
+        Ok(())
     }
 
-    pub fn undo_move(&mut self) -> Result<MoveResult, ()> {
-        if let Some(i) = self.current_move_index {
-            let result = self.get_move(&i);
+    /// Start a room actor on the next arbiter
+    fn spawn_room(&mut self, name: &str, room: Room, ctx: &mut Context<Self>) -> Addr<RoomActor> {
+        let arbiter = &self.arbiters[self.next_arbiter % self.arbiters.len()];
+        self.next_arbiter = self.next_arbiter.wrapping_add(1);
 
-            if i > 0 {
-                self.current_move_index = Some(i - 1);
-            } else {
-                self.current_move_index = None;
-            }
+        let name = name.to_string();
+        let registry = ctx.address();
+        let metrics = self.metrics.clone();
+        let ratings = self.ratings.clone();
 
-            result.ok_or(())
-        } else {
-            Err(())
-        }
+        RoomActor::start_in_arbiter(arbiter, move |_| {
+            RoomActor::new(name, room, registry, metrics, ratings)
+        })
     }
 
-    pub fn redo_move(&mut self) -> Result<MoveResult, ()> {
-        if self.current_move_index.map(|i| i as isize).unwrap_or(-1) < self.moves.len() as isize - 1
-        {
-            match self.current_move_index {
-                Some(i) => {
-                    self.current_move_index = Some(i + 1);
-                }
-                None => {
-                    self.current_move_index = Some(0);
-                }
-            }
-
-            self.get_move(&self.current_move_index.unwrap()).ok_or(())
-        } else {
-            Err(())
+    /// Address of a running room, the room is created when it does not exist
+    /// and restarted when its actor died
+    fn room_addr(
+        &mut self,
+        name: &str,
+        fen: Option<String>,
+        trash: Option<String>,
+        created_by: Option<&str>,
+        ctx: &mut Context<Self>,
+    ) -> Result<Addr<RoomActor>, ()> {
+        match self.rooms.get(name) {
+            Some(entry) if entry.addr.connected() => return Ok(entry.addr.clone()),
+            Some(_) => return self.restart_room(name, ctx),
+            None => {}
         }
-    }
 
-    pub fn truncate_moves_on_current_move(&mut self) {
-        if let Some(i) = self.current_move_index {
-            self.moves.truncate(i + 1);
-        } else {
-            self.moves.truncate(0);
-        }
+        let room = Room::new(fen, trash)?;
+
+        Ok(self.insert_room(name, room, created_by, ctx))
     }
-}
 
-impl ChessServer {
-    pub fn new(visitor_count: Arc<AtomicUsize>) -> ChessServer {
-        // default room
-        let mut rooms = HashMap::new();
-        rooms.insert(
-            "main".to_owned(),
-            Room::new(None, None).expect("Failed to create default room"),
+    /// Start and register a new room
+    fn insert_room(
+        &mut self,
+        name: &str,
+        room: Room,
+        created_by: Option<&str>,
+        ctx: &mut Context<Self>,
+    ) -> Addr<RoomActor> {
+        let snapshot = room.snapshot();
+        let addr = self.spawn_room(name, room, ctx);
+
+        self.rooms.insert(
+            name.to_string(),
+            RoomEntry {
+                addr: addr.clone(),
+                snapshot,
+                created_by: created_by.map(String::from),
+                empty_at: Some(Instant::now()),
+            },
         );
 
-        ChessServer {
-            sessions: HashMap::new(),
-            rooms,
-            visitor_count,
-        }
-    }
-}
-
-impl ChessServer {
-    /// Send message to all users in the room
-    fn send_message(&self, room_name: &str, message: &str, skip_id: Option<&str>) {
-        let skip_id = skip_id.unwrap_or("");
-        if let Some(sessions) = self.rooms.get(room_name).map(|m| m.sessions()) {
-            for (id, _) in sessions {
-                if *id != skip_id {
-                    if let Some(user) = self.sessions.get(id) {
-                        user.addr.do_send(Message(message.to_owned()));
-                    }
-                }
-            }
-        }
+        addr
     }
 
-    fn send_message_to_session(&self, id: &str, message: &str) {
-        if let Some(user) = self.sessions.get(id) {
-            user.addr.do_send(Message(message.to_owned()));
-        }
-    }
+    /// Start a new actor for the room from its last snapshot, with the users
+    /// that were in it
+    fn restart_room(&mut self, name: &str, ctx: &mut Context<Self>) -> Result<Addr<RoomActor>, ()> {
+        let Some(entry) = self.rooms.get(name) else {
+            return Err(());
+        };
 
-    fn disconnect_session(&mut self, id: &str) -> Option<&mut User> {
-        if let Some(user) = self.sessions.get_mut(id) {
-            user.disconected_at = Some(Instant::now());
+        tracing::warn!(room = %name, "Room actor stopped, restarting it from its last state");
+        self.metrics.room_restarts.inc();
 
-            if let Some(room) = self.rooms.get_mut(&user.current_room) {
-                room.disconnect_session(id);
+        let mut room = Room::from_snapshot(&entry.snapshot).or_else(|_| Room::new(None, None))?;
+        let sessions: Vec<Session> = self
+            .sessions
+            .values()
+            .filter(|session| session.room == name)
+            .cloned()
+            .collect();
+        for user in self.users.values() {
+            if user.away_rooms.contains_key(name) {
+                room.insert_away_member(&user.id, &user.name, user.registered);
+            }
+        }
+        for session in &sessions {
+            if let Some(user) = self.users.get(&session.user_id) {
+                room.insert_session(session, &user.name);
             }
-
-            Some(user)
-        } else {
-            None
         }
-    }
-
-    fn remove_session(&mut self, id: &str) -> Option<User> {
-        let user = self.sessions.remove(id);
 
-        if let Some(u) = user.as_ref() {
-            if let Some(room) = self.rooms.get_mut(&u.current_room) {
-                room.remove_session(id);
+        let addr = self.spawn_room(name, room, ctx);
+        if let Some(entry) = self.rooms.get_mut(name) {
+            entry.addr = addr.clone();
+        }
 
-                if room.sessions().is_empty() {
-                    room.empty_at = Some(Instant::now());
-                }
-            }
+        for session in sessions {
+            session.addr.do_send(RoomJoined {
+                name: name.to_string(),
+                addr: addr.clone(),
+            });
         }
 
-        user
+        Ok(addr)
     }
 
-    fn connect_session(&mut self, id: &str, addr: Addr<WsChessSession>) -> Option<&mut User> {
-        if let Some(user) = self.sessions.get_mut(id) {
-            user.addr.do_send(Disconnect { id: id.to_string() });
+    fn room_of(&self, id: &str) -> Option<Addr<RoomActor>> {
+        let session = self.sessions.get(id)?;
 
-            user.disconected_at = None;
-            user.addr = addr.clone();
-
-            if let Some(room) = self.rooms.get_mut(&user.current_room) {
-                room.connect_session(id, addr);
-            }
-
-            Some(user)
-        } else {
-            None
-        }
+        self.rooms
+            .get(&session.room)
+            .map(|entry| entry.addr.clone())
     }
 }
 
@@ -412,7 +497,11 @@ impl Actor for ChessServer {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        log::info!("Chess server started");
+        tracing::info!(arbiters = self.arbiters.len(), "Chess server started");
+
+        // default room
+        self.room_addr("main", None, None, None, ctx)
+            .expect("Failed to create default room");
 
         let room_timeout = Duration::from_secs(
             // 60 * 5 = 300 -> 5 minutes
@@ -430,123 +519,163 @@ impl Actor for ChessServer {
                 .unwrap_or(300),
         );
 
-        ctx.run_interval(Duration::from_secs(5), move |act, _| {
-            let mut rooms = vec![];
-            let mut sessions = vec![];
+        ctx.run_interval(Duration::from_secs(5), move |act, ctx| {
+            act.rematch_offers
+                .retain(|_, offer| offer.offered_at.elapsed() < REMATCH_OFFER_TTL);
 
-            for (name, room) in &mut act.rooms {
-                if let Some(empty_at) = room.empty_at {
-                    if empty_at.elapsed() > room_timeout {
-                        log::info!("Room {} is empty, removing", name);
-                        rooms.push(name.clone());
+            let mut forgotten = vec![];
+            let mut users = vec![];
+
+            for (id, user) in &mut act.users {
+                user.away_rooms.retain(|room, left_at| {
+                    let expired = left_at.elapsed() > user_timeout;
+                    if expired {
+                        forgotten.push((id.clone(), room.clone()));
                     }
-                }
-            }
+                    !expired
+                });
 
-            for (id, user) in &mut act.sessions {
                 if let Some(disconected_at) = user.disconected_at {
                     if disconected_at.elapsed() > user_timeout {
-                        log::info!("User {}:{} is disconected, removing", id, user.name);
-                        sessions.push(id.clone());
+                        tracing::info!(
+                            user_id = %id,
+                            name = %user.name,
+                            "User is disconnected, removing"
+                        );
+                        users.push(id.clone());
+                    }
+                }
+            }
+
+            for id in users {
+                if let Some(user) = act.users.remove(&id) {
+                    forgotten.extend(user.away_rooms.into_keys().map(|room| (id.clone(), room)));
+                }
+            }
+
+            for (user_id, room) in forgotten {
+                if let Some(entry) = act.rooms.get(&room) {
+                    entry.addr.do_send(room::Forget { user_id });
+                }
+            }
+
+            let occupied: HashSet<String> = act
+                .sessions
+                .values()
+                .map(|session| session.room.clone())
+                .chain(
+                    act.users
+                        .values()
+                        .flat_map(|user| user.away_rooms.keys().cloned()),
+                )
+                .collect();
+            let mut rooms = vec![];
+            let mut dead_rooms = vec![];
+
+            for (name, room) in &mut act.rooms {
+                if occupied.contains(name) || act.game_watchers.contains_key(name) {
+                    room.empty_at = None;
+
+                    if !room.addr.connected() {
+                        dead_rooms.push(name.clone());
                     }
+                    continue;
+                }
+
+                let empty_at = *room.empty_at.get_or_insert_with(Instant::now);
+                if empty_at.elapsed() > room_timeout {
+                    tracing::info!(room = %name, "Room is empty, removing");
+                    rooms.push(name.clone());
                 }
             }
 
+            // dropping the last address stops the room actor
             for name in rooms {
                 act.rooms.remove(&name);
             }
 
-            for id in sessions {
-                if let Some(user) = act.remove_session(&id) {
-                    act.send_message(
-                        &user.current_room,
-                        &format!("/remove_user {}", user.to_string()),
-                        None,
-                    );
-                };
+            for name in dead_rooms {
+                let _ = act.restart_room(&name, ctx);
             }
+
+            act.sync_metrics();
         });
     }
 }
 
 /// Handler for Connect message.
 ///
-/// Register new session and assign unique id to this session
+/// Register new session and send it to its room
 impl Handler<Connect> for ChessServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Connect, _: &mut Context<Self>) {
-        log::debug!("Someone joined");
-
-        // register session with random id
-        let Connect { addr, id, name } = msg;
-        if let Some(user) = self.connect_session(&id, addr.clone()).cloned() {
-            let user_string = user.to_string();
-            let room_name = user.current_room.clone();
-
-            if let Some(current_room) = self.rooms.get(&user.current_room) {
-                let is_checkmate = current_room.chess_board.is_checkmate();
-                let current_fen = current_room.current_fen.clone();
-                let trash = current_room.trash.clone();
-                let users = current_room.usernames().join(",");
-                let options_str = current_room.options_string();
-
-                // send message to all users in the room
-                self.send_message(
-                    &room_name,
-                    &format!("/connect_user {}", user_string),
-                    Some(&id),
-                );
+    #[tracing::instrument(name = "connect", skip_all, fields(session_id = %msg.id, user_id = %msg.user_id, room = field::Empty))]
+    fn handle(&mut self, msg: Connect, ctx: &mut Context<Self>) {
+        let _timer = self.metrics.handler_timer("connect");
 
-                // sync fen
-                self.send_message_to_session(
-                    &id,
-                    &format!("/sync_board {}|{}|{}", room_name, current_fen, trash),
-                );
-                // sync users
-                self.send_message_to_session(&id, &format!("/sync_users {}|{}", room_name, users));
-                // sync options
-                self.send_message_to_session(&id, &format!("/sync_options {}", options_str));
-                // notify user if checkmate
-                if is_checkmate {
-                    self.send_message_to_session(&id, "/checkmate");
-                }
-            }
-        } else {
-            let room_name = "main".to_string();
-            let user = User::new(id.clone(), name, addr, room_name.clone(), None);
-
-            let user_string = user.to_string();
-            self.sessions.insert(id.clone(), user.clone());
-            // auto join session to main room
-            let current_room = self
-                .rooms
-                .entry(room_name.clone())
-                .or_insert_with(|| Room::new(None, None).expect("Failed to create default room"));
-
-            current_room.insert_session(id.clone(), user);
-            current_room.empty_at = None;
-            let current_fen = current_room.current_fen.clone();
-            let trash = current_room.trash.clone();
-            let users = current_room.usernames().join(",");
-            let options_str = current_room.options_string();
-
-            let count = self.visitor_count.fetch_add(1, Ordering::SeqCst);
-            self.send_message(&room_name, &format!("Total visitors {count}"), None);
-
-            // send message to all users in the room
-            self.send_message(&room_name, &format!("/add_user {}", user_string), Some(&id));
-
-            // sync fen
-            self.send_message_to_session(
-                &id,
-                &format!("/sync_board {}|{}|{}", room_name, current_fen, trash),
-            );
-            // sync users
-            self.send_message_to_session(&id, &format!("/sync_users {}|{}", room_name, users));
-            // sync options
-            self.send_message_to_session(&id, &format!("/sync_options {}", options_str));
+        tracing::debug!("Someone joined");
+
+        let Connect {
+            id,
+            user_id,
+            name,
+            registered,
+            addr,
+            resume,
+        } = msg;
+
+        let is_new = !self.users.contains_key(&user_id);
+        let user = self.users.entry(user_id.clone()).or_insert_with(|| {
+            // auto join user to main room
+            User::new(user_id.clone(), name, registered, "main".to_string())
+        });
+        user.disconected_at = None;
+
+        // a reconnecting socket goes back to the room it was showing
+        let room_name = resume
+            .as_ref()
+            .map(|(room, _)| room.clone())
+            .filter(|room| self.rooms.contains_key(room))
+            .unwrap_or(user.current_room.clone());
+        user.away_rooms.remove(&room_name);
+        let name = user.name.clone();
+
+        let resume = resume
+            .filter(|(resume_room, _)| *resume_room == room_name)
+            .map(|(_, seq)| seq);
+        Span::current().record("room", room_name.as_str());
+
+        let Ok(room) = self.room_addr(&room_name, None, None, None, ctx) else {
+            tracing::error!("Failed to create room");
+            return;
+        };
+
+        let session = Session {
+            id: id.clone(),
+            user_id,
+            registered,
+            addr: addr.clone(),
+            room: room_name.clone(),
+        };
+        self.sessions.insert(id, session.clone());
+
+        room.do_send(room::Enter {
+            session,
+            name,
+            arrival: Arrival::Connected { resume },
+        });
+        if is_new {
+            let count = self.metrics.visitors.get();
+            self.metrics.visitors.inc();
+            room.do_send(room::Broadcast(format!("Total visitors {count}")));
         }
+
+        addr.do_send(RoomJoined {
+            name: room_name,
+            addr: room,
+        });
+
+        self.sync_metrics();
     }
 }
 
@@ -554,37 +683,36 @@ impl Handler<Connect> for ChessServer {
 impl Handler<Disconnect> for ChessServer {
     type Result = ();
 
+    #[tracing::instrument(name = "disconnect", skip_all, fields(session_id = %msg.id, room = field::Empty))]
     fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        log::info!("Someone disconnected");
+        let _timer = self.metrics.handler_timer("disconnect");
 
-        let mut rooms: Vec<(String, String)> = Vec::new();
+        tracing::info!("Someone disconnected");
 
-        // remove address
-        if let Some(user) = self.disconnect_session(&msg.id) {
-            rooms.push((
-                user.current_room.clone(),
-                format!("/disconnect_user {}", user.to_string()),
-            ));
-        }
+        let Some(session) = self.sessions.remove(&msg.id) else {
+            return;
+        };
+        Span::current().record("room", session.room.as_str());
 
-        // send message to all users in all rooms
-        for (room_name, message) in rooms {
-            self.send_message(&room_name, &message, Some(&msg.id));
+        if let Some(entry) = self.rooms.get(&session.room) {
+            entry.addr.do_send(room::Away { id: msg.id });
         }
-    }
-}
 
-/// Handler for Message message.
-impl Handler<ClientMessage> for ChessServer {
-    type Result = ();
+        let still_in_room = self
+            .sessions_of(&session.user_id)
+            .any(|other| other.room == session.room);
+        let still_connected = self.sessions_of(&session.user_id).next().is_some();
 
-    fn handle(&mut self, msg: ClientMessage, _: &mut Context<Self>) {
-        let Some(session) = self.sessions.get(&msg.id) else {
-            log::error!("No user found for id {}", msg.id);
-            return;
-        };
+        if let Some(user) = self.users.get_mut(&session.user_id) {
+            if !still_in_room {
+                user.away_rooms.insert(session.room, Instant::now());
+            }
+            if !still_connected {
+                user.disconected_at = Some(Instant::now());
+            }
+        }
 
-        self.send_message(&session.current_room, msg.msg.as_str(), Some(&msg.id));
+        self.sync_metrics();
     }
 }
 
@@ -593,420 +721,462 @@ impl Handler<ClientMessage> for ChessServer {
 impl Handler<Join> for ChessServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Join, _: &mut Context<Self>) {
+    #[tracing::instrument(name = "join", skip_all, fields(session_id = %msg.id, room = %msg.name))]
+    fn handle(&mut self, msg: Join, ctx: &mut Context<Self>) {
+        let _timer = self.metrics.handler_timer("join");
+
         let Join {
             id,
             name,
             fen,
             trash,
         } = msg;
-        let Some(user) = self.sessions.get_mut(&id) else {
-            log::error!("No user found for id {}", id);
+
+        let Some(user_id) = self.sessions.get(&id).map(|s| s.user_id.clone()) else {
+            tracing::error!("No user found");
             return;
         };
 
-        user.current_room = name.clone();
-        let mut rooms: Vec<(String, String)> = Vec::new();
-        let user_string = user.to_string();
+        if !self.rooms.contains_key(&name) {
+            if let Err(error) = self.check_room_limits(&user_id) {
+                self.send_message_to_session(&id, &format!("/error room_limit {}", error));
+                return;
+            }
+        }
 
-        // remove session from all rooms
-        for (n, current_room) in &mut self.rooms {
-            if current_room.remove_session(&id).is_some() {
-                rooms.push((n.clone(), format!("/remove_user {}", user_string)));
+        let Ok(room) = self.room_addr(&name, fen, trash, Some(&user_id), ctx) else {
+            self.send_message_to_session(&id, "/notify error Failed to create room");
+            return;
+        };
 
-                if current_room.sessions().is_empty() {
-                    current_room.empty_at = Some(Instant::now());
-                }
-            }
+        // remove session from its current room
+        if let Some(old_room) = self.room_of(&id) {
+            old_room.do_send(room::Leave { id: id.clone() });
         }
 
-        let current_room = self.rooms.get_mut(&name);
+        let Some(session) = self.sessions.get_mut(&id) else {
+            return;
+        };
+        session.room = name.clone();
+        let session = session.clone();
 
-        let current_room = if current_room.is_some() {
-            current_room.unwrap()
-        } else {
-            let Ok(room) = Room::new(fen.clone(), trash.clone()) else {
-                self.send_message_to_session(&id, "/notify error Failed to create room");
-                return;
-            };
-            self.rooms.entry(name.clone()).or_insert_with(|| room)
+        let Some(user) = self.users.get_mut(&user_id) else {
+            return;
         };
+        user.current_room = name.clone();
+        user.away_rooms.remove(&name);
 
-        current_room.insert_session(id.clone(), user.clone());
-        current_room.empty_at = None;
-        let current_fen = current_room.current_fen.clone();
-        let trash = current_room.trash.clone();
-        let users = current_room.usernames().join(",");
-        let options_str = current_room.options_string();
+        room.do_send(room::Enter {
+            session: session.clone(),
+            name: user.name.clone(),
+            arrival: Arrival::Joined,
+        });
+        session.addr.do_send(RoomJoined { name, addr: room });
+    }
+}
 
-        // send message to all users in all rooms
-        for (room_name, message) in rooms {
-            self.send_message(&room_name, &message, None);
+impl Handler<ForkRoom> for ChessServer {
+    type Result = ();
+
+    #[tracing::instrument(name = "fork_room", skip_all, fields(session_id = %msg.id, room = %msg.name))]
+    fn handle(&mut self, msg: ForkRoom, ctx: &mut Context<Self>) {
+        let _timer = self.metrics.handler_timer("fork_room");
+
+        let ForkRoom { id, name, room } = msg;
+
+        let Some(user_id) = self.sessions.get(&id).map(|s| s.user_id.clone()) else {
+            tracing::error!("No user found");
+            return;
+        };
+
+        if self.rooms.contains_key(&name) {
+            self.send_message_to_session(&id, "/notify error A room with this name already exists");
+            return;
         }
-        // sync fen
-        self.send_message_to_session(
-            &id,
-            &format!("/sync_board {}|{}|{}", name, current_fen, trash),
-        );
-        // sync users
-        self.send_message_to_session(&id, &format!("/sync_users {}|{}", name, users));
-        // sync options
-        self.send_message_to_session(&id, &format!("/sync_options {}", options_str));
-        // notify user
-        self.send_message_to_session(&id, &format!("/notify success Joined room {}", name));
-
-        // notify all users in room
-        self.send_message(&name, &format!("/add_user {}", user_string), Some(&id));
+
+        if let Err(error) = self.check_room_limits(&user_id) {
+            self.send_message_to_session(&id, &format!("/error room_limit {}", error));
+            return;
+        }
+
+        self.insert_room(&name, room, Some(&user_id), ctx);
+        ctx.notify(Join {
+            id,
+            name,
+            fen: None,
+            trash: None,
+        });
     }
 }
 
-impl Handler<Move> for ChessServer {
+impl Handler<UserSync> for ChessServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Move, _: &mut Self::Context) -> Self::Result {
-        let Move {
-            id,
-            piece,
-            from,
-            to,
-        } = msg;
+    #[tracing::instrument(name = "user_sync", skip_all, fields(user_id = %msg.user_id))]
+    fn handle(&mut self, msg: UserSync, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("user_sync");
 
-        let Some(session) = self.sessions.get(&id) else {
-            log::error!("No user found for id {}", id);
+        let Some(user) = self.users.get_mut(&msg.user_id) else {
+            tracing::error!("No user found");
             return;
         };
+        user.name = msg.name.clone();
 
-        if let Some(current_room) = self.rooms.get_mut(&session.current_room) {
-            let chess_board = &mut current_room.chess_board;
-            let from_position: Option<Position> = from.parse().ok();
-            let to_position: Option<Position> = to.parse().ok();
-            let current_fen = chess_board.fen.clone();
-            let trash = chess_board.trash_string();
-            let mut reactive_move_message = (0, None);
-            let chess_board_move_result;
-            match chess_board.move_piece(&piece, from_position.clone(), to_position.clone()) {
-                Ok(move_result) => {
-                    chess_board_move_result = Some(move_result.clone());
-                    match move_result {
-                        chess_board::enums::Move::Passant => {
-                            let to = to_position.clone().unwrap();
-                            let (piece, passant_pos) = match chess_board.turn {
-                                Turn::White => ("lp", Position::new(to.x, to.y - 1)),
-                                Turn::Black => ("dp", Position::new(to.x, to.y + 1)),
-                            };
-                            reactive_move_message = (
-                                0,
-                                Some(format!(
-                                    "/move {} {} deleted",
-                                    piece,
-                                    passant_pos.to_string()
-                                )),
-                            );
-                        }
-                        chess_board::enums::Move::Castle(castle_side) => {
-                            let (old_rook_x, new_rook_x) = match castle_side {
-                                CastlePosition::KingSide => (7, 5),
-                                CastlePosition::QueenSide => (0, 3),
-                            };
-                            let (piece, rook_pos) = match chess_board.turn {
-                                Turn::White => ("dr", Position::new(old_rook_x, 0)),
-                                Turn::Black => ("lr", Position::new(old_rook_x, 7)),
-                            };
-                            reactive_move_message = (
-                                0,
-                                Some(format!(
-                                    "/move {} {} {}",
-                                    piece,
-                                    rook_pos.to_string(),
-                                    Position::new(new_rook_x, rook_pos.y).to_string()
-                                )),
-                            );
-                        }
-                        chess_board::enums::Move::Promotion(_) => {
-                            reactive_move_message = (
-                                200,
-                                Some(format!(
-                                    "/sync_board {}|{}|{}",
-                                    session.current_room,
-                                    chess_board.fen.clone(),
-                                    chess_board.trash_string()
-                                )),
-                            );
+        let mut rooms: HashSet<String> = user.away_rooms.keys().cloned().collect();
+        for session in self.sessions_of(&msg.user_id) {
+            rooms.insert(session.room.clone());
+            session.addr.do_send(msg.clone());
+        }
+
+        for room in rooms {
+            if let Some(entry) = self.rooms.get(&room) {
+                entry.addr.do_send(room::Rename {
+                    user_id: msg.user_id.clone(),
+                    name: msg.name.clone(),
+                });
+            }
+        }
+    }
+}
+
+impl ChessServer {
+    /// Seat both players in a new room and move their sessions to it, the
+    /// room counts against the limits of `created_by`
+    fn start_game(
+        &mut self,
+        msg: StartGame,
+        created_by: Option<&str>,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), &'static str> {
+        let StartGame {
+            room: name,
+            white,
+            black,
+            time_control,
+            rated,
+            watcher,
+        } = msg;
+        let notify = |message: &str| {
+            for player in [&white, &black] {
+                match &player.session_id {
+                    Some(id) => self.send_message_to_session(id, message),
+                    None => {
+                        for session in self.sessions_of(&player.user_id) {
+                            session.addr.do_send(Message(message.to_owned()));
                         }
-                        _ => {}
                     }
                 }
-                Err(e) => {
-                    log::warn!(
-                        "Room: {} -> failed attempt to move piece {} from {} to {} -> {:?}",
-                        session.current_room,
-                        piece,
-                        from,
-                        to,
-                        e
-                    );
-                    self.send_message_to_session(
-                        &id,
-                        &format!(
-                            "/sync_board {}|{}|{}",
-                            session.current_room, current_fen, trash
-                        ),
-                    );
-                    return;
-                }
             }
-            let is_checkmate = chess_board.is_checkmate();
-            current_room.current_fen = chess_board.fen.clone();
-            current_room.trash = chess_board.trash_string();
-
-            let move_msg = format!("/move {} {} {}", piece, from, to);
-            let move_result = MoveResult {
-                stone: piece.parse().unwrap(),
-                from: from_position,
-                to: to_position,
-                chess_board_move: chess_board_move_result.unwrap(),
-                msg: move_msg.clone(),
-                previous_fen: current_fen,
-                previous_trash: trash,
-                current_fen: current_room.current_fen.clone(),
-                current_trash: current_room.trash.clone(),
-            };
-            current_room.truncate_moves_on_current_move();
-            current_room.push_move(move_result);
+        };
 
-            self.send_message(&session.current_room, &move_msg, Some(&id));
-            if let (timeout, Some(reactive_move_message)) = reactive_move_message {
-                std::thread::sleep(Duration::from_millis(timeout));
-                self.send_message(&session.current_room, &reactive_move_message, None);
+        let limits = match created_by {
+            Some(user_id) => self.check_room_limits(user_id),
+            None if self.rooms.len() >= self.limits.max_rooms => {
+                Err("The server reached the maximum number of rooms")
             }
-            if is_checkmate {
-                self.send_message(&session.current_room, "/checkmate", None)
+            None => Ok(()),
+        };
+        let room = if self.rooms.contains_key(&name) {
+            tracing::error!("Room of the game already exists");
+            Err("The room of the game already exists")
+        } else {
+            limits
+                .and_then(|_| Room::new(None, None).map_err(|_| "Failed to create room"))
+                .and_then(|mut room| {
+                    room.seat_players(&white, &black, time_control, rated)
+                        .map_err(|e| {
+                            tracing::error!(error = ?e, "Failed to seat the paired players");
+                            e.as_str()
+                        })?;
+                    Ok(room)
+                })
+        };
+        let room = match room {
+            Ok(room) => room,
+            Err(e) => {
+                notify(&format!("/notify error {}", e));
+                // the game won't be played, whoever waits for it moves on
+                if let Some(watcher) = watcher {
+                    watcher.do_send(not_started(&name, &white, &black, time_control));
+                }
+                return Err(e);
             }
         };
+
+        self.insert_room(&name, room, created_by, ctx);
+        if let Some(watcher) = watcher {
+            self.game_watchers.insert(name.clone(), watcher);
+        }
+
+        for player in [white, black] {
+            match player.session_id {
+                Some(id) => ctx.notify(Join {
+                    id,
+                    name: name.clone(),
+                    fen: None,
+                    trash: None,
+                }),
+                None => {
+                    // new tabs of the user open in the game too
+                    if let Some(user) = self.users.get_mut(&player.user_id) {
+                        user.current_room = name.clone();
+                    }
+                    for session in self.sessions_of(&player.user_id) {
+                        session
+                            .addr
+                            .do_send(Message(format!("/game_ready {}", name)));
+                    }
+                }
+            }
+        }
+
+        Ok(())
     }
 }
 
-impl Handler<Reset> for ChessServer {
+impl Handler<StartGame> for ChessServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Reset, _: &mut Self::Context) -> Self::Result {
-        let Some(session) = self.sessions.get(&msg.id) else {
-            log::error!("No user found for id {}", msg.id);
-            return;
-        };
+    #[tracing::instrument(name = "start_game", skip_all, fields(room = %msg.room))]
+    fn handle(&mut self, msg: StartGame, ctx: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("start_game");
 
-        if let Some(current_room) = self.rooms.get_mut(&session.current_room) {
-            let Ok(chess_board) = ChessBoardBuilder::new()
-                .fen(&current_room.original_fen)
-                .deleted_stones(&current_room.original_trash)
-                .validation(current_room.chess_board.validation)
-                .sync(current_room.chess_board.sync)
-                .build()
-            else {
-                self.send_message_to_session(&msg.id, "/notify error Failed to reset board");
-                return;
-            };
-            current_room.current_fen = current_room.original_fen.clone();
-            current_room.trash = current_room.original_trash.to_owned();
-            current_room.current_move_index = None;
-            current_room.chess_board = chess_board;
-
-            let current_fen = current_room.current_fen.clone();
-            let trash = current_room.trash.clone();
-
-            self.send_message(
-                &session.current_room,
-                &format!(
-                    "/sync_board {}|{}|{}",
-                    session.current_room, current_fen, trash
-                ),
-                None,
-            );
-        };
+        // the players and the watcher are told when it fails
+        let _ = self.start_game(msg, None, ctx);
     }
 }
 
-impl Handler<Undo> for ChessServer {
-    type Result = ();
+impl Handler<OfferRematch> for ChessServer {
+    type Result = Result<Option<String>, &'static str>;
 
-    fn handle(&mut self, msg: Undo, _: &mut Self::Context) -> Self::Result {
-        let Undo { id } = msg.clone();
+    #[tracing::instrument(name = "offer_rematch", skip_all, fields(game_id = %msg.game.id, user_id = %msg.user_id))]
+    fn handle(&mut self, msg: OfferRematch, ctx: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("offer_rematch");
 
-        let Some(session) = self.sessions.get(&id) else {
-            log::error!("No user found for id {}", id);
-            return;
+        let OfferRematch {
+            game,
+            user_id,
+            white_registered,
+            black_registered,
+        } = msg;
+        let (name, opponent_id) = match game.side_of(&user_id) {
+            Some(Turn::White) => (&game.white_name, &game.black_id),
+            Some(Turn::Black) => (&game.black_name, &game.white_id),
+            None => return Err("Only the players can ask for a rematch"),
         };
 
-        if let Some(current_room) = self.rooms.get_mut(&session.current_room) {
-            let msg;
-
-            match current_room.undo_move() {
-                Ok(move_result) => {
-                    msg = format!(
-                        "/sync_board {}|{}|{}",
-                        session.current_room, move_result.previous_fen, move_result.previous_trash
-                    );
-                    let Ok(chess_board) = ChessBoardBuilder::new()
-                        .fen(&move_result.previous_fen)
-                        .deleted_stones(&move_result.previous_trash)
-                        .validation(current_room.chess_board.validation)
-                        .sync(current_room.chess_board.sync)
-                        .build()
-                    else {
-                        let _ = current_room.redo_move();
-                        self.send_message_to_session(&id, "/notify error Failed to undo move");
-                        return;
-                    };
-                    current_room.current_fen = move_result.previous_fen;
-                    current_room.trash = move_result.previous_trash;
-                    current_room.chess_board = chess_board;
-                }
-                Err(_) => {
-                    let fen = current_room.current_fen.clone();
-                    let trash = current_room.trash.clone();
-                    self.send_message_to_session(&id, "/notify warning No more moves to undo");
-                    msg = format!("/sync_board {}|{}|{}", session.current_room, fen, trash);
+        let offered_by = self
+            .rematch_offers
+            .get(&game.id)
+            .filter(|offer| offer.offered_at.elapsed() < REMATCH_OFFER_TTL)
+            .map(|offer| offer.user_id.clone());
+        if offered_by.as_ref() != Some(opponent_id) {
+            // the room will count against the player asking first
+            self.check_room_limits(&user_id)?;
+
+            // asking again does not bother the opponent
+            if offered_by.as_ref() != Some(&user_id) {
+                self.rematch_offers.insert(
+                    game.id.clone(),
+                    RematchOffer {
+                        user_id: user_id.clone(),
+                        offered_at: Instant::now(),
+                    },
+                );
+                for session in self.sessions_of(opponent_id) {
+                    session.addr.do_send(Message(format!(
+                        "/notify success {} offers a rematch, ask for it from the game page to play",
+                        name
+                    )));
                 }
-            };
+                tracing::info!("Rematch offered");
+            }
 
-            self.send_message(&session.current_room, &msg, None);
-        } else {
-            log::error!("No room found with name {}", session.current_room);
+            return Ok(None);
         }
+
+        self.check_room_limits(opponent_id)
+            .map_err(|_| "The rematch can't get a room now, try again later")?;
+
+        let player = |user_id: &str, name: &str, registered: bool| Player {
+            session_id: None,
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+            registered,
+        };
+        let room = new_game_room();
+        let opponent_id = opponent_id.clone();
+        self.start_game(
+            StartGame {
+                room: room.clone(),
+                white: player(&game.black_id, &game.black_name, black_registered),
+                black: player(&game.white_id, &game.white_name, white_registered),
+                time_control: game
+                    .time_control
+                    .as_deref()
+                    .and_then(|tc| tc.parse::<TimeControl>().ok()),
+                rated: game.rated,
+                watcher: None,
+            },
+            Some(&opponent_id),
+            ctx,
+        )?;
+        // a rematch that could not start can be asked for again
+        self.rematch_offers.remove(&game.id);
+        tracing::info!(room = %room, "Rematch accepted");
+
+        Ok(Some(room))
     }
 }
 
-impl Handler<Redo> for ChessServer {
+/// Archive the game and hand the result to whoever waits for it
+impl Handler<GameFinished> for ChessServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Redo, _: &mut Self::Context) -> Self::Result {
-        let Redo { id } = msg.clone();
-
-        let Some(session) = self.sessions.get(&id) else {
-            log::error!("No user found for id {}", id);
-            return;
-        };
-
-        if let Some(current_room) = self.rooms.get_mut(&session.current_room) {
-            let msg;
-            let mut is_checkmate = false;
-
-            match current_room.redo_move() {
-                Ok(move_result) => {
-                    msg = format!(
-                        "/sync_board {}|{}|{}",
-                        session.current_room, move_result.current_fen, move_result.current_trash
-                    );
-                    let Ok(chess_board) = ChessBoardBuilder::new()
-                        .fen(&move_result.current_fen)
-                        .deleted_stones(&move_result.current_trash)
-                        .validation(current_room.chess_board.validation)
-                        .sync(current_room.chess_board.sync)
-                        .build()
-                    else {
-                        let _ = current_room.undo_move();
-                        self.send_message_to_session(&id, "/notify error Failed to redo move");
-                        return;
-                    };
-
-                    is_checkmate = chess_board.is_checkmate();
-                    current_room.current_fen = move_result.current_fen;
-                    current_room.trash = move_result.current_trash;
-                    current_room.chess_board = chess_board;
+    fn handle(&mut self, msg: GameFinished, ctx: &mut Self::Context) -> Self::Result {
+        let archive = self.archive.clone();
+        let record = msg.record.clone();
+        ctx.spawn(
+            async move {
+                match archive.store(&record).await {
+                    Ok(_) => tracing::info!(game_id = %record.id, "Game archived"),
+                    Err(e) => tracing::error!(error = ?e, "Failed to archive game"),
                 }
-                Err(_) => {
-                    let fen = current_room.current_fen.clone();
-                    let trash = current_room.trash.clone();
-                    self.send_message_to_session(&id, "/notify warning No more moves to redo");
-                    msg = format!("/sync_board {}|{}|{}", session.current_room, fen, trash);
-                }
-            };
-
-            self.send_message(&session.current_room, &msg, None);
-            if is_checkmate {
-                self.send_message(&session.current_room, "/checkmate", None)
             }
-        } else {
-            log::error!("No room found with name {}", session.current_room);
+            .into_actor(self),
+        );
+
+        if let Some(watcher) = self.game_watchers.remove(&msg.room) {
+            tracing::info!(room = %msg.room, result = %msg.result.to_string(), "Watched game ended");
+            watcher.do_send(msg);
         }
     }
 }
 
-impl Handler<UserSync> for ChessServer {
-    type Result = ();
+impl Handler<RoomBoard> for ChessServer {
+    type Result = Option<(String, String)>;
 
-    fn handle(&mut self, msg: UserSync, _: &mut Self::Context) -> Self::Result {
-        let UserSync { id, name } = msg.clone();
+    fn handle(&mut self, msg: RoomBoard, _: &mut Self::Context) -> Self::Result {
+        self.rooms
+            .get(&msg.name)
+            .map(|entry| entry.snapshot.board())
+    }
+}
 
-        let Some(user) = self.sessions.get_mut(&id) else {
-            log::error!("No user found for id {}", id);
-            return;
-        };
+impl Handler<RoomDiagram> for ChessServer {
+    type Result = ResponseFuture<Option<Diagram>>;
 
-        user.name = name.clone();
-        let addr = user.addr.clone();
+    fn handle(&mut self, msg: RoomDiagram, _: &mut Self::Context) -> Self::Result {
+        let addr = self.rooms.get(&msg.name).map(|entry| entry.addr.clone());
 
-        let user_string = user.to_string();
-        let current_room_name = user.current_room.clone();
+        Box::pin(async move { addr?.send(CurrentDiagram).await.ok() })
+    }
+}
 
-        if let Some(current_room) = self.rooms.get_mut(&current_room_name) {
-            current_room
-                .sessions
-                .get_mut(&id)
-                .expect(&format!(
-                    "User {}:{} was expected to be in the room {}",
-                    user.name, user.id, current_room_name
-                ))
-                .name = name;
-
-            // notify all users in room
-            self.send_message(
-                &current_room_name,
-                &format!("/add_user {}", user_string),
-                None,
-            );
-        };
-        addr.do_send(msg);
+#[cfg(feature = "gif")]
+impl Handler<RoomAnimation> for ChessServer {
+    type Result = ResponseFuture<Option<Animation>>;
+
+    fn handle(&mut self, msg: RoomAnimation, _: &mut Self::Context) -> Self::Result {
+        let addr = self.rooms.get(&msg.name).map(|entry| entry.addr.clone());
+
+        Box::pin(async move { addr?.send(room::CurrentAnimation).await.ok() })
     }
 }
 
-impl Handler<Options> for ChessServer {
+impl Handler<SaveSnapshot> for ChessServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Options, _: &mut Self::Context) -> Self::Result {
-        let Some(session) = self.sessions.get(&msg.id) else {
-            log::error!("No user found for id {}", msg.id);
-            return;
-        };
-
-        let Some(current_room) = self.rooms.get_mut(&session.current_room) else {
-            log::error!("No room found with name {}", session.current_room);
-            return;
-        };
+    fn handle(&mut self, msg: SaveSnapshot, _: &mut Self::Context) -> Self::Result {
+        if let Some(entry) = self.rooms.get_mut(&msg.name) {
+            entry.snapshot = msg.snapshot;
+        }
+    }
+}
 
-        let new_chess_board = ChessBoardBuilder::new()
-            .fen(&current_room.current_fen)
-            .deleted_stones(&current_room.trash)
-            .validation(msg.validation)
-            .sync(msg.sync)
-            .build();
+traced_handlers!(ChessServer; Connect, Disconnect, Join, ForkRoom, UserSync, StartGame);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{
+        archive::memory::InMemoryGameArchive, ratings::memory::InMemoryRatingRepository,
+    };
+
+    fn player(user_id: &str) -> Player {
+        Player {
+            session_id: None,
+            user_id: user_id.to_string(),
+            name: user_id.to_string(),
+            registered: false,
+        }
+    }
 
-        let result_msg;
+    #[actix_web::test]
+    async fn test_rematch_offers() {
+        let limits = RateLimitConfig {
+            max_rooms_per_user: 1,
+            ..RateLimitConfig::from_env()
+        };
+        let server = ChessServer::new(
+            Arc::new(Metrics::new().unwrap()),
+            limits,
+            Arc::new(InMemoryRatingRepository::new()),
+            Arc::new(InMemoryGameArchive::new()),
+        )
+        .start();
+        let rated_offer =
+            |game_id: &str, user_id: &str, rated: bool, registered: bool| OfferRematch {
+                game: GameRecord {
+                    id: game_id.to_string(),
+                    rated,
+                    ..not_started("game", &player("w"), &player("b"), None).record
+                },
+                user_id: user_id.to_string(),
+                white_registered: registered,
+                black_registered: registered,
+            };
+        let offer = |game_id: &str, user_id: &str| rated_offer(game_id, user_id, false, false);
 
-        if let Ok(chess_board) = new_chess_board {
-            current_room.chess_board = chess_board;
+        assert_eq!(
+            server.send(offer("first", "spectator")).await.unwrap(),
+            Err("Only the players can ask for a rematch")
+        );
 
-            result_msg = current_room.options_string();
-            self.send_message_to_session(&msg.id, "/notify success Options applied");
-        } else {
-            result_msg = current_room.options_string();
-            self.send_message_to_session(&msg.id, "/notify error Failed to apply options");
-        }
+        // offering twice is the same offer, the opponent takes it
+        assert_eq!(server.send(offer("first", "w")).await.unwrap(), Ok(None));
+        assert_eq!(server.send(offer("first", "w")).await.unwrap(), Ok(None));
+        let room = server.send(offer("first", "b")).await.unwrap();
+        assert!(matches!(room, Ok(Some(_))));
 
-        self.send_message(
-            &session.current_room,
-            &format!("/sync_options {}", result_msg),
-            None,
+        // the rematch room counts against the player who offered it
+        assert_eq!(
+            server.send(offer("second", "w")).await.unwrap(),
+            Err("You reached the maximum number of rooms")
+        );
+        assert_eq!(server.send(offer("second", "b")).await.unwrap(), Ok(None));
+
+        // a rematch that could not start keeps the offer
+        let server = ChessServer::new(
+            Arc::new(Metrics::new().unwrap()),
+            RateLimitConfig::from_env(),
+            Arc::new(InMemoryRatingRepository::new()),
+            Arc::new(InMemoryGameArchive::new()),
+        )
+        .start();
+        assert_eq!(
+            server
+                .send(rated_offer("rated", "w", true, false))
+                .await
+                .unwrap(),
+            Ok(None)
         );
+        assert!(server
+            .send(rated_offer("rated", "b", true, false))
+            .await
+            .unwrap()
+            .is_err());
+        let room = server.send(rated_offer("rated", "b", true, true)).await;
+        assert!(matches!(room.unwrap(), Ok(Some(_))));
     }
 }
@@ -0,0 +1,179 @@
+//! Moves of a room animated in a GIF, one frame per position with the names
+//! of the players above and below the board
+
+use super::{rasterize, Diagram, SQUARE};
+
+/// Height of the bars with the names of the players, in board units
+const NAME_BAR: f64 = 60.0;
+/// Frames are half the size of the SVG diagrams
+const SCALE: f32 = 0.5;
+/// The last position stays this many times longer before starting over
+const LAST_FRAME: u16 = 3;
+/// Every frame is drawn and quantized on each request, longer games only
+/// show their last positions
+pub const MAX_FRAMES: usize = 200;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Clone, Debug)]
+pub struct Animation {
+    frames: Vec<Diagram>,
+    flipped: bool,
+    white: Option<String>,
+    black: Option<String>,
+    /// Hundredths of a second each position is shown
+    delay: u16,
+}
+
+impl Animation {
+    /// Keeps the last `MAX_FRAMES` positions
+    pub fn new(mut frames: Vec<Diagram>) -> Self {
+        frames.drain(..frames.len().saturating_sub(MAX_FRAMES));
+
+        Self {
+            frames,
+            flipped: false,
+            white: None,
+            black: None,
+            delay: 100,
+        }
+    }
+
+    /// Black at the bottom
+    pub fn flipped(mut self, flipped: bool) -> Self {
+        self.flipped = flipped;
+        self
+    }
+
+    /// Names shown with the board, `None` keeps the name set before and
+    /// there are no name bars without names
+    pub fn players(mut self, white: Option<String>, black: Option<String>) -> Self {
+        self.white = white.or(self.white);
+        self.black = black.or(self.black);
+        self
+    }
+
+    /// Milliseconds each position is shown, GIFs count hundredths of a
+    /// second and browsers slow down anything under 20 milliseconds
+    pub fn delay(mut self, delay: u32) -> Self {
+        self.delay = (delay / 10).clamp(2, u16::MAX as u32) as u16;
+        self
+    }
+
+    fn frame_svg(&self, diagram: &Diagram) -> Result<String, ()> {
+        let board = diagram.clone().flipped(self.flipped).to_svg()?;
+        if self.white.is_none() && self.black.is_none() {
+            return Ok(board);
+        }
+
+        let (top, bottom) = if self.flipped {
+            (&self.white, &self.black)
+        } else {
+            (&self.black, &self.white)
+        };
+        let name = |name: &Option<String>, y: f64| {
+            format!(
+                r#"<text x="10" y="{}" font-family="sans-serif" font-size="32" font-weight="bold">{}</text>"#,
+                y,
+                escape(name.as_deref().unwrap_or("?"))
+            )
+        };
+        let (width, height) = (8.0 * SQUARE, 8.0 * SQUARE + 2.0 * NAME_BAR);
+
+        Ok(format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}"><rect width="{width}" height="{height}" fill="white"/>{}{}{}</svg>"#,
+            name(top, NAME_BAR - 18.0),
+            board.replacen("<svg ", &format!(r#"<svg y="{}" "#, NAME_BAR), 1),
+            name(bottom, height - 18.0),
+        ))
+    }
+
+    /// Fails without frames or when a FEN is invalid
+    pub fn to_gif(&self) -> Result<Vec<u8>, ()> {
+        // frames are drawn one at a time, the first one gives the size
+        let mut pixmaps = self
+            .frames
+            .iter()
+            .map(|diagram| rasterize(&self.frame_svg(diagram)?, SCALE));
+        let first = pixmaps.next().ok_or(())??;
+        let width = u16::try_from(first.width()).map_err(|_| ())?;
+        let height = u16::try_from(first.height()).map_err(|_| ())?;
+
+        let mut gif = vec![];
+        let mut encoder = gif::Encoder::new(&mut gif, width, height, &[]).map_err(|_| ())?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(|_| ())?;
+
+        for (i, pixmap) in std::iter::once(Ok(first)).chain(pixmaps).enumerate() {
+            // the pixmaps are opaque, their premultiplied colors are the colors
+            let mut pixmap = pixmap?;
+            let mut frame = gif::Frame::from_rgba_speed(width, height, pixmap.data_mut(), 10);
+            frame.delay = if i + 1 == self.frames.len() {
+                self.delay.saturating_mul(LAST_FRAME)
+            } else {
+                self.delay
+            };
+            encoder.write_frame(&frame).map_err(|_| ())?;
+        }
+        drop(encoder);
+
+        Ok(gif)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entities::position::Position, server::room::DEFAULT_FEN};
+
+    #[test]
+    fn test_animation() {
+        assert!(Animation::new(vec![]).to_gif().is_err());
+
+        let e2: Position = "e2".parse().unwrap();
+        let e4: Position = "e4".parse().unwrap();
+        let frames = vec![
+            Diagram::new(DEFAULT_FEN),
+            Diagram::new("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")
+                .last_move(Some((e2, e4))),
+        ];
+
+        let gif = Animation::new(frames.clone()).delay(500).to_gif().unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (400, 400));
+        let mut delays = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, vec![50, 150]);
+
+        // names go in bars above and below the board, escaped
+        let gif = Animation::new(frames)
+            .players(Some("<white>".to_string()), None)
+            .flipped(true)
+            .to_gif()
+            .unwrap();
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(&gif[..]).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (400, 460));
+
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        let dark_pixels = |rows: std::ops::Range<usize>| {
+            frame.buffer[rows.start * 400 * 4..rows.end * 400 * 4]
+                .chunks(4)
+                .filter(|rgba| rgba[0] < 128 && rgba[1] < 128 && rgba[2] < 128)
+                .count()
+        };
+        // the name bars are white with the names written in black
+        assert!(dark_pixels(0..30) > 0);
+        assert!(dark_pixels(430..460) > 0);
+
+        let long_game = vec![Diagram::new(DEFAULT_FEN); MAX_FRAMES + 10];
+        assert_eq!(Animation::new(long_game).frames.len(), MAX_FRAMES);
+    }
+}
@@ -0,0 +1,347 @@
+//! Board diagrams, a position drawn as an SVG image to paste in docs and
+//! chats and to preview room links.
+//!
+//! The pieces are the images of `public/static/chess/pieces` embedded in the
+//! SVG, so a diagram shows the same without the site. With the `png` feature
+//! diagrams can also be rasterized to PNG, and with the `gif` feature the
+//! moves of a room are animated in a GIF.
+
+#[cfg(feature = "gif")]
+pub mod animation;
+pub mod routes;
+
+use crate::entities::{
+    annotation::Shape,
+    chess_board::ChessBoard,
+    position::Position,
+    stone::{Color, Kind, Stone},
+};
+
+/// Side of a square, the board is 800 units wide like the board of the client
+const SQUARE: f64 = 100.0;
+const LIGHT_SQUARE: &str = "#dee3e6";
+const DARK_SQUARE: &str = "#8ca2ad";
+const LAST_MOVE: &str = "#9bc700";
+
+const PIECES: [(Color, [&str; 6]); 2] = [
+    (
+        Color::Light,
+        [
+            include_str!("../../../public/static/chess/pieces/lk.svg"),
+            include_str!("../../../public/static/chess/pieces/lq.svg"),
+            include_str!("../../../public/static/chess/pieces/lr.svg"),
+            include_str!("../../../public/static/chess/pieces/lb.svg"),
+            include_str!("../../../public/static/chess/pieces/ln.svg"),
+            include_str!("../../../public/static/chess/pieces/lp.svg"),
+        ],
+    ),
+    (
+        Color::Dark,
+        [
+            include_str!("../../../public/static/chess/pieces/dk.svg"),
+            include_str!("../../../public/static/chess/pieces/dq.svg"),
+            include_str!("../../../public/static/chess/pieces/dr.svg"),
+            include_str!("../../../public/static/chess/pieces/db.svg"),
+            include_str!("../../../public/static/chess/pieces/dn.svg"),
+            include_str!("../../../public/static/chess/pieces/dp.svg"),
+        ],
+    ),
+];
+
+fn piece_image(stone: &Stone) -> &'static str {
+    let index = match stone.kind() {
+        Kind::King => 0,
+        Kind::Queen => 1,
+        Kind::Rook => 2,
+        Kind::Bishop => 3,
+        Kind::Knight => 4,
+        Kind::Pawn => 5,
+    };
+    let (_, images) = PIECES
+        .iter()
+        .find(|(color, _)| *color == stone.color())
+        .unwrap();
+
+    images[index]
+}
+
+/// A position to draw with what is shown over it
+#[derive(Clone, Debug)]
+pub struct Diagram {
+    fen: String,
+    flipped: bool,
+    coordinates: bool,
+    last_move: Option<(Position, Position)>,
+    shapes: Vec<Shape>,
+}
+
+impl Diagram {
+    pub fn new(fen: &str) -> Self {
+        Self {
+            fen: fen.to_string(),
+            flipped: false,
+            coordinates: true,
+            last_move: None,
+            shapes: vec![],
+        }
+    }
+
+    /// Black at the bottom
+    pub fn flipped(mut self, flipped: bool) -> Self {
+        self.flipped = flipped;
+        self
+    }
+
+    pub fn coordinates(mut self, coordinates: bool) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+
+    /// Squares of the last move, they are highlighted
+    pub fn last_move(mut self, last_move: Option<(Position, Position)>) -> Self {
+        self.last_move = last_move;
+        self
+    }
+
+    /// Arrows and circles drawn over the pieces
+    pub fn shapes(mut self, shapes: Vec<Shape>) -> Self {
+        self.shapes = shapes;
+        self
+    }
+
+    /// Top left corner of a square
+    fn corner(&self, position: &Position) -> (f64, f64) {
+        let (x, y) = (position.x as f64 * SQUARE, position.y as f64 * SQUARE);
+        if self.flipped {
+            (7.0 * SQUARE - x, 7.0 * SQUARE - y)
+        } else {
+            (x, y)
+        }
+    }
+
+    fn center(&self, position: &Position) -> (f64, f64) {
+        let (x, y) = self.corner(position);
+        (x + SQUARE / 2.0, y + SQUARE / 2.0)
+    }
+
+    fn squares(&self) -> String {
+        let mut svg = String::new();
+        for y in 0..8 {
+            for x in 0..8 {
+                let (left, top) = self.corner(&Position::new(x, y));
+                let color = if (x + y) % 2 == 0 {
+                    LIGHT_SQUARE
+                } else {
+                    DARK_SQUARE
+                };
+                svg.push_str(&format!(
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                    left, top, SQUARE, SQUARE, color
+                ));
+            }
+        }
+
+        for position in self.last_move.iter().flat_map(|(from, to)| [from, to]) {
+            let (left, top) = self.corner(position);
+            svg.push_str(&format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="0.4"/>"#,
+                left, top, SQUARE, SQUARE, LAST_MOVE
+            ));
+        }
+
+        svg
+    }
+
+    /// Files along the bottom and ranks along the left side, in the color of
+    /// the other squares
+    fn coordinates_svg(&self) -> String {
+        let mut svg = String::new();
+        for i in 0..8 {
+            let color = |light: bool| if light { DARK_SQUARE } else { LIGHT_SQUARE };
+            let (file, rank) = if self.flipped {
+                ((b'h' - i as u8) as char, i + 1)
+            } else {
+                ((b'a' + i as u8) as char, 8 - i)
+            };
+            let offset = i as f64 * SQUARE;
+
+            svg.push_str(&format!(
+                r#"<text x="{}" y="{}" fill="{}" text-anchor="end">{}</text>"#,
+                offset + SQUARE - 6.0,
+                8.0 * SQUARE - 6.0,
+                color(i % 2 == 1),
+                file
+            ));
+            svg.push_str(&format!(
+                r#"<text x="6" y="{}" fill="{}">{}</text>"#,
+                offset + 22.0,
+                color(i % 2 == 0),
+                rank
+            ));
+        }
+
+        format!(
+            r#"<g font-family="sans-serif" font-size="20" font-weight="bold">{}</g>"#,
+            svg
+        )
+    }
+
+    fn pieces(&self, chess_board: &ChessBoard) -> String {
+        chess_board
+            .stones_and_positions_iter()
+            .map(|(position, stone)| {
+                let (left, top) = self.corner(&position);
+                piece_image(stone).trim().replacen(
+                    r#"width="150" height="150""#,
+                    &format!(
+                        r#"x="{}" y="{}" width="{}" height="{}""#,
+                        left, top, SQUARE, SQUARE
+                    ),
+                    1,
+                )
+            })
+            .collect()
+    }
+
+    /// Same arrows and circles as the annotations of the client, scaled to
+    /// the 800 units of the board
+    fn shapes_svg(&self) -> String {
+        let mut svg = String::new();
+        for shape in &self.shapes {
+            let color = shape.color.css_color();
+            let (x1, y1) = self.center(&shape.from);
+
+            if shape.is_circle() {
+                svg.push_str(&format!(
+                    r#"<circle cx="{}" cy="{}" r="44" fill="none" stroke="{}" stroke-width="6.4"/>"#,
+                    x1, y1, color
+                ));
+                continue;
+            }
+
+            // the arrow head ends a bit before the center of the square
+            let (x2, y2) = self.center(&shape.to);
+            let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+            let shorten = 24.0 / length;
+            let (x2, y2) = (x2 - (x2 - x1) * shorten, y2 - (y2 - y1) * shorten);
+            svg.push_str(&format!(
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="14.4" stroke-linecap="round" marker-end="url(#arrowhead-{})"/>"#,
+                x1, y1, x2, y2, color, shape.color.letter()
+            ));
+        }
+
+        let mut colors = vec![];
+        for shape in &self.shapes {
+            if !colors.contains(&shape.color) {
+                colors.push(shape.color);
+            }
+        }
+        let markers: String = colors
+            .into_iter()
+            .map(|color| {
+                format!(
+                    r#"<marker id="arrowhead-{}" orient="auto" markerWidth="4" markerHeight="8" refX="2.05" refY="2.01"><path d="M0,0 V4 L3,2 Z" fill="{}"/></marker>"#,
+                    color.letter(),
+                    color.css_color()
+                )
+            })
+            .collect();
+
+        format!(r#"<defs>{}</defs><g opacity="0.8">{}</g>"#, markers, svg)
+    }
+
+    /// Fails when the FEN is invalid
+    pub fn to_svg(&self) -> Result<String, ()> {
+        let chess_board = ChessBoard::new(&self.fen).map_err(|_| ())?;
+        let size = 8.0 * SQUARE;
+
+        Ok(format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {size} {size}">{}{}{}{}</svg>"#,
+            self.squares(),
+            if self.coordinates {
+                self.coordinates_svg()
+            } else {
+                String::new()
+            },
+            self.pieces(&chess_board),
+            self.shapes_svg(),
+        ))
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "png")] {
+        use std::sync::{Arc, OnceLock};
+
+        use resvg::{tiny_skia, usvg};
+
+        /// Drawn text is all `sans-serif`, the font ships with the binary so
+        /// images look the same on hosts without fonts installed
+        static FONT: &[u8] = include_bytes!("../../../public/static/fonts/DejaVuSans-Bold.ttf");
+
+        /// Font for the coordinates and the names, loaded by the first diagram
+        static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+
+        /// Draws an SVG diagram on a pixmap, `scale` times its size
+        pub fn rasterize(svg: &str, scale: f32) -> Result<tiny_skia::Pixmap, ()> {
+            let fontdb = FONTS.get_or_init(|| {
+                let mut fontdb = usvg::fontdb::Database::new();
+                fontdb.load_font_data(FONT.to_vec());
+                fontdb.set_sans_serif_family("DejaVu Sans");
+                Arc::new(fontdb)
+            });
+            let options = usvg::Options {
+                fontdb: fontdb.clone(),
+                ..Default::default()
+            };
+            let tree = usvg::Tree::from_str(svg, &options).map_err(|_| ())?;
+
+            let size = tree.size();
+            let mut pixmap = tiny_skia::Pixmap::new(
+                (size.width() * scale).ceil() as u32,
+                (size.height() * scale).ceil() as u32,
+            )
+            .ok_or(())?;
+            resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+            Ok(pixmap)
+        }
+
+        pub fn to_png(svg: &str) -> Result<Vec<u8>, ()> {
+            rasterize(svg, 1.0)?.encode_png().map_err(|_| ())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::room::DEFAULT_FEN;
+
+    #[test]
+    fn test_diagram() {
+        assert!(Diagram::new("not a fen").to_svg().is_err());
+
+        let svg = Diagram::new(DEFAULT_FEN).to_svg().unwrap();
+        assert_eq!(svg.matches("<rect").count(), 64);
+        assert_eq!(svg.matches("viewBox=\"0 0 150 150\"").count(), 32);
+        assert!(svg.contains(">a</text>"));
+
+        let e2: Position = "e2".parse().unwrap();
+        let e4: Position = "e4".parse().unwrap();
+        let svg = Diagram::new(DEFAULT_FEN)
+            .flipped(true)
+            .coordinates(false)
+            .last_move(Some((e2.clone(), e4.clone())))
+            .shapes(vec!["Gd2d4".parse().unwrap(), "Re4".parse().unwrap()])
+            .to_svg()
+            .unwrap();
+        assert_eq!(svg.matches("<rect").count(), 66);
+        assert!(!svg.contains("</text>"));
+        assert!(svg.contains("marker-end=\"url(#arrowhead-G)\""));
+        assert!(svg.contains("<circle"));
+        // e2 is the fourth square from the left of the second rank from the
+        // top once flipped
+        assert!(svg.contains(r##"<rect x="300" y="100" width="100" height="100" fill="#9bc700""##));
+    }
+}
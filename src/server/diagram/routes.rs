@@ -0,0 +1,187 @@
+//! `/diagram.svg` draws any position and `/rooms/{name}/board.svg` the one of
+//! a room. With the `png` feature both are also served as `.png`, with the
+//! `gif` feature `/rooms/{name}/game.gif` animates the moves of a room.
+
+use actix::Addr;
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    web, HttpResponse,
+};
+use serde::Deserialize;
+
+use super::Diagram;
+use crate::{
+    entities::{annotation::Shape, position::Position},
+    server::{
+        chess_server::{ChessServer, RoomDiagram},
+        responses::{error, unavailable},
+    },
+};
+
+#[cfg(feature = "gif")]
+use crate::server::chess_server::RoomAnimation;
+
+#[derive(Deserialize)]
+struct DiagramQuery {
+    fen: Option<String>,
+    /// `black` puts black at the bottom
+    orientation: Option<String>,
+    coordinates: Option<bool>,
+    /// Like `e2e4`
+    last_move: Option<String>,
+    /// Comma separated arrows and circles, like `Ge2e4,Rd4`
+    arrows: Option<String>,
+}
+
+impl DiagramQuery {
+    fn apply(&self, diagram: Diagram) -> Diagram {
+        diagram
+            .flipped(self.orientation.as_deref() == Some("black"))
+            .coordinates(self.coordinates.unwrap_or(true))
+    }
+
+    fn last_move(&self) -> Result<Option<(Position, Position)>, ()> {
+        let Some(last_move) = &self.last_move else {
+            return Ok(None);
+        };
+        if last_move.len() != 4 || !last_move.is_ascii() {
+            return Err(());
+        }
+        let from: Position = last_move[..2].parse()?;
+        let to: Position = last_move[2..].parse()?;
+        if [&from, &to].iter().any(|p| p.x > 7 || p.y > 7) {
+            return Err(());
+        }
+
+        Ok(Some((from, to)))
+    }
+
+    fn shapes(&self) -> Result<Vec<Shape>, ()> {
+        self.arrows
+            .iter()
+            .flat_map(|arrows| arrows.split(','))
+            .filter(|arrow| !arrow.is_empty())
+            .map(|arrow| arrow.parse::<Shape>())
+            .collect()
+    }
+}
+
+/// The diagram as an SVG or PNG image depending on `extension`
+fn image(diagram: &Diagram, extension: &str, cache_control: &str) -> HttpResponse {
+    let Ok(svg) = diagram.to_svg() else {
+        return error(StatusCode::BAD_REQUEST, "Invalid FEN");
+    };
+
+    let response = |content_type: &str, body: Vec<u8>| {
+        HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((header::CACHE_CONTROL, cache_control))
+            .body(body)
+    };
+
+    match extension {
+        "svg" => response("image/svg+xml", svg.into_bytes()),
+        #[cfg(feature = "png")]
+        "png" => match super::to_png(&svg) {
+            Ok(png) => response("image/png", png),
+            Err(_) => error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "The diagram could not be drawn",
+            ),
+        },
+        _ => error(StatusCode::NOT_FOUND, "Unknown image format"),
+    }
+}
+
+#[get("/diagram.{extension}")]
+async fn show(extension: web::Path<String>, query: web::Query<DiagramQuery>) -> HttpResponse {
+    let Some(fen) = &query.fen else {
+        return error(StatusCode::BAD_REQUEST, "The fen parameter is missing");
+    };
+    let Ok(last_move) = query.last_move() else {
+        return error(StatusCode::BAD_REQUEST, "Invalid last move");
+    };
+    let Ok(shapes) = query.shapes() else {
+        return error(StatusCode::BAD_REQUEST, "Invalid arrows");
+    };
+
+    let diagram = query
+        .apply(Diagram::new(fen))
+        .last_move(last_move)
+        .shapes(shapes);
+
+    // the same query always draws the same image
+    image(&diagram, &extension, "public, max-age=86400")
+}
+
+/// Position shown in the room with its last move and arrows, only the
+/// orientation and the coordinates parameters of `/diagram.svg` apply
+#[get("/rooms/{name}/board.{extension}")]
+async fn room_board(
+    server: web::Data<Addr<ChessServer>>,
+    path: web::Path<(String, String)>,
+    query: web::Query<DiagramQuery>,
+) -> HttpResponse {
+    let (name, extension) = path.into_inner();
+
+    match server.send(RoomDiagram { name }).await {
+        Ok(Some(diagram)) => image(&query.apply(diagram), &extension, "no-cache"),
+        Ok(None) => error(StatusCode::NOT_FOUND, "Room not found"),
+        Err(_) => unavailable("Rooms"),
+    }
+}
+
+#[cfg(feature = "gif")]
+#[derive(Deserialize)]
+struct GameQuery {
+    /// Milliseconds each position is shown
+    delay: Option<u32>,
+    orientation: Option<String>,
+    /// Names shown instead of the players seated
+    white: Option<String>,
+    black: Option<String>,
+}
+
+/// Positions from the start of the room to its current one
+#[cfg(feature = "gif")]
+#[get("/rooms/{name}/game.gif")]
+async fn room_game(
+    server: web::Data<Addr<ChessServer>>,
+    name: web::Path<String>,
+    query: web::Query<GameQuery>,
+) -> HttpResponse {
+    let animation = match server
+        .send(RoomAnimation {
+            name: name.into_inner(),
+        })
+        .await
+    {
+        Ok(Some(animation)) => animation,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Room not found"),
+        Err(_) => return unavailable("Rooms"),
+    };
+    let query = query.into_inner();
+    let animation = animation
+        .players(query.white, query.black)
+        .flipped(query.orientation.as_deref() == Some("black"))
+        .delay(query.delay.unwrap_or(1000));
+
+    // every frame is drawn and quantized, off the worker thread
+    match web::block(move || animation.to_gif()).await {
+        Ok(Ok(gif)) => HttpResponse::Ok()
+            .content_type("image/gif")
+            .insert_header((header::CACHE_CONTROL, "no-cache"))
+            .body(gif),
+        _ => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "The game could not be drawn",
+        ),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(show).service(room_board);
+    #[cfg(feature = "gif")]
+    cfg.service(room_game);
+}
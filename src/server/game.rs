@@ -0,0 +1,642 @@
+//! Game played in a room: who sits on each side, their clocks and the result.
+//!
+//! A game starts with the first move made while both seats are taken. When a
+//! seated player loses its connection the opponent sees a countdown of
+//! `GRACE_PERIOD` seconds (60 by default), after which it can claim the win
+//! or a draw. Depending on the room options the clocks keep running or pause
+//! until the player is back.
+
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+use crate::entities::{
+    chess_board::turns::Turn,
+    game::{
+        ClockStatus, GameResult, GameStatus, Outcome, Pool, SeatStatus, Termination, TimeControl,
+    },
+};
+
+use super::sessions::now_secs;
+
+fn grace_period() -> Duration {
+    Duration::from_secs(
+        env::var("GRACE_PERIOD")
+            .unwrap_or("60".to_string())
+            .parse::<u64>()
+            .unwrap_or(60),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameError {
+    SeatTaken,
+    NotSeated,
+    InProgress,
+    NotInProgress,
+    Finished,
+    NotYourPiece,
+    NotYourTurn,
+    OpponentConnected,
+    GracePeriod,
+    NotRegistered,
+}
+
+impl GameError {
+    /// Message shown to the user
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GameError::SeatTaken => "The seat is already taken",
+            GameError::NotSeated => "You are not playing in this room",
+            GameError::InProgress => "Not possible while a game is in progress",
+            GameError::NotInProgress => "No game in progress",
+            GameError::Finished => "The game is over, reset the board to play again",
+            GameError::NotYourPiece => "This piece belongs to the other player",
+            GameError::NotYourTurn => "Wait for your opponent to move",
+            GameError::OpponentConnected => "Your opponent is connected",
+            GameError::GracePeriod => "Your opponent can still reconnect",
+            GameError::NotRegistered => "Rated games are only played by logged in users",
+        }
+    }
+}
+
+/// What a player asks for when its opponent abandoned the game
+#[derive(Clone, Copy, Debug)]
+pub enum Claim {
+    Win,
+    Draw,
+}
+
+#[derive(Clone, Debug)]
+pub struct Seat {
+    pub user_id: String,
+    pub name: String,
+    /// Logged in to an account, `user_id` is then the account id
+    pub registered: bool,
+    /// When the player lost its last connection to the room
+    away_since: Option<Instant>,
+}
+
+impl Seat {
+    fn new(user_id: &str, name: &str, registered: bool) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+            registered,
+            away_since: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    white: Option<Seat>,
+    black: Option<Seat>,
+    time_control: Option<TimeControl>,
+    white_left: Duration,
+    black_left: Duration,
+    /// Side to move once the game started, its clock is the one running
+    to_move: Option<Turn>,
+    /// When the clock of `to_move` was last started, `None` while paused
+    running_since: Option<Instant>,
+    pause_when_away: bool,
+    /// Games between registered players change their ratings
+    rated: bool,
+    grace: Duration,
+    result: Option<GameResult>,
+    /// Seconds since the epoch of the first move with both seats taken
+    started_at: Option<u64>,
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Self {
+            white: None,
+            black: None,
+            time_control: None,
+            white_left: Duration::ZERO,
+            black_left: Duration::ZERO,
+            to_move: None,
+            running_since: None,
+            pause_when_away: false,
+            rated: false,
+            grace: grace_period(),
+            result: None,
+            started_at: None,
+        }
+    }
+
+    pub fn seat(&self, side: Turn) -> Option<&Seat> {
+        match side {
+            Turn::White => self.white.as_ref(),
+            Turn::Black => self.black.as_ref(),
+        }
+    }
+
+    fn seat_mut(&mut self, side: Turn) -> &mut Option<Seat> {
+        match side {
+            Turn::White => &mut self.white,
+            Turn::Black => &mut self.black,
+        }
+    }
+
+    fn time_left_mut(&mut self, side: Turn) -> &mut Duration {
+        match side {
+            Turn::White => &mut self.white_left,
+            Turn::Black => &mut self.black_left,
+        }
+    }
+
+    pub fn side_of(&self, user_id: &str) -> Option<Turn> {
+        [Turn::White, Turn::Black]
+            .into_iter()
+            .find(|side| self.seat(*side).map(|s| s.user_id == user_id) == Some(true))
+    }
+
+    pub fn is_in_progress(&self) -> bool {
+        self.to_move.is_some() && self.result.is_none()
+    }
+
+    pub fn pause_when_away(&self) -> bool {
+        self.pause_when_away
+    }
+
+    pub fn is_rated(&self) -> bool {
+        self.rated
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
+
+    pub fn time_control(&self) -> Option<TimeControl> {
+        self.time_control
+    }
+
+    pub fn started_at(&self) -> Option<u64> {
+        self.started_at
+    }
+
+    /// Pool the game counts for, rated games need a clock and two registered
+    /// players
+    pub fn rated_pool(&self) -> Option<Pool> {
+        let registered = [Turn::White, Turn::Black]
+            .into_iter()
+            .all(|side| self.seat(side).map(|s| s.registered) == Some(true));
+
+        self.time_control
+            .filter(|_| self.rated && registered)
+            .map(|time_control| time_control.pool())
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause_when_away
+            && [Turn::White, Turn::Black]
+                .into_iter()
+                .any(|side| self.seat(side).map(|s| s.away_since.is_some()) == Some(true))
+    }
+
+    /// Charges the time spent since the clock was started to the side to move
+    fn stop_clock(&mut self, now: Instant) {
+        if let (Some(side), Some(since)) = (self.to_move, self.running_since.take()) {
+            if self.time_control.is_some() {
+                let left = self.time_left_mut(side);
+                *left = left.saturating_sub(now.duration_since(since));
+            }
+        }
+    }
+
+    fn start_clock(&mut self, now: Instant) {
+        if self.is_in_progress() && !self.is_paused() {
+            self.running_since = Some(now);
+        }
+    }
+
+    pub fn time_left(&self, side: Turn, now: Instant) -> Duration {
+        let left = match side {
+            Turn::White => self.white_left,
+            Turn::Black => self.black_left,
+        };
+
+        match self.running_since {
+            Some(since) if self.to_move == Some(side) => {
+                left.saturating_sub(now.duration_since(since))
+            }
+            _ => left,
+        }
+    }
+
+    pub fn sit(
+        &mut self,
+        user_id: &str,
+        name: &str,
+        registered: bool,
+        side: Turn,
+    ) -> Result<(), GameError> {
+        if self.is_in_progress() {
+            return Err(GameError::InProgress);
+        }
+        if self.rated && !registered {
+            return Err(GameError::NotRegistered);
+        }
+
+        match self.seat(side) {
+            Some(seat) if seat.user_id == user_id => return Ok(()),
+            Some(_) => return Err(GameError::SeatTaken),
+            None => {}
+        }
+
+        // a user plays a single side
+        if let Some(other) = self.side_of(user_id) {
+            *self.seat_mut(other) = None;
+        }
+        *self.seat_mut(side) = Some(Seat::new(user_id, name, registered));
+
+        Ok(())
+    }
+
+    pub fn stand(&mut self, user_id: &str) -> Result<(), GameError> {
+        if self.is_in_progress() {
+            return Err(GameError::InProgress);
+        }
+
+        let side = self.side_of(user_id).ok_or(GameError::NotSeated)?;
+        *self.seat_mut(side) = None;
+
+        Ok(())
+    }
+
+    pub fn rename(&mut self, user_id: &str, name: &str) -> bool {
+        let Some(side) = self.side_of(user_id) else {
+            return false;
+        };
+        if let Some(seat) = self.seat_mut(side) {
+            seat.name = name.to_string();
+        }
+
+        true
+    }
+
+    pub fn set_time_control(&mut self, time_control: Option<TimeControl>) -> Result<(), GameError> {
+        if self.is_in_progress() {
+            return Err(GameError::InProgress);
+        }
+
+        self.time_control = time_control;
+        self.reset();
+
+        Ok(())
+    }
+
+    /// Anonymous players lose their seat in a rated game
+    pub fn set_rated(&mut self, rated: bool) -> Result<(), GameError> {
+        if rated != self.rated && self.is_in_progress() {
+            return Err(GameError::InProgress);
+        }
+
+        self.rated = rated;
+        if rated {
+            for side in [Turn::White, Turn::Black] {
+                if self.seat(side).map(|s| s.registered) == Some(false) {
+                    *self.seat_mut(side) = None;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set_pause_when_away(&mut self, pause_when_away: bool, now: Instant) {
+        self.stop_clock(now);
+        self.pause_when_away = pause_when_away;
+        self.start_clock(now);
+    }
+
+    /// Puts the game back to its start, the players keep their seats
+    pub fn reset(&mut self) {
+        let base = self
+            .time_control
+            .map(|tc| Duration::from_millis(tc.base_ms()))
+            .unwrap_or_default();
+
+        self.white_left = base;
+        self.black_left = base;
+        self.to_move = None;
+        self.running_since = None;
+        self.result = None;
+        self.started_at = None;
+
+        for seat in [&mut self.white, &mut self.black].into_iter().flatten() {
+            seat.away_since = None;
+        }
+    }
+
+    /// Whether `user_id` may reset the board or change the options, anyone
+    /// when no seat is taken and only the players otherwise, never while a
+    /// game is in progress
+    pub fn check_control(&self, user_id: &str) -> Result<(), GameError> {
+        if self.is_in_progress() {
+            return Err(GameError::InProgress);
+        }
+
+        let seated = self.white.is_some() || self.black.is_some();
+        if seated && self.side_of(user_id).is_none() {
+            return Err(GameError::NotSeated);
+        }
+
+        Ok(())
+    }
+
+    /// Whether `user_id` may move a stone of `side`, anyone can move the
+    /// stones of a free seat. Once the game started only the side to move
+    /// can.
+    pub fn check_move(&self, user_id: &str, side: Turn) -> Result<(), GameError> {
+        if self.result.is_some() {
+            return Err(GameError::Finished);
+        }
+
+        match self.seat(side) {
+            Some(seat) if seat.user_id != user_id => Err(GameError::NotYourPiece),
+            _ if self.to_move.is_some_and(|to_move| to_move != side) => Err(GameError::NotYourTurn),
+            _ => Ok(()),
+        }
+    }
+
+    /// Passes the clock to the opponent of `side`, returns whether the game
+    /// changed
+    pub fn record_move(&mut self, side: Turn, is_checkmate: bool, now: Instant) -> bool {
+        if self.white.is_none() || self.black.is_none() || self.result.is_some() {
+            return false;
+        }
+
+        self.stop_clock(now);
+        if self.to_move.is_some() {
+            if let Some(time_control) = self.time_control {
+                *self.time_left_mut(side) += Duration::from_millis(time_control.increment_ms());
+            }
+        } else {
+            self.started_at = Some(now_secs());
+        }
+        self.to_move = Some(!side);
+
+        if is_checkmate {
+            self.finish(Outcome::Win(side), Termination::Checkmate);
+        } else {
+            self.start_clock(now);
+        }
+
+        true
+    }
+
+    fn finish(&mut self, outcome: Outcome, termination: Termination) -> GameResult {
+        let result = GameResult::new(outcome, termination);
+        self.running_since = None;
+        self.result = Some(result);
+
+        result
+    }
+
+    /// Ends the game when the side to move ran out of time
+    pub fn check_flag(&mut self, now: Instant) -> Option<GameResult> {
+        if !self.is_in_progress() || self.time_control.is_none() {
+            return None;
+        }
+
+        let side = self.to_move?;
+        if !self.time_left(side, now).is_zero() {
+            return None;
+        }
+
+        self.stop_clock(now);
+        Some(self.finish(Outcome::Win(!side), Termination::Timeout))
+    }
+
+    /// The player lost its last connection, returns whether it is seated in
+    /// a game in progress
+    pub fn set_away(&mut self, user_id: &str, now: Instant) -> bool {
+        let Some(side) = self.side_of(user_id) else {
+            return false;
+        };
+        if !self.is_in_progress() {
+            return false;
+        }
+
+        self.stop_clock(now);
+        if let Some(seat) = self.seat_mut(side) {
+            seat.away_since.get_or_insert(now);
+        }
+        self.start_clock(now);
+
+        true
+    }
+
+    /// The player reconnected, returns whether it was away from its seat
+    pub fn set_back(&mut self, user_id: &str, now: Instant) -> bool {
+        let Some(side) = self.side_of(user_id) else {
+            return false;
+        };
+        if self.seat(side).and_then(|s| s.away_since).is_none() {
+            return false;
+        }
+
+        self.stop_clock(now);
+        if let Some(seat) = self.seat_mut(side) {
+            seat.away_since = None;
+        }
+        self.start_clock(now);
+
+        true
+    }
+
+    /// Ends the game in favour of `user_id` when its opponent has been away
+    /// for longer than the grace period
+    pub fn claim(
+        &mut self,
+        user_id: &str,
+        claim: Claim,
+        now: Instant,
+    ) -> Result<GameResult, GameError> {
+        let side = self.side_of(user_id).ok_or(GameError::NotSeated)?;
+        if !self.is_in_progress() {
+            return Err(GameError::NotInProgress);
+        }
+
+        let away_since = self
+            .seat(!side)
+            .and_then(|s| s.away_since)
+            .ok_or(GameError::OpponentConnected)?;
+        if now.duration_since(away_since) < self.grace {
+            return Err(GameError::GracePeriod);
+        }
+
+        self.stop_clock(now);
+        let outcome = match claim {
+            Claim::Win => Outcome::Win(side),
+            Claim::Draw => Outcome::Draw,
+        };
+
+        Ok(self.finish(outcome, Termination::Abandonment))
+    }
+
+    pub fn status(&self, now: Instant) -> GameStatus {
+        let seat_status = |side: Turn| {
+            self.seat(side).map(|seat| SeatStatus {
+                user_id: seat.user_id.clone(),
+                name: seat.name.clone(),
+                grace_ms: seat.away_since.map(|since| {
+                    self.grace
+                        .saturating_sub(now.duration_since(since))
+                        .as_millis() as u64
+                }),
+            })
+        };
+
+        GameStatus {
+            white: seat_status(Turn::White),
+            black: seat_status(Turn::Black),
+            clock: self.time_control.map(|time_control| ClockStatus {
+                time_control,
+                white_ms: self.time_left(Turn::White, now).as_millis() as u64,
+                black_ms: self.time_left(Turn::Black, now).as_millis() as u64,
+                running: self.running_since.and(self.to_move),
+            }),
+            result: self.result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started_game(now: Instant) -> Game {
+        let mut game = Game::new();
+        game.grace = Duration::from_secs(60);
+        game.set_time_control(Some(TimeControl {
+            base: 5,
+            increment: 0,
+        }))
+        .unwrap();
+        game.sit("w", "White", true, Turn::White).unwrap();
+        game.sit("b", "Black", false, Turn::Black).unwrap();
+        game.record_move(Turn::White, false, now);
+
+        game
+    }
+
+    #[test]
+    fn test_abandonment() {
+        let now = Instant::now();
+        let mut game = started_game(now);
+
+        assert_eq!(
+            game.claim("w", Claim::Win, now),
+            Err(GameError::OpponentConnected)
+        );
+
+        assert!(game.set_away("b", now));
+        let later = now + Duration::from_secs(30);
+        assert_eq!(
+            game.claim("w", Claim::Win, later),
+            Err(GameError::GracePeriod)
+        );
+
+        // back within the grace period, the game goes on
+        assert!(game.set_back("b", later));
+        assert!(game.is_in_progress());
+
+        assert!(game.set_away("b", later));
+        let expired = later + Duration::from_secs(60);
+        assert_eq!(
+            game.claim("b", Claim::Win, expired),
+            Err(GameError::OpponentConnected)
+        );
+        let result = game.claim("w", Claim::Draw, expired).unwrap();
+        assert_eq!(result.to_string(), "1/2-1/2 abandonment");
+        assert!(!game.is_in_progress());
+    }
+
+    #[test]
+    fn test_rated() {
+        let now = Instant::now();
+        let mut game = started_game(now);
+
+        assert_eq!(game.set_rated(true), Err(GameError::InProgress));
+        game.reset();
+        game.set_rated(true).unwrap();
+        assert!(game.seat(Turn::Black).is_none());
+        assert_eq!(
+            game.sit("b", "Black", false, Turn::Black),
+            Err(GameError::NotRegistered)
+        );
+
+        game.sit("b", "Black", true, Turn::Black).unwrap();
+        assert_eq!(game.rated_pool(), Some(Pool::Blitz));
+        game.set_time_control(None).unwrap();
+        assert_eq!(game.rated_pool(), None);
+    }
+
+    #[test]
+    fn test_control() {
+        let now = Instant::now();
+        let mut game = Game::new();
+        assert_eq!(game.check_control("spectator"), Ok(()));
+
+        game.sit("w", "White", false, Turn::White).unwrap();
+        assert_eq!(game.check_control("w"), Ok(()));
+        assert_eq!(game.check_control("spectator"), Err(GameError::NotSeated));
+
+        // nobody resets a game in progress, not even its players
+        let mut game = started_game(now);
+        assert_eq!(game.check_control("w"), Err(GameError::InProgress));
+        assert_eq!(game.check_control("spectator"), Err(GameError::InProgress));
+
+        game.set_away("b", now);
+        game.claim("w", Claim::Win, now + Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(game.check_control("b"), Ok(()));
+        assert_eq!(game.check_control("spectator"), Err(GameError::NotSeated));
+    }
+
+    #[test]
+    fn test_turns() {
+        let now = Instant::now();
+        let mut game = started_game(now);
+
+        assert_eq!(
+            game.check_move("w", Turn::White),
+            Err(GameError::NotYourTurn)
+        );
+        assert_eq!(
+            game.check_move("w", Turn::Black),
+            Err(GameError::NotYourPiece)
+        );
+        assert_eq!(game.check_move("b", Turn::Black), Ok(()));
+
+        game.record_move(Turn::Black, false, now);
+        assert_eq!(
+            game.check_move("b", Turn::Black),
+            Err(GameError::NotYourTurn)
+        );
+        assert_eq!(game.check_move("w", Turn::White), Ok(()));
+    }
+
+    #[test]
+    fn test_pause_when_away() {
+        let now = Instant::now();
+        let mut game = started_game(now);
+        game.set_pause_when_away(true, now);
+
+        game.set_away("b", now + Duration::from_secs(10));
+        let later = now + Duration::from_secs(100);
+        assert_eq!(game.time_left(Turn::Black, later), Duration::from_secs(290));
+
+        game.set_away("b", now);
+        game.set_pause_when_away(false, later);
+        assert_eq!(
+            game.time_left(Turn::Black, later + Duration::from_secs(10)),
+            Duration::from_secs(280)
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Moves of a room as a tree. A move played from an earlier position starts
+//! a variation instead of dropping the moves after it, undo and redo walk
+//! the line last visited.
+
+use std::collections::HashMap;
+
+use crate::entities::{
+    annotation::Annotation,
+    history::{History, HistoryMove},
+};
+
+use super::room::MoveResult;
+
+#[derive(Clone, Debug)]
+struct Node {
+    result: MoveResult,
+    parent: Option<usize>,
+    /// The first one is the main line
+    children: Vec<usize>,
+    /// Child redo goes to
+    line: Option<usize>,
+    annotation: Annotation,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MoveTree {
+    nodes: HashMap<usize, Node>,
+    /// Moves from the start position, the first one is the main line
+    roots: Vec<usize>,
+    root_line: Option<usize>,
+    /// Notes on the start position
+    start_annotation: Annotation,
+    current: Option<usize>,
+    next_id: usize,
+}
+
+impl MoveTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn get(&self, id: usize) -> Option<&MoveResult> {
+        self.nodes.get(&id).map(|node| &node.result)
+    }
+
+    fn children(&self, parent: Option<usize>) -> &[usize] {
+        match parent {
+            Some(id) => &self.nodes[&id].children,
+            None => &self.roots,
+        }
+    }
+
+    fn children_mut(&mut self, parent: Option<usize>) -> &mut Vec<usize> {
+        match parent {
+            Some(id) => &mut self.nodes.get_mut(&id).unwrap().children,
+            None => &mut self.roots,
+        }
+    }
+
+    fn set_line(&mut self, parent: Option<usize>, child: Option<usize>) {
+        match parent {
+            Some(id) => self.nodes.get_mut(&id).unwrap().line = child,
+            None => self.root_line = child,
+        }
+    }
+
+    fn line_of(&self, parent: Option<usize>) -> Option<usize> {
+        let line = match parent {
+            Some(id) => self.nodes[&id].line,
+            None => self.root_line,
+        };
+
+        line.or(self.children(parent).first().copied())
+    }
+
+    /// Plays a move from the current position, a move already in the tree
+    /// is followed instead of being added twice
+    pub fn push(&mut self, result: MoveResult) -> usize {
+        let parent = self.current;
+        let existing = self.children(parent).iter().copied().find(|id| {
+            let other = &self.nodes[id].result;
+            other.current_fen == result.current_fen && other.current_trash == result.current_trash
+        });
+
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.nodes.insert(
+                    id,
+                    Node {
+                        result,
+                        parent,
+                        children: vec![],
+                        line: None,
+                        annotation: Annotation::default(),
+                    },
+                );
+                self.children_mut(parent).push(id);
+                id
+            }
+        };
+
+        self.set_line(parent, Some(id));
+        self.current = Some(id);
+
+        id
+    }
+
+    /// Steps back and returns the move taken back
+    pub fn undo(&mut self) -> Option<MoveResult> {
+        let node = &self.nodes[&self.current?];
+        let result = node.result.clone();
+        self.current = node.parent;
+
+        Some(result)
+    }
+
+    /// Steps forward along the line last visited
+    pub fn redo(&mut self) -> Option<MoveResult> {
+        let id = self.line_of(self.current)?;
+        self.current = Some(id);
+
+        Some(self.nodes[&id].result.clone())
+    }
+
+    /// Goes to a move, or to the start position for `None`, redo then
+    /// follows the line leading to it
+    pub fn goto(&mut self, id: Option<usize>) -> Result<(), ()> {
+        if let Some(id) = id {
+            if !self.nodes.contains_key(&id) {
+                return Err(());
+            }
+        }
+
+        let mut child = id;
+        while let Some(id) = child {
+            let parent = self.nodes[&id].parent;
+            self.set_line(parent, Some(id));
+            child = parent;
+        }
+        self.current = id;
+
+        Ok(())
+    }
+
+    /// Makes the line leading to a move the main line
+    pub fn promote(&mut self, id: usize) -> Result<(), ()> {
+        if !self.nodes.contains_key(&id) {
+            return Err(());
+        }
+
+        let mut child = Some(id);
+        while let Some(id) = child {
+            let parent = self.nodes[&id].parent;
+            let siblings = self.children_mut(parent);
+            if let Some(i) = siblings.iter().position(|sibling| *sibling == id) {
+                let id = siblings.remove(i);
+                siblings.insert(0, id);
+            }
+            child = parent;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes a move with everything after it, the current move goes back
+    /// to its parent when it was deleted. Returns whether it moved.
+    pub fn delete(&mut self, id: usize) -> Result<bool, ()> {
+        let Some(parent) = self.nodes.get(&id).map(|node| node.parent) else {
+            return Err(());
+        };
+
+        self.children_mut(parent).retain(|child| *child != id);
+        if self.line_of(parent) == Some(id) {
+            self.set_line(parent, None);
+        }
+
+        let mut moved = false;
+        let mut deleted = vec![id];
+        while let Some(id) = deleted.pop() {
+            if let Some(node) = self.nodes.remove(&id) {
+                moved |= self.current == Some(id);
+                deleted.extend(node.children);
+            }
+        }
+        if moved {
+            self.current = parent;
+        }
+
+        Ok(moved)
+    }
+
+    /// Notes on a move, or on the start position for `None`
+    pub fn annotation(&self, node: Option<usize>) -> Option<&Annotation> {
+        match node {
+            Some(id) => self.nodes.get(&id).map(|node| &node.annotation),
+            None => Some(&self.start_annotation),
+        }
+    }
+
+    /// Notes to edit on a move, or on the start position for `None`
+    pub fn annotation_mut(&mut self, node: Option<usize>) -> Option<&mut Annotation> {
+        match node {
+            Some(id) => self.nodes.get_mut(&id).map(|node| &mut node.annotation),
+            None => Some(&mut self.start_annotation),
+        }
+    }
+
+    /// Ids of the moves from the start position to a move
+    pub fn path(&self, node: Option<usize>) -> Vec<usize> {
+        let mut path = vec![];
+        let mut node = node.filter(|id| self.nodes.contains_key(id));
+        while let Some(id) = node {
+            path.push(id);
+            node = self.nodes[&id].parent;
+        }
+        path.reverse();
+
+        path
+    }
+
+    /// Moves from the start position to the current one
+    pub fn line(&self) -> Vec<&MoveResult> {
+        self.path(self.current)
+            .into_iter()
+            .map(|id| &self.nodes[&id].result)
+            .collect()
+    }
+
+    fn push_history(&self, parent: Option<usize>, moves: &mut Vec<HistoryMove>) {
+        for id in self.children(parent) {
+            let node = &self.nodes[id];
+            moves.push(HistoryMove {
+                id: *id,
+                parent,
+                san: node.result.event.san.clone(),
+                annotation: node.annotation.clone(),
+            });
+            self.push_history(Some(*id), moves);
+        }
+    }
+
+    /// Tree shown to the clients, numbered from the start position
+    pub fn history(&self, start_fen: &str) -> History {
+        let mut fields = start_fen.split_whitespace().skip(1);
+        let black_first = fields.next() == Some("b");
+        let start_number = fields
+            .nth(3)
+            .and_then(|n| n.parse::<u32>().ok())
+            .unwrap_or(1);
+
+        let mut moves = vec![];
+        self.push_history(None, &mut moves);
+
+        History {
+            current: self.current,
+            start_number,
+            black_first,
+            start: self.start_annotation.clone(),
+            moves,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::chess_board::{enums::PromotionKind, ChessBoard, ChessBoardBuilder};
+    use crate::server::room::DEFAULT_FEN;
+
+    fn play(board: &mut ChessBoard, piece: &str, from: &str, to: &str) -> MoveResult {
+        let previous_fen = board.fen.clone();
+        let (chess_board_move, event) = board
+            .play_move(
+                piece,
+                from.parse().ok(),
+                to.parse().ok(),
+                PromotionKind::Queen,
+            )
+            .unwrap();
+
+        MoveResult {
+            from: from.parse().ok(),
+            to: to.parse().ok(),
+            stone: piece.parse().unwrap(),
+            chess_board_move,
+            previous_fen,
+            previous_trash: String::new(),
+            current_fen: event.fen.clone(),
+            current_trash: event.trash.clone(),
+            event,
+        }
+    }
+
+    #[test]
+    fn test_variations() {
+        let start = DEFAULT_FEN;
+        let board = || ChessBoardBuilder::new().fen(start).build().unwrap();
+        let mut tree = MoveTree::new();
+
+        let mut main = board();
+        let e4 = tree.push(play(&mut main, "lp", "e2", "e4"));
+        let e5 = tree.push(play(&mut main, "dp", "e7", "e5"));
+
+        let mut sicilian = board();
+        play(&mut sicilian, "lp", "e2", "e4");
+        tree.undo();
+        let c5 = tree.push(play(&mut sicilian, "dp", "c7", "c5"));
+
+        assert_eq!(tree.history(start).movetext(), "1. e4 e5 (1... c5)");
+
+        // redo follows the variation last visited
+        tree.undo();
+        assert_eq!(tree.redo().map(|m| m.event.san), Some("c5".to_string()));
+        tree.goto(Some(e5)).unwrap();
+        tree.goto(Some(e4)).unwrap();
+        assert_eq!(tree.redo().map(|m| m.event.san), Some("e5".to_string()));
+
+        tree.promote(c5).unwrap();
+        assert_eq!(tree.history(start).movetext(), "1. e4 c5 (1... e5)");
+        assert_eq!(tree.path(Some(c5)), vec![e4, c5]);
+
+        assert_eq!(tree.delete(e5), Ok(true));
+        assert_eq!(tree.current(), Some(e4));
+        assert_eq!(tree.history(start).movetext(), "1. e4 c5");
+        assert_eq!(tree.line().len(), 1);
+    }
+}
@@ -0,0 +1,145 @@
+//! HS256 tokens signed with one of several keys, the `kid` header names the
+//! key so old tokens keep verifying while the secret is rotated.
+
+use std::{collections::HashMap, env, fmt};
+
+use hmac::{Hmac, Mac};
+use jwt::{AlgorithmType, FromBase64, Header, SignWithKey, Token, Unverified, VerifyWithKey};
+use serde::Serialize;
+use sha2::Sha256;
+
+/// Kid of the key read from `JWT_SECRET`
+const DEFAULT_KID: &str = "default";
+
+#[derive(Debug)]
+pub enum KeysError {
+    Missing,
+    Invalid(String),
+}
+
+impl fmt::Display for KeysError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeysError::Missing => write!(f, "JWT_KEYS or JWT_SECRET must be set"),
+            KeysError::Invalid(entry) => write!(f, "invalid signing key `{}`", entry),
+        }
+    }
+}
+
+impl std::error::Error for KeysError {}
+
+#[derive(Debug)]
+pub enum TokenError {
+    Malformed,
+    UnknownKey,
+    InvalidSignature,
+    Expired,
+    WrongType,
+    Signing(jwt::Error),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed => write!(f, "malformed token"),
+            TokenError::UnknownKey => write!(f, "unknown signing key"),
+            TokenError::InvalidSignature => write!(f, "invalid signature"),
+            TokenError::Expired => write!(f, "expired token"),
+            TokenError::WrongType => write!(f, "wrong token type"),
+            TokenError::Signing(e) => write!(f, "failed to sign token: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+#[derive(Clone)]
+pub struct SigningKeys {
+    current: String,
+    keys: HashMap<String, Hmac<Sha256>>,
+}
+
+impl SigningKeys {
+    /// The first `(kid, secret)` pair signs new tokens, all of them verify
+    pub fn new<'a, I>(keys: I) -> Result<Self, KeysError>
+    where
+        I: IntoIterator<Item = (&'a str, &'a [u8])>,
+    {
+        let mut current = None;
+        let mut map = HashMap::new();
+
+        for (kid, secret) in keys {
+            if kid.is_empty() || secret.is_empty() {
+                return Err(KeysError::Invalid(kid.to_string()));
+            }
+            let key =
+                Hmac::new_from_slice(secret).map_err(|_| KeysError::Invalid(kid.to_string()))?;
+
+            current.get_or_insert_with(|| kid.to_string());
+            map.insert(kid.to_string(), key);
+        }
+
+        Ok(Self {
+            current: current.ok_or(KeysError::Missing)?,
+            keys: map,
+        })
+    }
+
+    /// Reads `JWT_KEYS=kid:secret,kid:secret`, newest first, or falls back to
+    /// a single `JWT_SECRET`
+    pub fn from_env() -> Result<Self, KeysError> {
+        if let Ok(keys) = env::var("JWT_KEYS") {
+            let pairs = keys
+                .split(',')
+                .map(|entry| entry.trim())
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| {
+                    entry
+                        .split_once(':')
+                        .map(|(kid, secret)| (kid, secret.as_bytes()))
+                        .ok_or_else(|| KeysError::Invalid(entry.to_string()))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            return Self::new(pairs);
+        }
+
+        match env::var("JWT_SECRET") {
+            Ok(secret) => Self::new([(DEFAULT_KID, secret.as_bytes())]),
+            Err(_) => Err(KeysError::Missing),
+        }
+    }
+
+    pub fn encode<C: Serialize>(&self, claims: &C) -> Result<String, TokenError> {
+        let header = Header {
+            algorithm: AlgorithmType::Hs256,
+            key_id: Some(self.current.clone()),
+            ..Default::default()
+        };
+
+        Token::new(header, claims)
+            .sign_with_key(&self.keys[&self.current])
+            .map(|t| t.as_str().to_owned())
+            .map_err(TokenError::Signing)
+    }
+
+    /// Verifies the signature with the key named by the token, tokens without
+    /// a kid use the `JWT_SECRET` one
+    pub fn decode<C: FromBase64>(&self, token: &str) -> Result<C, TokenError> {
+        let unverified: Token<Header, C, Unverified> =
+            Token::parse_unverified(token).map_err(|_| TokenError::Malformed)?;
+        let kid = unverified
+            .header()
+            .key_id
+            .clone()
+            .unwrap_or(DEFAULT_KID.to_string());
+        let key = self.keys.get(&kid).ok_or(TokenError::UnknownKey)?;
+
+        let verified = unverified
+            .verify_with_key(key)
+            .map_err(|_| TokenError::InvalidSignature)?;
+        let (_, claims) = verified.into();
+
+        Ok(claims)
+    }
+}
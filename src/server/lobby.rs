@@ -0,0 +1,445 @@
+//! `Lobby` is an actor next to the `ChessServer`. It keeps the open seeks,
+//! shows them to the sessions watching the lobby and pairs seekers, either
+//! when a user accepts a seek or when a new seek fits an open one. Seeks are
+//! matched in the order they were posted.
+//!
+//! Paired players get a room of their own from the `ChessServer`, both
+//! seated and with the clock of the seek.
+
+use std::{collections::HashMap, sync::Arc};
+
+use actix::prelude::*;
+
+use crate::entities::{
+    game::Pool,
+    seek::{ColorChoice, Seek, SeekRequest},
+};
+
+use super::{
+    chess_server::{self, ChessServer, Message, Player, StartGame},
+    game::GameError,
+    metrics::Metrics,
+    ratings::{glicko::DEFAULT_RATING, Rating, RatingRepository},
+    telemetry::{traced_handlers, Traced},
+    websockets::session::WsChessSession,
+};
+
+/// User of a session posting or accepting a seek
+#[derive(Clone, Debug)]
+pub struct Seeker {
+    pub session_id: String,
+    pub user_id: String,
+    pub name: String,
+    pub registered: bool,
+    pub addr: Addr<WsChessSession>,
+}
+
+impl Seeker {
+    fn notify(&self, message: &str) {
+        self.addr.do_send(Message(message.to_owned()));
+    }
+
+    fn player(&self) -> Player {
+        Player {
+            session_id: Some(self.session_id.clone()),
+            user_id: self.user_id.clone(),
+            name: self.name.clone(),
+            registered: self.registered,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct OpenSeek {
+    seek: Seek,
+    seeker: Seeker,
+    /// Rating in the pool of the seek, new players for anonymous seekers
+    rating: f64,
+}
+
+/// Posts a seek, it replaces the open seek of the user
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct PostSeek {
+    pub seeker: Seeker,
+    pub request: SeekRequest,
+}
+
+/// Withdraws the open seek of a user
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CancelSeek {
+    pub user_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct AcceptSeek {
+    pub seeker: Seeker,
+    pub seek_id: String,
+}
+
+/// Session shows the lobby, it gets the open seeks and their changes
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Watch {
+    pub id: String,
+    pub addr: Addr<WsChessSession>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Unwatch {
+    pub id: String,
+}
+
+/// Session closed, the seeks it posted are withdrawn
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct LeaveLobby {
+    pub id: String,
+}
+
+/// Whether two seeks can be paired, each seeker must be in the rating range
+/// of the other
+pub fn is_match(a: &SeekRequest, a_rating: f64, b: &SeekRequest, b_rating: f64) -> bool {
+    a.time_control == b.time_control
+        && a.rated == b.rated
+        && a.color.fits(b.color)
+        && a.range.map(|range| range.contains(b_rating)) != Some(false)
+        && b.range.map(|range| range.contains(a_rating)) != Some(false)
+}
+
+/// Whether the first seeker plays white, sides nobody asked for are drawn
+pub fn plays_white(first: ColorChoice, second: ColorChoice) -> bool {
+    match (first, second) {
+        (ColorChoice::White, _) | (_, ColorChoice::Black) => true,
+        (ColorChoice::Black, _) | (_, ColorChoice::White) => false,
+        _ => rand::random(),
+    }
+}
+
+pub struct Lobby {
+    /// Open seeks, oldest first
+    seeks: Vec<OpenSeek>,
+    /// Sessions showing the lobby
+    watchers: HashMap<String, Addr<WsChessSession>>,
+    server: Addr<ChessServer>,
+    ratings: Arc<dyn RatingRepository>,
+    metrics: Arc<Metrics>,
+}
+
+impl Lobby {
+    pub fn new(
+        server: Addr<ChessServer>,
+        ratings: Arc<dyn RatingRepository>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            seeks: vec![],
+            watchers: HashMap::new(),
+            server,
+            ratings,
+            metrics,
+        }
+    }
+
+    fn send_to_watchers(&self, message: &str) {
+        for addr in self.watchers.values() {
+            addr.do_send(Message(message.to_owned()));
+        }
+    }
+
+    fn sync_seeks_message(&self) -> String {
+        let seeks: Vec<String> = self
+            .seeks
+            .iter()
+            .map(|open| open.seek.to_string())
+            .collect();
+
+        format!("/sync_seeks {}", seeks.join(","))
+    }
+
+    /// Withdraws the seeks matching `predicate`
+    fn remove_seeks<P>(&mut self, predicate: P)
+    where
+        P: Fn(&OpenSeek) -> bool,
+    {
+        let (removed, kept): (Vec<OpenSeek>, Vec<OpenSeek>) = std::mem::take(&mut self.seeks)
+            .into_iter()
+            .partition(|open| predicate(open));
+        self.seeks = kept;
+
+        for open in removed {
+            self.send_to_watchers(&format!("/remove_seek {}", open.seek.id));
+        }
+    }
+
+    /// Runs `then` with the rating of the seeker in `pool`, anonymous users
+    /// have none
+    fn with_rating<F>(&mut self, seeker: Seeker, pool: Pool, ctx: &mut Context<Self>, then: F)
+    where
+        F: FnOnce(&mut Self, Seeker, Option<Rating>, &mut Context<Self>) + 'static,
+    {
+        if !seeker.registered {
+            then(self, seeker, None, ctx);
+            return;
+        }
+
+        let repository = self.ratings.clone();
+        let user_id = seeker.user_id.clone();
+        let fut = async move { repository.ratings_of(&user_id).await };
+
+        ctx.spawn(
+            fut.into_actor(self)
+                .map(move |ratings, act, ctx| match ratings {
+                    Ok(ratings) => {
+                        let rating = ratings.get(&pool).copied().unwrap_or_default();
+                        then(act, seeker, Some(rating), ctx);
+                    }
+                    Err(e) => {
+                        tracing::error!(error = ?e, "Failed to load the rating of a seeker");
+                        seeker.notify("/notify error Failed to load your rating");
+                    }
+                }),
+        );
+    }
+
+    /// Pairs the new seek with the oldest one it fits or leaves it open
+    fn open_seek(&mut self, seeker: Seeker, request: SeekRequest, rating: Option<Rating>) {
+        let open = OpenSeek {
+            seek: Seek {
+                id: uuid::Uuid::new_v4().simple().to_string(),
+                user_id: seeker.user_id.clone(),
+                name: seeker.name.clone(),
+                rating: rating.map(|rating| rating.to_string()),
+                request,
+            },
+            rating: rating.map(|rating| rating.rating).unwrap_or(DEFAULT_RATING),
+            seeker,
+        };
+        self.remove_seeks(|other| other.seek.user_id == open.seek.user_id);
+
+        let matched = self.seeks.iter().position(|other| {
+            is_match(
+                &other.seek.request,
+                other.rating,
+                &open.seek.request,
+                open.rating,
+            )
+        });
+
+        match matched {
+            Some(idx) => {
+                let other = self.seeks.remove(idx);
+                self.send_to_watchers(&format!("/remove_seek {}", other.seek.id));
+
+                let first_white = plays_white(other.seek.request.color, request.color);
+                self.pair(other.seeker, open.seeker, request, first_white);
+            }
+            None => {
+                tracing::info!(seek_id = %open.seek.id, seek = %request.to_string(), "Seek posted");
+                open.seeker
+                    .notify("/notify success Waiting for an opponent");
+                self.send_to_watchers(&format!("/add_seek {}", open.seek.to_string()));
+                self.seeks.push(open);
+            }
+        }
+    }
+
+    fn accept_seek(&mut self, seeker: Seeker, seek_id: &str, rating: Option<Rating>) {
+        let Some(idx) = self.seeks.iter().position(|open| open.seek.id == seek_id) else {
+            seeker.notify("/notify warning The seek is no longer open");
+            return;
+        };
+
+        let open = &self.seeks[idx];
+        let rating = rating.map(|rating| rating.rating).unwrap_or(DEFAULT_RATING);
+        let refusal = if open.seek.user_id == seeker.user_id {
+            Some("You can't accept your own seek")
+        } else if open.seek.request.rated && !seeker.registered {
+            Some(GameError::NotRegistered.as_str())
+        } else if open.seek.request.range.map(|range| range.contains(rating)) == Some(false) {
+            Some("Your rating is outside the range of the seek")
+        } else {
+            None
+        };
+        if let Some(refusal) = refusal {
+            seeker.notify(&format!("/notify warning {}", refusal));
+            return;
+        }
+
+        let open = self.seeks.remove(idx);
+        self.send_to_watchers(&format!("/remove_seek {}", open.seek.id));
+        self.remove_seeks(|other| other.seek.user_id == seeker.user_id);
+
+        let first_white = plays_white(open.seek.request.color, ColorChoice::Random);
+        self.pair(open.seeker, seeker, open.seek.request, first_white);
+    }
+
+    /// Asks the server for a room where both seekers are seated
+    fn pair(&mut self, first: Seeker, second: Seeker, request: SeekRequest, first_white: bool) {
+        let (white, black) = if first_white {
+            (first, second)
+        } else {
+            (second, first)
+        };
+
+        tracing::info!(
+            white = %white.user_id,
+            black = %black.user_id,
+            seek = %request.to_string(),
+            "Seekers paired"
+        );
+        self.server.do_send(Traced::new(StartGame {
+            room: chess_server::new_game_room(),
+            white: white.player(),
+            black: black.player(),
+            time_control: Some(request.time_control),
+            rated: request.rated,
+            watcher: None,
+        }));
+    }
+}
+
+impl Actor for Lobby {
+    type Context = Context<Self>;
+}
+
+impl Handler<PostSeek> for Lobby {
+    type Result = ();
+
+    #[tracing::instrument(name = "post_seek", skip_all, fields(session_id = %msg.seeker.session_id))]
+    fn handle(&mut self, msg: PostSeek, ctx: &mut Self::Context) {
+        let _timer = self.metrics.handler_timer("post_seek");
+
+        let PostSeek { seeker, request } = msg;
+        if request.rated && !seeker.registered {
+            seeker.notify(&format!(
+                "/notify warning {}",
+                GameError::NotRegistered.as_str()
+            ));
+            return;
+        }
+
+        self.with_rating(
+            seeker,
+            request.time_control.pool(),
+            ctx,
+            move |act, seeker, rating, _| act.open_seek(seeker, request, rating),
+        );
+    }
+}
+
+impl Handler<CancelSeek> for Lobby {
+    type Result = ();
+
+    #[tracing::instrument(name = "cancel_seek", skip_all, fields(user_id = %msg.user_id))]
+    fn handle(&mut self, msg: CancelSeek, _: &mut Self::Context) {
+        let _timer = self.metrics.handler_timer("cancel_seek");
+
+        self.remove_seeks(|open| open.seek.user_id == msg.user_id);
+    }
+}
+
+impl Handler<AcceptSeek> for Lobby {
+    type Result = ();
+
+    #[tracing::instrument(name = "accept_seek", skip_all, fields(session_id = %msg.seeker.session_id, seek_id = %msg.seek_id))]
+    fn handle(&mut self, msg: AcceptSeek, ctx: &mut Self::Context) {
+        let _timer = self.metrics.handler_timer("accept_seek");
+
+        let AcceptSeek { seeker, seek_id } = msg;
+        let Some(pool) = self
+            .seeks
+            .iter()
+            .find(|open| open.seek.id == seek_id)
+            .map(|open| open.seek.request.time_control.pool())
+        else {
+            seeker.notify("/notify warning The seek is no longer open");
+            return;
+        };
+
+        self.with_rating(seeker, pool, ctx, move |act, seeker, rating, _| {
+            act.accept_seek(seeker, &seek_id, rating)
+        });
+    }
+}
+
+impl Handler<Watch> for Lobby {
+    type Result = ();
+
+    fn handle(&mut self, msg: Watch, _: &mut Self::Context) {
+        msg.addr.do_send(Message(self.sync_seeks_message()));
+        self.watchers.insert(msg.id, msg.addr);
+    }
+}
+
+impl Handler<Unwatch> for Lobby {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unwatch, _: &mut Self::Context) {
+        self.watchers.remove(&msg.id);
+    }
+}
+
+impl Handler<LeaveLobby> for Lobby {
+    type Result = ();
+
+    fn handle(&mut self, msg: LeaveLobby, _: &mut Self::Context) {
+        self.watchers.remove(&msg.id);
+        self.remove_seeks(|open| open.seeker.session_id == msg.id);
+    }
+}
+
+traced_handlers!(Lobby; PostSeek, CancelSeek, AcceptSeek);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(s: &str) -> SeekRequest {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_seek_request() {
+        let seek = request("5+3 black rated 1400-1700");
+        assert_eq!(seek.color, ColorChoice::Black);
+        assert!(seek.rated);
+        assert_eq!(seek.to_string(), "5+3 black rated 1400-1700");
+        assert_eq!(request("10+0").to_string(), "10+0 random casual");
+        assert!("5+3 blue".parse::<SeekRequest>().is_err());
+        assert!("5+3 1700-1400".parse::<SeekRequest>().is_err());
+    }
+
+    #[test]
+    fn test_is_match() {
+        let open = request("5+3 white rated 1400-1700");
+
+        assert!(is_match(&open, 1500.0, &request("5+3 rated"), 1600.0));
+        assert!(!is_match(&open, 1500.0, &request("5+3 rated"), 1800.0));
+        assert!(!is_match(
+            &open,
+            1500.0,
+            &request("5+3 white rated"),
+            1600.0
+        ));
+        assert!(!is_match(&open, 1500.0, &request("5+3 casual"), 1600.0));
+        assert!(!is_match(&open, 1500.0, &request("3+2 rated"), 1600.0));
+        assert!(!is_match(
+            &open,
+            1500.0,
+            &request("5+3 rated 1000-1200"),
+            1600.0
+        ));
+    }
+
+    #[test]
+    fn test_plays_white() {
+        assert!(plays_white(ColorChoice::White, ColorChoice::Random));
+        assert!(plays_white(ColorChoice::Random, ColorChoice::Black));
+        assert!(!plays_white(ColorChoice::Black, ColorChoice::Random));
+        assert!(!plays_white(ColorChoice::Random, ColorChoice::White));
+    }
+}
@@ -0,0 +1,140 @@
+//! Prometheus metrics exposed on `/metrics`.
+//!
+//! Moves per second is not stored directly, it is meant to be queried as
+//! `rate(chess_moves_total[1m])`.
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::entities::chess_board::enums::{ChessBoardError, MoveError};
+
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    /// Open websocket sessions
+    pub active_sessions: IntGauge,
+    /// Known users by status, `online` or `away`
+    pub users: IntGaugeVec,
+    /// Rooms kept by the chess server
+    pub rooms: IntGauge,
+    /// Room actors restarted after they stopped unexpectedly
+    pub room_restarts: IntCounter,
+    /// Accepted moves
+    pub moves: IntCounter,
+    /// Rejected moves by `MoveError` kind, or `stale` when based on an old board
+    pub rejected_moves: IntCounterVec,
+    /// Websocket connections opened
+    pub ws_connects: IntCounter,
+    /// Websocket connections closed
+    pub ws_disconnects: IntCounter,
+    /// Sessions dropped because the client stopped answering pings
+    pub heartbeat_timeouts: IntCounter,
+    /// Users that connected for the first time
+    pub visitors: IntCounter,
+    /// Time spent by the chess server handling each message type
+    pub handler_duration: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("chess".to_string()), None)?;
+
+        let active_sessions = IntGauge::new("active_sessions", "Open websocket sessions")?;
+        let users = IntGaugeVec::new(Opts::new("users", "Known users by status"), &["status"])?;
+        let rooms = IntGauge::new("rooms", "Rooms kept by the chess server")?;
+        let room_restarts = IntCounter::new(
+            "room_restarts_total",
+            "Room actors restarted after they stopped unexpectedly",
+        )?;
+        let moves = IntCounter::new("moves_total", "Accepted moves")?;
+        let rejected_moves = IntCounterVec::new(
+            Opts::new("rejected_moves_total", "Rejected moves by error kind"),
+            &["kind"],
+        )?;
+        let ws_connects = IntCounter::new("ws_connects_total", "Websocket connections opened")?;
+        let ws_disconnects =
+            IntCounter::new("ws_disconnects_total", "Websocket connections closed")?;
+        let heartbeat_timeouts = IntCounter::new(
+            "heartbeat_timeouts_total",
+            "Sessions dropped because of a heartbeat timeout",
+        )?;
+        let visitors =
+            IntCounter::new("visitors_total", "Users that connected for the first time")?;
+        let handler_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "handler_duration_seconds",
+                "Time spent by the chess server handling a message",
+            )
+            .buckets(vec![
+                0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+                0.25, 0.5,
+            ]),
+            &["message"],
+        )?;
+
+        registry.register(Box::new(active_sessions.clone()))?;
+        registry.register(Box::new(users.clone()))?;
+        registry.register(Box::new(rooms.clone()))?;
+        registry.register(Box::new(room_restarts.clone()))?;
+        registry.register(Box::new(moves.clone()))?;
+        registry.register(Box::new(rejected_moves.clone()))?;
+        registry.register(Box::new(ws_connects.clone()))?;
+        registry.register(Box::new(ws_disconnects.clone()))?;
+        registry.register(Box::new(heartbeat_timeouts.clone()))?;
+        registry.register(Box::new(visitors.clone()))?;
+        registry.register(Box::new(handler_duration.clone()))?;
+
+        Ok(Self {
+            registry,
+            active_sessions,
+            users,
+            rooms,
+            room_restarts,
+            moves,
+            rejected_moves,
+            ws_connects,
+            ws_disconnects,
+            heartbeat_timeouts,
+            visitors,
+            handler_duration,
+        })
+    }
+
+    /// Starts a timer that records the handler latency when dropped
+    pub fn handler_timer(&self, message: &str) -> HistogramTimer {
+        self.handler_duration
+            .with_label_values(&[message])
+            .start_timer()
+    }
+
+    pub fn reject_move(&self, error: &ChessBoardError) {
+        let kind = match error {
+            ChessBoardError::InvalidMove(MoveError::NoStoneFound) => "no_stone_found",
+            ChessBoardError::InvalidMove(MoveError::InvalidMove) => "invalid_move",
+            ChessBoardError::InvalidFen(_) => "invalid_fen",
+            ChessBoardError::InvalidDeletedStones => "invalid_deleted_stones",
+            ChessBoardError::BuildError => "build_error",
+        };
+
+        self.rejected_moves.with_label_values(&[kind]).inc();
+    }
+
+    pub fn reject_stale_move(&self) {
+        self.rejected_moves.with_label_values(&["stale"]).inc();
+    }
+
+    pub fn set_users(&self, online: usize, away: usize) {
+        self.users.with_label_values(&["online"]).set(online as i64);
+        self.users.with_label_values(&["away"]).set(away as i64);
+    }
+
+    /// Metrics in the Prometheus text format
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+    }
+}
@@ -0,0 +1,298 @@
+//! Cross site request checks.
+//!
+//! Websocket handshakes and state changing requests must come from an
+//! allowed `Origin`, the page itself or one listed in `ALLOWED_ORIGINS`.
+//! State changing requests also need a double submit token: the
+//! `csrf_token` cookie, readable by the page, echoed in the `X-CSRF-Token`
+//! header, which other sites can't do.
+
+use std::{env, rc::Rc};
+
+use actix_service::{Service, Transform};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    cookie::{Cookie, SameSite},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, Method},
+    Error, HttpResponse,
+};
+use futures::{
+    future::{ok, Ready},
+    Future,
+};
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+#[derive(Clone, Debug, Default)]
+pub struct AllowedOrigins {
+    origins: Vec<String>,
+}
+
+impl AllowedOrigins {
+    pub fn new(origins: Vec<String>) -> Self {
+        Self {
+            origins: origins
+                .into_iter()
+                .map(|origin| origin.trim_end_matches('/').to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Comma separated `ALLOWED_ORIGINS`, like `https://chess.example.com`
+    pub fn from_env() -> Self {
+        let origins = env::var("ALLOWED_ORIGINS").unwrap_or_default();
+
+        Self::new(
+            origins
+                .split(',')
+                .map(|origin| origin.trim())
+                .filter(|origin| !origin.is_empty())
+                .map(String::from)
+                .collect(),
+        )
+    }
+
+    /// Requests from the page served by this host are always allowed
+    pub fn is_allowed(&self, origin: &str, host: &str) -> bool {
+        let origin = origin.to_lowercase();
+        let same_origin = origin
+            .split_once("://")
+            .map(|(_, origin_host)| origin_host == host.to_lowercase())
+            .unwrap_or(false);
+
+        same_origin || self.origins.contains(&origin)
+    }
+}
+
+pub struct CsrfProtection {
+    origins: Rc<AllowedOrigins>,
+    secure_cookie: bool,
+}
+
+impl CsrfProtection {
+    pub fn new(origins: AllowedOrigins, secure_cookie: bool) -> Self {
+        Self {
+            origins: Rc::new(origins),
+            secure_cookie,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfProtection
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CsrfProtectionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CsrfProtectionMiddleware {
+            service,
+            origins: self.origins.clone(),
+            secure_cookie: self.secure_cookie,
+        })
+    }
+}
+
+pub struct CsrfProtectionMiddleware<S> {
+    service: S,
+    origins: Rc<AllowedOrigins>,
+    secure_cookie: bool,
+}
+
+fn is_websocket_upgrade(req: &ServiceRequest) -> bool {
+    req.headers()
+        .get(header::UPGRADE)
+        .and_then(|upgrade| upgrade.to_str().ok())
+        .map(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+        .unwrap_or(false)
+}
+
+/// Whether the request is refused, with the reason
+fn rejection(req: &ServiceRequest, origins: &AllowedOrigins) -> Option<&'static str> {
+    let is_safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+
+    if is_safe && !is_websocket_upgrade(req) {
+        return None;
+    }
+
+    // browsers always send it on these requests, other clients can't be
+    // driven by a malicious page
+    if let Some(origin) = req.headers().get(header::ORIGIN) {
+        let host = req.connection_info().host().to_string();
+        let allowed = origin
+            .to_str()
+            .map(|origin| origins.is_allowed(origin, &host))
+            .unwrap_or(false);
+
+        if !allowed {
+            return Some("origin not allowed");
+        }
+    }
+
+    if is_safe {
+        return None;
+    }
+
+    let cookie = req.cookie(CSRF_COOKIE);
+    let token = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|token| token.to_str().ok());
+
+    match (cookie, token) {
+        (Some(cookie), Some(token)) if !token.is_empty() && cookie.value() == token => None,
+        _ => Some("missing or invalid csrf token"),
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfProtectionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Some(reason) = rejection(&req, &self.origins) {
+            tracing::warn!(path = req.path(), reason, "Cross site request rejected");
+            let res = HttpResponse::Forbidden().body(reason);
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+
+        // the page gets its token on the first request
+        let new_cookie = req.cookie(CSRF_COOKIE).is_none().then(|| {
+            Cookie::build(CSRF_COOKIE, uuid::Uuid::new_v4().simple().to_string())
+                .path("/")
+                .secure(self.secure_cookie)
+                .same_site(SameSite::Strict)
+                .finish()
+        });
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if let Some(cookie) = new_cookie {
+                res.response_mut().add_cookie(&cookie)?;
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, web, App};
+
+    const HOST: &str = "chess.example.com";
+
+    async fn status(req: test::TestRequest) -> StatusCode {
+        let app = test::init_service(
+            App::new()
+                .wrap(CsrfProtection::new(
+                    AllowedOrigins::new(vec!["https://other.example.com".to_string()]),
+                    true,
+                ))
+                .route("/ws", web::get().to(HttpResponse::Ok))
+                .route("/sessions", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        test::call_service(&app, req.insert_header((header::HOST, HOST)).to_request())
+            .await
+            .status()
+    }
+
+    fn upgrade(origin: &str) -> test::TestRequest {
+        test::TestRequest::get()
+            .uri("/ws")
+            .insert_header((header::UPGRADE, "websocket"))
+            .insert_header((header::ORIGIN, origin))
+    }
+
+    fn post(cookie: Option<&str>, token: Option<&str>) -> test::TestRequest {
+        let mut req = test::TestRequest::post()
+            .uri("/sessions")
+            .insert_header((header::ORIGIN, "https://chess.example.com"));
+        if let Some(cookie) = cookie {
+            req = req.cookie(Cookie::new(CSRF_COOKIE, cookie));
+        }
+        if let Some(token) = token {
+            req = req.insert_header((CSRF_HEADER, token));
+        }
+        req
+    }
+
+    #[actix_web::test]
+    async fn test_websocket_origin() {
+        assert_eq!(
+            status(upgrade("https://chess.example.com")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(upgrade("https://other.example.com")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(upgrade("https://evil.example.com")).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_double_submit_token() {
+        assert_eq!(status(post(None, None)).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(post(Some("token"), None)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(post(Some("token"), Some("other"))).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(post(Some("token"), Some("token"))).await,
+            StatusCode::OK
+        );
+
+        let cross_site = post(Some("token"), Some("token"))
+            .insert_header((header::ORIGIN, "https://evil.example.com"));
+        assert_eq!(status(cross_site).await, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_token_cookie() {
+        let app = test::init_service(
+            App::new()
+                .wrap(CsrfProtection::new(AllowedOrigins::default(), true))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        let cookie = res
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == CSRF_COOKIE)
+            .expect("csrf cookie should be set");
+        assert!(!cookie.value().is_empty());
+        assert_eq!(cookie.http_only(), None);
+    }
+}
@@ -1,3 +1,18 @@
+pub mod accounts;
+pub mod archive;
 pub mod chess_server;
+pub mod diagram;
+pub mod game;
+pub mod history;
+pub mod jwt;
+pub mod lobby;
+pub mod metrics;
 pub mod middlewares;
+pub mod rate_limit;
+pub mod ratings;
+pub mod responses;
+pub mod room;
+pub mod sessions;
+pub mod telemetry;
+pub mod tournaments;
 pub mod websockets;
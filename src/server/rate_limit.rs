@@ -0,0 +1,315 @@
+//! Token bucket rate limiting for websocket sessions.
+//!
+//! Every session owns a bucket and all sessions coming from the same IP also
+//! share an `IpRateLimiter` bucket, so opening several sockets does not give a
+//! client more throughput. The IP is the address of the peer, forwarded
+//! addresses are only read from the proxies listed in `TRUSTED_PROXIES`.
+
+use std::{
+    collections::HashMap,
+    env,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Amount of IP buckets kept before idle ones are dropped
+const IP_BUCKETS_CLEANUP_THRESHOLD: usize = 1024;
+
+pub(crate) fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<T>().ok())
+        .unwrap_or(default)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitConfig {
+    /// Tokens added to a session bucket per second
+    pub session_rate: f64,
+    /// Maximum tokens a session bucket can hold
+    pub session_burst: f64,
+    /// Tokens added to an IP bucket per second
+    pub ip_rate: f64,
+    /// Maximum tokens an IP bucket can hold
+    pub ip_burst: f64,
+    /// Maximum websocket frame size in bytes
+    pub max_frame_size: usize,
+    /// Maximum length of a text message in bytes
+    pub max_message_size: usize,
+    /// Violations tolerated before the session is disconnected
+    pub max_violations: u32,
+    /// Window after which the violations count is reset
+    pub violations_window: Duration,
+    /// Maximum rooms a single user can create
+    pub max_rooms_per_user: usize,
+    /// Maximum rooms on the server
+    pub max_rooms: usize,
+    /// Maximum unfinished tournaments a single user can create
+    pub max_tournaments_per_user: usize,
+    /// Maximum unfinished tournaments on the server
+    pub max_tournaments: usize,
+}
+
+impl RateLimitConfig {
+    pub fn from_env() -> Self {
+        Self {
+            session_rate: env_or("WS_SESSION_RATE", 5.0),
+            session_burst: env_or("WS_SESSION_BURST", 20.0),
+            ip_rate: env_or("WS_IP_RATE", 15.0),
+            ip_burst: env_or("WS_IP_BURST", 60.0),
+            max_frame_size: env_or("WS_MAX_FRAME_SIZE", 16_384),
+            max_message_size: env_or("WS_MAX_MESSAGE_SIZE", 512),
+            max_violations: env_or("WS_MAX_VIOLATIONS", 5),
+            violations_window: Duration::from_secs(env_or("WS_VIOLATIONS_WINDOW", 60)),
+            max_rooms_per_user: env_or("MAX_ROOMS_PER_USER", 5),
+            max_rooms: env_or("MAX_ROOMS", 1000),
+            max_tournaments_per_user: env_or("MAX_TOURNAMENTS_PER_USER", 2),
+            max_tournaments: env_or("MAX_TOURNAMENTS", 100),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64) -> Self {
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            updated_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated_at = now;
+    }
+
+    /// Whether `cost` tokens could be taken, without taking them
+    pub fn can_acquire(&mut self, cost: f64) -> bool {
+        self.refill(Instant::now());
+        self.tokens >= cost
+    }
+
+    /// Takes `cost` tokens from the bucket, returns false when there are not enough
+    pub fn try_acquire(&mut self, cost: f64) -> bool {
+        self.try_acquire_at(cost, Instant::now())
+    }
+
+    fn try_acquire_at(&mut self, cost: f64, now: Instant) -> bool {
+        self.refill(now);
+
+        if self.tokens >= cost {
+            self.tokens -= cost;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_full(&mut self) -> bool {
+        self.refill(Instant::now());
+        self.tokens >= self.capacity
+    }
+}
+
+/// Comma separated `TRUSTED_PROXIES`, like `127.0.0.1,10.0.0.2`
+pub fn trusted_proxies_from_env() -> Vec<IpAddr> {
+    env::var("TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect()
+}
+
+/// Buckets shared by every session of the same IP
+#[derive(Debug)]
+pub struct IpRateLimiter {
+    rate: f64,
+    capacity: f64,
+    trusted_proxies: Vec<IpAddr>,
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+impl IpRateLimiter {
+    pub fn new(config: &RateLimitConfig, trusted_proxies: Vec<IpAddr>) -> Self {
+        Self {
+            rate: config.ip_rate,
+            capacity: config.ip_burst,
+            trusted_proxies,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Address the limits apply to. Clients choose their `X-Forwarded-For`,
+    /// so it is only read when the peer is a trusted proxy, from the right
+    /// up to the first address that is not one.
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let peer = peer?;
+        if !self.trusted_proxies.contains(&peer) {
+            return Some(peer);
+        }
+
+        let mut client = peer;
+        for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip;
+            if !self.trusted_proxies.contains(&ip) {
+                break;
+            }
+        }
+
+        Some(client)
+    }
+
+    pub fn try_acquire(&self, ip: IpAddr, cost: f64) -> bool {
+        self.try_acquire_at(ip, cost, Instant::now())
+    }
+
+    fn try_acquire_at(&self, ip: IpAddr, cost: f64, now: Instant) -> bool {
+        let Ok(mut buckets) = self.buckets.lock() else {
+            tracing::error!("IP rate limiter lock is poisoned");
+            return true;
+        };
+
+        if buckets.len() > IP_BUCKETS_CLEANUP_THRESHOLD {
+            buckets.retain(|_, bucket| !bucket.is_full());
+        }
+
+        buckets
+            .entry(ip)
+            .or_insert_with(|| TokenBucket::new(self.rate, self.capacity))
+            .try_acquire_at(cost, now)
+    }
+}
+
+/// Tracks limit violations of a session inside a time window
+#[derive(Debug)]
+pub struct Violations {
+    count: u32,
+    window_start: Instant,
+}
+
+impl Violations {
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    /// Registers a violation and returns how many happened in the current window
+    pub fn register(&mut self, window: Duration) -> u32 {
+        self.register_at(window, Instant::now())
+    }
+
+    fn register_at(&mut self, window: Duration, now: Instant) -> u32 {
+        if now.saturating_duration_since(self.window_start) > window {
+            self.count = 0;
+            self.window_start = now;
+        }
+
+        self.count += 1;
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            ip_rate: 1.0,
+            ip_burst: 3.0,
+            ..RateLimitConfig::from_env()
+        }
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(2.0, 4.0);
+        let start = bucket.updated_at;
+
+        // the whole burst at once, then nothing
+        assert!(bucket.try_acquire_at(3.0, start));
+        assert!(bucket.try_acquire_at(1.0, start));
+        assert!(!bucket.try_acquire_at(1.0, start));
+
+        // two tokens a second, never more than the burst
+        assert!(!bucket.try_acquire_at(2.0, start + Duration::from_millis(900)));
+        assert!(bucket.try_acquire_at(2.0, start + Duration::from_secs(1)));
+        assert!(bucket.try_acquire_at(4.0, start + Duration::from_secs(60)));
+        assert!(!bucket.try_acquire_at(1.0, start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_ip_rate_limiter() {
+        let limiter = IpRateLimiter::new(&config(), vec![]);
+        let now = Instant::now();
+        let ip: IpAddr = "203.0.113.1".parse().unwrap();
+        let other: IpAddr = "203.0.113.2".parse().unwrap();
+
+        assert!(limiter.try_acquire_at(ip, 3.0, now));
+        assert!(!limiter.try_acquire_at(ip, 1.0, now));
+        // every IP has its own bucket
+        assert!(limiter.try_acquire_at(other, 1.0, now));
+        assert!(limiter.try_acquire_at(ip, 1.0, now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "203.0.113.1".parse().unwrap();
+        let forwarded = Some("198.51.100.7, 203.0.113.1, 10.0.0.1");
+
+        // forwarded addresses of untrusted peers are ignored
+        let limiter = IpRateLimiter::new(&config(), vec![]);
+        assert_eq!(limiter.client_ip(Some(client), forwarded), Some(client));
+        assert_eq!(limiter.client_ip(None, forwarded), None);
+
+        // behind the proxies the first address they did not add is the
+        // client, what it wrote before is ignored
+        let limiter = IpRateLimiter::new(&config(), vec![proxy]);
+        assert_eq!(limiter.client_ip(Some(proxy), forwarded), Some(client));
+        assert_eq!(limiter.client_ip(Some(proxy), None), Some(proxy));
+        assert_eq!(
+            limiter.client_ip(Some(proxy), Some("not an ip, 10.0.0.1")),
+            Some(proxy)
+        );
+    }
+
+    #[test]
+    fn test_violations() {
+        let window = Duration::from_secs(60);
+        let mut violations = Violations::new();
+        let start = violations.window_start;
+
+        assert_eq!(violations.register_at(window, start), 1);
+        assert_eq!(
+            violations.register_at(window, start + Duration::from_secs(30)),
+            2
+        );
+        assert_eq!(violations.register_at(window, start + window), 3);
+        // a new window starts over
+        assert_eq!(
+            violations.register_at(window, start + Duration::from_secs(61)),
+            1
+        );
+        assert_eq!(
+            violations.register_at(window, start + Duration::from_secs(62)),
+            2
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Glicko-2 rating updates, following Glickman's "Example of the Glicko-2
+//! system". Every game is its own rating period, like most online servers.
+
+use std::f64::consts::PI;
+
+/// Converts ratings to and from the Glicko-2 scale
+const SCALE: f64 = 173.7178;
+/// Constrains the change of volatility over time
+const TAU: f64 = 0.5;
+/// Convergence tolerance of the volatility iteration
+const EPSILON: f64 = 0.000_001;
+
+pub const DEFAULT_RATING: f64 = 1500.0;
+pub const DEFAULT_DEVIATION: f64 = 350.0;
+pub const DEFAULT_VOLATILITY: f64 = 0.06;
+/// Lower bound of the deviation so active players still move
+const MIN_DEVIATION: f64 = 45.0;
+/// Ratings with a higher deviation are shown as provisional
+pub const PROVISIONAL_DEVIATION: f64 = 110.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    pub games: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+            games: 0,
+        }
+    }
+}
+
+impl Rating {
+    pub fn is_provisional(&self) -> bool {
+        self.deviation > PROVISIONAL_DEVIATION
+    }
+
+    /// Rounded rating, with a `?` while provisional
+    pub fn to_string(&self) -> String {
+        let provisional = if self.is_provisional() { "?" } else { "" };
+        format!("{}{}", self.rating.round() as i64, provisional)
+    }
+
+    fn mu(&self) -> f64 {
+        (self.rating - DEFAULT_RATING) / SCALE
+    }
+
+    fn phi(&self) -> f64 {
+        self.deviation / SCALE
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+fn expected_score(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+    1.0 / (1.0 + (-g(phi_j) * (mu - mu_j)).exp())
+}
+
+/// New volatility, found with the Illinois algorithm
+fn volatility(sigma: f64, phi: f64, v: f64, delta: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+            - (x - a) / (TAU * TAU)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > EPSILON {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+
+    (big_a / 2.0).exp()
+}
+
+/// Rating of `player` after a period with `results`, pairs of opponent and
+/// score (1 win, 0.5 draw, 0 loss)
+pub fn update(player: Rating, results: &[(Rating, f64)]) -> Rating {
+    let (mu, phi) = (player.mu(), player.phi());
+
+    if results.is_empty() {
+        let phi = (phi * phi + player.volatility * player.volatility).sqrt();
+        return Rating {
+            deviation: (phi * SCALE).min(DEFAULT_DEVIATION),
+            ..player
+        };
+    }
+
+    let mut v_inv = 0.0;
+    let mut improvement = 0.0;
+    for (opponent, score) in results {
+        let (mu_j, phi_j) = (opponent.mu(), opponent.phi());
+        let e = expected_score(mu, mu_j, phi_j);
+        v_inv += g(phi_j).powi(2) * e * (1.0 - e);
+        improvement += g(phi_j) * (score - e);
+    }
+    let v = 1.0 / v_inv;
+    let delta = v * improvement;
+
+    let sigma = volatility(player.volatility, phi, v, delta);
+    let phi_star = (phi * phi + sigma * sigma).sqrt();
+    let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let new_mu = mu + new_phi * new_phi * improvement;
+
+    Rating {
+        rating: new_mu * SCALE + DEFAULT_RATING,
+        deviation: (new_phi * SCALE).clamp(MIN_DEVIATION, DEFAULT_DEVIATION),
+        volatility: sigma,
+        games: player.games + results.len() as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_glickman_example() {
+        let player = rating(1500.0, 200.0);
+        let results = [
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ];
+
+        let updated = update(player, &results);
+        assert!((updated.rating - 1464.06).abs() < 0.01);
+        assert!((updated.deviation - 151.52).abs() < 0.01);
+        assert!((updated.volatility - 0.05999).abs() < 0.00001);
+        assert_eq!(updated.games, 3);
+    }
+
+    #[test]
+    fn test_single_game() {
+        let (white, black) = (Rating::default(), Rating::default());
+
+        let winner = update(white, &[(black, 1.0)]);
+        let loser = update(black, &[(white, 0.0)]);
+        assert!(winner.rating > DEFAULT_RATING);
+        assert!((winner.rating - DEFAULT_RATING + loser.rating - DEFAULT_RATING).abs() < 0.01);
+        assert!(winner.deviation < DEFAULT_DEVIATION);
+        assert_eq!(winner.to_string(), "1662?");
+    }
+}
@@ -0,0 +1,65 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+
+use super::{RatedGame, Rating, RatingError, RatingRepository, UnratedGame};
+use crate::entities::game::Pool;
+
+/// Ratings lost on restart, used without a database and in tests
+#[derive(Debug, Default)]
+pub struct InMemoryRatingRepository {
+    ratings: Mutex<HashMap<(String, Pool), Rating>>,
+    games: Mutex<Vec<RatedGame>>,
+}
+
+impl InMemoryRatingRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RatingRepository for InMemoryRatingRepository {
+    async fn ratings_of(&self, user_id: &str) -> Result<HashMap<Pool, Rating>, RatingError> {
+        let ratings = self.ratings.lock().unwrap();
+
+        Ok(Pool::ALL
+            .into_iter()
+            .filter_map(|pool| {
+                ratings
+                    .get(&(user_id.to_string(), pool))
+                    .map(|rating| (pool, *rating))
+            })
+            .collect())
+    }
+
+    async fn record_game(&self, game: &UnratedGame) -> Result<RatedGame, RatingError> {
+        // held until both ratings are written
+        let mut ratings = self.ratings.lock().unwrap();
+        let rating_of = |user_id: &str| {
+            ratings
+                .get(&(user_id.to_string(), game.pool))
+                .copied()
+                .unwrap_or_default()
+        };
+        let rated = game.rate(rating_of(&game.white_id), rating_of(&game.black_id));
+
+        ratings.insert((rated.white_id.clone(), rated.pool), rated.white_after);
+        ratings.insert((rated.black_id.clone(), rated.pool), rated.black_after);
+        self.games.lock().unwrap().push(rated.clone());
+
+        Ok(rated)
+    }
+
+    async fn games_of(&self, user_id: &str) -> Result<Vec<RatedGame>, RatingError> {
+        Ok(self
+            .games
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|game| game.white_id == user_id || game.black_id == user_id)
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,160 @@
+//! Glicko-2 ratings of registered users, one per time control pool.
+//!
+//! A game is rated when the room is marked as rated, has a clock and both
+//! players are logged in to an account. The rated game is stored with the
+//! ratings of both players before and after it, which is the rating history,
+//! under the id of its archive record.
+//! Like accounts, ratings are stored in Postgres when `DATABASE_URL` is set
+//! and in memory otherwise.
+
+pub mod glicko;
+pub mod memory;
+pub mod postgres;
+
+use std::{collections::HashMap, env, fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+
+pub use self::glicko::Rating;
+use self::{memory::InMemoryRatingRepository, postgres::PgRatingRepository};
+use super::sessions::now_secs;
+use crate::entities::{
+    chess_board::turns::Turn,
+    game::{GameResult, Outcome, Pool},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RatingError {
+    Storage(String),
+}
+
+/// A finished game to rate, not stored yet
+#[derive(Clone, Debug)]
+pub struct UnratedGame {
+    /// Id of the archive record of the game
+    pub id: String,
+    pub room: String,
+    pub pool: Pool,
+    pub white_id: String,
+    pub black_id: String,
+    pub result: GameResult,
+}
+
+/// A finished rated game with the rating changes it caused
+#[derive(Clone, Debug)]
+pub struct RatedGame {
+    /// Id of the archive record of the game
+    pub id: String,
+    pub room: String,
+    pub pool: Pool,
+    pub white_id: String,
+    pub black_id: String,
+    pub result: GameResult,
+    pub white_before: Rating,
+    pub white_after: Rating,
+    pub black_before: Rating,
+    pub black_after: Rating,
+    /// Seconds since the epoch
+    pub finished_at: u64,
+}
+
+/// Storage of the ratings and of the rated games
+#[async_trait]
+pub trait RatingRepository: Debug + Send + Sync {
+    /// Ratings of a user in the pools it played in
+    async fn ratings_of(&self, user_id: &str) -> Result<HashMap<Pool, Rating>, RatingError>;
+
+    /// Rates the game from the stored ratings of both players and stores it
+    /// with their new ones. Reading and writing the ratings is atomic, games
+    /// of a player ending together are rated one after the other.
+    async fn record_game(&self, game: &UnratedGame) -> Result<RatedGame, RatingError>;
+
+    /// Rated games of a user, most recent first
+    async fn games_of(&self, user_id: &str) -> Result<Vec<RatedGame>, RatingError>;
+}
+
+/// Postgres when `DATABASE_URL` is set, in memory otherwise
+pub async fn repository_from_env() -> Arc<dyn RatingRepository> {
+    match env::var("DATABASE_URL") {
+        Ok(url) => match PgRatingRepository::connect(&url).await {
+            Ok(repository) => Arc::new(repository),
+            Err(e) => panic!("Failed to connect to the ratings database: {:?}", e),
+        },
+        Err(_) => Arc::new(InMemoryRatingRepository::new()),
+    }
+}
+
+/// Score of `side` in a finished game
+fn score(result: &GameResult, side: Turn) -> f64 {
+    match result.outcome {
+        Outcome::Win(winner) if winner == side => 1.0,
+        Outcome::Win(_) => 0.0,
+        Outcome::Draw => 0.5,
+    }
+}
+
+impl UnratedGame {
+    /// The game rated from the ratings of the players before it
+    pub fn rate(&self, white_before: Rating, black_before: Rating) -> RatedGame {
+        let result = self.result;
+
+        RatedGame {
+            id: self.id.clone(),
+            room: self.room.clone(),
+            pool: self.pool,
+            white_id: self.white_id.clone(),
+            black_id: self.black_id.clone(),
+            result,
+            white_before,
+            white_after: glicko::update(
+                white_before,
+                &[(black_before, score(&result, Turn::White))],
+            ),
+            black_before,
+            black_after: glicko::update(
+                black_before,
+                &[(white_before, score(&result, Turn::Black))],
+            ),
+            finished_at: now_secs(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::game::Termination;
+
+    #[actix_web::test]
+    async fn test_rate_game() {
+        let repository = InMemoryRatingRepository::new();
+        let unrated = UnratedGame {
+            id: "game".to_string(),
+            room: "main".to_string(),
+            pool: Pool::Blitz,
+            white_id: "w".to_string(),
+            black_id: "b".to_string(),
+            result: GameResult::new(Outcome::Win(Turn::Black), Termination::Timeout),
+        };
+
+        let game = repository.record_game(&unrated).await.unwrap();
+        assert_eq!(game.id, "game");
+        assert!(game.black_after.rating > game.black_before.rating);
+        assert!(game.white_after.rating < game.white_before.rating);
+
+        let ratings = repository.ratings_of("b").await.unwrap();
+        assert_eq!(ratings.get(&Pool::Blitz), Some(&game.black_after));
+        assert_eq!(ratings.get(&Pool::Rapid), None);
+        assert_eq!(repository.games_of("w").await.unwrap().len(), 1);
+
+        // the next game starts from the ratings the first one left
+        let rematch = UnratedGame {
+            id: "rematch".to_string(),
+            ..unrated
+        };
+        let next = repository.record_game(&rematch).await.unwrap();
+        assert_eq!(next.black_before, game.black_after);
+        assert_eq!(next.white_before, game.white_after);
+        assert_eq!(repository.games_of("b").await.unwrap().len(), 2);
+    }
+}
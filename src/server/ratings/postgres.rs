@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use sqlx::{postgres::PgPoolOptions, PgPool, Row};
+
+use super::{RatedGame, Rating, RatingError, RatingRepository, UnratedGame};
+use crate::entities::game::{GameResult, Pool};
+
+const CREATE_RATINGS: &str = "
+    CREATE TABLE IF NOT EXISTS ratings (
+        user_id TEXT NOT NULL,
+        pool TEXT NOT NULL,
+        rating DOUBLE PRECISION NOT NULL,
+        deviation DOUBLE PRECISION NOT NULL,
+        volatility DOUBLE PRECISION NOT NULL,
+        games INTEGER NOT NULL,
+        PRIMARY KEY (user_id, pool)
+    )";
+
+const CREATE_RATED_GAMES: &str = "
+    CREATE TABLE IF NOT EXISTS rated_games (
+        id TEXT PRIMARY KEY,
+        room TEXT NOT NULL,
+        pool TEXT NOT NULL,
+        white_id TEXT NOT NULL,
+        black_id TEXT NOT NULL,
+        result TEXT NOT NULL,
+        white_before JSONB NOT NULL,
+        white_after JSONB NOT NULL,
+        black_before JSONB NOT NULL,
+        black_after JSONB NOT NULL,
+        finished_at BIGINT NOT NULL
+    )";
+
+const CREATE_RATED_GAMES_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS rated_games_players_idx ON rated_games (white_id, black_id)";
+
+const UPSERT_RATING: &str = "
+    INSERT INTO ratings (user_id, pool, rating, deviation, volatility, games)
+    VALUES ($1, $2, $3, $4, $5, $6)
+    ON CONFLICT (user_id, pool) DO UPDATE SET
+        rating = EXCLUDED.rating,
+        deviation = EXCLUDED.deviation,
+        volatility = EXCLUDED.volatility,
+        games = EXCLUDED.games";
+
+/// Default rows of players without a rating in the pool, so that they can
+/// be locked like the others
+const INSERT_DEFAULT_RATING: &str = "
+    INSERT INTO ratings (user_id, pool, rating, deviation, volatility, games)
+    VALUES ($1, $2, $3, $4, $5, $6)
+    ON CONFLICT (user_id, pool) DO NOTHING";
+
+/// Ratings of the players of a game, locked until the game is recorded
+const LOCK_RATINGS: &str = "
+    SELECT * FROM ratings WHERE user_id = ANY($1) AND pool = $2
+    ORDER BY user_id
+    FOR UPDATE";
+
+#[derive(Debug)]
+pub struct PgRatingRepository {
+    pool: PgPool,
+}
+
+impl PgRatingRepository {
+    /// Connects and creates the ratings tables when missing
+    pub async fn connect(url: &str) -> Result<Self, RatingError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(url)
+            .await
+            .map_err(storage_error)?;
+
+        for query in [CREATE_RATINGS, CREATE_RATED_GAMES, CREATE_RATED_GAMES_INDEX] {
+            sqlx::query(query)
+                .execute(&pool)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        Ok(Self { pool })
+    }
+}
+
+fn storage_error(e: sqlx::Error) -> RatingError {
+    tracing::error!(error = %e, "Ratings database error");
+    RatingError::Storage(e.to_string())
+}
+
+fn rating_to_json(rating: &Rating) -> serde_json::Value {
+    serde_json::json!({
+        "rating": rating.rating,
+        "deviation": rating.deviation,
+        "volatility": rating.volatility,
+        "games": rating.games,
+    })
+}
+
+fn rating_from_json(value: &serde_json::Value) -> Rating {
+    let field = |name: &str| value.get(name).and_then(|v| v.as_f64());
+    let default = Rating::default();
+
+    Rating {
+        rating: field("rating").unwrap_or(default.rating),
+        deviation: field("deviation").unwrap_or(default.deviation),
+        volatility: field("volatility").unwrap_or(default.volatility),
+        games: field("games").unwrap_or_default() as u32,
+    }
+}
+
+fn rating_from_row(row: &sqlx::postgres::PgRow) -> Result<Rating, RatingError> {
+    Ok(Rating {
+        rating: row.try_get("rating").map_err(storage_error)?,
+        deviation: row.try_get("deviation").map_err(storage_error)?,
+        volatility: row.try_get("volatility").map_err(storage_error)?,
+        games: row.try_get::<i32, _>("games").map_err(storage_error)? as u32,
+    })
+}
+
+fn game_from_row(row: &sqlx::postgres::PgRow) -> Result<RatedGame, RatingError> {
+    let text = |name: &str| row.try_get::<String, _>(name).map_err(storage_error);
+    let json = |name: &str| {
+        row.try_get::<serde_json::Value, _>(name)
+            .map(|value| rating_from_json(&value))
+            .map_err(storage_error)
+    };
+    let invalid = |name: &str| RatingError::Storage(format!("invalid {} in rated_games", name));
+
+    Ok(RatedGame {
+        id: text("id")?,
+        room: text("room")?,
+        pool: text("pool")?.parse::<Pool>().map_err(|_| invalid("pool"))?,
+        white_id: text("white_id")?,
+        black_id: text("black_id")?,
+        result: text("result")?
+            .parse::<GameResult>()
+            .map_err(|_| invalid("result"))?,
+        white_before: json("white_before")?,
+        white_after: json("white_after")?,
+        black_before: json("black_before")?,
+        black_after: json("black_after")?,
+        finished_at: row
+            .try_get::<i64, _>("finished_at")
+            .map_err(storage_error)? as u64,
+    })
+}
+
+#[async_trait]
+impl RatingRepository for PgRatingRepository {
+    async fn ratings_of(&self, user_id: &str) -> Result<HashMap<Pool, Rating>, RatingError> {
+        let rows = sqlx::query("SELECT * FROM ratings WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        let mut ratings = HashMap::new();
+        for row in rows {
+            let Ok(pool) = row
+                .try_get::<String, _>("pool")
+                .map_err(storage_error)?
+                .parse::<Pool>()
+            else {
+                continue;
+            };
+
+            ratings.insert(pool, rating_from_row(&row)?);
+        }
+
+        Ok(ratings)
+    }
+
+    async fn record_game(&self, game: &UnratedGame) -> Result<RatedGame, RatingError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        // players are always locked in the same order, two games can't wait
+        // on each other
+        let mut players = [game.white_id.clone(), game.black_id.clone()];
+        players.sort();
+        let default = Rating::default();
+        for user_id in &players {
+            sqlx::query(INSERT_DEFAULT_RATING)
+                .bind(user_id)
+                .bind(game.pool.as_str())
+                .bind(default.rating)
+                .bind(default.deviation)
+                .bind(default.volatility)
+                .bind(default.games as i32)
+                .execute(&mut *tx)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        let mut ratings = HashMap::new();
+        for row in sqlx::query(LOCK_RATINGS)
+            .bind(&players[..])
+            .bind(game.pool.as_str())
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(storage_error)?
+        {
+            ratings.insert(
+                row.try_get::<String, _>("user_id").map_err(storage_error)?,
+                rating_from_row(&row)?,
+            );
+        }
+        let rating_of = |user_id: &str| ratings.get(user_id).copied().unwrap_or_default();
+        let game = game.rate(rating_of(&game.white_id), rating_of(&game.black_id));
+
+        sqlx::query(
+            "INSERT INTO rated_games (id, room, pool, white_id, black_id, result, white_before, \
+             white_after, black_before, black_after, finished_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(&game.id)
+        .bind(&game.room)
+        .bind(game.pool.as_str())
+        .bind(&game.white_id)
+        .bind(&game.black_id)
+        .bind(game.result.to_string())
+        .bind(rating_to_json(&game.white_before))
+        .bind(rating_to_json(&game.white_after))
+        .bind(rating_to_json(&game.black_before))
+        .bind(rating_to_json(&game.black_after))
+        .bind(game.finished_at as i64)
+        .execute(&mut *tx)
+        .await
+        .map_err(storage_error)?;
+
+        for (user_id, rating) in [
+            (&game.white_id, &game.white_after),
+            (&game.black_id, &game.black_after),
+        ] {
+            sqlx::query(UPSERT_RATING)
+                .bind(user_id)
+                .bind(game.pool.as_str())
+                .bind(rating.rating)
+                .bind(rating.deviation)
+                .bind(rating.volatility)
+                .bind(rating.games as i32)
+                .execute(&mut *tx)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        tx.commit().await.map_err(storage_error)?;
+
+        Ok(game)
+    }
+
+    async fn games_of(&self, user_id: &str) -> Result<Vec<RatedGame>, RatingError> {
+        sqlx::query(
+            "SELECT * FROM rated_games WHERE white_id = $1 OR black_id = $1 \
+             ORDER BY finished_at DESC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(storage_error)?
+        .iter()
+        .map(game_from_row)
+        .collect()
+    }
+}
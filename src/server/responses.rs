@@ -0,0 +1,17 @@
+//! Answers shared by the JSON endpoints. Errors are answered as
+//! `{"error": "..."}`, the message is shown to the user as is.
+
+use actix_web::{http::StatusCode, HttpResponse};
+
+pub fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({ "error": message }))
+}
+
+/// The actor or the storage behind an endpoint is down, `what` is like
+/// `Games`
+pub fn unavailable(what: &str) -> HttpResponse {
+    error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        &format!("{} are not available right now", what),
+    )
+}
@@ -0,0 +1,2222 @@
+//! `RoomActor` owns the board and the users of a single room. Sessions send
+//! their moves straight to the room they are in, so rooms never wait on each
+//! other. The room reports a snapshot of its state to the `ChessServer`
+//! registry after every change, which is used to restart it if it dies.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use actix::prelude::*;
+
+use crate::entities::{
+    annotation::Shape,
+    archive::{GameRecord, RecordedMove},
+    chess_board::{
+        self, enums::PromotionKind, move_event::MoveEvent, turns::Turn, ChessBoard,
+        ChessBoardBuilder,
+    },
+    game::{Pool, TimeControl},
+    position::Position,
+    setup::Setup,
+    stone::{Color, Stone},
+};
+
+use super::{
+    archive,
+    chess_server::{ChessServer, ForkRoom, GameFinished, Message, Player, SaveSnapshot, Session},
+    diagram::Diagram,
+    game::{self, Game},
+    history::MoveTree,
+    metrics::Metrics,
+    ratings::{Rating, RatingRepository, UnratedGame},
+    sessions::now_secs,
+    telemetry::traced_handlers,
+    websockets::session::WsChessSession,
+};
+
+#[cfg(feature = "gif")]
+use super::diagram::animation::Animation;
+
+/// How many room events are kept so reconnecting clients can catch up
+const ROOM_EVENTS_BUFFER: usize = 256;
+
+/// How often the clock of the side to move is checked for a flag fall
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+pub use crate::entities::setup::DEFAULT_FEN;
+
+/// How a session arrived in the room
+#[derive(Clone, Debug)]
+pub enum Arrival {
+    /// The session switched rooms with `/join`
+    Joined,
+    /// A new socket was opened, `resume` is the last event the client saw in
+    /// this room
+    Connected { resume: Option<u64> },
+}
+
+/// Session enters the room
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Enter {
+    pub session: Session,
+    /// Name of the user owning the session
+    pub name: String,
+    pub arrival: Arrival,
+}
+
+/// Session left the room, its user leaves with its last session
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Leave {
+    pub id: String,
+}
+
+/// Session lost its connection, its user is kept in the room as away when
+/// it has no other session in it
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Away {
+    pub id: String,
+}
+
+/// Away user did not come back in time
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Forget {
+    pub user_id: String,
+}
+
+/// User changed its name
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Rename {
+    pub user_id: String,
+    pub name: String,
+}
+
+/// Send a message to every user in the room
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Broadcast(pub String);
+
+/// Send message to the other users of the room
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ClientMessage {
+    /// Id of the client session
+    pub id: String,
+    /// Peer message
+    pub msg: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Move {
+    pub id: String,
+    pub piece: String,
+    pub from: String,
+    pub to: String,
+    /// Piece a pawn reaching the last rank becomes
+    pub promotion: PromotionKind,
+    /// Last room event the client saw when it made the move
+    pub base_seq: Option<u64>,
+}
+
+/// Client missed some room events, `seq` is the last one it applied
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Resync {
+    pub id: String,
+    pub seq: Option<u64>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Reset {
+    pub id: String,
+}
+
+/// Position shown in the room, to draw it as a diagram
+#[derive(Message)]
+#[rtype(result = "Diagram")]
+pub struct CurrentDiagram;
+
+/// Moves from the start position to the current one, to animate them
+#[cfg(feature = "gif")]
+#[derive(Message)]
+#[rtype(result = "Animation")]
+pub struct CurrentAnimation;
+
+/// Position of the board editor becomes the start position of the room
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct LoadPosition {
+    pub id: String,
+    pub fen: String,
+}
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct Undo {
+    pub id: String,
+}
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct Redo {
+    pub id: String,
+}
+
+/// Go to a move of the history, `None` is the start position
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Goto {
+    pub id: String,
+    pub node: Option<usize>,
+}
+
+/// Make the line leading to a move the main line
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Promote {
+    pub id: String,
+    pub node: usize,
+}
+
+/// Delete a move of the history with the moves after it
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct DeleteMove {
+    pub id: String,
+    pub node: usize,
+}
+
+/// Open a new room from a move of the history and move the session to it
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Fork {
+    pub id: String,
+    /// Name of the new room
+    pub name: String,
+    /// Move the new room starts from, `None` for the move shown on the board
+    pub node: Option<Option<usize>>,
+    /// Copy the moves leading to it instead of starting from it
+    pub with_history: bool,
+}
+
+#[derive(Debug)]
+pub enum AnnotationChange {
+    Comment(String),
+    /// Sets a NAG, or clears it when it was set
+    ToggleNag(u8),
+    /// Draws a shape, or erases it when it was drawn
+    ToggleShape(Shape),
+    ClearShapes,
+}
+
+/// Annotate a move of the history, `None` is the start position
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Annotate {
+    pub id: String,
+    pub node: Option<usize>,
+    pub change: AnnotationChange,
+}
+
+/// Session asks for the PGN of the room with its variations
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ExportPgn {
+    pub id: String,
+}
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct Options {
+    pub id: String,
+    pub validation: bool,
+    pub sync: bool,
+    /// Stop the clocks while a seated player is disconnected
+    pub pause_clock: bool,
+    /// Games change the ratings of the players
+    pub rated: bool,
+}
+
+/// User of the session takes a side of the board
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Sit {
+    pub id: String,
+    pub side: Turn,
+}
+
+/// User of the session leaves its seat
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Stand {
+    pub id: String,
+}
+
+/// Set the time control of the next game, `None` plays without clocks
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetClock {
+    pub id: String,
+    pub time_control: Option<TimeControl>,
+}
+
+/// Player ends a game its opponent abandoned
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Claim {
+    pub id: String,
+    pub claim: game::Claim,
+}
+
+#[derive(Clone, Debug)]
+pub struct MoveResult {
+    pub from: Option<Position>,
+    pub to: Option<Position>,
+    pub stone: Stone,
+    pub chess_board_move: chess_board::enums::Move,
+    pub event: MoveEvent,
+    pub previous_fen: String,
+    pub previous_trash: String,
+    pub current_fen: String,
+    pub current_trash: String,
+}
+
+/// Last consistent state of a room, enough to rebuild it after a crash with
+/// its moves, variations and annotations
+#[derive(Clone, Debug)]
+pub struct RoomSnapshot {
+    original_fen: String,
+    original_trash: String,
+    current_fen: String,
+    trash: String,
+    validation: bool,
+    sync: bool,
+    seq: u64,
+    game: Game,
+    history: MoveTree,
+}
+
+impl RoomSnapshot {
+    /// Board and trash of the room
+    pub fn board(&self) -> (String, String) {
+        (self.current_fen.clone(), self.trash.clone())
+    }
+}
+
+/// A user shown in the room with the sessions it has open in it, a member
+/// without sessions is away
+#[derive(Clone, Debug)]
+pub struct Member {
+    pub id: String,
+    pub name: String,
+    /// Logged in to an account, only registered users have ratings
+    pub registered: bool,
+    pub sessions: HashMap<String, Addr<WsChessSession>>,
+}
+
+impl Member {
+    pub fn new(id: String, name: String, registered: bool) -> Self {
+        Self {
+            id,
+            name,
+            registered,
+            sessions: HashMap::new(),
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        !self.sessions.is_empty()
+    }
+
+    pub fn to_string(&self) -> String {
+        let status = if self.is_online() { "online" } else { "away" };
+        format!("{}:{}:{}", self.id, self.name, status)
+    }
+}
+
+#[derive(Debug)]
+pub struct Room {
+    original_fen: String,
+    current_fen: String,
+    chess_board: ChessBoard,
+    history: MoveTree,
+    /// Users of the room by user id
+    members: HashMap<String, Member>,
+    /// User id of every session in the room, by session id
+    sessions: HashMap<String, String>,
+    original_trash: String,
+    trash: String,
+    seq: u64,
+    /// Sequence number, session that caused it and message of the last events
+    events: VecDeque<(u64, String, String)>,
+    game: Game,
+}
+
+impl Room {
+    pub fn new(fen: Option<String>, trash: Option<String>) -> Result<Self, ()> {
+        let fen = fen.unwrap_or(DEFAULT_FEN.to_string());
+        let trash = trash.unwrap_or("".to_string());
+        let chess_board = ChessBoardBuilder::new()
+            .fen(&fen)
+            .deleted_stones(&trash)
+            .validation(false)
+            .sync(true)
+            .build()
+            .map_err(|_| ())?;
+
+        Ok(Self {
+            original_fen: fen.clone(),
+            current_fen: fen.clone(),
+            chess_board,
+            history: MoveTree::new(),
+            members: HashMap::new(),
+            sessions: HashMap::new(),
+            trash: trash.clone(),
+            original_trash: trash,
+            seq: 0,
+            events: VecDeque::new(),
+            game: Game::new(),
+        })
+    }
+
+    pub fn from_snapshot(snapshot: &RoomSnapshot) -> Result<Self, ()> {
+        let chess_board = ChessBoardBuilder::new()
+            .fen(&snapshot.current_fen)
+            .deleted_stones(&snapshot.trash)
+            .validation(snapshot.validation)
+            .sync(snapshot.sync)
+            .build()
+            .map_err(|_| ())?;
+
+        Ok(Self {
+            original_fen: snapshot.original_fen.clone(),
+            current_fen: snapshot.current_fen.clone(),
+            chess_board,
+            history: snapshot.history.clone(),
+            members: HashMap::new(),
+            sessions: HashMap::new(),
+            original_trash: snapshot.original_trash.clone(),
+            trash: snapshot.trash.clone(),
+            seq: snapshot.seq,
+            events: VecDeque::new(),
+            game: snapshot.game.clone(),
+        })
+    }
+
+    pub fn snapshot(&self) -> RoomSnapshot {
+        RoomSnapshot {
+            original_fen: self.original_fen.clone(),
+            original_trash: self.original_trash.clone(),
+            current_fen: self.current_fen.clone(),
+            trash: self.trash.clone(),
+            validation: self.chess_board.validation,
+            sync: self.chess_board.sync,
+            seq: self.seq,
+            game: self.game.clone(),
+            history: self.history.clone(),
+        }
+    }
+
+    /// Current position with the squares of its move and its arrows
+    pub fn diagram(&self) -> Diagram {
+        let current = self.history.current();
+        let last_move = current
+            .and_then(|id| self.history.get(id))
+            .and_then(|result| result.from.clone().zip(result.to.clone()));
+        let shapes = self
+            .history
+            .annotation(current)
+            .map(|annotation| annotation.shapes.clone())
+            .unwrap_or_default();
+
+        Diagram::new(&self.current_fen)
+            .last_move(last_move)
+            .shapes(shapes)
+    }
+
+    /// Positions from the start to the current one with the names of the
+    /// players seated
+    #[cfg(feature = "gif")]
+    pub fn animation(&self) -> Animation {
+        let moves = self.history.line().into_iter().map(|result| {
+            let last_move = result.from.clone().zip(result.to.clone());
+            Diagram::new(&result.current_fen).last_move(last_move)
+        });
+        let frames = std::iter::once(Diagram::new(&self.original_fen))
+            .chain(moves)
+            .collect();
+        let name = |side| self.game.seat(side).map(|seat| seat.name.clone());
+
+        Animation::new(frames).players(name(Turn::White), name(Turn::Black))
+    }
+
+    pub fn members(&self) -> &HashMap<String, Member> {
+        &self.members
+    }
+
+    pub fn session_ids(&self) -> Vec<String> {
+        self.sessions.keys().cloned().collect()
+    }
+
+    pub fn member_of(&self, session_id: &str) -> Option<&Member> {
+        self.members.get(self.sessions.get(session_id)?)
+    }
+
+    pub fn session_addr(&self, session_id: &str) -> Option<&Addr<WsChessSession>> {
+        self.member_of(session_id)?.sessions.get(session_id)
+    }
+
+    /// Adds a session to the member of its user, returns whether the member
+    /// was online before or `None` for a new member
+    pub fn insert_session(&mut self, session: &Session, name: &str) -> Option<bool> {
+        let previous = self.members.get(&session.user_id).map(|m| m.is_online());
+        let member = self
+            .members
+            .entry(session.user_id.clone())
+            .or_insert_with(|| {
+                Member::new(
+                    session.user_id.clone(),
+                    name.to_string(),
+                    session.registered,
+                )
+            });
+
+        member
+            .sessions
+            .insert(session.id.clone(), session.addr.clone());
+        self.sessions
+            .insert(session.id.clone(), session.user_id.clone());
+
+        previous
+    }
+
+    /// Adds a user that is away, used to restore a room
+    pub fn insert_away_member(&mut self, user_id: &str, name: &str, registered: bool) {
+        self.members
+            .entry(user_id.to_string())
+            .or_insert_with(|| Member::new(user_id.to_string(), name.to_string(), registered));
+    }
+
+    /// Removes a session and returns the member it belonged to
+    pub fn remove_session(&mut self, session_id: &str) -> Option<&Member> {
+        let user_id = self.sessions.remove(session_id)?;
+        let member = self.members.get_mut(&user_id)?;
+        member.sessions.remove(session_id);
+
+        Some(member)
+    }
+
+    pub fn remove_member(&mut self, user_id: &str) -> Option<Member> {
+        let member = self.members.remove(user_id)?;
+        for session_id in member.sessions.keys() {
+            self.sessions.remove(session_id);
+        }
+
+        Some(member)
+    }
+
+    pub fn rename_member(&mut self, user_id: &str, name: &str) -> Option<&Member> {
+        let member = self.members.get_mut(user_id)?;
+        member.name = name.to_string();
+
+        Some(member)
+    }
+
+    /// Sets up the game of two paired players, played by the rules
+    pub fn seat_players(
+        &mut self,
+        white: &Player,
+        black: &Player,
+        time_control: Option<TimeControl>,
+        rated: bool,
+    ) -> Result<(), game::GameError> {
+        self.game.set_time_control(time_control)?;
+        self.game.set_rated(rated)?;
+
+        for (side, player) in [(Turn::White, white), (Turn::Black, black)] {
+            self.game
+                .sit(&player.user_id, &player.name, player.registered, side)?;
+        }
+        self.chess_board.validation = true;
+
+        Ok(())
+    }
+
+    /// Starts the room over from `fen`, without moves and with the game
+    /// reset
+    pub fn load_position(&mut self, fen: &str) -> Result<(), &'static str> {
+        let setup = fen.parse::<Setup>().map_err(|_| "Invalid FEN")?;
+        setup.validate().map_err(|error| error.as_str())?;
+
+        let fen = setup.to_string();
+        let chess_board = ChessBoardBuilder::new()
+            .fen(&fen)
+            .validation(self.chess_board.validation)
+            .sync(self.chess_board.sync)
+            .build()
+            .map_err(|_| "Failed to load position")?;
+        self.original_fen = fen.clone();
+        self.current_fen = fen;
+        self.original_trash = String::new();
+        self.trash = String::new();
+        self.history = MoveTree::new();
+        self.chess_board = chess_board;
+        self.game.reset();
+
+        Ok(())
+    }
+
+    pub fn usernames(&self) -> Vec<String> {
+        self.members
+            .values()
+            .map(|member| member.to_string())
+            .collect()
+    }
+
+    pub fn options_string(&self) -> String {
+        let validation = self.chess_board.validation;
+        let sync = self.chess_board.sync;
+        let mut str = String::new();
+
+        if validation {
+            str.push_str(" validation");
+        }
+
+        if sync {
+            str.push_str(" sync");
+        }
+
+        if self.game.pause_when_away() {
+            str.push_str(" pause");
+        }
+
+        if self.game.is_rated() {
+            str.push_str(" rated");
+        }
+
+        str.trim().to_string()
+    }
+
+    pub fn push_move(&mut self, result: MoveResult) {
+        self.history.push(result);
+    }
+
+    pub fn undo_move(&mut self) -> Result<MoveResult, ()> {
+        self.history.undo().ok_or(())
+    }
+
+    pub fn redo_move(&mut self) -> Result<MoveResult, ()> {
+        self.history.redo().ok_or(())
+    }
+
+    /// Board and trash after a move of the history, the original ones for
+    /// the start position
+    pub fn position_of(&self, node: Option<usize>) -> Option<(String, String)> {
+        match node {
+            Some(node) => self
+                .history
+                .get(node)
+                .map(|result| (result.current_fen.clone(), result.current_trash.clone())),
+            None => Some((self.original_fen.clone(), self.original_trash.clone())),
+        }
+    }
+
+    /// New room starting from a move of the history, or from the start
+    /// position of this one with the moves leading to it when `with_history`
+    /// is set. The game is not copied.
+    pub fn fork(&self, node: Option<usize>, with_history: bool) -> Result<Room, ()> {
+        let (fen, trash) = self.position_of(node).ok_or(())?;
+        if !with_history {
+            return Room::new(Some(fen), Some(trash));
+        }
+
+        let mut room = Room::new(
+            Some(self.original_fen.clone()),
+            Some(self.original_trash.clone()),
+        )?;
+        if let Some(annotation) = self.history.annotation(None) {
+            *room.history.annotation_mut(None).ok_or(())? = annotation.clone();
+        }
+        for id in self.history.path(node) {
+            let result = self.history.get(id).ok_or(())?;
+            let copy = room.history.push(result.clone());
+            if let Some(annotation) = self.history.annotation(Some(id)) {
+                *room.history.annotation_mut(Some(copy)).ok_or(())? = annotation.clone();
+            }
+        }
+        room.chess_board = ChessBoardBuilder::new()
+            .fen(&fen)
+            .deleted_stones(&trash)
+            .validation(false)
+            .sync(true)
+            .build()
+            .map_err(|_| ())?;
+        room.current_fen = fen;
+        room.trash = trash;
+
+        Ok(room)
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Records a state change and returns the message to broadcast for it
+    pub fn push_event(&mut self, message: &str, author: &str) -> String {
+        self.seq += 1;
+        let event = format!("/event {} {}", self.seq, message);
+
+        self.events
+            .push_back((self.seq, author.to_string(), event.clone()));
+        if self.events.len() > ROOM_EVENTS_BUFFER {
+            self.events.pop_front();
+        }
+
+        event
+    }
+
+    /// Events after `seq`, `None` when they are no longer buffered
+    pub fn events_since(&self, seq: u64) -> Option<Vec<String>> {
+        if seq > self.seq {
+            return None;
+        }
+
+        if let Some((first_seq, _, _)) = self.events.front() {
+            if *first_seq > seq + 1 {
+                return None;
+            }
+        } else if seq != self.seq {
+            return None;
+        }
+
+        Some(
+            self.events
+                .iter()
+                .filter(|(event_seq, _, _)| *event_seq > seq)
+                .map(|(_, _, event)| event.clone())
+                .collect(),
+        )
+    }
+
+    /// Whether a move based on `base_seq` missed changes made by someone else,
+    /// the events of the mover itself can't conflict with its move
+    pub fn is_stale(&self, base_seq: u64, id: &str) -> bool {
+        if base_seq >= self.seq {
+            return base_seq > self.seq;
+        }
+
+        match self.events.front() {
+            Some((first_seq, _, _)) if *first_seq <= base_seq + 1 => self
+                .events
+                .iter()
+                .filter(|(event_seq, _, _)| *event_seq > base_seq)
+                .any(|(_, author, _)| author != id),
+            _ => true,
+        }
+    }
+
+    /// Tells the client its move was not applied, `reason` is `stale` or
+    /// `invalid`
+    pub fn rollback_message(&self, room_name: &str, reason: &str) -> String {
+        format!(
+            "/rollback {} {}|{}|{}|{}",
+            reason, room_name, self.current_fen, self.trash, self.seq
+        )
+    }
+
+    pub fn sync_board_message(&self, room_name: &str) -> String {
+        format!(
+            "/sync_board {}|{}|{}|{}",
+            room_name, self.current_fen, self.trash, self.seq
+        )
+    }
+
+    pub fn sync_history_message(&self) -> String {
+        format!(
+            "/sync_history {}",
+            self.history.history(&self.original_fen).to_string()
+        )
+    }
+
+    /// PGN of the moves played in the room with their variations
+    pub fn pgn(&self, name: &str) -> String {
+        let result = self
+            .game
+            .result()
+            .map(|result| result.score())
+            .unwrap_or("*");
+        let mut pgn = format!(
+            "[Event \"Analysis\"]\n[Site \"{}\"]\n[Result \"{}\"]\n",
+            name, result
+        );
+        if self.original_fen != DEFAULT_FEN {
+            pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", self.original_fen));
+        }
+
+        let movetext = self.history.history(&self.original_fen).movetext();
+        pgn.push('\n');
+        pgn.push_str(movetext.as_str());
+        if !movetext.is_empty() {
+            pgn.push(' ');
+        }
+        pgn.push_str(result);
+        pgn.push('\n');
+
+        pgn
+    }
+
+    pub fn sync_game_message(&self) -> String {
+        format!(
+            "/sync_game {}",
+            self.game.status(Instant::now()).to_string()
+        )
+    }
+
+    /// Pool the ratings shown in the room belong to, blitz without a clock
+    pub fn rating_pool(&self) -> Pool {
+        self.game
+            .time_control()
+            .map(|time_control| time_control.pool())
+            .unwrap_or(Pool::Blitz)
+    }
+
+    /// Archive record of the finished game, with the moves up to the one
+    /// shown
+    pub fn game_record(&self, name: &str) -> Option<GameRecord> {
+        let result = self.game.result()?;
+        let white = self.game.seat(Turn::White)?;
+        let black = self.game.seat(Turn::Black)?;
+        let played = self.history.line();
+        let finished_at = now_secs();
+
+        let mut record = GameRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            room: name.to_string(),
+            white_id: white.user_id.clone(),
+            white_name: white.name.clone(),
+            black_id: black.user_id.clone(),
+            black_name: black.name.clone(),
+            result: result.to_string(),
+            rated: self.game.rated_pool().is_some(),
+            time_control: self.game.time_control().map(|tc| tc.to_string()),
+            start_fen: played
+                .first()
+                .map(|played| played.previous_fen.clone())
+                .unwrap_or(self.current_fen.clone()),
+            moves: played
+                .iter()
+                .map(|played| RecordedMove {
+                    san: played.event.san.clone(),
+                    fen: played.current_fen.clone(),
+                })
+                .collect(),
+            pgn: String::new(),
+            final_fen: self.current_fen.clone(),
+            started_at: self.game.started_at().unwrap_or(finished_at),
+            finished_at,
+        };
+        record.pgn = archive::pgn(&record);
+
+        Some(record)
+    }
+}
+
+#[derive(Debug)]
+pub struct RoomActor {
+    name: String,
+    room: Room,
+    registry: Addr<ChessServer>,
+    metrics: Arc<Metrics>,
+    ratings: Arc<dyn RatingRepository>,
+    /// Ratings of the registered members, by user id
+    member_ratings: HashMap<String, HashMap<Pool, Rating>>,
+}
+
+impl RoomActor {
+    pub fn new(
+        name: String,
+        room: Room,
+        registry: Addr<ChessServer>,
+        metrics: Arc<Metrics>,
+        ratings: Arc<dyn RatingRepository>,
+    ) -> Self {
+        Self {
+            name,
+            room,
+            registry,
+            metrics,
+            ratings,
+            member_ratings: HashMap::new(),
+        }
+    }
+
+    /// Send message to every session in the room
+    fn send_message(&self, message: &str, skip_id: Option<&str>) {
+        let skip_id = skip_id.unwrap_or("");
+        for member in self.room.members().values() {
+            for (id, addr) in &member.sessions {
+                if *id != skip_id {
+                    addr.do_send(Message(message.to_owned()));
+                }
+            }
+        }
+    }
+
+    fn send_message_to_session(&self, id: &str, message: &str) {
+        if let Some(addr) = self.room.session_addr(id) {
+            addr.do_send(Message(message.to_owned()));
+        }
+    }
+
+    /// Record a room state change made by `author` and send it to all users
+    /// in the room
+    fn send_event(&mut self, message: &str, author: &str) {
+        let event = self.room.push_event(message, author);
+        let seq = self.room.seq();
+        tracing::debug!(
+            seq,
+            recipients = self.room.session_ids().len(),
+            "Broadcasting room event"
+        );
+
+        self.send_message(&event, None);
+
+        self.save_snapshot();
+    }
+
+    /// Hand the current state to the registry so the room can be restarted
+    fn save_snapshot(&self) {
+        self.registry.do_send(SaveSnapshot {
+            name: self.name.clone(),
+            snapshot: self.room.snapshot(),
+        });
+    }
+
+    /// Send the board to a session, only the missed events when the client
+    /// can resume
+    fn sync_board(&self, id: &str, board: Option<Vec<String>>) {
+        match board {
+            Some(events) => {
+                for event in events {
+                    self.send_message_to_session(id, &event);
+                }
+            }
+            None => self.send_message_to_session(id, &self.room.sync_board_message(&self.name)),
+        }
+    }
+
+    /// Send the full room state to a session
+    fn sync_session(&self, id: &str, board: Option<Vec<String>>) {
+        self.sync_board(id, board);
+        // sync users
+        self.send_message_to_session(
+            id,
+            &format!(
+                "/sync_users {}|{}",
+                self.name,
+                self.room.usernames().join(",")
+            ),
+        );
+        // sync options
+        self.send_message_to_session(id, &format!("/sync_options {}", self.room.options_string()));
+        // sync game
+        self.send_message_to_session(id, &self.room.sync_game_message());
+        // sync history
+        self.send_message_to_session(id, &self.room.sync_history_message());
+        // sync ratings
+        self.send_message_to_session(id, &self.sync_ratings_message());
+    }
+
+    /// Ratings of the registered members in the pool of the room, unrated
+    /// members are left out
+    fn sync_ratings_message(&self) -> String {
+        let pool = self.room.rating_pool();
+        let ratings: Vec<String> = self
+            .member_ratings
+            .iter()
+            .filter(|(user_id, _)| self.room.members().contains_key(*user_id))
+            .map(|(user_id, ratings)| {
+                let rating = ratings.get(&pool).copied().unwrap_or_default();
+                format!("{}:{}", user_id, rating.to_string())
+            })
+            .collect();
+
+        format!("/sync_ratings {}|{}", pool.as_str(), ratings.join(","))
+    }
+
+    fn broadcast_ratings(&self) {
+        self.send_message(&self.sync_ratings_message(), None);
+    }
+
+    /// Fetch the ratings of a registered member and show them to the room
+    fn load_ratings(&mut self, user_id: String, ctx: &mut Context<Self>) {
+        if self.member_ratings.contains_key(&user_id) {
+            return;
+        }
+
+        let repository = self.ratings.clone();
+        let fut = async move {
+            let ratings = repository.ratings_of(&user_id).await;
+            (user_id, ratings)
+        };
+
+        ctx.spawn(
+            fut.into_actor(self)
+                .map(|(user_id, ratings), act, _| match ratings {
+                    Ok(ratings) => {
+                        act.member_ratings.insert(user_id, ratings);
+                        act.broadcast_ratings();
+                    }
+                    Err(e) => tracing::error!(error = ?e, "Failed to load ratings"),
+                }),
+        );
+    }
+
+    /// Report the game that just ended to the registry and rate it
+    fn finish_game(&mut self, ctx: &mut Context<Self>) {
+        if let (Some(result), Some(record)) =
+            (self.room.game.result(), self.room.game_record(&self.name))
+        {
+            self.rate_finished_game(&record.id, ctx);
+            self.registry.do_send(GameFinished {
+                room: self.name.clone(),
+                result,
+                record,
+            });
+        }
+    }
+
+    /// Rate the game that just ended when it counts for the ratings, under
+    /// the id of its archive record
+    fn rate_finished_game(&mut self, game_id: &str, ctx: &mut Context<Self>) {
+        let game = &self.room.game;
+        let (Some(pool), Some(result)) = (game.rated_pool(), game.result()) else {
+            return;
+        };
+        let (Some(white), Some(black)) = (game.seat(Turn::White), game.seat(Turn::Black)) else {
+            return;
+        };
+
+        let repository = self.ratings.clone();
+        let game = UnratedGame {
+            id: game_id.to_string(),
+            room: self.name.clone(),
+            pool,
+            white_id: white.user_id.clone(),
+            black_id: black.user_id.clone(),
+            result,
+        };
+        let fut = async move { repository.record_game(&game).await };
+
+        ctx.spawn(fut.into_actor(self).map(|rated, act, _| match rated {
+            Ok(game) => {
+                tracing::info!(
+                    game_id = %game.id,
+                    pool = game.pool.as_str(),
+                    "Rated game recorded"
+                );
+                for (user_id, rating) in [
+                    (&game.white_id, game.white_after),
+                    (&game.black_id, game.black_after),
+                ] {
+                    act.member_ratings
+                        .entry(user_id.clone())
+                        .or_default()
+                        .insert(game.pool, rating);
+                }
+                act.broadcast_ratings();
+            }
+            Err(e) => tracing::error!(error = ?e, "Failed to rate game"),
+        }));
+    }
+
+    fn addr_of(&self, id: &str) -> Option<&Addr<WsChessSession>> {
+        self.room.session_addr(id)
+    }
+
+    /// Send the seats, clocks and result to every session, the clocks are
+    /// only correct when received so the game is never replayed as an event
+    fn broadcast_game(&self) {
+        self.send_message(&self.room.sync_game_message(), None);
+        self.save_snapshot();
+    }
+
+    fn broadcast_history(&self) {
+        self.send_message(&self.room.sync_history_message(), None);
+        self.save_snapshot();
+    }
+
+    /// Undo, redo and the other history moves would change the board of a
+    /// game in progress, the session is told so
+    fn history_locked(&self, id: &str) -> bool {
+        if !self.room.game.is_in_progress() {
+            return false;
+        }
+
+        self.send_message_to_session(
+            id,
+            &format!("/notify warning {}", game::GameError::InProgress.as_str()),
+        );
+        true
+    }
+
+    /// Whether the session may reset the board or change the options, the
+    /// seated players keep spectators from doing it
+    fn controls_board(&self, id: &str) -> bool {
+        let user_id = self
+            .room
+            .member_of(id)
+            .map(|member| member.id.clone())
+            .unwrap_or_default();
+
+        match self.room.game.check_control(&user_id) {
+            Ok(()) => true,
+            Err(e) => {
+                self.send_message_to_session(id, &format!("/notify warning {}", e.as_str()));
+                false
+            }
+        }
+    }
+
+    /// Put the position of the current move of the history on the board,
+    /// `author` is the session that moved through the history
+    fn show_current_position(&mut self, author: &str) -> bool {
+        let room = &mut self.room;
+        let Some((fen, trash)) = room.position_of(room.history.current()) else {
+            return false;
+        };
+        let Ok(chess_board) = ChessBoardBuilder::new()
+            .fen(&fen)
+            .deleted_stones(&trash)
+            .validation(room.chess_board.validation)
+            .sync(room.chess_board.sync)
+            .build()
+        else {
+            return false;
+        };
+
+        let is_checkmate = chess_board.is_checkmate();
+        let msg = format!("/sync_board {}|{}|{}", self.name, fen, trash);
+        room.current_fen = fen;
+        room.trash = trash;
+        room.chess_board = chess_board;
+
+        self.send_event(&msg, author);
+        if is_checkmate {
+            self.send_event("/checkmate", author);
+        }
+
+        true
+    }
+
+    /// End the game of a player that ran out of time
+    fn check_flag(&mut self, ctx: &mut Context<Self>) {
+        let Some(result) = self.room.game.check_flag(Instant::now()) else {
+            return;
+        };
+
+        tracing::info!(result = %result.to_string(), "Game ended on time");
+        self.broadcast_game();
+        self.finish_game(ctx);
+    }
+}
+
+fn side_of(stone: &Stone) -> Turn {
+    match stone.color() {
+        Color::Light => Turn::White,
+        Color::Dark => Turn::Black,
+    }
+}
+
+impl Actor for RoomActor {
+    type Context = Context<Self>;
+
+    /// A restarted room still has its users, bring them back in sync
+    fn started(&mut self, ctx: &mut Self::Context) {
+        tracing::info!(room = %self.name, "Room started");
+
+        for id in self.room.session_ids() {
+            self.sync_session(&id, None);
+        }
+
+        let registered: Vec<String> = self
+            .room
+            .members()
+            .values()
+            .filter(|member| member.registered)
+            .map(|member| member.id.clone())
+            .collect();
+        for user_id in registered {
+            self.load_ratings(user_id, ctx);
+        }
+
+        ctx.run_interval(CLOCK_CHECK_INTERVAL, |act, ctx| act.check_flag(ctx));
+    }
+}
+
+impl Handler<Enter> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "enter", skip_all, fields(session_id = %msg.session.id, room = %self.name))]
+    fn handle(&mut self, msg: Enter, ctx: &mut Self::Context) {
+        let _timer = self.metrics.handler_timer("enter");
+
+        let Enter {
+            session,
+            name,
+            arrival,
+        } = msg;
+        let id = session.id.clone();
+        let previous = self.room.insert_session(&session, &name);
+        let Some(member) = self.room.member_of(&id) else {
+            return;
+        };
+        let member_string = member.to_string();
+        let user_id = member.id.clone();
+        let registered = member.registered;
+
+        // other sessions of an online user already show it
+        match previous {
+            None => self.send_message(&format!("/add_user {}", member_string), Some(&id)),
+            Some(false) => {
+                self.send_message(&format!("/connect_user {}", member_string), Some(&id))
+            }
+            Some(true) => {}
+        }
+
+        match arrival {
+            Arrival::Joined => {
+                self.sync_session(&id, None);
+                // notify user
+                self.send_message_to_session(
+                    &id,
+                    &format!("/notify success Joined room {}", self.name),
+                );
+            }
+            Arrival::Connected { resume } => {
+                let missed_events = resume.and_then(|seq| self.room.events_since(seq));
+                self.sync_session(&id, missed_events);
+            }
+        }
+
+        // notify user if checkmate
+        if self.room.chess_board.is_checkmate() {
+            self.send_message_to_session(&id, "/checkmate");
+        }
+
+        // a player back within the grace period continues its game
+        if previous != Some(true) && self.room.game.set_back(&user_id, Instant::now()) {
+            tracing::info!(user_id = %user_id, "Player is back");
+            self.broadcast_game();
+        }
+
+        if registered {
+            self.load_ratings(user_id, ctx);
+        }
+    }
+}
+
+impl Handler<Leave> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "leave", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Leave, _: &mut Self::Context) {
+        let _timer = self.metrics.handler_timer("leave");
+
+        let Some(member) = self.room.remove_session(&msg.id) else {
+            return;
+        };
+        if member.is_online() {
+            return;
+        }
+
+        let user_id = member.id.clone();
+        if let Some(member) = self.room.remove_member(&user_id) {
+            self.send_message(&format!("/remove_user {}", member.to_string()), None);
+        }
+        self.member_ratings.remove(&user_id);
+
+        if self.room.game.set_away(&user_id, Instant::now()) {
+            tracing::info!(user_id = %user_id, "Player left its game");
+            self.broadcast_game();
+        }
+    }
+}
+
+impl Handler<Away> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "away", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Away, _: &mut Self::Context) {
+        let _timer = self.metrics.handler_timer("away");
+
+        let Some(member) = self.room.remove_session(&msg.id) else {
+            return;
+        };
+        if member.is_online() {
+            return;
+        }
+
+        let user_id = member.id.clone();
+        let message = format!("/disconnect_user {}", member.to_string());
+        self.send_message(&message, None);
+
+        if self.room.game.set_away(&user_id, Instant::now()) {
+            tracing::info!(user_id = %user_id, "Player disconnected from its game");
+            self.broadcast_game();
+        }
+    }
+}
+
+impl Handler<Forget> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "forget", skip_all, fields(user_id = %msg.user_id, room = %self.name))]
+    fn handle(&mut self, msg: Forget, _: &mut Self::Context) {
+        let _timer = self.metrics.handler_timer("forget");
+
+        let is_away = self
+            .room
+            .members()
+            .get(&msg.user_id)
+            .map(|member| !member.is_online())
+            .unwrap_or(false);
+        if !is_away {
+            return;
+        }
+
+        if let Some(member) = self.room.remove_member(&msg.user_id) {
+            self.send_message(&format!("/remove_user {}", member.to_string()), None);
+        }
+        self.member_ratings.remove(&msg.user_id);
+    }
+}
+
+impl Handler<Rename> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "rename", skip_all, fields(user_id = %msg.user_id, room = %self.name))]
+    fn handle(&mut self, msg: Rename, _: &mut Self::Context) {
+        let _timer = self.metrics.handler_timer("rename");
+
+        let Some(member) = self.room.rename_member(&msg.user_id, &msg.name) else {
+            tracing::error!("No user found");
+            return;
+        };
+        let member_string = member.to_string();
+
+        // notify all users in room
+        self.send_message(&format!("/add_user {}", member_string), None);
+
+        if self.room.game.rename(&msg.user_id, &msg.name) {
+            self.broadcast_game();
+        }
+    }
+}
+
+impl Handler<Broadcast> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Broadcast, _: &mut Self::Context) {
+        self.send_message(&msg.0, None);
+    }
+}
+
+impl Handler<ClientMessage> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "client_message", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: ClientMessage, _: &mut Self::Context) {
+        let _timer = self.metrics.handler_timer("client_message");
+
+        if self.addr_of(&msg.id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        self.send_message(msg.msg.as_str(), Some(&msg.id));
+    }
+}
+
+impl Handler<Move> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "move", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Move, ctx: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("move");
+
+        let Move {
+            id,
+            piece,
+            from,
+            to,
+            promotion,
+            base_seq,
+        } = msg;
+
+        if self.addr_of(&id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        // the client moved on a board that changed in the meantime
+        if let Some(base_seq) = base_seq.filter(|seq| self.room.is_stale(*seq, &id)) {
+            self.metrics.reject_stale_move();
+            tracing::info!(base_seq, seq = self.room.seq(), "Rejected stale move");
+            self.send_message_to_session(&id, &self.room.rollback_message(&self.name, "stale"));
+            return;
+        }
+
+        let Ok(stone) = piece.parse::<Stone>() else {
+            tracing::warn!(piece = %piece, "Invalid piece");
+            return;
+        };
+
+        let user_id = self
+            .room
+            .member_of(&id)
+            .map(|member| member.id.clone())
+            .unwrap_or_default();
+        let side = side_of(&stone);
+        if let Err(e) = self.room.game.check_move(&user_id, side) {
+            tracing::info!(error = ?e, "Move refused by the game");
+            self.send_message_to_session(&id, &self.room.rollback_message(&self.name, "invalid"));
+            self.send_message_to_session(&id, &format!("/notify warning {}", e.as_str()));
+            return;
+        }
+
+        let chess_board = &mut self.room.chess_board;
+        let from_position: Option<Position> = from.parse().ok();
+        let to_position: Option<Position> = to.parse().ok();
+        let current_fen = chess_board.fen.clone();
+        let trash = chess_board.trash_string();
+        let (chess_board_move, move_event) = match chess_board.play_move(
+            &piece,
+            from_position.clone(),
+            to_position.clone(),
+            promotion,
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                self.metrics.reject_move(&e);
+                tracing::warn!(
+                    piece = %piece,
+                    from = %from,
+                    to = %to,
+                    error = ?e,
+                    "Failed attempt to move piece"
+                );
+                self.send_message_to_session(
+                    &id,
+                    &self.room.rollback_message(&self.name, "invalid"),
+                );
+                return;
+            }
+        };
+
+        self.metrics.moves.inc();
+        let is_checkmate = chess_board.is_checkmate();
+        self.room.current_fen = move_event.fen.clone();
+        self.room.trash = move_event.trash.clone();
+
+        tracing::debug!(san = %move_event.san, "Piece moved");
+
+        let move_msg = format!("/move_event {}", move_event.to_string());
+        let move_result = MoveResult {
+            stone,
+            from: from_position,
+            to: to_position,
+            chess_board_move,
+            event: move_event,
+            previous_fen: current_fen,
+            previous_trash: trash,
+            current_fen: self.room.current_fen.clone(),
+            current_trash: self.room.trash.clone(),
+        };
+        self.room.push_move(move_result);
+
+        // the mover already shows its own piece on the new square, it still
+        // needs the side effects so it gets the event too
+        self.send_event(&move_msg, &id);
+        if is_checkmate {
+            self.send_event("/checkmate", &id);
+        }
+        self.broadcast_history();
+
+        if self
+            .room
+            .game
+            .record_move(side, is_checkmate, Instant::now())
+        {
+            self.broadcast_game();
+            if self.room.game.result().is_some() {
+                self.finish_game(ctx);
+            }
+        }
+    }
+}
+
+impl Handler<Reset> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "reset", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Reset, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("reset");
+
+        if self.addr_of(&msg.id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        // a game in progress only ends with a result, it never vanishes
+        if self.history_locked(&msg.id) || !self.controls_board(&msg.id) {
+            return;
+        }
+
+        let room = &mut self.room;
+        let Ok(chess_board) = ChessBoardBuilder::new()
+            .fen(&room.original_fen)
+            .deleted_stones(&room.original_trash)
+            .validation(room.chess_board.validation)
+            .sync(room.chess_board.sync)
+            .build()
+        else {
+            self.send_message_to_session(&msg.id, "/notify error Failed to reset board");
+            return;
+        };
+        room.current_fen = room.original_fen.clone();
+        room.trash = room.original_trash.to_owned();
+        let _ = room.history.goto(None);
+        room.chess_board = chess_board;
+        room.game.reset();
+
+        let sync_board = format!(
+            "/sync_board {}|{}|{}",
+            self.name, room.current_fen, room.trash
+        );
+        self.send_event(&sync_board, &msg.id);
+        self.broadcast_game();
+        self.broadcast_history();
+    }
+}
+
+impl Handler<CurrentDiagram> for RoomActor {
+    type Result = MessageResult<CurrentDiagram>;
+
+    fn handle(&mut self, _: CurrentDiagram, _: &mut Self::Context) -> Self::Result {
+        MessageResult(self.room.diagram())
+    }
+}
+
+#[cfg(feature = "gif")]
+impl Handler<CurrentAnimation> for RoomActor {
+    type Result = MessageResult<CurrentAnimation>;
+
+    fn handle(&mut self, _: CurrentAnimation, _: &mut Self::Context) -> Self::Result {
+        MessageResult(self.room.animation())
+    }
+}
+
+impl Handler<LoadPosition> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "load_position", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: LoadPosition, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("load_position");
+
+        if self.addr_of(&msg.id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        // seated players keep spectators from replacing the game position
+        if self.history_locked(&msg.id) || !self.controls_board(&msg.id) {
+            return;
+        }
+
+        if let Err(error) = self.room.load_position(&msg.fen) {
+            self.send_message_to_session(&msg.id, &format!("/notify error {}", error));
+            return;
+        }
+
+        let room = &self.room;
+        let sync_board = format!(
+            "/sync_board {}|{}|{}",
+            self.name, room.current_fen, room.trash
+        );
+        self.send_event(&sync_board, &msg.id);
+        self.broadcast_game();
+        self.broadcast_history();
+    }
+}
+
+impl Handler<Undo> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "undo", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Undo, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("undo");
+
+        let Undo { id } = msg;
+
+        if self.addr_of(&id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        if self.history_locked(&id) {
+            return;
+        }
+
+        let room = &mut self.room;
+        let msg;
+
+        match room.undo_move() {
+            Ok(move_result) => {
+                msg = format!(
+                    "/sync_board {}|{}|{}",
+                    self.name, move_result.previous_fen, move_result.previous_trash
+                );
+                let Ok(chess_board) = ChessBoardBuilder::new()
+                    .fen(&move_result.previous_fen)
+                    .deleted_stones(&move_result.previous_trash)
+                    .validation(room.chess_board.validation)
+                    .sync(room.chess_board.sync)
+                    .build()
+                else {
+                    let _ = room.redo_move();
+                    self.send_message_to_session(&id, "/notify error Failed to undo move");
+                    return;
+                };
+                room.current_fen = move_result.previous_fen;
+                room.trash = move_result.previous_trash;
+                room.chess_board = chess_board;
+            }
+            Err(_) => {
+                let fen = room.current_fen.clone();
+                let trash = room.trash.clone();
+                msg = format!("/sync_board {}|{}|{}", self.name, fen, trash);
+                self.send_message_to_session(&id, "/notify warning No more moves to undo");
+            }
+        };
+
+        self.send_event(&msg, &id);
+        self.broadcast_history();
+    }
+}
+
+impl Handler<Redo> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "redo", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Redo, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("redo");
+
+        let Redo { id } = msg;
+
+        if self.addr_of(&id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        if self.history_locked(&id) {
+            return;
+        }
+
+        let room = &mut self.room;
+        let msg;
+        let mut is_checkmate = false;
+
+        match room.redo_move() {
+            Ok(move_result) => {
+                msg = format!(
+                    "/sync_board {}|{}|{}",
+                    self.name, move_result.current_fen, move_result.current_trash
+                );
+                let Ok(chess_board) = ChessBoardBuilder::new()
+                    .fen(&move_result.current_fen)
+                    .deleted_stones(&move_result.current_trash)
+                    .validation(room.chess_board.validation)
+                    .sync(room.chess_board.sync)
+                    .build()
+                else {
+                    let _ = room.undo_move();
+                    self.send_message_to_session(&id, "/notify error Failed to redo move");
+                    return;
+                };
+
+                is_checkmate = chess_board.is_checkmate();
+                room.current_fen = move_result.current_fen;
+                room.trash = move_result.current_trash;
+                room.chess_board = chess_board;
+            }
+            Err(_) => {
+                let fen = room.current_fen.clone();
+                let trash = room.trash.clone();
+                msg = format!("/sync_board {}|{}|{}", self.name, fen, trash);
+                self.send_message_to_session(&id, "/notify warning No more moves to redo");
+            }
+        };
+
+        self.send_event(&msg, &id);
+        if is_checkmate {
+            self.send_event("/checkmate", &id)
+        }
+        self.broadcast_history();
+    }
+}
+
+impl Handler<Goto> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "goto", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Goto, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("goto");
+
+        let Goto { id, node } = msg;
+
+        if self.addr_of(&id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        if self.history_locked(&id) {
+            return;
+        }
+
+        let previous = self.room.history.current();
+        if self.room.history.goto(node).is_err() {
+            self.send_message_to_session(&id, "/notify warning This move no longer exists");
+            return;
+        }
+        if !self.show_current_position(&id) {
+            let _ = self.room.history.goto(previous);
+            self.send_message_to_session(&id, "/notify error Failed to go to move");
+            return;
+        }
+
+        self.broadcast_history();
+    }
+}
+
+impl Handler<Promote> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "promote", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Promote, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("promote");
+
+        let Promote { id, node } = msg;
+
+        if self.addr_of(&id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        if self.history_locked(&id) {
+            return;
+        }
+
+        if self.room.history.promote(node).is_err() {
+            self.send_message_to_session(&id, "/notify warning This move no longer exists");
+            return;
+        }
+
+        self.broadcast_history();
+    }
+}
+
+impl Handler<DeleteMove> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "delete_move", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: DeleteMove, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("delete_move");
+
+        let DeleteMove { id, node } = msg;
+
+        if self.addr_of(&id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        if self.history_locked(&id) {
+            return;
+        }
+
+        match self.room.history.delete(node) {
+            Ok(true) => {
+                // the board showed a deleted move
+                if !self.show_current_position(&id) {
+                    self.send_message_to_session(&id, "/notify error Failed to show the board");
+                }
+            }
+            Ok(false) => {}
+            Err(_) => {
+                self.send_message_to_session(&id, "/notify warning This move no longer exists");
+                return;
+            }
+        }
+
+        self.broadcast_history();
+    }
+}
+
+impl Handler<Annotate> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "annotate", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Annotate, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("annotate");
+
+        let Annotate { id, node, change } = msg;
+
+        if self.addr_of(&id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        if self.history_locked(&id) {
+            return;
+        }
+
+        let Some(annotation) = self.room.history.annotation_mut(node) else {
+            self.send_message_to_session(&id, "/notify warning This move no longer exists");
+            return;
+        };
+        match change {
+            AnnotationChange::Comment(comment) => annotation.comment = comment,
+            AnnotationChange::ToggleNag(nag) => annotation.toggle_nag(nag),
+            AnnotationChange::ToggleShape(shape) => annotation.toggle_shape(shape),
+            AnnotationChange::ClearShapes => annotation.shapes.clear(),
+        }
+
+        self.broadcast_history();
+    }
+}
+
+impl Handler<Fork> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "fork", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Fork, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("fork");
+
+        if self.addr_of(&msg.id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        let node = msg.node.unwrap_or(self.room.history.current());
+        let Ok(room) = self.room.fork(node, msg.with_history) else {
+            self.send_message_to_session(&msg.id, "/notify warning This move no longer exists");
+            return;
+        };
+
+        self.registry.do_send(ForkRoom {
+            id: msg.id,
+            name: msg.name,
+            room,
+        });
+    }
+}
+
+impl Handler<ExportPgn> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "export_pgn", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: ExportPgn, _: &mut Self::Context) -> Self::Result {
+        self.send_message_to_session(&msg.id, &format!("/pgn {}", self.room.pgn(&self.name)));
+    }
+}
+
+impl Handler<Options> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "options", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Options, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("options");
+
+        if self.addr_of(&msg.id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        // the rules and the clock policy of a game can't change under its
+        // players, the form of the session goes back to the room options
+        if !self.controls_board(&msg.id) {
+            self.send_message_to_session(
+                &msg.id,
+                &format!("/sync_options {}", self.room.options_string()),
+            );
+            return;
+        }
+
+        let room = &mut self.room;
+        let new_chess_board = ChessBoardBuilder::new()
+            .fen(&room.current_fen)
+            .deleted_stones(&room.trash)
+            .validation(msg.validation)
+            .sync(msg.sync)
+            .build();
+
+        if let Ok(chess_board) = new_chess_board {
+            room.chess_board = chess_board;
+            self.send_message_to_session(&msg.id, "/notify success Options applied");
+        } else {
+            self.send_message_to_session(&msg.id, "/notify error Failed to apply options");
+        }
+
+        self.room
+            .game
+            .set_pause_when_away(msg.pause_clock, Instant::now());
+
+        if let Err(e) = self.room.game.set_rated(msg.rated) {
+            self.send_message_to_session(&msg.id, &format!("/notify warning {}", e.as_str()));
+        }
+
+        let result_msg = self.room.options_string();
+        self.send_event(&format!("/sync_options {}", result_msg), &msg.id);
+        self.broadcast_game();
+    }
+}
+
+impl Handler<Resync> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "resync", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Resync, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("resync");
+
+        if self.addr_of(&msg.id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        let missed_events = msg.seq.and_then(|seq| self.room.events_since(seq));
+        tracing::debug!(
+            seq = ?msg.seq,
+            resumed = missed_events.is_some(),
+            "Client resync"
+        );
+        self.sync_board(&msg.id, missed_events);
+    }
+}
+
+impl Handler<Sit> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "sit", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Sit, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("sit");
+
+        let Some(member) = self.room.member_of(&msg.id) else {
+            tracing::error!("No user found");
+            return;
+        };
+        let (user_id, name) = (member.id.clone(), member.name.clone());
+        let registered = member.registered;
+
+        match self.room.game.sit(&user_id, &name, registered, msg.side) {
+            Ok(_) => self.broadcast_game(),
+            Err(e) => {
+                self.send_message_to_session(&msg.id, &format!("/notify warning {}", e.as_str()))
+            }
+        }
+    }
+}
+
+impl Handler<Stand> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "stand", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Stand, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("stand");
+
+        let Some(user_id) = self.room.member_of(&msg.id).map(|m| m.id.clone()) else {
+            tracing::error!("No user found");
+            return;
+        };
+
+        match self.room.game.stand(&user_id) {
+            Ok(_) => self.broadcast_game(),
+            Err(e) => {
+                self.send_message_to_session(&msg.id, &format!("/notify warning {}", e.as_str()))
+            }
+        }
+    }
+}
+
+impl Handler<SetClock> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "set_clock", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: SetClock, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("set_clock");
+
+        if self.addr_of(&msg.id).is_none() {
+            tracing::error!("No user found");
+            return;
+        }
+
+        match self.room.game.set_time_control(msg.time_control) {
+            Ok(_) => {
+                self.broadcast_game();
+                self.broadcast_ratings();
+            }
+            Err(e) => {
+                self.send_message_to_session(&msg.id, &format!("/notify warning {}", e.as_str()))
+            }
+        }
+    }
+}
+
+impl Handler<Claim> for RoomActor {
+    type Result = ();
+
+    #[tracing::instrument(name = "claim", skip_all, fields(session_id = %msg.id, room = %self.name))]
+    fn handle(&mut self, msg: Claim, ctx: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("claim");
+
+        let Some(user_id) = self.room.member_of(&msg.id).map(|m| m.id.clone()) else {
+            tracing::error!("No user found");
+            return;
+        };
+
+        match self.room.game.claim(&user_id, msg.claim, Instant::now()) {
+            Ok(result) => {
+                tracing::info!(result = %result.to_string(), "Abandoned game claimed");
+                self.broadcast_game();
+                self.finish_game(ctx);
+            }
+            Err(e) => {
+                self.send_message_to_session(&msg.id, &format!("/notify warning {}", e.as_str()))
+            }
+        }
+    }
+}
+
+traced_handlers!(
+    RoomActor;
+    ClientMessage,
+    Move,
+    Reset,
+    LoadPosition,
+    Undo,
+    Redo,
+    Goto,
+    Promote,
+    DeleteMove,
+    Annotate,
+    Fork,
+    ExportPgn,
+    Options,
+    Resync,
+    Sit,
+    Stand,
+    SetClock,
+    Claim,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays a move on the board of the room and records it like the `Move`
+    /// handler does
+    fn play(room: &mut Room, piece: &str, from: &str, to: &str) {
+        let previous_fen = room.current_fen.clone();
+        let previous_trash = room.trash.clone();
+        let (chess_board_move, event) = room
+            .chess_board
+            .play_move(
+                piece,
+                from.parse().ok(),
+                to.parse().ok(),
+                PromotionKind::Queen,
+            )
+            .unwrap();
+        room.current_fen = event.fen.clone();
+        room.trash = event.trash.clone();
+
+        room.push_move(MoveResult {
+            from: from.parse().ok(),
+            to: to.parse().ok(),
+            stone: piece.parse().unwrap(),
+            chess_board_move,
+            previous_fen,
+            previous_trash,
+            current_fen: event.fen.clone(),
+            current_trash: event.trash.clone(),
+            event,
+        });
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut room = Room::new(None, None).unwrap();
+        play(&mut room, "lp", "e2", "e4");
+        play(&mut room, "dp", "e7", "e5");
+        room.undo_move().unwrap();
+        play(&mut room, "dp", "c7", "c5");
+        room.history
+            .annotation_mut(room.history.current())
+            .unwrap()
+            .comment = "Sicilian".to_string();
+        room.push_event("/move_event ...", "session");
+
+        let restarted = Room::from_snapshot(&room.snapshot()).unwrap();
+        assert_eq!(restarted.current_fen, room.current_fen);
+        assert_eq!(restarted.seq(), room.seq());
+        assert_eq!(restarted.pgn("room"), room.pgn("room"));
+        assert_eq!(
+            restarted.sync_history_message(),
+            room.sync_history_message()
+        );
+        assert_eq!(
+            restarted
+                .history
+                .line()
+                .iter()
+                .map(|result| result.event.san.clone())
+                .collect::<Vec<_>>(),
+            vec!["e4", "c5"]
+        );
+    }
+
+    #[test]
+    fn test_seated_game() {
+        let player = |user_id: &str| Player {
+            session_id: None,
+            user_id: user_id.to_string(),
+            name: user_id.to_string(),
+            registered: false,
+        };
+        let mut room = Room::new(None, None).unwrap();
+        room.seat_players(&player("w"), &player("b"), None, false)
+            .unwrap();
+        assert!(room.chess_board.validation);
+
+        play(&mut room, "lp", "e2", "e4");
+        room.game.record_move(Turn::White, false, Instant::now());
+
+        // white plays again, out of turn
+        assert_eq!(
+            room.game.check_move("w", Turn::White),
+            Err(game::GameError::NotYourTurn)
+        );
+        assert!(room
+            .chess_board
+            .play_move(
+                "lp",
+                "d2".parse().ok(),
+                "d4".parse().ok(),
+                PromotionKind::Queen
+            )
+            .is_err());
+        assert_eq!(room.game.check_move("b", Turn::Black), Ok(()));
+    }
+
+    #[test]
+    fn test_load_position() {
+        let player = |user_id: &str| Player {
+            session_id: None,
+            user_id: user_id.to_string(),
+            name: user_id.to_string(),
+            registered: false,
+        };
+        let mut room = Room::new(None, None).unwrap();
+        room.seat_players(&player("w"), &player("b"), None, false)
+            .unwrap();
+
+        // waiting for the first move the players control the board, not
+        // the spectators
+        assert_eq!(
+            room.game.check_control("spectator"),
+            Err(game::GameError::NotSeated)
+        );
+        assert_eq!(room.game.check_control("w"), Ok(()));
+
+        play(&mut room, "lp", "e2", "e4");
+        let fen = "4k3/8/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(room.load_position("not a fen"), Err("Invalid FEN"));
+        assert_eq!(room.load_position(fen), Ok(()));
+        assert_eq!(room.original_fen, fen);
+        assert_eq!(room.current_fen, fen);
+        assert!(room.history.line().is_empty());
+    }
+
+    #[test]
+    fn test_events() {
+        let mut room = Room::new(None, None).unwrap();
+        assert_eq!(room.events_since(0), Some(vec![]));
+        assert!(!room.is_stale(0, "a"));
+
+        assert_eq!(room.push_event("/checkmate", "a"), "/event 1 /checkmate");
+        room.push_event("/checkmate", "a");
+        room.push_event("/checkmate", "b");
+        assert_eq!(
+            room.events_since(1),
+            Some(vec![
+                "/event 2 /checkmate".to_string(),
+                "/event 3 /checkmate".to_string()
+            ])
+        );
+        assert_eq!(room.events_since(3), Some(vec![]));
+
+        // someone else moved after the base
+        assert!(room.is_stale(2, "a"));
+        // only the mover's own events came after it
+        assert!(!room.is_stale(2, "b"));
+        assert!(!room.is_stale(3, "a"));
+
+        // a base from the future is a client out of sync
+        assert!(room.is_stale(4, "a"));
+        assert_eq!(room.events_since(4), None);
+
+        // bases older than the buffer can't be checked
+        for _ in 0..ROOM_EVENTS_BUFFER {
+            room.push_event("/checkmate", "a");
+        }
+        assert_eq!(room.events_since(2), None);
+        assert!(room.is_stale(2, "a"));
+        let first = room.seq() - ROOM_EVENTS_BUFFER as u64;
+        assert_eq!(
+            room.events_since(first).map(|events| events.len()),
+            Some(ROOM_EVENTS_BUFFER)
+        );
+        assert!(!room.is_stale(first, "a"));
+    }
+
+    #[test]
+    fn test_events_after_restart() {
+        let mut room = Room::new(None, None).unwrap();
+        room.push_event("/checkmate", "a");
+        room.push_event("/checkmate", "b");
+
+        // the buffer starts empty, only clients up to date can resume
+        let mut room = Room::from_snapshot(&room.snapshot()).unwrap();
+        assert_eq!(room.seq(), 2);
+        assert_eq!(room.events_since(2), Some(vec![]));
+        assert_eq!(room.events_since(1), None);
+        assert!(!room.is_stale(2, "a"));
+        assert!(room.is_stale(1, "b"));
+
+        room.push_event("/checkmate", "a");
+        assert_eq!(
+            room.events_since(2),
+            Some(vec!["/event 3 /checkmate".to_string()])
+        );
+        assert!(!room.is_stale(2, "a"));
+        assert!(room.is_stale(2, "b"));
+    }
+}
@@ -0,0 +1,306 @@
+//! Session cookies holding the signed `SessionPayload` of a user, anonymous
+//! or logged in to an account.
+//!
+//! Every session is issued as a short lived access token, read by the
+//! websocket and the API, and a long lived refresh token only sent to
+//! `/sessions` to get a new pair. Both cookies are `HttpOnly`, the client
+//! learns who it is from `GET /sessions/me`.
+
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    http::StatusCode,
+    HttpRequest, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    jwt::{KeysError, SigningKeys, TokenError},
+    rate_limit::env_or,
+};
+
+pub const SESSION_COOKIE: &str = "session_token";
+pub const REFRESH_COOKIE: &str = "refresh_token";
+
+/// The refresh token is only sent to the session endpoints
+const REFRESH_COOKIE_PATH: &str = "/sessions";
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SessionPayload {
+    pub sub: String,
+    pub name: String,
+    pub iat: u64,
+    /// Set when the session is logged in to an account, `sub` is then the
+    /// account id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+    /// Sessions generation of the account when the session was logged in,
+    /// refresh tokens of older generations are refused
+    #[serde(default)]
+    pub generation: u64,
+    /// Login the account session comes from, kept by refreshes so a logout
+    /// ends this login and not the other ones of the account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub login_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Claims {
+    #[serde(flatten)]
+    session: SessionPayload,
+    exp: u64,
+    typ: TokenType,
+}
+
+#[derive(Clone, Debug)]
+pub struct SessionConfig {
+    /// Lifetime of the access token, in seconds
+    pub access_ttl: u64,
+    /// Lifetime of the refresh token, in seconds
+    pub refresh_ttl: u64,
+    pub secure: bool,
+    pub same_site: SameSite,
+    pub domain: Option<String>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            access_ttl: 15 * 60,
+            refresh_ttl: 30 * 24 * 60 * 60,
+            secure: true,
+            same_site: SameSite::Lax,
+            domain: None,
+        }
+    }
+}
+
+impl SessionConfig {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let same_site = match env::var("COOKIE_SAME_SITE")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "strict" => SameSite::Strict,
+            "none" => SameSite::None,
+            _ => SameSite::Lax,
+        };
+
+        Self {
+            access_ttl: env_or("ACCESS_TOKEN_TTL", default.access_ttl),
+            refresh_ttl: env_or("REFRESH_TOKEN_TTL", default.refresh_ttl),
+            secure: env_or("COOKIE_SECURE", default.secure),
+            same_site,
+            domain: env::var("COOKIE_DOMAIN").ok().filter(|d| !d.is_empty()),
+        }
+    }
+}
+
+/// Signs, verifies and turns sessions into cookies
+#[derive(Clone)]
+pub struct Sessions {
+    keys: SigningKeys,
+    config: SessionConfig,
+}
+
+impl Sessions {
+    pub fn new(keys: SigningKeys, config: SessionConfig) -> Self {
+        Self { keys, config }
+    }
+
+    pub fn from_env() -> Result<Self, KeysError> {
+        Ok(Self::new(
+            SigningKeys::from_env()?,
+            SessionConfig::from_env(),
+        ))
+    }
+
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    fn encode(&self, session: &SessionPayload, typ: TokenType) -> Result<String, TokenError> {
+        let ttl = match typ {
+            TokenType::Access => self.config.access_ttl,
+            TokenType::Refresh => self.config.refresh_ttl,
+        };
+        let claims = Claims {
+            session: session.clone(),
+            exp: now_secs() + ttl,
+            typ,
+        };
+
+        self.keys.encode(&claims)
+    }
+
+    /// Verified session of a token of the given type, with its expiry
+    pub fn decode(&self, token: &str, typ: TokenType) -> Result<(SessionPayload, u64), TokenError> {
+        let claims: Claims = self.keys.decode(token)?;
+
+        if claims.typ != typ {
+            return Err(TokenError::WrongType);
+        }
+        if claims.exp <= now_secs() {
+            return Err(TokenError::Expired);
+        }
+
+        Ok((claims.session, claims.exp))
+    }
+
+    fn cookie(
+        &self,
+        name: &'static str,
+        value: String,
+        path: &'static str,
+        ttl: u64,
+    ) -> Cookie<'static> {
+        let mut cookie = Cookie::build(name, value)
+            .path(path)
+            .http_only(true)
+            .secure(self.config.secure)
+            .same_site(self.config.same_site)
+            .max_age(Duration::seconds(ttl as i64))
+            .finish();
+
+        if let Some(domain) = self.config.domain.clone() {
+            cookie.set_domain(domain);
+        }
+
+        cookie
+    }
+
+    /// Access and refresh cookies of a new token pair
+    pub fn cookies(&self, session: &SessionPayload) -> Result<[Cookie<'static>; 2], TokenError> {
+        let access = self.encode(session, TokenType::Access)?;
+        let refresh = self.encode(session, TokenType::Refresh)?;
+
+        Ok([
+            self.cookie(SESSION_COOKIE, access, "/", self.config.access_ttl),
+            self.cookie(
+                REFRESH_COOKIE,
+                refresh,
+                REFRESH_COOKIE_PATH,
+                self.config.refresh_ttl,
+            ),
+        ])
+    }
+
+    /// Answers with the cookies of a new token pair for `session`
+    pub fn response(&self, status: StatusCode, session: &SessionPayload) -> HttpResponse {
+        match self.cookies(session) {
+            Ok([access, refresh]) => HttpResponse::build(status)
+                .cookie(access)
+                .cookie(refresh)
+                .finish(),
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to encode the session");
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+
+    /// Verified session of the access cookie of a request, with its expiry
+    pub fn from_request(&self, req: &HttpRequest) -> Option<(SessionPayload, u64)> {
+        let cookie = req.cookie(SESSION_COOKIE)?;
+        self.decode(cookie.value(), TokenType::Access).ok()
+    }
+
+    /// Verified session of the refresh cookie of a request
+    pub fn refresh_from_request(&self, req: &HttpRequest) -> Option<SessionPayload> {
+        let cookie = req.cookie(REFRESH_COOKIE)?;
+        self.decode(cookie.value(), TokenType::Refresh)
+            .ok()
+            .map(|(session, _)| session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> SessionPayload {
+        SessionPayload {
+            sub: "user".to_string(),
+            name: "Alice".to_string(),
+            iat: 0,
+            account_id: None,
+            generation: 0,
+            login_id: None,
+        }
+    }
+
+    fn sessions_with(keys: &[(&str, &str)], access_ttl: u64) -> Sessions {
+        let keys = SigningKeys::new(keys.iter().map(|(kid, s)| (*kid, s.as_bytes()))).unwrap();
+        let config = SessionConfig {
+            access_ttl,
+            ..Default::default()
+        };
+
+        Sessions::new(keys, config)
+    }
+
+    #[test]
+    fn test_token_types_and_expiry() {
+        let sessions = sessions_with(&[("a", "secret a")], 60);
+        let access = sessions.encode(&session(), TokenType::Access).unwrap();
+        let refresh = sessions.encode(&session(), TokenType::Refresh).unwrap();
+
+        assert_eq!(
+            sessions.decode(&access, TokenType::Access).unwrap().0,
+            session()
+        );
+        assert!(matches!(
+            sessions.decode(&refresh, TokenType::Access),
+            Err(TokenError::WrongType)
+        ));
+
+        let expired = sessions_with(&[("a", "secret a")], 0);
+        let access = expired.encode(&session(), TokenType::Access).unwrap();
+        assert!(matches!(
+            expired.decode(&access, TokenType::Access),
+            Err(TokenError::Expired)
+        ));
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let old = sessions_with(&[("a", "secret a")], 60);
+        let rotated = sessions_with(&[("b", "secret b"), ("a", "secret a")], 60);
+        let retired = sessions_with(&[("b", "secret b")], 60);
+
+        let token = old.encode(&session(), TokenType::Access).unwrap();
+        assert!(rotated.decode(&token, TokenType::Access).is_ok());
+        assert!(matches!(
+            retired.decode(&token, TokenType::Access),
+            Err(TokenError::UnknownKey)
+        ));
+
+        let forged = sessions_with(&[("b", "other secret")], 60)
+            .encode(&session(), TokenType::Access)
+            .unwrap();
+        assert!(matches!(
+            rotated.decode(&forged, TokenType::Access),
+            Err(TokenError::InvalidSignature)
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+//! Tracing setup for the server.
+//!
+//! `RUST_LOG` filters spans and events, `LOG_FORMAT` switches the output
+//! between `pretty` (default) and `json`. Events from the `log` crate, used by
+//! the code shared with the client, are forwarded to the same subscriber.
+//!
+//! When built with the `otlp` feature and `OTEL_EXPORTER_OTLP_ENDPOINT` is
+//! set, spans are also exported to an OpenTelemetry collector.
+
+use std::env;
+
+use cfg_if::cfg_if;
+use tracing::Span;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+cfg_if! {
+    if #[cfg(feature = "otlp")] {
+        use opentelemetry::KeyValue;
+        use opentelemetry_otlp::WithExportConfig;
+        use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+
+        fn otlp_layer<S>() -> Result<Option<impl Layer<S>>, opentelemetry::trace::TraceError>
+        where
+            S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+        {
+            let Ok(endpoint) = env::var("OTEL_EXPORTER_OTLP_ENDPOINT") else {
+                return Ok(None);
+            };
+            let service_name =
+                env::var("OTEL_SERVICE_NAME").unwrap_or("chess_web".to_string());
+
+            // accept the `traceparent` header of incoming requests
+            opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(
+                    trace::config()
+                        .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)])),
+                )
+                .install_batch(runtime::Tokio)?;
+
+            Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+        }
+    }
+}
+
+/// Installs the global subscriber, must be called once from inside the
+/// actix runtime
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let fmt_layer = match env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+        _ => tracing_subscriber::fmt::layer().pretty().boxed(),
+    };
+
+    let registry = tracing_subscriber::registry().with(filter).with(fmt_layer);
+
+    cfg_if! {
+        if #[cfg(feature = "otlp")] {
+            match otlp_layer() {
+                Ok(otlp) => {
+                    let enabled = otlp.is_some();
+                    registry.with(otlp).init();
+
+                    if enabled {
+                        tracing::info!("Exporting spans to the OTLP collector");
+                    }
+                }
+                Err(e) => {
+                    registry.init();
+                    tracing::error!(error = %e, "Failed to start the OTLP exporter");
+                }
+            }
+        } else {
+            registry.init();
+        }
+    }
+}
+
+/// Flushes the spans that are still waiting to be exported
+pub fn shutdown() {
+    cfg_if! {
+        if #[cfg(feature = "otlp")] {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// Message sent with the span of its sender, the handler span becomes its
+/// child so a move can be followed from the websocket frame to the broadcast
+pub struct Traced<M> {
+    pub msg: M,
+    pub span: Span,
+}
+
+impl<M> Traced<M> {
+    pub fn new(msg: M) -> Self {
+        Self {
+            msg,
+            span: Span::current(),
+        }
+    }
+}
+
+impl<M: actix::Message<Result = ()>> actix::Message for Traced<M> {
+    type Result = ();
+}
+
+/// Implements `Handler<Traced<M>>` for each message by entering the sender
+/// span and forwarding the message to `Handler<M>`
+macro_rules! traced_handlers {
+    ($actor:ty; $($msg:ty),* $(,)?) => {
+        $(
+            impl actix::Handler<$crate::server::telemetry::Traced<$msg>> for $actor {
+                type Result = ();
+
+                fn handle(
+                    &mut self,
+                    traced: $crate::server::telemetry::Traced<$msg>,
+                    ctx: &mut Self::Context,
+                ) {
+                    let _enter = traced.span.enter();
+                    <Self as actix::Handler<$msg>>::handle(self, traced.msg, ctx);
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use traced_handlers;
@@ -0,0 +1,247 @@
+//! `TournamentDirector` is an actor holding the tournaments. It asks the chess
+//! server for a seated room per game of a round and is told by the server
+//! when those games end.
+
+use std::{collections::HashMap, sync::Arc};
+
+use actix::prelude::*;
+
+use super::{Entrant, Tournament, TournamentError};
+use crate::{
+    entities::tournament::{NewTournament, TournamentState, TournamentSummary, TournamentView},
+    server::{
+        chess_server::{ChessServer, GameFinished, Player, StartGame},
+        metrics::Metrics,
+        rate_limit::RateLimitConfig,
+        telemetry::Traced,
+    },
+};
+
+type TournamentResult = Result<TournamentView, TournamentError>;
+
+#[derive(Message)]
+#[rtype(result = "Result<TournamentView, TournamentError>")]
+pub struct CreateTournament {
+    pub creator_id: String,
+    pub tournament: NewTournament,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<TournamentView, TournamentError>")]
+pub struct JoinTournament {
+    pub id: String,
+    pub entrant: Entrant,
+}
+
+/// Starts a tournament, only its creator can
+#[derive(Message)]
+#[rtype(result = "Result<TournamentView, TournamentError>")]
+pub struct StartTournament {
+    pub id: String,
+    pub user_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<TournamentView, TournamentError>")]
+pub struct GetTournament {
+    pub id: String,
+}
+
+/// Summaries of all tournaments, newest first
+#[derive(Message)]
+#[rtype(result = "Vec<TournamentSummary>")]
+pub struct ListTournaments;
+
+pub struct TournamentDirector {
+    /// In creation order
+    tournaments: Vec<Tournament>,
+    /// Tournament of each room being played, by room name
+    rooms: HashMap<String, String>,
+    server: Addr<ChessServer>,
+    metrics: Arc<Metrics>,
+    limits: RateLimitConfig,
+}
+
+impl TournamentDirector {
+    pub fn new(server: Addr<ChessServer>, metrics: Arc<Metrics>, limits: RateLimitConfig) -> Self {
+        Self {
+            tournaments: vec![],
+            rooms: HashMap::new(),
+            server,
+            metrics,
+            limits,
+        }
+    }
+
+    /// Whether `user_id` may create one more tournament, finished ones don't
+    /// count
+    fn check_tournament_limits(&self, user_id: &str) -> Result<(), TournamentError> {
+        let open: Vec<&Tournament> = self
+            .tournaments
+            .iter()
+            .filter(|tournament| tournament.state != TournamentState::Finished)
+            .collect();
+
+        if open.len() >= self.limits.max_tournaments {
+            return Err(TournamentError::ServerFull);
+        }
+
+        let created_by_user = open
+            .iter()
+            .filter(|tournament| tournament.creator_id == user_id)
+            .count();
+        if created_by_user >= self.limits.max_tournaments_per_user {
+            return Err(TournamentError::TooManyTournaments);
+        }
+
+        Ok(())
+    }
+
+    fn tournament_mut(&mut self, id: &str) -> Result<&mut Tournament, TournamentError> {
+        self.tournaments
+            .iter_mut()
+            .find(|tournament| tournament.id == id)
+            .ok_or(TournamentError::NotFound)
+    }
+
+    /// Asks the server for the rooms of the current round of a tournament
+    fn start_round(&mut self, id: &str, ctx: &mut Context<Self>) {
+        let Ok(tournament) = self.tournament_mut(id) else {
+            return;
+        };
+        let Some(pairings) = tournament.current_round() else {
+            return;
+        };
+
+        let player = |index: usize| {
+            let entrant = &tournament.players[index];
+            Player {
+                session_id: None,
+                user_id: entrant.user_id.clone(),
+                name: entrant.name.clone(),
+                registered: entrant.registered,
+            }
+        };
+        let games: Vec<StartGame> = pairings
+            .iter()
+            .filter_map(|pairing| {
+                Some(StartGame {
+                    room: pairing.room.clone()?,
+                    white: player(pairing.white),
+                    black: player(pairing.black?),
+                    time_control: Some(tournament.time_control),
+                    rated: tournament.rated,
+                    watcher: Some(ctx.address().recipient()),
+                })
+            })
+            .collect();
+
+        tracing::info!(
+            tournament_id = %id,
+            round = tournament.round_pairings.len(),
+            games = games.len(),
+            "Tournament round paired"
+        );
+        for game in games {
+            self.rooms.insert(game.room.clone(), id.to_string());
+            self.server.do_send(Traced::new(game));
+        }
+    }
+}
+
+impl Actor for TournamentDirector {
+    type Context = Context<Self>;
+}
+
+impl Handler<CreateTournament> for TournamentDirector {
+    type Result = TournamentResult;
+
+    #[tracing::instrument(name = "create_tournament", skip_all, fields(user_id = %msg.creator_id))]
+    fn handle(&mut self, msg: CreateTournament, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("create_tournament");
+
+        self.check_tournament_limits(&msg.creator_id)?;
+
+        let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        let tournament = Tournament::new(id, msg.creator_id, &msg.tournament)?;
+        let view = tournament.view();
+        tracing::info!(tournament_id = %tournament.id, "Tournament created");
+        self.tournaments.push(tournament);
+
+        Ok(view)
+    }
+}
+
+impl Handler<JoinTournament> for TournamentDirector {
+    type Result = TournamentResult;
+
+    #[tracing::instrument(name = "join_tournament", skip_all, fields(tournament_id = %msg.id, user_id = %msg.entrant.user_id))]
+    fn handle(&mut self, msg: JoinTournament, _: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("join_tournament");
+
+        let tournament = self.tournament_mut(&msg.id)?;
+        tournament.register(msg.entrant)?;
+
+        Ok(tournament.view())
+    }
+}
+
+impl Handler<StartTournament> for TournamentDirector {
+    type Result = TournamentResult;
+
+    #[tracing::instrument(name = "start_tournament", skip_all, fields(tournament_id = %msg.id, user_id = %msg.user_id))]
+    fn handle(&mut self, msg: StartTournament, ctx: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("start_tournament");
+
+        self.tournament_mut(&msg.id)?.start(&msg.user_id)?;
+        self.start_round(&msg.id, ctx);
+
+        self.tournament_mut(&msg.id)
+            .map(|tournament| tournament.view())
+    }
+}
+
+impl Handler<GetTournament> for TournamentDirector {
+    type Result = TournamentResult;
+
+    fn handle(&mut self, msg: GetTournament, _: &mut Self::Context) -> Self::Result {
+        self.tournament_mut(&msg.id)
+            .map(|tournament| tournament.view())
+    }
+}
+
+impl Handler<ListTournaments> for TournamentDirector {
+    type Result = MessageResult<ListTournaments>;
+
+    fn handle(&mut self, _: ListTournaments, _: &mut Self::Context) -> Self::Result {
+        MessageResult(
+            self.tournaments
+                .iter()
+                .rev()
+                .map(Tournament::summary)
+                .collect(),
+        )
+    }
+}
+
+/// Records the result of a tournament game, the next round starts after the
+/// last game of a round
+impl Handler<GameFinished> for TournamentDirector {
+    type Result = ();
+
+    #[tracing::instrument(name = "tournament_game_finished", skip_all, fields(room = %msg.room))]
+    fn handle(&mut self, msg: GameFinished, ctx: &mut Self::Context) -> Self::Result {
+        let _timer = self.metrics.handler_timer("tournament_game_finished");
+
+        let Some(id) = self.rooms.remove(&msg.room) else {
+            return;
+        };
+        let Ok(tournament) = self.tournament_mut(&id) else {
+            return;
+        };
+
+        if tournament.record_result(&msg.room, &msg.record.white_id, msg.result) {
+            self.start_round(&id, ctx);
+        }
+    }
+}
@@ -0,0 +1,587 @@
+//! Round robin and Swiss tournaments.
+//!
+//! Players register while the tournament is open and its creator starts it.
+//! Every game of a round gets a room with both players seated, named
+//! `{id}-r{round}-b{board}`, and the next round is paired once all the games
+//! of the current one reported their result. A bye scores a point.
+//! Tournaments only live in memory, in the `TournamentDirector` actor.
+
+pub mod director;
+pub mod pairing;
+pub mod routes;
+
+use std::collections::HashSet;
+
+use self::pairing::SwissPlayer;
+use crate::entities::{
+    chess_board::turns::Turn,
+    game::{GameResult, Outcome, TimeControl},
+    tournament::{
+        NewTournament, PairingView, PlayerView, Standing, TournamentFormat, TournamentState,
+        TournamentSummary, TournamentView,
+    },
+};
+
+const NAME_MAX_LEN: usize = 64;
+const MAX_ROUNDS: u32 = 20;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TournamentError {
+    NotFound,
+    InvalidName,
+    InvalidTimeControl,
+    InvalidRounds,
+    RegistrationClosed,
+    AlreadyRegistered,
+    NotCreator,
+    NotEnoughPlayers,
+    NotRegistered,
+    TooManyTournaments,
+    ServerFull,
+}
+
+impl TournamentError {
+    /// Message shown to the user
+    pub fn as_str(&self) -> &str {
+        match self {
+            TournamentError::NotFound => "Tournament not found",
+            TournamentError::InvalidName => "Tournament names have 1 to 64 characters",
+            TournamentError::InvalidTimeControl => "Time controls are written like 5+3",
+            TournamentError::InvalidRounds => "Tournaments have 1 to 20 rounds",
+            TournamentError::RegistrationClosed => "The tournament already started",
+            TournamentError::AlreadyRegistered => "You are already registered",
+            TournamentError::NotCreator => "Only the creator can start the tournament",
+            TournamentError::NotEnoughPlayers => "A tournament needs at least 2 players",
+            TournamentError::NotRegistered => {
+                "Rated tournaments are only played by logged in users"
+            }
+            TournamentError::TooManyTournaments => {
+                "You reached the maximum number of open tournaments"
+            }
+            TournamentError::ServerFull => {
+                "The server reached the maximum number of open tournaments"
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entrant {
+    pub user_id: String,
+    pub name: String,
+    /// Logged in to an account, needed for rated games
+    pub registered: bool,
+}
+
+/// Game of a round between two entrants, by index, or a bye without black
+#[derive(Clone, Debug)]
+pub struct Pairing {
+    pub white: usize,
+    pub black: Option<usize>,
+    pub room: Option<String>,
+    pub result: Option<GameResult>,
+}
+
+impl Pairing {
+    fn is_finished(&self) -> bool {
+        self.black.is_none() || self.result.is_some()
+    }
+
+    fn opponent_of(&self, player: usize) -> Option<usize> {
+        match self.black {
+            Some(black) if self.white == player => Some(black),
+            Some(black) if black == player => Some(self.white),
+            _ => None,
+        }
+    }
+
+    /// Points scored by `player`, `None` while the game is played
+    fn points_of(&self, player: usize) -> Option<f64> {
+        let side = if self.white == player {
+            Turn::White
+        } else if self.black == Some(player) {
+            Turn::Black
+        } else {
+            return None;
+        };
+
+        if self.black.is_none() {
+            return Some(1.0);
+        }
+
+        self.result.map(|result| match result.outcome {
+            Outcome::Win(winner) if winner == side => 1.0,
+            Outcome::Win(_) => 0.0,
+            Outcome::Draw => 0.5,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Tournament {
+    pub id: String,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub time_control: TimeControl,
+    pub rounds: u32,
+    pub rated: bool,
+    pub creator_id: String,
+    /// In registration order, which seeds the pairings
+    pub players: Vec<Entrant>,
+    /// Pairings of the rounds paired so far
+    pub round_pairings: Vec<Vec<Pairing>>,
+    pub state: TournamentState,
+}
+
+impl Tournament {
+    pub fn new(
+        id: String,
+        creator_id: String,
+        new: &NewTournament,
+    ) -> Result<Self, TournamentError> {
+        let name = new.name.trim();
+        if name.is_empty() || name.chars().count() > NAME_MAX_LEN {
+            return Err(TournamentError::InvalidName);
+        }
+
+        let time_control = new
+            .time_control
+            .parse::<TimeControl>()
+            .map_err(|_| TournamentError::InvalidTimeControl)?;
+
+        if new.format == TournamentFormat::Swiss && !(1..=MAX_ROUNDS).contains(&new.rounds) {
+            return Err(TournamentError::InvalidRounds);
+        }
+
+        Ok(Self {
+            id,
+            name: name.to_string(),
+            format: new.format,
+            time_control,
+            rounds: new.rounds,
+            rated: new.rated,
+            creator_id,
+            players: vec![],
+            round_pairings: vec![],
+            state: TournamentState::Registering,
+        })
+    }
+
+    pub fn register(&mut self, entrant: Entrant) -> Result<(), TournamentError> {
+        if self.state != TournamentState::Registering {
+            return Err(TournamentError::RegistrationClosed);
+        }
+
+        if self.index_of(&entrant.user_id).is_some() {
+            return Err(TournamentError::AlreadyRegistered);
+        }
+
+        // its games could not be rated
+        if self.rated && !entrant.registered {
+            return Err(TournamentError::NotRegistered);
+        }
+
+        self.players.push(entrant);
+
+        Ok(())
+    }
+
+    /// Closes the registration and pairs the first round
+    pub fn start(&mut self, user_id: &str) -> Result<(), TournamentError> {
+        if self.creator_id != user_id {
+            return Err(TournamentError::NotCreator);
+        }
+
+        if self.state != TournamentState::Registering {
+            return Err(TournamentError::RegistrationClosed);
+        }
+
+        if self.players.len() < 2 {
+            return Err(TournamentError::NotEnoughPlayers);
+        }
+
+        let all_play_all = pairing::round_robin_rounds(self.players.len()) as u32;
+        self.rounds = match self.format {
+            TournamentFormat::RoundRobin => all_play_all,
+            // more rounds would force rematches
+            TournamentFormat::Swiss => self.rounds.min(all_play_all),
+        };
+        self.state = TournamentState::Running;
+        self.next_round();
+
+        Ok(())
+    }
+
+    fn index_of(&self, user_id: &str) -> Option<usize> {
+        self.players
+            .iter()
+            .position(|player| player.user_id == user_id)
+    }
+
+    /// Pairings of the round being played
+    pub fn current_round(&self) -> Option<&[Pairing]> {
+        self.round_pairings.last().map(Vec::as_slice)
+    }
+
+    /// Pairs the next round, the tournament is over after the last round or
+    /// when the Swiss pairing runs out of opponents
+    fn next_round(&mut self) {
+        let round = self.round_pairings.len();
+        if round as u32 >= self.rounds {
+            self.state = TournamentState::Finished;
+            return;
+        }
+
+        let pairs = match self.format {
+            TournamentFormat::RoundRobin => Some(pairing::round_robin(self.players.len(), round)),
+            TournamentFormat::Swiss => pairing::swiss(&self.swiss_players()),
+        };
+        let Some(pairs) = pairs else {
+            self.state = TournamentState::Finished;
+            return;
+        };
+
+        let mut board = 0;
+        let pairings = pairs
+            .into_iter()
+            .map(|(white, black)| {
+                let room = black.map(|_| {
+                    board += 1;
+                    format!("{}-r{}-b{}", self.id, round + 1, board)
+                });
+
+                Pairing {
+                    white,
+                    black,
+                    room,
+                    result: None,
+                }
+            })
+            .collect();
+        self.round_pairings.push(pairings);
+    }
+
+    /// Records the result of the game played in `room`, pairs the next round
+    /// once it was the last game of the current one. Returns whether a new
+    /// round was paired.
+    pub fn record_result(&mut self, room: &str, white_id: &str, result: GameResult) -> bool {
+        if self.state != TournamentState::Running {
+            return false;
+        }
+
+        let white_index = self.index_of(white_id);
+        let Some(pairing) = self
+            .round_pairings
+            .iter_mut()
+            .flatten()
+            .find(|pairing| pairing.room.as_deref() == Some(room) && pairing.result.is_none())
+        else {
+            return false;
+        };
+
+        // the players swapped seats in the room
+        let result = match (result.outcome, white_index == Some(pairing.white)) {
+            (Outcome::Win(side), false) => GameResult::new(Outcome::Win(!side), result.termination),
+            _ => result,
+        };
+        pairing.result = Some(result);
+
+        let round_over = self
+            .current_round()
+            .is_some_and(|pairings| pairings.iter().all(Pairing::is_finished));
+        if !round_over {
+            return false;
+        }
+
+        self.next_round();
+
+        self.state == TournamentState::Running
+    }
+
+    /// Points of every player, by index
+    fn points(&self) -> Vec<f64> {
+        (0..self.players.len())
+            .map(|player| {
+                self.round_pairings
+                    .iter()
+                    .flatten()
+                    .filter_map(|pairing| pairing.points_of(player))
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Players ranked for the next Swiss round, by score then seed
+    fn swiss_players(&self) -> Vec<SwissPlayer> {
+        let points = self.points();
+        let mut players: Vec<SwissPlayer> = (0..self.players.len())
+            .map(|index| {
+                let mut player = SwissPlayer {
+                    index,
+                    half_points: (points[index] * 2.0) as u32,
+                    opponents: HashSet::new(),
+                    had_bye: false,
+                    color_balance: 0,
+                    last_white: None,
+                };
+
+                for pairing in self.round_pairings.iter().flatten() {
+                    if let Some(opponent) = pairing.opponent_of(index) {
+                        let white = pairing.white == index;
+                        player.opponents.insert(opponent);
+                        player.color_balance += if white { 1 } else { -1 };
+                        player.last_white = Some(white);
+                    } else if pairing.white == index {
+                        player.had_bye = true;
+                    }
+                }
+
+                player
+            })
+            .collect();
+
+        players.sort_by(|a, b| {
+            b.half_points
+                .cmp(&a.half_points)
+                .then(a.index.cmp(&b.index))
+        });
+
+        players
+    }
+
+    /// Ranking by points, then Buchholz (points of the opponents) and then
+    /// Sonneborn-Berger (points of the beaten opponents plus half the points
+    /// of the drawn ones)
+    pub fn standings(&self) -> Vec<Standing> {
+        let points = self.points();
+        let mut standings: Vec<(usize, f64, f64, f64)> = (0..self.players.len())
+            .map(|player| {
+                let mut buchholz = 0.0;
+                let mut sonneborn_berger = 0.0;
+
+                for pairing in self.round_pairings.iter().flatten() {
+                    let (Some(opponent), Some(score)) =
+                        (pairing.opponent_of(player), pairing.points_of(player))
+                    else {
+                        continue;
+                    };
+
+                    buchholz += points[opponent];
+                    sonneborn_berger += score * points[opponent];
+                }
+
+                (player, points[player], buchholz, sonneborn_berger)
+            })
+            .collect();
+
+        standings.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then(b.2.total_cmp(&a.2))
+                .then(b.3.total_cmp(&a.3))
+                .then(a.0.cmp(&b.0))
+        });
+
+        standings
+            .into_iter()
+            .enumerate()
+            .map(
+                |(rank, (player, points, buchholz, sonneborn_berger))| Standing {
+                    rank: rank + 1,
+                    user_id: self.players[player].user_id.clone(),
+                    name: self.players[player].name.clone(),
+                    points,
+                    buchholz,
+                    sonneborn_berger,
+                },
+            )
+            .collect()
+    }
+
+    pub fn summary(&self) -> TournamentSummary {
+        TournamentSummary {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            format: self.format,
+            time_control: self.time_control.to_string(),
+            rounds: self.rounds,
+            state: self.state,
+            players: self.players.len(),
+        }
+    }
+
+    pub fn view(&self) -> TournamentView {
+        let user_id = |index: usize| self.players[index].user_id.clone();
+        let pairings = self
+            .round_pairings
+            .iter()
+            .map(|pairings| {
+                pairings
+                    .iter()
+                    .map(|pairing| PairingView {
+                        white: user_id(pairing.white),
+                        black: pairing.black.map(user_id),
+                        room: pairing.room.clone(),
+                        result: pairing.result.map(|result| result.score().to_string()),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        TournamentView {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            format: self.format,
+            time_control: self.time_control.to_string(),
+            rounds: self.rounds,
+            rated: self.rated,
+            state: self.state,
+            creator_id: self.creator_id.clone(),
+            players: self
+                .players
+                .iter()
+                .map(|player| PlayerView {
+                    user_id: player.user_id.clone(),
+                    name: player.name.clone(),
+                })
+                .collect(),
+            pairings,
+            standings: self.standings(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::game::Termination;
+
+    fn new_tournament(format: TournamentFormat, players: usize) -> Tournament {
+        new_rated_tournament(format, players, false)
+    }
+
+    fn new_rated_tournament(format: TournamentFormat, players: usize, rated: bool) -> Tournament {
+        let new = NewTournament {
+            name: "Weekly".to_string(),
+            format,
+            time_control: "5+3".to_string(),
+            rounds: 3,
+            rated,
+        };
+        let mut tournament = Tournament::new("t1".to_string(), "p0".to_string(), &new).unwrap();
+        for i in 0..players {
+            tournament
+                .register(Entrant {
+                    user_id: format!("p{}", i),
+                    name: format!("Player {}", i),
+                    registered: true,
+                })
+                .unwrap();
+        }
+
+        tournament
+    }
+
+    /// White wins every game of the current round
+    fn play_round(tournament: &mut Tournament) -> bool {
+        let games: Vec<(String, String)> = tournament
+            .current_round()
+            .unwrap()
+            .iter()
+            .filter_map(|pairing| {
+                let room = pairing.room.clone()?;
+                Some((room, tournament.players[pairing.white].user_id.clone()))
+            })
+            .collect();
+
+        let win = GameResult::new(Outcome::Win(Turn::White), Termination::Checkmate);
+        games
+            .into_iter()
+            .map(|(room, white_id)| tournament.record_result(&room, &white_id, win))
+            .last()
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn test_start() {
+        let mut tournament = new_tournament(TournamentFormat::RoundRobin, 1);
+        assert_eq!(
+            tournament.start("p0"),
+            Err(TournamentError::NotEnoughPlayers)
+        );
+
+        let mut tournament = new_tournament(TournamentFormat::RoundRobin, 4);
+        assert_eq!(tournament.start("p1"), Err(TournamentError::NotCreator));
+        assert_eq!(tournament.start("p0"), Ok(()));
+        assert_eq!(tournament.rounds, 3);
+        assert_eq!(
+            tournament.register(Entrant {
+                user_id: "p9".to_string(),
+                name: "Late".to_string(),
+                registered: false,
+            }),
+            Err(TournamentError::RegistrationClosed)
+        );
+
+        let rooms: Vec<Option<String>> = tournament
+            .current_round()
+            .unwrap()
+            .iter()
+            .map(|pairing| pairing.room.clone())
+            .collect();
+        assert_eq!(
+            rooms,
+            vec![Some("t1-r1-b1".to_string()), Some("t1-r1-b2".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_rated_registration() {
+        let mut tournament = new_rated_tournament(TournamentFormat::Swiss, 2, true);
+        assert_eq!(
+            tournament.register(Entrant {
+                user_id: "guest".to_string(),
+                name: "Guest".to_string(),
+                registered: false,
+            }),
+            Err(TournamentError::NotRegistered)
+        );
+        assert_eq!(tournament.players.len(), 2);
+    }
+
+    #[test]
+    fn test_round_robin_standings() {
+        let mut tournament = new_tournament(TournamentFormat::RoundRobin, 3);
+        tournament.start("p0").unwrap();
+
+        assert!(play_round(&mut tournament));
+        assert!(play_round(&mut tournament));
+        assert!(!play_round(&mut tournament));
+        assert_eq!(tournament.state, TournamentState::Finished);
+
+        // everyone won its white game and had a bye
+        let standings = tournament.standings();
+        assert!(standings.iter().all(|standing| standing.points == 2.0));
+        assert!(standings.iter().all(|standing| standing.buchholz == 4.0));
+        assert!(standings
+            .iter()
+            .all(|standing| standing.sonneborn_berger == 2.0));
+    }
+
+    #[test]
+    fn test_swapped_seats() {
+        let mut tournament = new_tournament(TournamentFormat::Swiss, 2);
+        tournament.start("p0").unwrap();
+        assert_eq!(tournament.rounds, 1);
+
+        let pairing = &tournament.current_round().unwrap()[0];
+        let room = pairing.room.clone().unwrap();
+        let black_id = tournament.players[pairing.black.unwrap()].user_id.clone();
+        let win = GameResult::new(Outcome::Win(Turn::White), Termination::Timeout);
+        assert!(!tournament.record_result(&room, &black_id, win));
+
+        let standings = tournament.standings();
+        assert_eq!(standings[0].user_id, black_id);
+        assert_eq!(standings[0].points, 1.0);
+        assert_eq!(tournament.state, TournamentState::Finished);
+    }
+}
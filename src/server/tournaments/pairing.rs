@@ -0,0 +1,200 @@
+//! Pairings of a round. Players are indices into the list of entrants, a
+//! pair is `(white, black)` and `None` stands for the bye.
+
+use std::collections::HashSet;
+
+/// Pairings of `round` (from 0) of a round robin with `players` entrants,
+/// using the circle method of the Berger tables. Odd fields give a bye to a
+/// different player every round.
+pub fn round_robin(players: usize, round: usize) -> Vec<(usize, Option<usize>)> {
+    let mut seats: Vec<Option<usize>> = (0..players).map(Some).collect();
+    if players % 2 == 1 {
+        seats.push(None);
+    }
+    let n = seats.len();
+    if n < 2 {
+        return vec![];
+    }
+
+    // the last seat stays, the others turn around it
+    let mut rotated: Vec<Option<usize>> =
+        (0..n - 1).map(|i| seats[(i + round) % (n - 1)]).collect();
+    rotated.push(seats[n - 1]);
+
+    (0..n / 2)
+        .filter_map(|board| {
+            let (mut first, mut second) = (rotated[board], rotated[n - 1 - board]);
+            // the fixed seat changes colour every round
+            if board == 0 && round % 2 == 1 {
+                std::mem::swap(&mut first, &mut second);
+            }
+
+            match (first, second) {
+                (Some(white), Some(black)) => Some((white, Some(black))),
+                (Some(player), None) | (None, Some(player)) => Some((player, None)),
+                (None, None) => None,
+            }
+        })
+        .collect()
+}
+
+/// Rounds of a round robin, everyone meets everyone once
+pub fn round_robin_rounds(players: usize) -> usize {
+    players + players % 2 - 1
+}
+
+/// State of a Swiss player when pairing the next round
+#[derive(Clone, Debug)]
+pub struct SwissPlayer {
+    pub index: usize,
+    /// Points, in half points so score groups compare exactly
+    pub half_points: u32,
+    pub opponents: HashSet<usize>,
+    pub had_bye: bool,
+    /// Whites minus blacks
+    pub color_balance: i32,
+    /// Colour of the last game, `true` for white
+    pub last_white: Option<bool>,
+}
+
+/// Dutch style pairing of a Swiss round. `ranked` is best first. The lowest
+/// player without a bye gets it on odd fields. In every score group the top
+/// half meets the bottom half in order, players who already met are swapped
+/// with the next candidate and players left alone float down to the next
+/// group. `None` when no pairing avoids a rematch.
+pub fn swiss(ranked: &[SwissPlayer]) -> Option<Vec<(usize, Option<usize>)>> {
+    let mut remaining: Vec<&SwissPlayer> = ranked.iter().collect();
+    let mut pairs = vec![];
+
+    if remaining.len() % 2 == 1 {
+        let bye = remaining.iter().rposition(|player| !player.had_bye)?;
+        pairs.push((remaining.remove(bye).index, None));
+    }
+
+    let games = pair_rest(&remaining)?;
+    pairs.splice(
+        0..0,
+        games.into_iter().map(|(a, b)| {
+            let (white, black) = colors(a, b);
+            (white.index, Some(black.index))
+        }),
+    );
+
+    Some(pairs)
+}
+
+/// Pairs the best remaining player first, with its Dutch opponent when
+/// possible, and backtracks on dead ends
+fn pair_rest<'a>(remaining: &[&'a SwissPlayer]) -> Option<Vec<(&'a SwissPlayer, &'a SwissPlayer)>> {
+    let Some((top, rest)) = remaining.split_first() else {
+        return Some(vec![]);
+    };
+
+    // the score group of the top player, it is the first half of it
+    let group = 1 + rest
+        .iter()
+        .take_while(|player| player.half_points == top.half_points)
+        .count();
+    let half = group / 2;
+    let candidates = rest[half.saturating_sub(1)..]
+        .iter()
+        .chain(rest[..half.saturating_sub(1)].iter().rev());
+
+    for opponent in candidates {
+        if top.opponents.contains(&opponent.index) {
+            continue;
+        }
+
+        let others: Vec<&SwissPlayer> = rest
+            .iter()
+            .filter(|player| player.index != opponent.index)
+            .copied()
+            .collect();
+        if let Some(mut pairs) = pair_rest(&others) {
+            pairs.insert(0, (*top, *opponent));
+            return Some(pairs);
+        }
+    }
+
+    None
+}
+
+/// White goes to the player who had fewer whites, then to the one who had
+/// black last, then to the higher ranked one
+fn colors<'a>(a: &'a SwissPlayer, b: &'a SwissPlayer) -> (&'a SwissPlayer, &'a SwissPlayer) {
+    if a.color_balance != b.color_balance {
+        return if a.color_balance < b.color_balance {
+            (a, b)
+        } else {
+            (b, a)
+        };
+    }
+
+    match (a.last_white, b.last_white) {
+        (Some(true), Some(false)) | (Some(true), None) => (b, a),
+        _ => (a, b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(index: usize, half_points: u32, opponents: &[usize]) -> SwissPlayer {
+        SwissPlayer {
+            index,
+            half_points,
+            opponents: opponents.iter().copied().collect(),
+            had_bye: false,
+            color_balance: 0,
+            last_white: None,
+        }
+    }
+
+    #[test]
+    fn test_round_robin() {
+        for players in [4, 5] {
+            let mut met = HashSet::new();
+            for round in 0..round_robin_rounds(players) {
+                let pairs = round_robin(players, round);
+                assert_eq!(pairs.len(), players.div_ceil(2));
+
+                for (white, black) in pairs {
+                    if let Some(black) = black {
+                        assert!(met.insert((white.min(black), white.max(black))));
+                    }
+                }
+            }
+            assert_eq!(met.len(), players * (players - 1) / 2);
+        }
+    }
+
+    #[test]
+    fn test_swiss_first_round() {
+        let ranked: Vec<SwissPlayer> = (0..5).map(|i| player(i, 0, &[])).collect();
+
+        let pairs = swiss(&ranked).unwrap();
+        assert_eq!(pairs, vec![(0, Some(2)), (1, Some(3)), (4, None)]);
+    }
+
+    #[test]
+    fn test_swiss_avoids_rematches() {
+        // 0 and 1 won against 2 and 3, the leaders meet next
+        let ranked = vec![
+            player(0, 2, &[2]),
+            player(1, 2, &[3]),
+            player(2, 0, &[0]),
+            player(3, 0, &[1]),
+        ];
+        assert_eq!(swiss(&ranked).unwrap(), vec![(0, Some(1)), (2, Some(3))]);
+
+        // they already met, so the pairing crosses the score groups
+        let ranked = vec![
+            player(0, 2, &[2, 1]),
+            player(1, 2, &[3, 0]),
+            player(2, 0, &[0, 3]),
+            player(3, 0, &[1, 2]),
+        ];
+        assert_eq!(swiss(&ranked).unwrap(), vec![(0, Some(3)), (1, Some(2))]);
+    }
+}
@@ -0,0 +1,147 @@
+//! `/api/tournaments` endpoints. Reading is open to everyone, creating,
+//! joining and starting need a session.
+
+use actix::Addr;
+use actix_web::{get, http::StatusCode, post, web, HttpRequest, HttpResponse};
+
+use super::{
+    director::{
+        CreateTournament, GetTournament, JoinTournament, ListTournaments, StartTournament,
+        TournamentDirector,
+    },
+    Entrant, TournamentError,
+};
+use crate::{
+    entities::tournament::{NewTournament, TournamentView},
+    server::{
+        responses::{self, unavailable},
+        sessions::Sessions,
+    },
+};
+
+type Director = web::Data<Addr<TournamentDirector>>;
+
+fn error_response(error: TournamentError) -> HttpResponse {
+    let status = match error {
+        TournamentError::NotFound => StatusCode::NOT_FOUND,
+        TournamentError::NotCreator | TournamentError::NotRegistered => StatusCode::FORBIDDEN,
+        TournamentError::TooManyTournaments | TournamentError::ServerFull => {
+            StatusCode::TOO_MANY_REQUESTS
+        }
+        TournamentError::RegistrationClosed | TournamentError::AlreadyRegistered => {
+            StatusCode::CONFLICT
+        }
+        TournamentError::InvalidName
+        | TournamentError::InvalidTimeControl
+        | TournamentError::InvalidRounds
+        | TournamentError::NotEnoughPlayers => StatusCode::BAD_REQUEST,
+    };
+
+    responses::error(status, error.as_str())
+}
+
+fn unauthorized() -> HttpResponse {
+    responses::error(StatusCode::UNAUTHORIZED, "No session")
+}
+
+/// Answers with the tournament view or the error of the director
+async fn respond<M>(director: &Director, msg: M, status: StatusCode) -> HttpResponse
+where
+    M: actix::Message<Result = Result<TournamentView, TournamentError>> + Send + 'static,
+    TournamentDirector: actix::Handler<M>,
+{
+    match director.send(msg).await {
+        Ok(Ok(view)) => HttpResponse::build(status).json(view),
+        Ok(Err(e)) => error_response(e),
+        Err(_) => unavailable("Tournaments"),
+    }
+}
+
+#[get("/api/tournaments")]
+async fn list(director: Director) -> HttpResponse {
+    match director.send(ListTournaments).await {
+        Ok(tournaments) => HttpResponse::Ok().json(tournaments),
+        Err(_) => unavailable("Tournaments"),
+    }
+}
+
+#[post("/api/tournaments")]
+#[tracing::instrument(skip_all)]
+async fn create(
+    req: HttpRequest,
+    director: Director,
+    sessions: web::Data<Sessions>,
+    body: web::Json<NewTournament>,
+) -> HttpResponse {
+    let Some((session, _)) = sessions.from_request(&req) else {
+        return unauthorized();
+    };
+
+    let msg = CreateTournament {
+        creator_id: session.sub,
+        tournament: body.into_inner(),
+    };
+
+    respond(&director, msg, StatusCode::CREATED).await
+}
+
+#[get("/api/tournaments/{id}")]
+async fn show(director: Director, id: web::Path<String>) -> HttpResponse {
+    let msg = GetTournament {
+        id: id.into_inner(),
+    };
+
+    respond(&director, msg, StatusCode::OK).await
+}
+
+#[post("/api/tournaments/{id}/join")]
+#[tracing::instrument(skip_all)]
+async fn join(
+    req: HttpRequest,
+    director: Director,
+    sessions: web::Data<Sessions>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    let Some((session, _)) = sessions.from_request(&req) else {
+        return unauthorized();
+    };
+
+    let msg = JoinTournament {
+        id: id.into_inner(),
+        entrant: Entrant {
+            registered: session.account_id.is_some(),
+            user_id: session.sub,
+            name: session.name,
+        },
+    };
+
+    respond(&director, msg, StatusCode::OK).await
+}
+
+#[post("/api/tournaments/{id}/start")]
+#[tracing::instrument(skip_all)]
+async fn start(
+    req: HttpRequest,
+    director: Director,
+    sessions: web::Data<Sessions>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    let Some((session, _)) = sessions.from_request(&req) else {
+        return unauthorized();
+    };
+
+    let msg = StartTournament {
+        id: id.into_inner(),
+        user_id: session.sub,
+    };
+
+    respond(&director, msg, StatusCode::OK).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(create)
+        .service(show)
+        .service(join)
+        .service(start);
+}
@@ -1,21 +1,48 @@
 use std::{
     env,
+    net::IpAddr,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use actix::prelude::*;
 use actix_web_actors::ws;
+use tracing::Span;
 
-use crate::server::chess_server::{self, ChessServer};
+use crate::entities::{
+    annotation::{nag_glyph, Shape},
+    chess_board::enums::PromotionKind,
+    game::{side_from_str, TimeControl},
+    history::node_from_str,
+    seek::SeekRequest,
+};
+use crate::server::{
+    chess_server::{self, ChessServer},
+    game,
+    lobby::{self, Lobby, Seeker},
+    metrics::Metrics,
+    rate_limit::{IpRateLimiter, RateLimitConfig, TokenBucket, Violations},
+    room::{self, RoomActor},
+    telemetry::Traced,
+};
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 
+/// Room names end at the first space of the messages carrying them and `|`
+/// separates the position from the trash in `/join`
+fn is_valid_room_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(|c: char| c == '|' || c.is_whitespace())
+}
+
 #[derive(Debug)]
 pub struct WsChessSession {
-    /// unique session id
+    /// unique session id, every socket gets its own
     pub id: String,
 
+    /// id of the user owning the session, several sessions can share it
+    pub user_id: String,
+
     /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
     /// otherwise we drop connection.
     pub hb: Instant,
@@ -23,25 +50,154 @@ pub struct WsChessSession {
     /// peer name
     pub name: String,
 
+    /// Logged in to an account
+    pub registered: bool,
+
     /// Chat server
     pub addr: Addr<ChessServer>,
 
+    /// Open seeks and pairing
+    pub lobby: Addr<Lobby>,
+
+    /// Room the session is in, moves and chat are sent to it directly
+    pub room: Option<Addr<RoomActor>>,
+
     /// Is authenticated
     pub authenticated_at: Option<Instant>,
 
-    /// Disconnected at
-    pub disconnected_at: Option<Instant>,
+    /// Room and last event sequence seen by a reconnecting client
+    pub resume: Option<(String, u64)>,
+
+    /// Peer address, shared rate limit key
+    pub ip: Option<IpAddr>,
+
+    /// Rate limiter shared by all sessions
+    pub ip_limiter: Arc<IpRateLimiter>,
+
+    /// Session rate limit
+    pub bucket: TokenBucket,
+
+    /// Limits configuration
+    pub limits: RateLimitConfig,
+
+    /// Limit violations of this session
+    pub violations: Violations,
+
+    /// Server metrics
+    pub metrics: Arc<Metrics>,
+
+    /// Span covering the whole session, frames are traced as its children
+    pub span: Span,
 }
 
 impl WsChessSession {
-    pub fn new(addr: Addr<ChessServer>, id: String, name: String) -> Self {
+    pub fn new(
+        addr: Addr<ChessServer>,
+        lobby: Addr<Lobby>,
+        id: String,
+        user_id: String,
+        name: String,
+        registered: bool,
+        resume: Option<(String, u64)>,
+        ip: Option<IpAddr>,
+        ip_limiter: Arc<IpRateLimiter>,
+        limits: RateLimitConfig,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let span = tracing::info_span!(
+            parent: None,
+            "ws_session",
+            session_id = %id,
+            user_id = %user_id,
+            ip = ?ip
+        );
+        // keep a link to the upgrade request without making it the trace root
+        span.follows_from(Span::current());
+
         Self {
             id,
+            user_id,
             hb: Instant::now(),
             name,
+            registered,
             addr,
+            lobby,
+            room: None,
             authenticated_at: None,
-            disconnected_at: None,
+            resume,
+            ip,
+            ip_limiter,
+            bucket: TokenBucket::new(limits.session_rate, limits.session_burst),
+            limits,
+            violations: Violations::new(),
+            metrics,
+            span,
+        }
+    }
+
+    /// Cost in tokens of a client message, room creation and seeks are
+    /// more expensive than moves and chat
+    fn message_cost(message: &str) -> f64 {
+        match message
+            .split_once(' ')
+            .map(|(cmd, _)| cmd)
+            .unwrap_or(message)
+        {
+            "/join" | "/fork" => 5.0,
+            "/seek" => 3.0,
+            _ => 1.0,
+        }
+    }
+
+    /// Takes tokens from the session and the IP buckets, or from neither
+    fn acquire(&mut self, cost: f64) -> bool {
+        if !self.bucket.can_acquire(cost) {
+            return false;
+        }
+        if let Some(ip) = self.ip {
+            if !self.ip_limiter.try_acquire(ip, cost) {
+                return false;
+            }
+        }
+
+        self.bucket.try_acquire(cost)
+    }
+
+    /// Forwards a message to the room of the session
+    fn send_to_room<M>(&self, ctx: &mut ws::WebsocketContext<Self>, msg: M)
+    where
+        M: Message<Result = ()> + Send + 'static,
+        RoomActor: Handler<Traced<M>>,
+    {
+        match &self.room {
+            Some(room) => room.do_send(Traced::new(msg)),
+            None => ctx.text("!!! not in a room"),
+        }
+    }
+
+    fn seeker(&self, ctx: &ws::WebsocketContext<Self>) -> Seeker {
+        Seeker {
+            session_id: self.id.clone(),
+            user_id: self.user_id.clone(),
+            name: self.name.clone(),
+            registered: self.registered,
+            addr: ctx.address(),
+        }
+    }
+
+    /// Sends a structured error to the client and disconnects it on repeat
+    fn violation(&mut self, ctx: &mut ws::WebsocketContext<Self>, code: &str, message: &str) {
+        ctx.text(format!("/error {} {}", code, message));
+
+        let count = self.violations.register(self.limits.violations_window);
+        if count >= self.limits.max_violations {
+            tracing::warn!(count, "Session exceeded the limits, disconnecting");
+
+            ctx.close(Some(ws::CloseReason {
+                code: ws::CloseCode::Policy,
+                description: Some("too many limit violations".to_string()),
+            }));
+            ctx.stop();
         }
     }
 
@@ -60,11 +216,13 @@ impl WsChessSession {
             // check client heartbeats
             if Instant::now().duration_since(act.hb) > client_timeout {
                 // heartbeat timed out
-                log::info!("Websocket Client heartbeat failed, disconnecting!");
+                let _enter = act.span.enter();
+                tracing::info!("Websocket Client heartbeat failed, disconnecting!");
+                act.metrics.heartbeat_timeouts.inc();
 
                 // notify chat server
                 act.addr
-                    .do_send(chess_server::Disconnect { id: act.id.clone() });
+                    .do_send(Traced::new(chess_server::Disconnect { id: act.id.clone() }));
 
                 // stop actor
                 ctx.stop();
@@ -84,6 +242,13 @@ impl Actor for WsChessSession {
     /// Method is called on actor start.
     /// We register ws session with ChatServer
     fn started(&mut self, ctx: &mut Self::Context) {
+        let span = self.span.clone();
+        let _enter = span.enter();
+        tracing::info!("Websocket session started");
+
+        self.metrics.ws_connects.inc();
+        self.metrics.active_sessions.inc();
+
         // we'll start heartbeat process on session start.
         self.hb(ctx);
 
@@ -93,22 +258,36 @@ impl Actor for WsChessSession {
         // HttpContext::state() is instance of WsChatSessionState, state is shared
         // across all routes within application
         let addr = ctx.address();
-        self.addr.do_send(chess_server::Connect {
+        self.addr.do_send(Traced::new(chess_server::Connect {
             id: self.id.clone(),
+            user_id: self.user_id.clone(),
             name: self.name.clone(),
+            registered: self.registered,
             addr,
-        })
+            resume: self.resume.take(),
+        }))
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        let _enter = self.span.enter();
+
         // notify chat server
-        if self.disconnected_at.is_none() {
-            self.addr.do_send(chess_server::Disconnect {
-                id: self.id.clone(),
-            });
-        }
+        self.addr.do_send(Traced::new(chess_server::Disconnect {
+            id: self.id.clone(),
+        }));
+        self.lobby.do_send(lobby::LeaveLobby {
+            id: self.id.clone(),
+        });
         Running::Stop
     }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        self.span
+            .in_scope(|| tracing::info!("Websocket session stopped"));
+
+        self.metrics.ws_disconnects.inc();
+        self.metrics.active_sessions.dec();
+    }
 }
 
 /// Handle messages from chat server, we simply send it to peer websocket
@@ -120,35 +299,41 @@ impl Handler<chess_server::Message> for WsChessSession {
     }
 }
 
-impl Handler<chess_server::UserSync> for WsChessSession {
+impl Handler<chess_server::RoomJoined> for WsChessSession {
     type Result = ();
 
-    fn handle(&mut self, msg: chess_server::UserSync, _: &mut Self::Context) {
-        self.name = msg.name;
+    fn handle(&mut self, msg: chess_server::RoomJoined, _: &mut Self::Context) {
+        let _enter = self.span.enter();
+        tracing::debug!(room = %msg.name, "Session entered room");
+
+        self.room = Some(msg.addr);
     }
 }
 
-impl Handler<chess_server::Disconnect> for WsChessSession {
+impl Handler<chess_server::UserSync> for WsChessSession {
     type Result = ();
 
-    fn handle(&mut self, _: chess_server::Disconnect, ctx: &mut Self::Context) {
-        self.disconnected_at = Some(Instant::now());
-        ctx.stop();
+    fn handle(&mut self, msg: chess_server::UserSync, _: &mut Self::Context) {
+        self.name = msg.name;
     }
 }
 
 /// WebSocket message handler
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsChessSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let session_span = self.span.clone();
+        let _enter = session_span.enter();
+
         let msg = match msg {
-            Err(_) => {
+            Err(e) => {
+                tracing::warn!(error = %e, "Websocket protocol error");
                 ctx.stop();
                 return;
             }
             Ok(msg) => msg,
         };
 
-        log::debug!("WEBSOCKET MESSAGE: {msg:?}");
+        tracing::trace!(?msg, "Websocket frame");
         match msg {
             ws::Message::Ping(msg) => {
                 self.hb = Instant::now();
@@ -159,6 +344,24 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsChessSession {
             }
             ws::Message::Text(text) => {
                 let m = text.trim();
+                let message_type = if m.starts_with('/') {
+                    m.split_once(' ').map(|(cmd, _)| cmd).unwrap_or(m)
+                } else {
+                    "chat"
+                };
+                let span = tracing::info_span!("ws_message", message = message_type);
+                let _enter = span.enter();
+
+                if m.len() > self.limits.max_message_size {
+                    self.violation(ctx, "message_too_large", "Message is too large");
+                    return;
+                }
+
+                if !self.acquire(Self::message_cost(m)) {
+                    self.violation(ctx, "rate_limited", "Too many messages, slow down");
+                    return;
+                }
+
                 // we check for /sss type of messages
                 if m.starts_with('/') {
                     let (cmd, input) = m.split_once(' ').unwrap_or((m, ""));
@@ -174,79 +377,307 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsChessSession {
                                         }
                                         None => (Some(s.to_owned().to_owned()), None),
                                     });
-                                self.addr.do_send(chess_server::Join {
+                                self.addr.do_send(Traced::new(chess_server::Join {
                                     id: self.id.clone(),
                                     name: room_name,
                                     fen: params.clone().unwrap_or((None, None)).0,
                                     trash: params.unwrap_or((None, None)).1,
-                                });
+                                }));
                             } else {
                                 ctx.text("!!! room name is required");
                             }
                         }
-                        "/username" => {
-                            if input != "" {
-                                self.name = input.to_owned();
-
-                                self.addr.do_send(chess_server::UserSync {
-                                    id: self.id.clone(),
-                                    name: self.name.clone(),
-                                });
-                            } else {
-                                ctx.text("!!! name is required");
-                            }
-                        }
                         "/move" => {
-                            let v: Vec<&str> = input.splitn(3, ' ').collect();
-                            if v.len() == 3 {
+                            let v: Vec<&str> = input.splitn(4, ' ').collect();
+                            if v.len() >= 3 {
                                 let piece = v[0].to_owned();
                                 let from = v[1].to_owned();
-                                let to = v[2].to_owned();
+                                // a promotion other than a queen is asked like `e8=N`
+                                let (to, promotion) = match v[2].split_once('=') {
+                                    Some((to, letter)) => {
+                                        let Some(promotion) = letter
+                                            .chars()
+                                            .next()
+                                            .and_then(|c| PromotionKind::from_letter(c).ok())
+                                        else {
+                                            ctx.text("!!! invalid promotion");
+                                            return;
+                                        };
+                                        (to.to_owned(), promotion)
+                                    }
+                                    None => (v[2].to_owned(), PromotionKind::Queen),
+                                };
+                                let base_seq = v.get(3).and_then(|seq| seq.parse::<u64>().ok());
 
-                                self.addr.do_send(chess_server::Move {
-                                    id: self.id.clone(),
-                                    piece,
-                                    from,
-                                    to,
-                                });
+                                self.send_to_room(
+                                    ctx,
+                                    room::Move {
+                                        id: self.id.clone(),
+                                        piece,
+                                        from,
+                                        to,
+                                        promotion,
+                                        base_seq,
+                                    },
+                                );
                             } else {
                                 ctx.text("!!! move is required");
                             }
                         }
+                        "/resync" => {
+                            self.send_to_room(
+                                ctx,
+                                room::Resync {
+                                    id: self.id.clone(),
+                                    seq: input.parse::<u64>().ok(),
+                                },
+                            );
+                        }
                         "/reset" => {
-                            self.addr.do_send(chess_server::Reset {
-                                id: self.id.clone(),
-                            });
+                            self.send_to_room(
+                                ctx,
+                                room::Reset {
+                                    id: self.id.clone(),
+                                },
+                            );
+                        }
+                        "/load_fen" => {
+                            if input.is_empty() {
+                                ctx.text("!!! fen is required");
+                            } else {
+                                self.send_to_room(
+                                    ctx,
+                                    room::LoadPosition {
+                                        id: self.id.clone(),
+                                        fen: input.to_owned(),
+                                    },
+                                );
+                            }
                         }
                         "/undo" => {
-                            self.addr.do_send(chess_server::Undo {
-                                id: self.id.clone(),
-                            });
+                            self.send_to_room(
+                                ctx,
+                                room::Undo {
+                                    id: self.id.clone(),
+                                },
+                            );
                         }
                         "/redo" => {
-                            self.addr.do_send(chess_server::Redo {
-                                id: self.id.clone(),
-                            });
+                            self.send_to_room(
+                                ctx,
+                                room::Redo {
+                                    id: self.id.clone(),
+                                },
+                            );
+                        }
+                        "/goto" => match node_from_str(input) {
+                            Ok(node) => self.send_to_room(
+                                ctx,
+                                room::Goto {
+                                    id: self.id.clone(),
+                                    node,
+                                },
+                            ),
+                            Err(_) => ctx.text("!!! move must be a move id or start"),
+                        },
+                        "/promote" => match input.parse::<usize>() {
+                            Ok(node) => self.send_to_room(
+                                ctx,
+                                room::Promote {
+                                    id: self.id.clone(),
+                                    node,
+                                },
+                            ),
+                            Err(_) => ctx.text("!!! move id is required"),
+                        },
+                        "/delete_move" => match input.parse::<usize>() {
+                            Ok(node) => self.send_to_room(
+                                ctx,
+                                room::DeleteMove {
+                                    id: self.id.clone(),
+                                    node,
+                                },
+                            ),
+                            Err(_) => ctx.text("!!! move id is required"),
+                        },
+                        "/comment" | "/nag" | "/shape" | "/clear_shapes" => {
+                            let (node, value) = input.split_once(' ').unwrap_or((input, ""));
+                            let change = match cmd {
+                                "/comment" => {
+                                    Some(room::AnnotationChange::Comment(value.trim().to_string()))
+                                }
+                                "/nag" => value
+                                    .parse::<u8>()
+                                    .ok()
+                                    .filter(|nag| nag_glyph(*nag).is_some())
+                                    .map(room::AnnotationChange::ToggleNag),
+                                "/shape" => value
+                                    .parse::<Shape>()
+                                    .ok()
+                                    .map(room::AnnotationChange::ToggleShape),
+                                _ => Some(room::AnnotationChange::ClearShapes),
+                            };
+                            match (node_from_str(node), change) {
+                                (Ok(node), Some(change)) => self.send_to_room(
+                                    ctx,
+                                    room::Annotate {
+                                        id: self.id.clone(),
+                                        node,
+                                        change,
+                                    },
+                                ),
+                                (Err(_), _) => ctx.text("!!! move must be a move id or start"),
+                                (_, None) => ctx.text("!!! invalid annotation"),
+                            }
+                        }
+                        "/fork" => {
+                            let mut args = input.split_whitespace();
+                            let name = args.next().map(str::to_owned);
+                            let mut node = None;
+                            let mut with_history = false;
+                            let mut valid = true;
+                            for arg in args {
+                                match arg {
+                                    "history" => with_history = true,
+                                    arg => match node_from_str(arg) {
+                                        Ok(n) if node.is_none() => node = Some(n),
+                                        _ => valid = false,
+                                    },
+                                }
+                            }
+
+                            match name {
+                                Some(name) if !is_valid_room_name(&name) => {
+                                    ctx.text("!!! room names can't contain spaces or |")
+                                }
+                                Some(name) if valid => self.send_to_room(
+                                    ctx,
+                                    room::Fork {
+                                        id: self.id.clone(),
+                                        name,
+                                        node,
+                                        with_history,
+                                    },
+                                ),
+                                Some(_) => ctx.text("!!! move must be a move id or start"),
+                                None => ctx.text("!!! room name is required"),
+                            }
+                        }
+                        "/pgn" => {
+                            self.send_to_room(
+                                ctx,
+                                room::ExportPgn {
+                                    id: self.id.clone(),
+                                },
+                            );
                         }
                         "/options" => {
-                            self.addr.do_send(chess_server::Options {
-                                id: self.id.clone(),
-                                validation: input.contains("validation"),
-                                sync: input.contains("sync"),
-                            });
+                            self.send_to_room(
+                                ctx,
+                                room::Options {
+                                    id: self.id.clone(),
+                                    validation: input.contains("validation"),
+                                    sync: input.contains("sync"),
+                                    pause_clock: input.contains("pause"),
+                                    rated: input.contains("rated"),
+                                },
+                            );
+                        }
+                        "/lobby" => self.lobby.do_send(lobby::Watch {
+                            id: self.id.clone(),
+                            addr: ctx.address(),
+                        }),
+                        "/leave_lobby" => self.lobby.do_send(lobby::Unwatch {
+                            id: self.id.clone(),
+                        }),
+                        "/seek" => match input.parse::<SeekRequest>() {
+                            Ok(request) => self.lobby.do_send(Traced::new(lobby::PostSeek {
+                                seeker: self.seeker(ctx),
+                                request,
+                            })),
+                            Err(_) => {
+                                ctx.text("!!! seek must look like 5+3 random rated 1400-1700")
+                            }
+                        },
+                        "/cancel_seek" => self.lobby.do_send(Traced::new(lobby::CancelSeek {
+                            user_id: self.user_id.clone(),
+                        })),
+                        "/accept" => {
+                            if input != "" {
+                                self.lobby.do_send(Traced::new(lobby::AcceptSeek {
+                                    seeker: self.seeker(ctx),
+                                    seek_id: input.to_owned(),
+                                }));
+                            } else {
+                                ctx.text("!!! seek id is required");
+                            }
+                        }
+                        "/sit" => match side_from_str(input) {
+                            Ok(side) => self.send_to_room(
+                                ctx,
+                                room::Sit {
+                                    id: self.id.clone(),
+                                    side,
+                                },
+                            ),
+                            Err(_) => ctx.text("!!! side must be white or black"),
+                        },
+                        "/stand" => {
+                            self.send_to_room(
+                                ctx,
+                                room::Stand {
+                                    id: self.id.clone(),
+                                },
+                            );
+                        }
+                        "/clock" => {
+                            let time_control = match input {
+                                "off" => Ok(None),
+                                _ => input.parse::<TimeControl>().map(Some),
+                            };
+                            match time_control {
+                                Ok(time_control) => self.send_to_room(
+                                    ctx,
+                                    room::SetClock {
+                                        id: self.id.clone(),
+                                        time_control,
+                                    },
+                                ),
+                                Err(_) => ctx.text("!!! clock must be off or minutes+seconds"),
+                            }
+                        }
+                        "/claim" => {
+                            let claim = match input {
+                                "win" => game::Claim::Win,
+                                "draw" => game::Claim::Draw,
+                                _ => {
+                                    ctx.text("!!! claim must be win or draw");
+                                    return;
+                                }
+                            };
+                            self.send_to_room(
+                                ctx,
+                                room::Claim {
+                                    id: self.id.clone(),
+                                    claim,
+                                },
+                            );
                         }
                         _ => ctx.text(format!("!!! unknown command: {m:?}")),
                     }
                 } else {
                     let msg = format!("{name}: {m}", name = self.name);
                     // send message to chat server
-                    self.addr.do_send(chess_server::ClientMessage {
-                        id: self.id.clone(),
-                        msg,
-                    })
+                    self.send_to_room(
+                        ctx,
+                        room::ClientMessage {
+                            id: self.id.clone(),
+                            msg,
+                        },
+                    )
                 }
             }
-            ws::Message::Binary(_) => log::error!("Unexpected binary"),
+            ws::Message::Binary(_) => tracing::error!("Unexpected binary"),
             ws::Message::Close(reason) => {
                 ctx.close(reason);
                 ctx.stop();
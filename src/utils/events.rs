@@ -20,6 +20,23 @@ impl EventTargetExt for web_sys::TouchEvent {
     }
 }
 
+pub trait EventButtonExt {
+    /// Right click, it draws on the board instead of moving pieces
+    fn is_secondary_button(&self) -> bool;
+}
+
+impl EventButtonExt for web_sys::MouseEvent {
+    fn is_secondary_button(&self) -> bool {
+        self.button() == 2
+    }
+}
+
+impl EventButtonExt for web_sys::TouchEvent {
+    fn is_secondary_button(&self) -> bool {
+        false
+    }
+}
+
 pub trait EventPositionExt {
     fn position(&self) -> (i32, i32);
 }
@@ -0,0 +1,88 @@
+use cfg_if::cfg_if;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Double submit token, the server refuses posts without it
+const CSRF_COOKIE: &str = "csrf_token";
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Value of a cookie readable by the page
+#[allow(unused_variables)]
+fn get_cookie_value(name: &str) -> Option<String> {
+    cfg_if! {
+        if #[cfg(not(feature = "ssr"))] {
+            let document = web_sys::window()?.document()?;
+            let document = super::js_cast::<web_sys::HtmlDocument, _>(document)?;
+            let cookie = document.cookie().ok()?;
+            let cookie = cookie
+                .split(";")
+                .map(|s| s.trim())
+                .find(|s| s.starts_with(&format!("{}=", name)))?;
+            let cookie = cookie.split("=").nth(1)?;
+            Some(cookie.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+async fn error_message(response: gloo_net::http::Response) -> String {
+    match response.json::<ErrorBody>().await {
+        Ok(body) => body.error,
+        Err(_) => format!("Request failed with status {}", response.status()),
+    }
+}
+
+/// Gets a JSON body, the error is the message sent by the server
+pub async fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, String> {
+    let response = gloo_net::http::Request::get(url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.ok() {
+        return Err(error_message(response).await);
+    }
+
+    response.json::<T>().await.map_err(|e| e.to_string())
+}
+
+async fn post<T: Serialize>(url: &str, body: &T) -> Result<gloo_net::http::Response, String> {
+    let response = gloo_net::http::Request::post(url)
+        .header(
+            CSRF_HEADER,
+            &get_cookie_value(CSRF_COOKIE).unwrap_or_default(),
+        )
+        .json(body)
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.ok() {
+        return Ok(response);
+    }
+
+    Err(error_message(response).await)
+}
+
+/// Posts `body` as JSON, the error is the message sent by the server
+pub async fn post_json<T: Serialize>(url: &str, body: &T) -> Result<(), String> {
+    post(url, body).await.map(|_| ())
+}
+
+/// Posts `body` as JSON and reads the JSON answer
+pub async fn post_json_for<T: Serialize, R: DeserializeOwned>(
+    url: &str,
+    body: &T,
+) -> Result<R, String> {
+    post(url, body)
+        .await?
+        .json::<R>()
+        .await
+        .map_err(|e| e.to_string())
+}
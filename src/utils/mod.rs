@@ -2,20 +2,12 @@ pub mod class_list;
 pub mod element_pool;
 pub mod elements;
 pub mod events;
-pub mod jwt;
+pub mod http;
+pub mod session;
 pub mod style;
+pub mod url;
 
 use cfg_if::cfg_if;
-use serde::{Deserialize, Serialize};
-
-use crate::entities::chess_board::signals::ChessBoardSignals;
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct SessionPayload {
-    pub sub: String,
-    pub name: String,
-    pub iat: u64,
-}
 
 cfg_if! {
     if #[cfg(not(feature = "ssr"))] {
@@ -33,43 +25,25 @@ cfg_if! {
     }
 }
 
-#[allow(unused_variables)]
-pub fn get_cookie_value(name: &str) -> Option<String> {
+/// Milliseconds since the epoch on the client clock
+pub fn now_ms() -> f64 {
     cfg_if! {
         if #[cfg(not(feature = "ssr"))] {
-            let document = web_sys::window()?.document()?;
-            let document = js_cast::<web_sys::HtmlDocument, _>(document)?;
-            let cookie = document.cookie().ok()?;
-            let cookie = cookie
-                .split(";")
-                .map(|s| s.trim())
-                .find(|s| s.starts_with(&format!("{}=", name)))?;
-            let cookie = cookie.split("=").nth(1)?;
-            Some(cookie.to_string())
+            js_sys::Date::now()
         } else {
-            None
+            0.0
         }
     }
 }
+
 cfg_if! {
     if #[cfg(not(feature = "ssr"))] {
-        use wasm_bindgen::JsValue;
-
         pub fn closure<F>(callback: F) -> wasm_bindgen::prelude::Closure<dyn FnMut()>
         where
             F: FnMut() + 'static,
         {
             wasm_bindgen::prelude::Closure::<dyn FnMut()>::new(callback)
         }
-
-        pub fn closure_with_arg<F>(
-            callback: F,
-        ) -> wasm_bindgen::prelude::Closure<dyn FnMut(JsValue)>
-        where
-        F: FnMut(JsValue) + 'static,
-        {
-            wasm_bindgen::prelude::Closure::<dyn FnMut(JsValue)>::new(callback)
-        }
     }
 }
 
@@ -78,7 +52,9 @@ pub trait WindowExt {
     where
         F: FnMut() + 'static;
 
-    fn post_user_name(&self, name: &str, chess_board_signals: ChessBoardSignals);
+    fn set_interval_callback<F>(&self, callback: F, miliseconds: i32) -> Option<i32>
+    where
+        F: FnMut() + 'static;
 }
 
 #[allow(unused_variables)]
@@ -106,22 +82,25 @@ impl WindowExt for web_sys::Window {
         }
     }
 
-    fn post_user_name(&self, name: &str, chess_board_signals: ChessBoardSignals) {
+    fn set_interval_callback<F>(&self, callback: F, miliseconds: i32) -> Option<i32>
+    where
+        F: FnMut() + 'static,
+    {
         cfg_if! {
             if #[cfg(not(feature = "ssr"))] {
-                use wasm_bindgen::JsValue;
-
-                let mut request_init = web_sys::RequestInit::new();
-                request_init
-                    .method("POST")
-                    .body(Some(&JsValue::from_str(&format!("{} \"username\": \"{}\" {}", "{", name, "}"))));
-                let promise = self.fetch_with_str_and_init("/sessions", &request_init);
+                use wasm_bindgen::JsCast;
 
-                let start_websocket = closure_with_arg(move |_: JsValue| {
-                    chess_board_signals.start_websocket();
-                });
-                let _ = promise.then(&start_websocket);
-                start_websocket.forget();
+                let closure = closure(callback);
+                let args = js_sys::Array::new();
+                let interval_callback_handle = self.set_interval_with_callback_and_timeout_and_arguments(
+                    closure.as_ref().unchecked_ref(),
+                    miliseconds,
+                    &args,
+                    );
+                closure.forget();
+                interval_callback_handle.ok()
+            } else {
+                None
             }
         }
     }
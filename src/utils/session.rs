@@ -0,0 +1,66 @@
+//! Session of the client, the tokens are `HttpOnly` cookies so the server
+//! tells who the user is and when the access token has to be refreshed.
+
+use std::cell::Cell;
+
+use leptos::{spawn_local, SignalSet};
+
+use super::{
+    http::{get_json, post_json},
+    WindowExt,
+};
+use crate::entities::{chess_board::signals::ChessBoardSignals, session::SessionInfo};
+
+/// Share of the access token lifetime after which it is silently refreshed
+const REFRESH_AT: f64 = 0.8;
+
+thread_local! {
+    static REFRESH_TIMEOUT: Cell<Option<i32>> = const { Cell::new(None) };
+}
+
+fn schedule_refresh(chess_board_signals: ChessBoardSignals, expires_in: u64) {
+    let window = web_sys::window().unwrap();
+
+    if let Some(id) = REFRESH_TIMEOUT.with(|timeout| timeout.take()) {
+        window.clear_timeout_with_handle(id);
+    }
+
+    let delay = (expires_in as f64 * 1000.0 * REFRESH_AT) as i32;
+    let id = window.set_timeout_callback(
+        move || {
+            spawn_local(async move {
+                refresh_session(chess_board_signals).await;
+            })
+        },
+        delay,
+    );
+    REFRESH_TIMEOUT.with(|timeout| timeout.set(id));
+}
+
+/// Reads the session, an expired access token is refreshed once, and plans
+/// the next refresh
+pub async fn load_session(chess_board_signals: ChessBoardSignals) -> Option<SessionInfo> {
+    let session = match get_json::<SessionInfo>("/sessions/me").await {
+        Ok(session) => Some(session),
+        Err(_) => match post_json("/sessions/refresh", &()).await {
+            Ok(_) => get_json::<SessionInfo>("/sessions/me").await.ok(),
+            Err(_) => None,
+        },
+    };
+
+    if let Some(session) = session.as_ref() {
+        schedule_refresh(chess_board_signals, session.expires_in);
+    }
+    chess_board_signals.session().set(session.clone());
+
+    session
+}
+
+/// Trades the refresh token for a new pair, open sockets are not affected
+pub async fn refresh_session(chess_board_signals: ChessBoardSignals) -> Option<SessionInfo> {
+    if let Err(error) = post_json("/sessions/refresh", &()).await {
+        log::warn!("Failed to refresh the session: {}", error);
+    }
+
+    load_session(chess_board_signals).await
+}
@@ -65,6 +65,8 @@ module.exports = {
             '69': '17.25rem',
             '73': '18.25rem',
             '74': '18.5rem',
+            '79.5': '19.875rem',
+            '85.5': '21.375rem',
             '88': '22rem',
         },
         padding: {